@@ -0,0 +1,63 @@
+/*
+ * Entry verification for C, exported by the solana crate when built with
+ * the `ffi` feature:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * Keep in sync with src/ffi.rs.
+ */
+
+#ifndef SOLANA_H
+#define SOLANA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SOLANA_OK 0
+/* A pointer argument is null. */
+#define SOLANA_ERR_NULL -1
+/* The bytes don't hold what the function expects. */
+#define SOLANA_ERR_MALFORMED -2
+/* The function panicked. */
+#define SOLANA_ERR_PANIC -3
+
+/* The size of the hashes passed as start_hash. */
+#define SOLANA_HASH_SIZE 32
+
+/* A decoded entry, owned by the caller until passed to solana_free. */
+typedef struct SolanaEntry SolanaEntry;
+
+/*
+ * Decode the bincode-serialized entry in the len bytes at bytes, and store a
+ * handle to it in out_handle. The bytes must hold exactly one entry.
+ */
+int32_t solana_entry_decode(const uint8_t *bytes, size_t len, SolanaEntry **out_handle);
+
+/*
+ * Return 1 if the entry of handle follows from the 32 byte hash at
+ * start_hash, or 0 if it doesn't.
+ */
+int32_t solana_entry_verify(const SolanaEntry *handle, const uint8_t *start_hash);
+
+/*
+ * Return 1 if the entries of the bincode-serialized list in the len bytes at
+ * bytes each follow from the one before, and the first from the 32 byte hash
+ * at start_hash. Otherwise, return 0 and store the index of the first entry
+ * that doesn't in out_failed_height.
+ */
+int32_t solana_entries_verify_chain(const uint8_t *bytes, size_t len,
+                                    const uint8_t *start_hash,
+                                    uint64_t *out_failed_height);
+
+/* Free a handle from solana_entry_decode. Null is ignored. */
+void solana_free(SolanaEntry *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -5,93 +5,593 @@
 
 extern crate libc;
 
+use bincode::{deserialize, serialize};
+use byteorder::{ByteOrder, LittleEndian};
 use chrono::prelude::*;
+use entry::Entry;
 use event::Event;
-use hash::Hash;
+use hash::{hash, Hash};
 use mint::Mint;
+use parallel::*;
 use plan::{Payment, Plan, Witness};
-use rayon::prelude::*;
-use signature::{KeyPair, PublicKey, Signature};
+use signature::{KeyPair, KeyPairUtil, PublicKey, Signature};
 use std::collections::hash_map::Entry::Occupied;
 use std::collections::{HashMap, HashSet, VecDeque};
+use result::Error;
 use std::result;
-use std::sync::RwLock;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use transaction::Transaction;
 
 pub const MAX_ENTRY_IDS: usize = 1024 * 4;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum AccountingError {
     AccountNotFound,
     InsufficientFunds,
     InvalidTransferSignature,
+    /// The transaction's plan doesn't spend exactly its tokens.
+    InvalidPlan,
+    /// The transaction's `last_id` was never registered or has aged out of
+    /// the last `MAX_ENTRY_IDS` entry ids.
+    LastIdNotFound,
 }
 
 pub type Result<T> = result::Result<T, AccountingError>;
 
+/// Identifies a serialized AccountantSnapshot.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"SOLSNAPS";
+/// The format version written after the magic.
+pub const SNAPSHOT_VERSION: u32 = 1;
+/// Magic, followed by the little-endian format version.
+pub const SNAPSHOT_HEADER_SIZE: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuditError {
+    /// The tokens add up to more than an i64 can hold.
+    SupplyOverflow,
+    /// The tokens add up to `actual` rather than `expected`.
+    SupplyMismatch { expected: i64, actual: i64 },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The bytes don't start with `SNAPSHOT_MAGIC`.
+    BadMagic,
+    /// The snapshot was written in a format version this build can't read.
+    UnsupportedVersion(u32),
+    /// The snapshot's tokens add up to more than an i64 can hold.
+    SupplyOverflow,
+}
+
+/// The state of an Accountant between entries. Every collection is sorted,
+/// giving each state a single serialized form.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AccountantSnapshot {
+    pub balances: Vec<(PublicKey, i64)>,
+    /// Plans waiting on a witness, by the signature of their transaction.
+    pub pending: Vec<(Signature, Plan)>,
+    /// The registered entry ids, oldest first, each with the signatures
+    /// reserved under it.
+    pub last_ids: Vec<(Hash, Vec<Signature>)>,
+    pub time_sources: Vec<PublicKey>,
+    pub last_time: DateTime<Utc>,
+}
+
+impl AccountantSnapshot {
+    /// Serialize the snapshot after a header of `SNAPSHOT_MAGIC` and
+    /// `SNAPSHOT_VERSION`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        let mut version = [0u8; 4];
+        LittleEndian::write_u32(&mut version, SNAPSHOT_VERSION);
+        bytes.extend_from_slice(&version);
+        bytes.extend(serialize(self).unwrap());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> result::Result<Self, Error> {
+        if bytes.len() < SNAPSHOT_HEADER_SIZE || bytes[..8] != SNAPSHOT_MAGIC {
+            return Err(Error::Snapshot(SnapshotError::BadMagic));
+        }
+        let version = LittleEndian::read_u32(&bytes[8..SNAPSHOT_HEADER_SIZE]);
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::Snapshot(SnapshotError::UnsupportedVersion(version)));
+        }
+        let snapshot: Self = deserialize(&bytes[SNAPSHOT_HEADER_SIZE..])?;
+        if snapshot.checked_total_supply().is_none() {
+            return Err(Error::Snapshot(SnapshotError::SupplyOverflow));
+        }
+        Ok(snapshot)
+    }
+
+    /// Return the tokens in the snapshot's balances and pending plans, or
+    /// `None` if they add up to more than an i64 can hold.
+    fn checked_total_supply(&self) -> Option<i64> {
+        let balances = self.balances.iter().map(|x| x.1);
+        let pending = self.pending.iter().map(|x| x.1.payments()[0].tokens);
+        balances
+            .chain(pending)
+            .try_fold(0i64, |total, tokens| total.checked_add(tokens))
+    }
+
+    /// Return the hash of the snapshot's serialized form, which two
+    /// accountants share only if they hold the same state.
+    pub fn hash(&self) -> Hash {
+        hash(&serialize(self).unwrap())
+    }
+}
+
+/// The number of separately locked shards the balances are split into.
+pub const NUM_BALANCE_SHARDS: usize = 16;
+
+type BalanceShard = RwLock<HashMap<PublicKey, RwLock<i64>>>;
+
+/// Return the index of the shard holding the balance of `pubkey`.
+fn shard_index(pubkey: &PublicKey) -> usize {
+    pubkey[0] as usize % NUM_BALANCE_SHARDS
+}
+
+/// Return a flag for each shard, all set.
+fn dirty_flags() -> Vec<AtomicBool> {
+    (0..NUM_BALANCE_SHARDS)
+        .map(|_| AtomicBool::new(true))
+        .collect()
+}
+
+/// Balances split into shards by the first byte of the public key, so that
+/// adding an account only locks out the accounts of its own shard. A shard
+/// is marked dirty when it changes, until it's next published.
+struct Balances {
+    shards: Vec<BalanceShard>,
+    dirty: Vec<AtomicBool>,
+}
+
+impl Balances {
+    fn new() -> Self {
+        Balances {
+            shards: (0..NUM_BALANCE_SHARDS)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            dirty: dirty_flags(),
+        }
+    }
+
+    fn shard(&self, pubkey: &PublicKey) -> &BalanceShard {
+        &self.shards[shard_index(pubkey)]
+    }
+
+    /// Mark the shard of `pubkey` dirty. Call after changing it, so that a
+    /// publisher that clears the flag first can't miss the change.
+    fn mark_dirty(&self, pubkey: &PublicKey) {
+        self.dirty[shard_index(pubkey)].store(true, Ordering::Relaxed);
+    }
+}
+
 /// Commit funds to the 'to' party.
-fn apply_payment(balances: &RwLock<HashMap<PublicKey, RwLock<i64>>>, payment: &Payment) {
-    if balances.read().unwrap().contains_key(&payment.to) {
-        let bals = balances.read().unwrap();
-        *bals[&payment.to].write().unwrap() += payment.tokens;
-    } else {
-        let mut bals = balances.write().unwrap();
-        bals.insert(payment.to, RwLock::new(payment.tokens));
+fn apply_payment(balances: &Balances, payment: &Payment) {
+    let shard = balances.shard(&payment.to);
+    if let Some(bal) = shard.read().unwrap().get(&payment.to) {
+        *bal.write().unwrap() += payment.tokens;
+        balances.mark_dirty(&payment.to);
+        return;
+    }
+    let mut bals = shard.write().unwrap();
+    let bal = bals.entry(payment.to).or_insert_with(|| RwLock::new(0));
+    *bal.get_mut().unwrap() += payment.tokens;
+    balances.mark_dirty(&payment.to);
+}
+
+/// Return the shards of the accounts `tr` may debit or credit.
+fn transaction_shards(tr: &Transaction) -> Vec<usize> {
+    let mut shards = vec![shard_index(&tr.from)];
+    for payment in tr.data.plan.payments() {
+        shards.push(shard_index(&payment.to));
+    }
+    shards
+}
+
+/// Transaction fees, paid by the sender on top of the tokens it spends.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Fees {
+    /// The account credited with every fee. Transactions it sends are free.
+    pub collector: PublicKey,
+    pub per_transaction: i64,
+}
+
+/// A change to an account's balance.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ActivityRecord {
+    /// The number of Entry IDs registered since history was turned on.
+    pub entry_height: u64,
+    /// The signature of the transaction that made the change.
+    pub tx_id: Signature,
+    pub delta: i64,
+    /// The other side of the change, or `None` when releasing a plan that
+    /// was pending before history was turned on.
+    pub counterparty: Option<PublicKey>,
+}
+
+type ActivityShard = Mutex<HashMap<PublicKey, VecDeque<ActivityRecord>>>;
+
+/// The most recent activity of each account, sharded and marked dirty like
+/// the balances, and the sender of each pending plan so that its release can
+/// name them.
+struct History {
+    depth: usize,
+    entry_height: AtomicUsize,
+    shards: Vec<ActivityShard>,
+    dirty: Vec<AtomicBool>,
+    senders: Mutex<HashMap<Signature, PublicKey>>,
+}
+
+impl History {
+    fn new(depth: usize) -> Self {
+        History {
+            depth,
+            entry_height: AtomicUsize::new(0),
+            shards: (0..NUM_BALANCE_SHARDS)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+            dirty: dirty_flags(),
+            senders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a change to the balance of `pubkey`, evicting its oldest record
+    /// if it already has `depth` of them.
+    fn record(
+        &self,
+        pubkey: PublicKey,
+        tx_id: Signature,
+        delta: i64,
+        counterparty: Option<PublicKey>,
+    ) {
+        let record = ActivityRecord {
+            entry_height: self.entry_height.load(Ordering::Relaxed) as u64,
+            tx_id,
+            delta,
+            counterparty,
+        };
+        let mut records = self.shards[shard_index(&pubkey)].lock().unwrap();
+        let records = records.entry(pubkey).or_default();
+        if records.len() == self.depth {
+            records.pop_front();
+        }
+        records.push_back(record);
+        self.dirty[shard_index(&pubkey)].store(true, Ordering::Relaxed);
+    }
+}
+
+/// Counters of how `process_verified_transactions_par` split its batches.
+#[derive(Debug, Default)]
+pub struct AccountantMetrics {
+    /// Groups of transactions applied in parallel.
+    pub parallel_groups: AtomicUsize,
+    /// Transactions that touched a shard already touched by an earlier
+    /// transaction of their group, and so started a new group.
+    pub conflicts: AtomicUsize,
+}
+
+/// An immutable copy of the accountant's balances and activity, as of the
+/// last time it published one.
+#[derive(Default)]
+pub struct AccountantView {
+    last_id: Hash,
+    balances: Vec<Arc<HashMap<PublicKey, i64>>>,
+    activity: Vec<Arc<HashMap<PublicKey, VecDeque<ActivityRecord>>>>,
+}
+
+impl AccountantView {
+    pub fn get_balance(&self, pubkey: &PublicKey) -> Option<i64> {
+        let shard = self.balances.get(shard_index(pubkey));
+        shard.and_then(|x| x.get(pubkey)).cloned()
+    }
+
+    pub fn last_id(&self) -> Hash {
+        self.last_id
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.balances.iter().map(|shard| shard.len()).sum()
+    }
+
+    /// Return the balance of every account, sorted by public key.
+    pub fn collect_balances(&self) -> Vec<(PublicKey, i64)> {
+        let mut balances: Vec<_> = self.balances
+            .iter()
+            .flat_map(|shard| shard.iter().map(|(pubkey, bal)| (*pubkey, *bal)))
+            .collect();
+        balances.sort();
+        balances
+    }
+
+    pub fn recent_activity(&self, pubkey: &PublicKey, limit: usize) -> Vec<ActivityRecord> {
+        match self.activity.get(shard_index(pubkey)).and_then(|x| x.get(pubkey)) {
+            Some(records) => records.iter().rev().take(limit).cloned().collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// Serves reads from the views an Accountant publishes, without taking any of
+/// its locks. Reads are as of the last time the accountant published, which
+/// it does each time it registers an Entry ID, so they're at most an entry
+/// behind. Each method reads the latest view; use `view` to make several
+/// reads of the same one.
+#[derive(Clone)]
+pub struct AccountantReader {
+    published: Arc<RwLock<Arc<AccountantView>>>,
+}
+
+impl AccountantReader {
+    pub fn view(&self) -> Arc<AccountantView> {
+        self.published.read().unwrap().clone()
+    }
+
+    pub fn get_balance(&self, pubkey: &PublicKey) -> Option<i64> {
+        self.view().get_balance(pubkey)
+    }
+
+    pub fn last_id(&self) -> Hash {
+        self.view().last_id()
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.view().account_count()
+    }
+
+    pub fn recent_activity(&self, pubkey: &PublicKey, limit: usize) -> Vec<ActivityRecord> {
+        self.view().recent_activity(pubkey, limit)
     }
 }
 
 pub struct Accountant {
-    balances: RwLock<HashMap<PublicKey, RwLock<i64>>>,
+    balances: Balances,
     pending: RwLock<HashMap<Signature, Plan>>,
     last_ids: RwLock<VecDeque<(Hash, RwLock<HashSet<Signature>>)>>,
     time_sources: RwLock<HashSet<PublicKey>>,
     last_time: RwLock<DateTime<Utc>>,
+    fees: Option<Fees>,
+    /// The tokens the accountant started with, which it never mints more of.
+    supply: i64,
+    history: Option<History>,
+    published: Arc<RwLock<Arc<AccountantView>>>,
+    /// Held while publishing, so that a publisher can't replace a view with
+    /// an older one.
+    publishing: Mutex<()>,
+    metrics: AccountantMetrics,
 }
 
 impl Accountant {
     /// Create an Accountant using a deposit.
     pub fn new_from_deposit(deposit: &Payment) -> Self {
-        let balances = RwLock::new(HashMap::new());
+        let balances = Balances::new();
         apply_payment(&balances, deposit);
-        Accountant {
+        let acc = Accountant {
             balances,
             pending: RwLock::new(HashMap::new()),
             last_ids: RwLock::new(VecDeque::new()),
             time_sources: RwLock::new(HashSet::new()),
             last_time: RwLock::new(Utc.timestamp(0, 0)),
+            fees: None,
+            supply: deposit.tokens,
+            history: None,
+            published: Arc::default(),
+            publishing: Mutex::new(()),
+            metrics: AccountantMetrics::default(),
+        };
+        acc.publish();
+        acc
+    }
+
+    /// Create an Accountant from the first two entries of a genesis block:
+    /// an empty entry whose id is the seed, and an entry whose first event
+    /// deposits every token with the mint, a transaction from the mint to
+    /// itself rather than a transfer. The deposit's entry id is registered,
+    /// but the entries after it are left to the caller. Returns None if
+    /// `entries` doesn't start with a deposit.
+    pub fn new_from_genesis(entries: &[Entry]) -> Option<Self> {
+        let entry1 = entries.get(1)?;
+        let deposit = match entry1.events.first() {
+            Some(Event::Transaction(tr)) => tr.data.plan.final_payment()?,
+            _ => return None,
+        };
+        let acc = Self::new_from_deposit(&deposit);
+        acc.register_entry_id(&entry1.id);
+        Some(acc)
+    }
+
+    /// Create an Accountant with the state of `snapshot`. Panics if its
+    /// tokens add up to more than an i64 can hold, which
+    /// `AccountantSnapshot::from_bytes` checks.
+    pub fn from_snapshot(snapshot: AccountantSnapshot) -> Self {
+        let supply = snapshot
+            .checked_total_supply()
+            .expect("snapshot supply overflows an i64");
+        let balances = Balances::new();
+        for (pubkey, bal) in snapshot.balances {
+            let shard = balances.shard(&pubkey);
+            shard.write().unwrap().insert(pubkey, RwLock::new(bal));
+        }
+        let last_ids = snapshot
+            .last_ids
+            .into_iter()
+            .map(|(last_id, sigs)| (last_id, RwLock::new(sigs.into_iter().collect())))
+            .collect();
+        let acc = Accountant {
+            balances,
+            pending: RwLock::new(snapshot.pending.into_iter().collect()),
+            last_ids: RwLock::new(last_ids),
+            time_sources: RwLock::new(snapshot.time_sources.into_iter().collect()),
+            last_time: RwLock::new(snapshot.last_time),
+            fees: None,
+            supply,
+            history: None,
+            published: Arc::default(),
+            publishing: Mutex::new(()),
+            metrics: AccountantMetrics::default(),
+        };
+        acc.publish();
+        acc
+    }
+
+    /// Charge `fees` for every transaction processed from now on. Every
+    /// accountant replaying a ledger must be given the same fees.
+    pub fn set_fees(&mut self, fees: Option<Fees>) {
+        self.fees = fees;
+    }
+
+    pub fn fees(&self) -> Option<Fees> {
+        self.fees
+    }
+
+    /// Keep the `depth` most recent changes to each account's balance, or
+    /// none if `depth` is zero. Forgets any history kept so far.
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history = if depth == 0 {
+            None
+        } else {
+            Some(History::new(depth))
+        };
+        self.publish();
+    }
+
+    /// Return up to `limit` of the most recent changes to the balance of
+    /// `pubkey`, newest first.
+    pub fn recent_activity(&self, pubkey: &PublicKey, limit: usize) -> Vec<ActivityRecord> {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return vec![],
+        };
+        let records = history.shards[shard_index(pubkey)].lock().unwrap();
+        match records.get(pubkey) {
+            Some(records) => records.iter().rev().take(limit).cloned().collect(),
+            None => vec![],
         }
     }
 
-    /// Create an Accountant with only a Mint. Typically used by unit tests.
+    fn record_activity(
+        &self,
+        pubkey: PublicKey,
+        tx_id: Signature,
+        delta: i64,
+        counterparty: Option<PublicKey>,
+    ) {
+        if let Some(ref history) = self.history {
+            history.record(pubkey, tx_id, delta, counterparty);
+        }
+    }
+
+    /// Record the release of the plan of the transaction signed `tx_sig`.
+    fn record_release(&self, tx_sig: Signature, payment: &Payment) {
+        if let Some(ref history) = self.history {
+            let sender = history.senders.lock().unwrap().remove(&tx_sig);
+            history.record(payment.to, tx_sig, payment.tokens, sender);
+        }
+    }
+
+    /// Return the fee charged to the sender of `tr`.
+    fn fee(&self, tr: &Transaction) -> i64 {
+        match self.fees {
+            Some(ref fees) if fees.collector != tr.from => fees.per_transaction,
+            _ => 0,
+        }
+    }
+
+    /// Move every token in the fee collector's account to `to`, which may be
+    /// a key nobody holds to burn them. Returns the transaction that does so
+    /// for the caller to record in the ledger, or `None` if there are no fees
+    /// or `keypair` isn't the collector's. The transaction depends only on
+    /// the collector's balance and `last_id`, so every node collecting from
+    /// the same state makes the same one.
+    pub fn collect_fees(
+        &self,
+        keypair: &KeyPair,
+        to: PublicKey,
+        last_id: Hash,
+    ) -> Result<Option<Transaction>> {
+        let collector = match self.fees {
+            Some(ref fees) if fees.collector == keypair.pubkey() => fees.collector,
+            _ => return Ok(None),
+        };
+        let tokens = self.get_balance(&collector).unwrap_or(0);
+        if tokens == 0 {
+            return Ok(None);
+        }
+        let tr = Transaction::new(keypair, to, tokens, last_id);
+        self.process_verified_transaction(&tr)?;
+        Ok(Some(tr))
+    }
+
+    /// Return the accountant's state. Take it between entries: the parts of
+    /// the state are read one at a time, so a snapshot taken while events
+    /// are processed may not match any state the accountant was in.
+    pub fn snapshot(&self) -> AccountantSnapshot {
+        let mut pending: Vec<_> = self.pending
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(sig, plan)| (*sig, plan.clone()))
+            .collect();
+        pending.sort_by_key(|x| x.0);
+        let last_ids = self.last_ids
+            .read()
+            .unwrap()
+            .iter()
+            .map(|&(last_id, ref sigs)| {
+                let mut sigs: Vec<_> = sigs.read().unwrap().iter().cloned().collect();
+                sigs.sort();
+                (last_id, sigs)
+            })
+            .collect();
+        let mut time_sources: Vec<_> = self.time_sources.read().unwrap().iter().cloned().collect();
+        time_sources.sort();
+        AccountantSnapshot {
+            balances: self.collect_balances(),
+            pending,
+            last_ids,
+            time_sources,
+            last_time: *self.last_time.read().unwrap(),
+        }
+    }
+
+    /// Return the hash of the accountant's snapshot.
+    pub fn snapshot_hash(&self) -> Hash {
+        self.snapshot().hash()
+    }
+
+    /// Create an Accountant with only a Mint, with the balances of the
+    /// mint's genesis block. Typically used by unit tests.
     pub fn new(mint: &Mint) -> Self {
         let deposit = Payment {
             to: mint.pubkey(),
             tokens: mint.tokens,
         };
         let acc = Self::new_from_deposit(&deposit);
-        acc.register_entry_id(&mint.last_id());
+        let entries = mint.create_entries();
+        acc.register_entry_id(&entries[1].id);
+        acc.process_verified_entries(&entries[2..]);
         acc
     }
 
     fn reserve_signature(signatures: &RwLock<HashSet<Signature>>, sig: &Signature) -> bool {
-        if signatures.read().unwrap().contains(sig) {
-            return false;
-        }
-        signatures.write().unwrap().insert(*sig);
-        true
+        // Check and insert under one lock so that two threads can't both
+        // reserve the same signature.
+        signatures.write().unwrap().insert(*sig)
     }
 
-    fn reserve_signature_with_last_id(&self, sig: &Signature, last_id: &Hash) -> bool {
-        if let Some(entry) = self.last_ids
-            .read()
-            .unwrap()
-            .iter()
-            .rev()
-            .find(|x| x.0 == *last_id)
-        {
-            return Self::reserve_signature(&entry.1, sig);
+    /// Reserve `sig` in the bucket of `last_id`. Signatures are only
+    /// remembered while their `last_id` is within the window, which is
+    /// enough: once it ages out, a replay fails with `LastIdNotFound`.
+    fn reserve_signature_with_last_id(&self, sig: &Signature, last_id: &Hash) -> Result<()> {
+        let last_ids = self.last_ids.read().unwrap();
+        match last_ids.iter().rev().find(|x| x.0 == *last_id) {
+            Some(entry) if Self::reserve_signature(&entry.1, sig) => Ok(()),
+            Some(_) => Err(AccountingError::InvalidTransferSignature),
+            None => Err(AccountingError::LastIdNotFound),
         }
-        false
     }
 
     /// Tell the accountant which Entry IDs exist on the ledger. This function
@@ -104,12 +604,87 @@ impl Accountant {
             last_ids.pop_front();
         }
         last_ids.push_back((*last_id, RwLock::new(HashSet::new())));
+        if let Some(ref history) = self.history {
+            history.entry_height.fetch_add(1, Ordering::Relaxed);
+        }
+        drop(last_ids);
+        self.publish();
+    }
+
+    /// Return a reader of the views the accountant publishes.
+    pub fn reader(&self) -> AccountantReader {
+        AccountantReader {
+            published: self.published.clone(),
+        }
+    }
+
+    /// Publish a view of the accountant's balances and activity to its
+    /// readers, copying only the shards that changed since the last one.
+    /// The view is only consistent if no transactions are being processed.
+    pub fn publish(&self) {
+        let _publishing = self.publishing.lock().unwrap();
+        let old = self.published.read().unwrap().clone();
+        let balances = (0..NUM_BALANCE_SHARDS)
+            .map(|i| match old.balances.get(i) {
+                Some(shard) if !self.balances.dirty[i].swap(false, Ordering::Relaxed) => {
+                    shard.clone()
+                }
+                _ => {
+                    let bals = self.balances.shards[i].read().unwrap();
+                    let bals = bals.iter().map(|(pubkey, bal)| (*pubkey, *bal.read().unwrap()));
+                    Arc::new(bals.collect())
+                }
+            })
+            .collect();
+        let activity = match self.history {
+            Some(ref history) => (0..NUM_BALANCE_SHARDS)
+                .map(|i| match old.activity.get(i) {
+                    Some(shard) if !history.dirty[i].swap(false, Ordering::Relaxed) => {
+                        shard.clone()
+                    }
+                    _ => Arc::new(history.shards[i].lock().unwrap().clone()),
+                })
+                .collect(),
+            None => vec![],
+        };
+        let view = AccountantView {
+            last_id: self.last_id(),
+            balances,
+            activity,
+        };
+        *self.published.write().unwrap() = Arc::new(view);
+    }
+
+    /// Return the most recently registered Entry ID, or the default hash if
+    /// none has been registered.
+    pub fn last_id(&self) -> Hash {
+        let last_ids = self.last_ids.read().unwrap();
+        last_ids.back().map(|x| x.0).unwrap_or_default()
+    }
+
+    /// Return the index of each of `ids` that a transaction can still use as
+    /// its `last_id`, along with its age: the number of Entry IDs registered
+    /// after it.
+    pub fn count_valid_ids(&self, ids: &[Hash]) -> Vec<(usize, u64)> {
+        let last_ids = self.last_ids.read().unwrap();
+        let mut ret = vec![];
+        for (i, id) in ids.iter().enumerate() {
+            if let Some(age) = last_ids.iter().rev().position(|x| x.0 == *id) {
+                ret.push((i, age as u64));
+            }
+        }
+        ret
     }
 
     /// Deduct tokens from the 'from' address the account has sufficient
-    /// funds and isn't a duplicate.
+    /// funds and isn't a duplicate. Refuses a plan that doesn't spend exactly
+    /// the tokens deducted, whoever signed it.
     pub fn process_verified_transaction_debits(&self, tr: &Transaction) -> Result<()> {
-        let bals = self.balances.read().unwrap();
+        if !tr.verify_plan() {
+            return Err(AccountingError::InvalidPlan);
+        }
+
+        let bals = self.balances.shard(&tr.from).read().unwrap();
 
         // Hold a write lock before the condition check, so that a debit can't occur
         // between checking the balance and the withdraw.
@@ -119,26 +694,39 @@ impl Accountant {
         }
         let mut bal = option.unwrap().write().unwrap();
 
-        if *bal < tr.data.tokens {
-            return Err(AccountingError::InsufficientFunds);
-        }
+        let cost = match tr.data.tokens.checked_add(self.fee(tr)) {
+            Some(cost) if cost <= *bal => cost,
+            _ => return Err(AccountingError::InsufficientFunds),
+        };
 
-        if !self.reserve_signature_with_last_id(&tr.sig, &tr.data.last_id) {
-            return Err(AccountingError::InvalidTransferSignature);
-        }
+        self.reserve_signature_with_last_id(&tr.sig, &tr.data.last_id)?;
 
-        *bal -= tr.data.tokens;
+        *bal -= cost;
+        self.balances.mark_dirty(&tr.from);
+        let to = tr.data.plan.payments()[0].to;
+        self.record_activity(tr.from, tr.sig, -cost, Some(to));
 
         Ok(())
     }
 
     pub fn process_verified_transaction_credits(&self, tr: &Transaction) {
+        let fee = self.fee(tr);
+        if fee > 0 {
+            let collector = self.fees.unwrap().collector;
+            apply_payment(&self.balances, &Payment { tokens: fee, to: collector });
+            self.record_activity(collector, tr.sig, fee, Some(tr.from));
+        }
+
         let mut plan = tr.data.plan.clone();
         plan.apply_witness(&Witness::Timestamp(*self.last_time.read().unwrap()));
 
         if let Some(ref payment) = plan.final_payment() {
             apply_payment(&self.balances, payment);
+            self.record_activity(payment.to, tr.sig, payment.tokens, Some(tr.from));
         } else {
+            if let Some(ref history) = self.history {
+                history.senders.lock().unwrap().insert(tr.sig, tr.from);
+            }
             let mut pending = self.pending.write().unwrap();
             pending.insert(tr.sig, plan);
         }
@@ -170,6 +758,52 @@ impl Accountant {
             .collect()
     }
 
+    pub fn metrics(&self) -> &AccountantMetrics {
+        &self.metrics
+    }
+
+    /// Process verified transactions with the same results as processing
+    /// them one at a time, in order. Runs of transactions whose accounts fall
+    /// in disjoint shards are processed in parallel; a transaction sharing a
+    /// shard with an earlier one of its run starts the next run.
+    pub fn process_verified_transactions_par(&self, trs: &[Transaction]) -> Vec<Result<()>> {
+        let mut results = Vec::with_capacity(trs.len());
+        let mut start = 0;
+        let mut touched = [false; NUM_BALANCE_SHARDS];
+        for (i, tr) in trs.iter().enumerate() {
+            let mut shards = transaction_shards(tr);
+            // A fee credits the collector and appends to its history, so it
+            // conflicts with any other transaction touching the collector.
+            if self.fee(tr) > 0 {
+                shards.push(shard_index(&self.fees.unwrap().collector));
+            }
+            if shards.iter().any(|&shard| touched[shard]) {
+                self.metrics.conflicts.fetch_add(1, Ordering::Relaxed);
+                results.extend(self.process_disjoint_transactions(&trs[start..i]));
+                start = i;
+                touched = [false; NUM_BALANCE_SHARDS];
+            }
+            for shard in shards {
+                touched[shard] = true;
+            }
+        }
+        results.extend(self.process_disjoint_transactions(&trs[start..]));
+        results
+    }
+
+    /// Process transactions that touch disjoint shards.
+    fn process_disjoint_transactions(&self, trs: &[Transaction]) -> Vec<Result<()>> {
+        if trs.len() < 2 {
+            return trs.iter()
+                .map(|tr| self.process_verified_transaction(tr))
+                .collect();
+        }
+        self.metrics.parallel_groups.fetch_add(1, Ordering::Relaxed);
+        trs.par_iter()
+            .map(|tr| self.process_verified_transaction(tr))
+            .collect()
+    }
+
     fn partition_events(events: Vec<Event>) -> (Vec<Transaction>, Vec<Event>) {
         let mut trs = vec![];
         let mut rest = vec![];
@@ -191,25 +825,132 @@ impl Accountant {
         Ok(())
     }
 
-    /// Process a Witness Signature that has already been verified.
-    fn process_verified_sig(&self, from: PublicKey, tx_sig: Signature) -> Result<()> {
+    /// Process the events of each verified entry, and then register the
+    /// entry's id, as a validator replaying the ledger would. Consecutive
+    /// transactions are processed as one batch, with the same results as
+    /// processing them in order, so one may spend what an earlier one in the
+    /// entry paid it. Returns the result of each event, in the same position as the event.
+    /// An event that fails doesn't stop the events after it.
+    pub fn process_verified_entries(&self, entries: &[Entry]) -> Vec<Vec<Result<()>>> {
+        entries
+            .iter()
+            .map(|entry| {
+                let mut results = Vec::with_capacity(entry.events.len());
+                let mut trs = vec![];
+                for event in &entry.events {
+                    if let Event::Transaction(ref tr) = *event {
+                        trs.push(tr.clone());
+                    } else {
+                        self.process_batch(&mut trs, &mut results);
+                        results.push(self.process_verified_event(event));
+                    }
+                }
+                self.process_batch(&mut trs, &mut results);
+                self.register_entry_id(&entry.id);
+                debug_assert_eq!(self.audit(self.supply), Ok(()));
+                results
+            })
+            .collect()
+    }
+
+    /// Process the events of `entry` and register its id, as
+    /// `process_verified_entries` would, but only if every event succeeds.
+    /// Otherwise, return the index and error of the first event to fail,
+    /// leaving the accountant as it was. The events are first processed by a
+    /// scratch accountant holding a copy of the state they read, so nothing
+    /// else may change that state until they're processed for real.
+    pub fn process_entry_atomic(
+        &mut self,
+        entry: &Entry,
+    ) -> result::Result<(), (usize, AccountingError)> {
+        let scratch = self.scratch_for(entry);
+        let mut results = scratch.process_verified_entries(slice::from_ref(entry));
+        for (i, result) in results.remove(0).into_iter().enumerate() {
+            if let Err(err) = result {
+                return Err((i, err));
+            }
+        }
+        self.process_verified_entries(slice::from_ref(entry));
+        Ok(())
+    }
+
+    /// Return an accountant with a copy of the state that processing the
+    /// events of `entry` reads: the balances of the transactions' senders,
+    /// the signatures seen with their `last_id`s, and, if there are witness
+    /// events, the pending plans. Credits can't fail, so the accounts they
+    /// land in only need to be copied if a later debit reads them.
+    fn scratch_for(&self, entry: &Entry) -> Accountant {
+        let mut senders = HashSet::new();
+        let mut last_ids = HashSet::new();
+        let mut has_witnesses = false;
+        for event in &entry.events {
+            match *event {
+                Event::Transaction(ref tr) => {
+                    senders.insert(tr.from);
+                    last_ids.insert(tr.data.last_id);
+                }
+                _ => has_witnesses = true,
+            }
+        }
+        let pending = if has_witnesses {
+            let pending = self.pending.read().unwrap();
+            pending.iter().map(|(sig, plan)| (*sig, plan.clone())).collect()
+        } else {
+            vec![]
+        };
+        let snapshot = AccountantSnapshot {
+            balances: senders
+                .into_iter()
+                .filter_map(|pubkey| self.get_balance(&pubkey).map(|bal| (pubkey, bal)))
+                .collect(),
+            pending,
+            last_ids: self.last_ids
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|x| last_ids.contains(&x.0))
+                .map(|x| (x.0, x.1.read().unwrap().iter().cloned().collect()))
+                .collect(),
+            time_sources: self.time_sources.read().unwrap().iter().cloned().collect(),
+            last_time: *self.last_time.read().unwrap(),
+        };
+        let mut scratch = Accountant::from_snapshot(snapshot);
+        scratch.set_fees(self.fees);
+        scratch
+    }
+
+    /// Process and empty `trs`, appending their results to `results`.
+    fn process_batch(&self, trs: &mut Vec<Transaction>, results: &mut Vec<Result<()>>) {
+        if trs.is_empty() {
+            return;
+        }
+        results.extend(self.process_verified_transactions_par(trs));
+        trs.clear();
+    }
+
+    /// Process a Witness Signature that has already been verified, returning
+    /// the payment it releases, if any.
+    fn process_verified_sig(&self, from: PublicKey, tx_sig: Signature) -> Vec<Payment> {
+        let mut payments = vec![];
         if let Occupied(mut e) = self.pending.write().unwrap().entry(tx_sig) {
             e.get_mut().apply_witness(&Witness::Signature(from));
-            if let Some(ref payment) = e.get().final_payment() {
-                apply_payment(&self.balances, payment);
+            if let Some(payment) = e.get().final_payment() {
+                apply_payment(&self.balances, &payment);
+                self.record_release(tx_sig, &payment);
                 e.remove_entry();
+                payments.push(payment);
             }
         };
-
-        Ok(())
+        payments
     }
 
-    /// Process a Witness Timestamp that has already been verified.
-    fn process_verified_timestamp(&self, from: PublicKey, dt: DateTime<Utc>) -> Result<()> {
-        // If this is the first timestamp we've seen, it probably came from the genesis block,
-        // so we'll trust it.
+    /// Process a Witness Timestamp that has already been verified, returning
+    /// the payments it releases in order of their transaction's signature.
+    fn process_verified_timestamp(&self, from: PublicKey, dt: DateTime<Utc>) -> Vec<Payment> {
+        // If this is the first timestamp we've seen, and the genesis block didn't name a time
+        // source, it probably came from the genesis block, so we'll trust it.
         if *self.last_time.read().unwrap() == Utc.timestamp(0, 0) {
-            self.time_sources.write().unwrap().insert(from);
+            self.add_first_time_source(from);
         }
 
         if self.time_sources.read().unwrap().contains(&from) {
@@ -217,36 +958,68 @@ impl Accountant {
                 *self.last_time.write().unwrap() = dt;
             }
         } else {
-            return Ok(());
+            return vec![];
         }
 
-        // Check to see if any timelocked transactions can be completed.
-        let mut completed = vec![];
-
         // Hold 'pending' write lock until the end of this function. Otherwise another thread can
         // double-spend if it enters before the modified plan is removed from 'pending'.
         let mut pending = self.pending.write().unwrap();
-        for (key, plan) in pending.iter_mut() {
-            plan.apply_witness(&Witness::Timestamp(*self.last_time.read().unwrap()));
-            if let Some(ref payment) = plan.final_payment() {
-                apply_payment(&self.balances, payment);
-                completed.push(key.clone());
+        let mut sigs: Vec<_> = pending.keys().cloned().collect();
+        sigs.sort();
+
+        // Check to see if any timelocked transactions can be completed.
+        let witness = Witness::Timestamp(*self.last_time.read().unwrap());
+        let mut payments = vec![];
+        for sig in sigs {
+            let payment = {
+                let plan = pending.get_mut(&sig).unwrap();
+                plan.apply_witness(&witness);
+                plan.final_payment()
+            };
+            if let Some(payment) = payment {
+                apply_payment(&self.balances, &payment);
+                self.record_release(sig, &payment);
+                pending.remove(&sig);
+                payments.push(payment);
             }
         }
+        payments
+    }
 
-        for key in completed {
-            pending.remove(&key);
+    /// Trust `source` if no time source is trusted yet.
+    fn add_first_time_source(&self, source: PublicKey) {
+        let mut time_sources = self.time_sources.write().unwrap();
+        if time_sources.is_empty() {
+            time_sources.insert(source);
         }
+    }
 
-        Ok(())
+    /// Apply a verified Timestamp or Signature event to the pending plans,
+    /// returning the payments it releases. A timestamp that releases several
+    /// plans pays them out in order of their transaction's signature, and
+    /// only moves the accountant's clock forward. A TimeSource event releases
+    /// no payments. Returns no payments for a Transaction event, which isn't
+    /// processed.
+    pub fn process_witness(&self, event: &Event) -> Vec<Payment> {
+        match *event {
+            Event::Transaction(_) => vec![],
+            Event::Signature { from, tx_sig, .. } => self.process_verified_sig(from, tx_sig),
+            Event::Timestamp { from, dt, .. } => self.process_verified_timestamp(from, dt),
+            Event::TimeSource { source, .. } => {
+                self.add_first_time_source(source);
+                vec![]
+            }
+        }
     }
 
     /// Process an Transaction or Witness that has already been verified.
     pub fn process_verified_event(&self, event: &Event) -> Result<()> {
         match *event {
             Event::Transaction(ref tr) => self.process_verified_transaction(tr),
-            Event::Signature { from, tx_sig, .. } => self.process_verified_sig(from, tx_sig),
-            Event::Timestamp { from, dt, .. } => self.process_verified_timestamp(from, dt),
+            _ => {
+                self.process_witness(event);
+                Ok(())
+            }
         }
     }
 
@@ -281,17 +1054,95 @@ impl Accountant {
     }
 
     pub fn get_balance(&self, pubkey: &PublicKey) -> Option<i64> {
-        let bals = self.balances.read().unwrap();
+        let bals = self.balances.shard(pubkey).read().unwrap();
         bals.get(pubkey).map(|x| *x.read().unwrap())
     }
+
+    /// Return the number of accounts, including those with a zero balance.
+    pub fn account_count(&self) -> usize {
+        self.balances
+            .shards
+            .iter()
+            .map(|shard| shard.read().unwrap().len())
+            .sum()
+    }
+
+    /// Return the balance of every account, including those with a zero
+    /// balance, sorted by public key. While transactions are processed,
+    /// each balance is read at a different moment, so the total may not add
+    /// up.
+    pub fn collect_balances(&self) -> Vec<(PublicKey, i64)> {
+        let mut balances = vec![];
+        for shard in &self.balances.shards {
+            let bals = shard.read().unwrap();
+            balances.extend(bals.iter().map(|(pubkey, bal)| (*pubkey, *bal.read().unwrap())));
+        }
+        balances.sort();
+        balances
+    }
+
+    /// Return the number of tokens held by pending plans.
+    pub fn pending_tokens(&self) -> i64 {
+        let pending = self.pending.read().unwrap();
+        pending.values().map(|plan| plan.payments()[0].tokens).sum()
+    }
+
+    /// Return the tokens in the balances and pending plans, or `None` if they
+    /// add up to more than an i64 can hold.
+    fn checked_total_supply(&self) -> Option<i64> {
+        let mut total = self.pending
+            .read()
+            .unwrap()
+            .values()
+            .try_fold(0i64, |total, plan| total.checked_add(plan.payments()[0].tokens))?;
+        for shard in &self.balances.shards {
+            for bal in shard.read().unwrap().values() {
+                total = total.checked_add(*bal.read().unwrap())?;
+            }
+        }
+        Some(total)
+    }
+
+    /// Return the tokens in the balances, including the fee collector's, and
+    /// in the pending plans. Like `collect_balances`, only adds up while no
+    /// transactions are processed.
+    pub fn total_supply(&self) -> i64 {
+        self.checked_total_supply()
+            .expect("total supply overflows an i64")
+    }
+
+    /// Check that the accountant holds exactly `expected_supply` tokens. Debug
+    /// builds check after every entry they process that the accountant holds
+    /// the tokens it started with.
+    pub fn audit(&self, expected_supply: i64) -> result::Result<(), AuditError> {
+        match self.checked_total_supply() {
+            Some(actual) if actual == expected_supply => Ok(()),
+            Some(actual) => Err(AuditError::SupplyMismatch {
+                expected: expected_supply,
+                actual,
+            }),
+            None => Err(AuditError::SupplyOverflow),
+        }
+    }
+
+    /// Return the `n` accounts with the largest balances, largest first, with
+    /// ties sorted by public key.
+    pub fn top_accounts(&self, n: usize) -> Vec<(PublicKey, i64)> {
+        let mut balances = self.collect_balances();
+        balances.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        balances.truncate(n);
+        balances
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use bincode::serialize;
+    use entry::{create_entry, next_tick};
     use hash::hash;
     use signature::KeyPairUtil;
+    use std::thread::spawn;
 
     #[test]
     fn test_accountant() {
@@ -334,6 +1185,32 @@ mod tests {
         assert_eq!(acc.get_balance(&bob_pubkey).unwrap(), 1_000);
     }
 
+    #[test]
+    fn test_invalid_plan() {
+        let mint = Mint::new(10_000);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let bob_pubkey = KeyPair::new().pubkey();
+
+        let mut tr = Transaction::new(&keypair, bob_pubkey, 100, mint.last_id());
+        tr.data.plan = Plan::new_payment(1_000, bob_pubkey);
+        tr.sign(&keypair);
+        assert!(tr.verify_sig());
+        assert_eq!(
+            acc.process_verified_transaction(&tr),
+            Err(AccountingError::InvalidPlan)
+        );
+
+        // Negative tokens would credit the sender.
+        let tr = Transaction::new(&keypair, bob_pubkey, -100, mint.last_id());
+        assert_eq!(
+            acc.process_verified_transaction(&tr),
+            Err(AccountingError::InvalidPlan)
+        );
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(10_000));
+        assert_eq!(acc.get_balance(&bob_pubkey), None);
+    }
+
     #[test]
     fn test_transfer_to_newb() {
         let alice = Mint::new(10_000);
@@ -364,10 +1241,16 @@ mod tests {
 
         // Now, acknowledge the time in the condition occurred and
         // that bob's funds are now available.
-        acc.process_verified_timestamp(alice.pubkey(), dt).unwrap();
+        assert_eq!(
+            acc.process_verified_timestamp(alice.pubkey(), dt),
+            vec![Payment {
+                tokens: 1,
+                to: bob_pubkey,
+            }]
+        );
         assert_eq!(acc.get_balance(&bob_pubkey), Some(1));
 
-        acc.process_verified_timestamp(alice.pubkey(), dt).unwrap(); // <-- Attack! Attempt to process completed transaction.
+        acc.process_verified_timestamp(alice.pubkey(), dt); // <-- Attack! Attempt to process completed transaction.
         assert_ne!(acc.get_balance(&bob_pubkey), Some(2));
     }
 
@@ -378,7 +1261,7 @@ mod tests {
         let alice_keypair = alice.keypair();
         let bob_pubkey = KeyPair::new().pubkey();
         let dt = Utc::now();
-        acc.process_verified_timestamp(alice.pubkey(), dt).unwrap();
+        assert!(acc.process_verified_timestamp(alice.pubkey(), dt).is_empty());
 
         // It's now past now, so this transfer should be processed immediately.
         acc.transfer_on_date(1, &alice_keypair, bob_pubkey, dt, alice.last_id())
@@ -406,21 +1289,111 @@ mod tests {
         assert_eq!(acc.get_balance(&bob_pubkey), None);
 
         // Now, cancel the trancaction. Alice gets her funds back, Bob never sees them.
-        acc.process_verified_sig(alice.pubkey(), sig).unwrap();
+        assert_eq!(
+            acc.process_verified_sig(alice.pubkey(), sig),
+            vec![Payment {
+                tokens: 1,
+                to: alice.pubkey(),
+            }]
+        );
         assert_eq!(acc.get_balance(&alice.pubkey()), Some(1));
         assert_eq!(acc.get_balance(&bob_pubkey), None);
 
-        acc.process_verified_sig(alice.pubkey(), sig).unwrap(); // <-- Attack! Attempt to cancel completed transaction.
+        assert!(acc.process_verified_sig(alice.pubkey(), sig).is_empty()); // <-- Attack! Attempt to cancel completed transaction.
         assert_ne!(acc.get_balance(&alice.pubkey()), Some(2));
     }
 
+    #[test]
+    fn test_witness_releases_timed_payments() {
+        let mint = Mint::new(10);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let carol_pubkey = KeyPair::new().pubkey();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let early_dt = "2018-03-01T00:00:00Z".parse().unwrap();
+        let sig0 = acc.transfer_on_date(1, &keypair, bob_pubkey, dt, mint.last_id())
+            .unwrap();
+        let sig1 = acc.transfer_on_date(2, &keypair, carol_pubkey, dt, mint.last_id())
+            .unwrap();
+
+        let event = Event::new_timestamp(&keypair, early_dt);
+        assert!(acc.process_witness(&event).is_empty());
+
+        // Both plans are released by one timestamp, in order of signature.
+        let mut expected = vec![
+            (sig0, Payment { tokens: 1, to: bob_pubkey }),
+            (sig1, Payment { tokens: 2, to: carol_pubkey }),
+        ];
+        expected.sort_by_key(|x| x.0);
+        let expected: Vec<_> = expected.into_iter().map(|x| x.1).collect();
+        let event = Event::new_timestamp(&keypair, dt);
+        assert_eq!(acc.process_witness(&event), expected);
+        assert_eq!(acc.get_balance(&bob_pubkey), Some(1));
+        assert_eq!(acc.get_balance(&carol_pubkey), Some(2));
+
+        // The clock never moves backwards, so this payment is released at once.
+        let event = Event::new_timestamp(&keypair, early_dt);
+        assert!(acc.process_witness(&event).is_empty());
+        acc.transfer_on_date(3, &keypair, bob_pubkey, dt, mint.last_id())
+            .unwrap();
+        assert_eq!(acc.get_balance(&bob_pubkey), Some(4));
+    }
+
+    #[test]
+    fn test_witness_releases_authorized_payment() {
+        let mint = Mint::new(10);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let approver = KeyPair::new();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let mut tr = Transaction::new(&keypair, bob_pubkey, 10, mint.last_id());
+        tr.data.plan = Plan::new_authorized_payment(approver.pubkey(), 10, bob_pubkey);
+        tr.sign(&keypair);
+        acc.process_verified_transaction(&tr).unwrap();
+        assert_eq!(acc.get_balance(&bob_pubkey), None);
+
+        let event = Event::new_signature(&KeyPair::new(), tr.sig);
+        assert!(acc.process_witness(&event).is_empty());
+        let event = Event::new_signature(&approver, tr.sig);
+        assert_eq!(
+            acc.process_witness(&event),
+            vec![Payment {
+                tokens: 10,
+                to: bob_pubkey,
+            }]
+        );
+        assert_eq!(acc.get_balance(&bob_pubkey), Some(10));
+        assert!(acc.process_witness(&event).is_empty());
+    }
+
+    #[test]
+    fn test_witness_without_plan() {
+        let mint = Mint::new(10);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let event = Event::new_signature(&keypair, Signature::default());
+        assert!(acc.process_witness(&event).is_empty());
+        let event = Event::new_timestamp(&keypair, Utc::now());
+        assert!(acc.process_witness(&event).is_empty());
+        let tr = Transaction::new(&keypair, KeyPair::new().pubkey(), 1, mint.last_id());
+        assert!(acc.process_witness(&Event::Transaction(tr)).is_empty());
+        assert_eq!(acc.collect_balances(), vec![(mint.pubkey(), 10)]);
+    }
+
     #[test]
     fn test_duplicate_event_signature() {
         let alice = Mint::new(1);
         let acc = Accountant::new(&alice);
         let sig = Signature::default();
-        assert!(acc.reserve_signature_with_last_id(&sig, &alice.last_id()));
-        assert!(!acc.reserve_signature_with_last_id(&sig, &alice.last_id()));
+        assert_eq!(
+            acc.reserve_signature_with_last_id(&sig, &alice.last_id()),
+            Ok(())
+        );
+        assert_eq!(
+            acc.reserve_signature_with_last_id(&sig, &alice.last_id()),
+            Err(AccountingError::InvalidTransferSignature)
+        );
     }
 
     #[test]
@@ -433,7 +1406,486 @@ mod tests {
             acc.register_entry_id(&last_id);
         }
         // Assert we're no longer able to use the oldest entry ID.
-        assert!(!acc.reserve_signature_with_last_id(&sig, &alice.last_id()));
+        assert_eq!(
+            acc.reserve_signature_with_last_id(&sig, &alice.last_id()),
+            Err(AccountingError::LastIdNotFound)
+        );
+    }
+
+    #[test]
+    fn test_count_valid_ids() {
+        let mint = Mint::new(1);
+        let acc = Accountant::new(&mint);
+        assert_eq!(acc.last_id(), mint.last_id());
+        let ids: Vec<_> = (0..MAX_ENTRY_IDS)
+            .map(|i| hash(&serialize(&i).unwrap()))
+            .collect();
+        for id in &ids {
+            acc.register_entry_id(id);
+        }
+        assert_eq!(acc.last_id(), ids[MAX_ENTRY_IDS - 1]);
+
+        // The mint's last id has rotated out of the window.
+        let queries = [ids[0], mint.last_id(), ids[MAX_ENTRY_IDS - 1]];
+        let oldest_age = MAX_ENTRY_IDS as u64 - 1;
+        assert_eq!(
+            acc.count_valid_ids(&queries),
+            vec![(0, oldest_age), (2, 0)]
+        );
+
+        // Registering another id expires the oldest.
+        acc.register_entry_id(&mint.last_id());
+        assert_eq!(acc.count_valid_ids(&queries), vec![(1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn test_signature_buckets_are_bounded() {
+        let mint = Mint::new(1_000_000);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let tr = Transaction::new(&keypair, bob_pubkey, 1, mint.last_id());
+        acc.process_verified_transaction(&tr).unwrap();
+        assert_eq!(
+            acc.process_verified_transaction(&tr),
+            Err(AccountingError::InvalidTransferSignature)
+        );
+
+        // Rotate the window a few times, spending from each entry id.
+        for i in 0..MAX_ENTRY_IDS * 3 {
+            let last_id = hash(&serialize(&i).unwrap()); // Unique hash
+            acc.register_entry_id(&last_id);
+            if i % 64 == 0 {
+                let tr = Transaction::new(&keypair, bob_pubkey, 1, last_id);
+                acc.process_verified_transaction(&tr).unwrap();
+            }
+        }
+        let last_ids = acc.last_ids.read().unwrap();
+        assert_eq!(last_ids.len(), MAX_ENTRY_IDS);
+        let num_sigs: usize = last_ids.iter().map(|x| x.1.read().unwrap().len()).sum();
+        assert_eq!(num_sigs, MAX_ENTRY_IDS / 64);
+        drop(last_ids);
+
+        // The replay is no longer caught as a duplicate, but as expired.
+        assert_eq!(
+            acc.process_verified_transaction(&tr),
+            Err(AccountingError::LastIdNotFound)
+        );
+        assert_eq!(acc.get_balance(&bob_pubkey), Some(1 + 3 * MAX_ENTRY_IDS as i64 / 64));
+    }
+
+    #[test]
+    fn test_process_verified_entries_chained_transfers() {
+        let mint = Mint::new(10);
+        let acc = Accountant::new(&mint);
+        let bob = KeyPair::new();
+        let carol_pubkey = KeyPair::new().pubkey();
+        let last_id = mint.last_id();
+
+        // Bob spends what the same entry paid him.
+        let events = vec![
+            Event::Transaction(Transaction::new(&mint.keypair(), bob.pubkey(), 5, last_id)),
+            Event::Transaction(Transaction::new(&bob, carol_pubkey, 3, last_id)),
+        ];
+        let entry = create_entry(&last_id, 0, events);
+        assert_eq!(acc.process_verified_entries(&[entry]), vec![vec![Ok(()), Ok(())]]);
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(5));
+        assert_eq!(acc.get_balance(&bob.pubkey()), Some(2));
+        assert_eq!(acc.get_balance(&carol_pubkey), Some(3));
+    }
+
+    #[test]
+    fn test_process_verified_entries() {
+        let mint = Mint::new(10_000);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let carol_pubkey = KeyPair::new().pubkey();
+        let last_id = mint.last_id();
+
+        // The second transaction fails, but the ones around it still apply.
+        let events = vec![
+            Event::Transaction(Transaction::new(&keypair, alice.pubkey(), 1_000, last_id)),
+            Event::Transaction(Transaction::new(&keypair, bob_pubkey, 20_000, last_id)),
+            Event::new_timestamp(&keypair, Utc::now()),
+            Event::Transaction(Transaction::new(&keypair, carol_pubkey, 500, last_id)),
+        ];
+        let e0 = create_entry(&last_id, 0, events);
+
+        // A transaction may refer to the id of any earlier entry, ticks included.
+        let e1 = next_tick(&e0.id, 1);
+        let tr = Transaction::new(&alice, bob_pubkey, 500, e1.id);
+        let e2 = create_entry(&e1.id, 0, vec![Event::Transaction(tr)]);
+
+        let results = acc.process_verified_entries(&[e0, e1, e2]);
+        assert_eq!(
+            results,
+            vec![
+                vec![
+                    Ok(()),
+                    Err(AccountingError::InsufficientFunds),
+                    Ok(()),
+                    Ok(()),
+                ],
+                vec![],
+                vec![Ok(())],
+            ]
+        );
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(8_500));
+        assert_eq!(acc.get_balance(&alice.pubkey()), Some(500));
+        assert_eq!(acc.get_balance(&bob_pubkey), Some(500));
+        assert_eq!(acc.get_balance(&carol_pubkey), Some(500));
+    }
+
+    #[test]
+    fn test_collect_balances() {
+        let mint = Mint::new(10_000);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let pubkeys: Vec<_> = (0..32).map(|_| KeyPair::new().pubkey()).collect();
+        for (i, pubkey) in pubkeys.iter().enumerate() {
+            acc.transfer(i as i64 + 1, &keypair, *pubkey, mint.last_id())
+                .unwrap();
+        }
+
+        // Accounts spent down to zero are still listed.
+        let alice = KeyPair::new();
+        acc.transfer(7, &keypair, alice.pubkey(), mint.last_id())
+            .unwrap();
+        acc.transfer(7, &alice, pubkeys[0], mint.last_id()).unwrap();
+        assert_eq!(acc.account_count(), 34);
+
+        let balances = acc.collect_balances();
+        assert_eq!(balances.len(), 34);
+        assert!(balances.windows(2).all(|x| x[0].0 < x[1].0));
+        assert!(balances.contains(&(alice.pubkey(), 0)));
+        assert!(balances.contains(&(pubkeys[0], 8)));
+        let total: i64 = balances.iter().map(|x| x.1).sum();
+        assert_eq!(total, 10_000);
+
+        let top = acc.top_accounts(3);
+        assert_eq!(
+            top,
+            vec![
+                (mint.pubkey(), 10_000 - 528 - 7),
+                (pubkeys[31], 32),
+                (pubkeys[30], 31),
+            ]
+        );
+        assert_eq!(acc.top_accounts(100).len(), 34);
+        assert_eq!(acc.top_accounts(100).last(), Some(&(alice.pubkey(), 0)));
+    }
+
+    /// Return a mint and entries that transfer, tick, and leave a timelocked
+    /// transfer pending until a later timestamp.
+    fn snapshot_ledger() -> (Mint, Vec<Entry>) {
+        let mint = Mint::new(10_000);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let early_dt = "1970-01-01T00:00:01Z".parse().unwrap();
+        let mut entries = vec![];
+        let mut last_id = mint.last_id();
+        for i in 0..8 {
+            let tr = if i == 2 {
+                Transaction::new_on_date(&keypair, bob_pubkey, dt, 100, last_id)
+            } else {
+                Transaction::new(&keypair, alice.pubkey(), i + 1, last_id)
+            };
+            let mut events = vec![Event::Transaction(tr)];
+            if i == 1 {
+                events.push(Event::new_timestamp(&keypair, early_dt));
+            }
+            if i == 6 {
+                events.push(Event::new_timestamp(&keypair, dt));
+            }
+            let entry = create_entry(&last_id, 0, events);
+            let tick = next_tick(&entry.id, 1);
+            last_id = tick.id;
+            entries.push(entry);
+            entries.push(tick);
+        }
+        (mint, entries)
+    }
+
+    #[test]
+    fn test_snapshot_then_replay() {
+        let (mint, entries) = snapshot_ledger();
+        let acc = Accountant::new(&mint);
+        acc.process_verified_entries(&entries);
+        let full_replay = acc.snapshot();
+        assert!(full_replay.pending.is_empty());
+        assert_eq!(full_replay.balances.len(), 3);
+
+        // Restoring at any height and replaying the rest gives the same state.
+        for height in 0..entries.len() {
+            let acc = Accountant::new(&mint);
+            acc.process_verified_entries(&entries[..height]);
+            let snapshot = acc.snapshot();
+            let bytes = snapshot.to_bytes();
+            let restored = Accountant::from_snapshot(AccountantSnapshot::from_bytes(&bytes).unwrap());
+            assert_eq!(restored.snapshot(), snapshot);
+            assert_eq!(restored.snapshot_hash(), acc.snapshot_hash());
+
+            restored.process_verified_entries(&entries[height..]);
+            assert_eq!(restored.snapshot(), full_replay);
+            assert_eq!(restored.snapshot_hash(), full_replay.hash());
+        }
+    }
+
+    #[test]
+    fn test_snapshot_pending_plan() {
+        let (mint, entries) = snapshot_ledger();
+        let acc = Accountant::new(&mint);
+        acc.process_verified_entries(&entries[..6]);
+        let snapshot = acc.snapshot();
+        assert_eq!(snapshot.pending.len(), 1);
+        assert_eq!(snapshot.time_sources, vec![mint.pubkey()]);
+        assert_eq!(snapshot.last_ids.len(), 7);
+        assert_eq!(snapshot.last_ids.iter().map(|x| x.1.len()).sum::<usize>(), 3);
+        assert_ne!(snapshot.hash(), Accountant::new(&mint).snapshot_hash());
+    }
+
+    #[test]
+    fn test_snapshot_header() {
+        let mint = Mint::new(1);
+        let mut bytes = Accountant::new(&mint).snapshot().to_bytes();
+        assert_eq!(bytes[..8], SNAPSHOT_MAGIC);
+        bytes[8] = 2;
+        assert_matches!(
+            AccountantSnapshot::from_bytes(&bytes),
+            Err(Error::Snapshot(SnapshotError::UnsupportedVersion(2)))
+        );
+        bytes[0] = 0;
+        assert_matches!(
+            AccountantSnapshot::from_bytes(&bytes),
+            Err(Error::Snapshot(SnapshotError::BadMagic))
+        );
+        assert_matches!(
+            AccountantSnapshot::from_bytes(&bytes[..4]),
+            Err(Error::Snapshot(SnapshotError::BadMagic))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_supply_overflow() {
+        let mint = Mint::new(1);
+        let mut snapshot = Accountant::new(&mint).snapshot();
+        snapshot.balances = vec![
+            (KeyPair::new().pubkey(), i64::MAX),
+            (KeyPair::new().pubkey(), 1),
+        ];
+        assert_matches!(
+            AccountantSnapshot::from_bytes(&snapshot.to_bytes()),
+            Err(Error::Snapshot(SnapshotError::SupplyOverflow))
+        );
+    }
+
+    /// Return an accountant whose mint has funded each of `keypairs`.
+    fn funded_accountant(mint: &Mint, keypairs: &[KeyPair]) -> Accountant {
+        let acc = Accountant::new(mint);
+        for keypair in keypairs {
+            acc.transfer(100, &mint.keypair(), keypair.pubkey(), mint.last_id())
+                .unwrap();
+        }
+        acc
+    }
+
+    /// Return `len` transactions between `keypairs` and a few unfunded
+    /// accounts, chosen by a generator seeded with `seed`. Some overspend,
+    /// some are replays, and some wait on a date.
+    fn random_transactions(
+        seed: u64,
+        len: usize,
+        mint: &Mint,
+        keypairs: &[KeyPair],
+    ) -> Vec<Transaction> {
+        let mut state = seed;
+        let mut next = |n: usize| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) as usize % n
+        };
+        let unfunded: Vec<_> = (0..3).map(|_| KeyPair::new()).collect();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let mut trs: Vec<Transaction> = vec![];
+        for _ in 0..len {
+            if !trs.is_empty() && next(10) == 0 {
+                let tr = trs[next(trs.len())].clone();
+                trs.push(tr);
+                continue;
+            }
+            let from = if next(8) == 0 {
+                &unfunded[next(unfunded.len())]
+            } else {
+                &keypairs[next(keypairs.len())]
+            };
+            let to = if next(4) == 0 {
+                unfunded[next(unfunded.len())].pubkey()
+            } else {
+                keypairs[next(keypairs.len())].pubkey()
+            };
+            let tokens = next(60) as i64 + 1;
+            let tr = if next(6) == 0 {
+                Transaction::new_on_date(from, to, dt, tokens, mint.last_id())
+            } else {
+                Transaction::new(from, to, tokens, mint.last_id())
+            };
+            trs.push(tr);
+        }
+        trs
+    }
+
+    #[test]
+    fn test_parallel_transactions_match_sequential() {
+        let mint = Mint::new(10_000);
+        let keypairs: Vec<_> = (0..8).map(|_| KeyPair::new()).collect();
+        for seed in 0..8 {
+            let trs = random_transactions(seed, 200, &mint, &keypairs);
+            let sequential = funded_accountant(&mint, &keypairs);
+            let parallel = funded_accountant(&mint, &keypairs);
+            let expected: Vec<_> = trs.iter()
+                .map(|tr| sequential.process_verified_transaction(tr))
+                .collect();
+            assert_eq!(parallel.process_verified_transactions_par(&trs), expected);
+            assert_eq!(parallel.snapshot(), sequential.snapshot());
+            assert!(parallel.metrics().parallel_groups.load(Ordering::Relaxed) > 0);
+        }
+    }
+
+    #[test]
+    fn test_parallel_transaction_metrics() {
+        let mint = Mint::new(10_000);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let pubkey = KeyPair::new().pubkey();
+
+        // Every transaction debits the mint, so each conflicts with the last.
+        let trs: Vec<_> = (0..3)
+            .map(|i| Transaction::new(&keypair, pubkey, i + 1, mint.last_id()))
+            .collect();
+        assert_eq!(
+            acc.process_verified_transactions_par(&trs),
+            vec![Ok(()), Ok(()), Ok(())]
+        );
+        assert_eq!(acc.metrics().conflicts.load(Ordering::Relaxed), 2);
+        assert_eq!(acc.metrics().parallel_groups.load(Ordering::Relaxed), 0);
+        assert_eq!(acc.get_balance(&pubkey), Some(6));
+        assert_eq!(acc.account_count(), 2);
+    }
+
+    fn fee_accountant(mint: &Mint, fees: Fees) -> Accountant {
+        let mut acc = Accountant::new(mint);
+        acc.set_fees(Some(fees));
+        acc
+    }
+
+    #[test]
+    fn test_fees_replay() {
+        let mint = Mint::new(10_000);
+        let collector = KeyPair::new();
+        let treasury = KeyPair::new().pubkey();
+        let fees = Fees {
+            collector: collector.pubkey(),
+            per_transaction: 3,
+        };
+        let leader = fee_accountant(&mint, fees);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new();
+
+        // Alice can't afford the fee of a third transaction.
+        let tr0 = Transaction::new(&keypair, alice.pubkey(), 100, mint.last_id());
+        leader.process_verified_transaction(&tr0).unwrap();
+        let tr1 = Transaction::new(&alice, mint.pubkey(), 96, mint.last_id());
+        let tr2 = Transaction::new(&alice, mint.pubkey(), 1, mint.last_id());
+        let results = leader.process_verified_transactions_par(&[tr1.clone(), tr2]);
+        assert_eq!(results[1], Err(AccountingError::InsufficientFunds));
+        assert_eq!(leader.get_balance(&alice.pubkey()), Some(1));
+        assert_eq!(leader.get_balance(&collector.pubkey()), Some(6));
+        let e0 = create_entry(&mint.last_id(), 0, vec![Event::Transaction(tr0)]);
+        let e1 = create_entry(&e0.id, 0, vec![Event::Transaction(tr1)]);
+        leader.register_entry_id(&e0.id);
+        leader.register_entry_id(&e1.id);
+
+        // Only the collector can collect, and only when there's something to.
+        assert_eq!(leader.collect_fees(&alice, treasury, e1.id), Ok(None));
+        let tr = leader.collect_fees(&collector, treasury, e1.id).unwrap().unwrap();
+        assert_eq!(tr.data.tokens, 6);
+        assert_eq!(leader.collect_fees(&collector, treasury, e1.id), Ok(None));
+        assert_eq!(leader.get_balance(&collector.pubkey()), Some(0));
+        assert_eq!(leader.get_balance(&treasury), Some(6));
+        let e2 = create_entry(&e1.id, 0, vec![Event::Transaction(tr.clone())]);
+        leader.register_entry_id(&e2.id);
+        let entries = vec![e0, e1, e2];
+
+        // A replica with the same fees replays to the same state.
+        let replica = fee_accountant(&mint, fees);
+        let results = replica.process_verified_entries(&entries);
+        assert!(results.iter().flatten().all(|x| x.is_ok()));
+        assert_eq!(replica.snapshot_hash(), leader.snapshot_hash());
+
+        // The collection transaction is the same wherever it's made.
+        let replica = fee_accountant(&mint, fees);
+        replica.process_verified_entries(&entries[..2]);
+        assert_eq!(
+            replica.collect_fees(&collector, treasury, entries[1].id),
+            Ok(Some(tr))
+        );
+
+        // A replica without the fees doesn't agree.
+        let replica = Accountant::new(&mint);
+        replica.process_verified_entries(&entries);
+        assert_ne!(replica.snapshot_hash(), leader.snapshot_hash());
+    }
+
+    #[test]
+    fn test_parallel_fees_match_sequential() {
+        let mint = Mint::new(10_000);
+        let collector = KeyPair::new();
+        let fees = Fees {
+            collector: collector.pubkey(),
+            per_transaction: 3,
+        };
+        // Each sender and recipient in a shard of its own, apart from the
+        // collector's and the mint's.
+        let mut used = vec![shard_index(&fees.collector), shard_index(&mint.pubkey())];
+        let mut keypairs = vec![];
+        while keypairs.len() < 6 {
+            let keypair = KeyPair::new();
+            let shard = shard_index(&keypair.pubkey());
+            if !used.contains(&shard) {
+                used.push(shard);
+                keypairs.push(keypair);
+            }
+        }
+        let (senders, recipients) = keypairs.split_at(3);
+        let fee_funded_accountant = || {
+            let acc = fee_accountant(&mint, fees);
+            for keypair in senders {
+                acc.transfer(100, &mint.keypair(), keypair.pubkey(), mint.last_id())
+                    .unwrap();
+            }
+            acc
+        };
+        let sequential = fee_funded_accountant();
+        let parallel = fee_funded_accountant();
+        assert_eq!(sequential.get_balance(&collector.pubkey()), Some(9));
+
+        // The collector overspends before the fees that would cover it.
+        let mut trs = vec![Transaction::new(&collector, mint.pubkey(), 10, mint.last_id())];
+        for (sender, recipient) in senders.iter().zip(recipients) {
+            trs.push(Transaction::new(sender, recipient.pubkey(), 1, mint.last_id()));
+        }
+        let expected: Vec<_> = trs.iter()
+            .map(|tr| sequential.process_verified_transaction(tr))
+            .collect();
+        assert_eq!(expected[0], Err(AccountingError::InsufficientFunds));
+        assert_eq!(parallel.process_verified_transactions_par(&trs), expected);
+        assert_eq!(parallel.snapshot_hash(), sequential.snapshot_hash());
+        assert_eq!(parallel.metrics().conflicts.load(Ordering::Relaxed), 3);
     }
 
     #[test]
@@ -446,6 +1898,274 @@ mod tests {
         let trs = vec![tr0, tr1];
         assert!(acc.process_verified_transactions(trs)[1].is_err());
     }
+
+    #[test]
+    fn test_process_entry_atomic() {
+        let mint = Mint::new(100);
+        let mut acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new();
+        let (bob, carol) = (KeyPair::new().pubkey(), KeyPair::new().pubkey());
+        acc.transfer(1, &keypair, alice.pubkey(), mint.last_id())
+            .unwrap();
+        let before = acc.snapshot();
+
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let events = vec![
+            Event::Transaction(Transaction::new(&keypair, bob, 10, mint.last_id())),
+            Event::Transaction(Transaction::new(&alice, bob, 5, mint.last_id())),
+            Event::Transaction(Transaction::new_on_date(&keypair, carol, dt, 20, mint.last_id())),
+        ];
+        let entry = create_entry(&mint.last_id(), 0, events);
+        assert_eq!(
+            acc.process_entry_atomic(&entry),
+            Err((1, AccountingError::InsufficientFunds))
+        );
+        assert_eq!(acc.snapshot(), before);
+        assert_eq!(acc.last_id(), mint.last_id());
+
+        // The best-effort path applies the other transactions.
+        let results = acc.process_verified_entries(&[entry]);
+        assert_eq!(
+            results[0],
+            vec![Ok(()), Err(AccountingError::InsufficientFunds), Ok(())]
+        );
+        assert_eq!(acc.get_balance(&bob), Some(10));
+        assert_eq!(acc.pending_tokens(), 20);
+    }
+
+    #[test]
+    fn test_process_entry_atomic_witness() {
+        let mint = Mint::new(100);
+        let mut acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new();
+        let bob = KeyPair::new().pubkey();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        acc.transfer_on_date(10, &keypair, alice.pubkey(), dt, mint.last_id())
+            .unwrap();
+
+        // The timestamp releases the tokens that alice spends.
+        let tr = Transaction::new(&alice, bob, 10, mint.last_id());
+        let events = vec![Event::new_timestamp(&keypair, dt), Event::Transaction(tr)];
+        let entry = create_entry(&mint.last_id(), 0, events);
+        assert_eq!(acc.process_entry_atomic(&entry), Ok(()));
+        assert_eq!(acc.get_balance(&bob), Some(10));
+        assert_eq!(acc.get_balance(&alice.pubkey()), Some(0));
+        assert_eq!(acc.last_id(), entry.id);
+    }
+
+    #[test]
+    fn test_reader_is_never_torn() {
+        let mint = Mint::new(10_000);
+        let keypairs: Vec<_> = (0..8).map(|_| KeyPair::new()).collect();
+        let acc = Arc::new(funded_accountant(&mint, &keypairs));
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let reader = acc.reader();
+                let done = done.clone();
+                spawn(move || {
+                    let mut num_views = 0;
+                    loop {
+                        let view = reader.view();
+                        let total: i64 = view.collect_balances().iter().map(|x| x.1).sum();
+                        assert_eq!(total, 10_000);
+                        num_views += 1;
+                        if done.load(Ordering::Relaxed) {
+                            return num_views;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut last_id = mint.last_id();
+        for i in 0..100 {
+            let events = keypairs
+                .iter()
+                .enumerate()
+                .map(|(j, keypair)| {
+                    let to = keypairs[(i + j + 1) % keypairs.len()].pubkey();
+                    let tokens = (i % 5) as i64 + 1;
+                    Event::Transaction(Transaction::new(keypair, to, tokens, mint.last_id()))
+                })
+                .collect();
+            let entry = create_entry(&last_id, 0, events);
+            last_id = entry.id;
+            acc.process_verified_entries(&[entry]);
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+
+        let reader = acc.reader();
+        assert_eq!(reader.last_id(), last_id);
+        assert_eq!(reader.view().collect_balances(), acc.collect_balances());
+        assert_eq!(reader.account_count(), acc.account_count());
+        let pubkey = keypairs[0].pubkey();
+        assert_eq!(reader.get_balance(&pubkey), acc.get_balance(&pubkey));
+    }
+
+    /// Add `tokens` to the balance of `pubkey` behind the accountant's back.
+    fn corrupt_balance(acc: &Accountant, pubkey: &PublicKey, tokens: i64) {
+        let bals = acc.balances.shard(pubkey).read().unwrap();
+        *bals[pubkey].write().unwrap() += tokens;
+    }
+
+    #[test]
+    fn test_audit_pending_plans() {
+        let mint = Mint::new(100);
+        let acc = Accountant::new(&mint);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        assert_eq!(acc.audit(100), Ok(()));
+
+        // Escrow three conditional payments.
+        let cancelled = acc.transfer_on_date(10, &keypair, alice.pubkey(), dt, mint.last_id())
+            .unwrap();
+        acc.transfer_on_date(20, &keypair, alice.pubkey(), dt, mint.last_id())
+            .unwrap();
+        let plan = Plan::new_authorized_payment(alice.pubkey(), 30, alice.pubkey());
+        let tr = Transaction::new_with_plan(&keypair, plan, 30, mint.last_id());
+        acc.process_verified_transaction(&tr).unwrap();
+        assert_eq!(acc.pending_tokens(), 60);
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(40));
+        assert_eq!(acc.audit(100), Ok(()));
+
+        // Cancel one, refunding the mint.
+        acc.process_verified_event(&Event::new_signature(&keypair, cancelled))
+            .unwrap();
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(50));
+        assert_eq!(acc.pending_tokens(), 50);
+        assert_eq!(acc.audit(100), Ok(()));
+
+        // Fulfill the others.
+        acc.process_verified_event(&Event::new_timestamp(&keypair, dt))
+            .unwrap();
+        assert_eq!(acc.pending_tokens(), 30);
+        assert_eq!(acc.audit(100), Ok(()));
+        acc.process_verified_event(&Event::new_signature(&alice, tr.sig))
+            .unwrap();
+        assert_eq!(acc.pending_tokens(), 0);
+        assert_eq!(acc.get_balance(&alice.pubkey()), Some(50));
+        assert_eq!(acc.total_supply(), 100);
+        assert_eq!(acc.audit(100), Ok(()));
+
+        corrupt_balance(&acc, &alice.pubkey(), 1);
+        assert_eq!(
+            acc.audit(100),
+            Err(AuditError::SupplyMismatch {
+                expected: 100,
+                actual: 101,
+            })
+        );
+        corrupt_balance(&acc, &alice.pubkey(), i64::MAX - 51);
+        assert_eq!(acc.audit(100), Err(AuditError::SupplyOverflow));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_corrupt_balance_fails_replay() {
+        let mint = Mint::new(100);
+        let acc = Accountant::new(&mint);
+        corrupt_balance(&acc, &mint.pubkey(), -1);
+        acc.process_verified_entries(&[next_tick(&mint.last_id(), 1)]);
+    }
+
+    #[test]
+    fn test_activity_matches_balances() {
+        let mint = Mint::new(10_000);
+        let keypairs: Vec<_> = (0..8).map(|_| KeyPair::new()).collect();
+        let mut acc = Accountant::new(&mint);
+        acc.set_history_depth(1_000);
+        acc.set_fees(Some(Fees {
+            collector: KeyPair::new().pubkey(),
+            per_transaction: 1,
+        }));
+        for keypair in &keypairs {
+            acc.transfer(100, &mint.keypair(), keypair.pubkey(), mint.last_id())
+                .unwrap();
+        }
+        let trs = random_transactions(0, 200, &mint, &keypairs);
+        acc.process_verified_transactions_par(&trs);
+        assert!(acc.pending_tokens() > 0);
+
+        // Release the dated transactions.
+        let dt = "2018-04-02T00:00:00Z".parse().unwrap();
+        acc.process_verified_event(&Event::new_timestamp(&mint.keypair(), dt))
+            .unwrap();
+        assert_eq!(acc.pending_tokens(), 0);
+
+        for (pubkey, bal) in acc.collect_balances() {
+            let start = if pubkey == mint.pubkey() { 10_000 } else { 0 };
+            let activity = acc.recent_activity(&pubkey, 1_000);
+            assert!(activity.len() < 1_000);
+            let delta: i64 = activity.iter().map(|x| x.delta).sum();
+            assert_eq!(start + delta, bal);
+        }
+    }
+
+    #[test]
+    fn test_activity_eviction() {
+        let mint = Mint::new(100);
+        let mut acc = Accountant::new(&mint);
+        acc.set_history_depth(3);
+        let keypair = mint.keypair();
+        let alice = KeyPair::new().pubkey();
+        for tokens in 1..5 {
+            acc.transfer(tokens, &keypair, alice, mint.last_id()).unwrap();
+            acc.register_entry_id(&hash(&serialize(&tokens).unwrap()));
+        }
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let last_id = acc.last_id();
+        let sig = acc.transfer_on_date(5, &keypair, alice, dt, last_id)
+            .unwrap();
+        assert_eq!(acc.recent_activity(&alice, 10).len(), 3);
+
+        // A release counts as activity of the recipient, from the sender.
+        acc.process_verified_event(&Event::new_timestamp(&keypair, dt))
+            .unwrap();
+        let activity = acc.recent_activity(&alice, 10);
+        let deltas: Vec<_> = activity.iter().map(|x| x.delta).collect();
+        assert_eq!(deltas, vec![5, 4, 3]);
+        assert_eq!(activity[0].tx_id, sig);
+        assert_eq!(activity[0].counterparty, Some(mint.pubkey()));
+        assert_eq!(activity[0].entry_height, 4);
+        assert_eq!(activity[1].entry_height, 3);
+        assert_eq!(acc.recent_activity(&alice, 2), &activity[..2]);
+        acc.publish();
+        assert_eq!(acc.reader().recent_activity(&alice, 10), activity);
+
+        let activity = acc.recent_activity(&mint.pubkey(), 1);
+        assert_eq!(activity[0].delta, -5);
+        assert_eq!(activity[0].counterparty, Some(alice));
+    }
+
+    #[test]
+    fn test_activity_disabled() {
+        let mint = Mint::new(100);
+        let mut acc = Accountant::new(&mint);
+        let alice = KeyPair::new().pubkey();
+        acc.transfer(1, &mint.keypair(), alice, mint.last_id())
+            .unwrap();
+        assert!(acc.recent_activity(&alice, 10).is_empty());
+
+        acc.set_history_depth(1);
+        acc.transfer(2, &mint.keypair(), alice, mint.last_id())
+            .unwrap();
+        assert_eq!(acc.recent_activity(&alice, 10).len(), 1);
+
+        acc.set_history_depth(0);
+        assert!(acc.history.is_none());
+        acc.transfer(3, &mint.keypair(), alice, mint.last_id())
+            .unwrap();
+        assert!(acc.recent_activity(&alice, 10).is_empty());
+        assert_eq!(acc.get_balance(&alice), Some(6));
+    }
 }
 
 #[cfg(all(feature = "unstable", test))]
@@ -2,45 +2,123 @@
 //! Accountant API to the network. Its message encoding is currently
 //! in flux. Clients should use AccountantStub to interact with it.
 
-use accountant::Accountant;
-use bincode::{deserialize, serialize};
-use ecdsa;
+use accountant::{Accountant, AccountantReader, AccountingError};
+use bincode::{deserialize, serialize, serialized_size};
 use entry::Entry;
 use event::Event;
 use hash::Hash;
 use historian::Historian;
+use ledger;
+use node::Persistence;
 use packet;
-use packet::SharedPackets;
-use rayon::prelude::*;
+use packet::{BlobRecycler, SharedPackets};
+use parallel::*;
 use recorder::Signal;
-use result::Result;
+use reject_log::{Reason, RejectLog, Rejection, Stage, Subject};
+use result::{Error, Result};
 use serde_json;
-use signature::PublicKey;
-use std::cmp::max;
-use std::collections::VecDeque;
+use signature::{KeyPair, PublicKey};
+use sigverify;
+use sigverify::Ed25519Verifier;
+use std::cmp::min;
+use std::collections::{HashMap, VecDeque};
 use std::io::Write;
-use std::net::{SocketAddr, UdpSocket};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver};
 use std::sync::{Arc, Mutex};
-use std::thread::{spawn, JoinHandle};
-use std::time::Duration;
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
 use streamer;
 use transaction::Transaction;
 
+/// The largest request accepted over UDP. A request that fills a packet may
+/// have been truncated, so larger requests must be made over TCP.
+pub const MAX_REQUEST_SIZE: usize = packet::PACKET_DATA_SIZE - 1;
+
+/// How long an entry subscription lasts unless the client refreshes it.
+pub const SUBSCRIPTION_TIMEOUT_MS: u64 = 5_000;
+
+/// Consecutive failed sends to a subscriber after which it's dropped.
+pub const MAX_PUSH_FAILURES: usize = 3;
+
+/// The most entries pushed to a subscriber at a time, bounding how long a
+/// subscriber catching up holds the server.
+const MAX_PUSH_ENTRIES: usize = 1024;
+
+/// The most entries kept in memory for subscribers and `GetEntries` when
+/// the server has no ledger on disk.
+const MAX_RECENT_ENTRIES: usize = 4 * MAX_PUSH_ENTRIES;
+
+/// How often new entries are pushed to subscribers.
+const PUSH_INTERVAL_MS: u64 = 10;
+
+/// The most entries read for a single `GetEntries` request.
+pub const MAX_GET_ENTRIES: u64 = 1024;
+
 pub struct AccountantSkel<W: Write + Send + 'static> {
     acc: Accountant,
+    reader: AccountantReader,
     last_id: Hash,
     writer: W,
     historian: Historian,
+    fee_collection: Option<FeeCollection>,
+    /// Without persistence, the latest `MAX_RECENT_ENTRIES` entries recorded
+    /// since the server started, the last of which is at `height - 1`.
+    recent: VecDeque<Entry>,
+    /// Without persistence, the number of entries recorded since the server
+    /// started.
+    height: u64,
+    subscriptions: HashMap<SocketAddr, Subscription>,
+    subscription_timeout: Duration,
+    intake: streamer::ReceiverConfig,
+    intake_metrics: Arc<streamer::ReceiverMetrics>,
+    persistence: Option<Persistence>,
+    rejections: Arc<RejectLog>,
 }
 
+/// A client that entries are pushed to.
+struct Subscription {
+    /// The height of the next entry to push.
+    height: u64,
+    /// The index of the next blob to push.
+    blob_index: u64,
+    failures: usize,
+    expires: Instant,
+}
+
+/// How often, and where, the node collects the fees its accountant charges.
+struct FeeCollection {
+    keypair: KeyPair,
+    to: PublicKey,
+    interval: u64,
+    num_ticks: u64,
+}
+
+/// A request to the server. Each request with a response has a `req_id`,
+/// chosen by the client, that the server copies into its response.
 #[cfg_attr(feature = "cargo-clippy", allow(large_enum_variant))]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
     Transaction(Transaction),
-    GetBalance { key: PublicKey },
-    GetLastId,
+    GetBalance { key: PublicKey, req_id: u64 },
+    GetLastId { req_id: u64 },
+    CheckIds { ids: Vec<Hash>, req_id: u64 },
+    /// Push the entries from `from_height` on to the sender, as blobs
+    /// indexed from 0. Repeat the request to keep the subscription from
+    /// expiring. A subscription from before the oldest entry the server
+    /// keeps is refused, and dropped if it falls that far behind.
+    Subscribe { from_height: u64 },
+    /// Fetch up to `limit` of the latest work the server dropped or
+    /// rejected, newest first.
+    GetRejections { limit: u64, req_id: u64 },
+    /// Fetch up to `max_count` entries from `start_height` on. The response
+    /// may hold fewer, to fit in a datagram.
+    GetEntries {
+        start_height: u64,
+        max_count: u64,
+        req_id: u64,
+    },
 }
 
 impl Request {
@@ -55,30 +133,314 @@ impl Request {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Response {
-    Balance { key: PublicKey, val: Option<i64> },
+    Balance {
+        key: PublicKey,
+        val: Option<i64>,
+        req_id: u64,
+    },
     Entries { entries: Vec<Entry> },
-    LastId { id: Hash },
+    LastId { id: Hash, req_id: u64 },
+    Rejections {
+        rejections: Vec<Rejection>,
+        req_id: u64,
+    },
+    /// The index of each requested id that's still valid, and its age.
+    ValidIds {
+        valid: Vec<(usize, u64)>,
+        req_id: u64,
+    },
+    /// The response to the request doesn't fit in a datagram. Make the
+    /// request over TCP instead.
+    TooLarge { req_id: u64 },
+    /// Entries from the requested height on, none if the ledger's `height`
+    /// doesn't reach it.
+    EntryRange {
+        entries: Vec<Entry>,
+        height: u64,
+        req_id: u64,
+    },
+    /// The requested entries are older than the oldest the server keeps,
+    /// which is at `first_height`.
+    EntriesTooOld { first_height: u64, req_id: u64 },
+}
+
+impl Response {
+    /// The `req_id` of the request this responds to, if any.
+    pub fn req_id(&self) -> Option<u64> {
+        match *self {
+            Response::Balance { req_id, .. }
+            | Response::LastId { req_id, .. }
+            | Response::Rejections { req_id, .. }
+            | Response::ValidIds { req_id, .. }
+            | Response::TooLarge { req_id }
+            | Response::EntryRange { req_id, .. }
+            | Response::EntriesTooOld { req_id, .. } => Some(req_id),
+            Response::Entries { .. } => None,
+        }
+    }
 }
 
 impl<W: Write + Send + 'static> AccountantSkel<W> {
     /// Create a new AccountantSkel that wraps the given Accountant.
     pub fn new(acc: Accountant, last_id: Hash, writer: W, historian: Historian) -> Self {
         AccountantSkel {
+            reader: acc.reader(),
             acc,
             last_id,
             writer,
             historian,
+            fee_collection: None,
+            recent: VecDeque::new(),
+            height: 0,
+            subscriptions: HashMap::new(),
+            subscription_timeout: Duration::from_millis(SUBSCRIPTION_TIMEOUT_MS),
+            intake: streamer::ReceiverConfig {
+                max_packet_size: MAX_REQUEST_SIZE,
+                ..Default::default()
+            },
+            intake_metrics: Arc::default(),
+            persistence: None,
+            rejections: Arc::default(),
+        }
+    }
+
+    /// Append each entry recorded from now on to the ledger of
+    /// `persistence`, periodically snapshotting the accountant alongside
+    /// it. The ledger must end with the id the skel was created with.
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = Some(persistence);
+    }
+
+    /// Limit the requests `serve` accepts over UDP with `config`, rather than
+    /// the default limits, which drop requests larger than
+    /// `MAX_REQUEST_SIZE`. Takes effect on the next call to `serve`.
+    pub fn set_intake_limits(&mut self, config: streamer::ReceiverConfig) {
+        self.intake = config;
+    }
+
+    /// The log of the work dropped or rejected by each stage of `serve`.
+    pub fn reject_log(&self) -> Arc<RejectLog> {
+        self.rejections.clone()
+    }
+
+    /// The counts of the requests dropped on arrival since `serve` was last
+    /// called.
+    pub fn intake_metrics(&self) -> Arc<streamer::ReceiverMetrics> {
+        self.intake_metrics.clone()
+    }
+
+    /// Expire entry subscriptions `timeout` after they were last refreshed,
+    /// rather than after `SUBSCRIPTION_TIMEOUT_MS`.
+    pub fn set_subscription_timeout(&mut self, timeout: Duration) {
+        self.subscription_timeout = timeout;
+    }
+
+    /// The height of the ledger: with persistence, that of the ledger on
+    /// disk, and otherwise the number of entries recorded since the server
+    /// started.
+    fn height(&self) -> u64 {
+        match self.persistence {
+            Some(ref persistence) => persistence.height(),
+            None => self.height,
         }
     }
 
+    /// The height of the oldest entry the server keeps.
+    fn first_height(&self) -> u64 {
+        match self.persistence {
+            Some(_) => 0,
+            None => self.height - self.recent.len() as u64,
+        }
+    }
+
+    /// Keep `entry` among the recent entries, forgetting the oldest if there
+    /// are more than `MAX_RECENT_ENTRIES`.
+    fn keep_recent(&mut self, entry: Entry) {
+        if self.recent.len() == MAX_RECENT_ENTRIES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(entry);
+        self.height += 1;
+    }
+
+    /// Read up to `max_count` entries from `start_height` on, and return them
+    /// with the height of the ledger. With persistence, they're read from
+    /// the ledger on disk, and otherwise from the recent entries, which must
+    /// reach back to `start_height`.
+    fn read_entries(&self, start_height: u64, max_count: usize) -> Result<(Vec<Entry>, u64)> {
+        if let Some(ref persistence) = self.persistence {
+            let entries = persistence.read_entries(start_height, max_count)?;
+            return Ok((entries, persistence.height()));
+        }
+        let first_height = self.first_height();
+        assert!(start_height >= first_height, "entries older than those kept");
+        let start = (min(start_height, self.height) - first_height) as usize;
+        let end = start + min(max_count, self.recent.len() - start);
+        let entries = self.recent.range(start..end).cloned().collect();
+        Ok((entries, self.height))
+    }
+
+    /// Subscribe `addr` to the entries from `from_height` on, or refresh its
+    /// subscription if it has one.
+    fn subscribe(&mut self, addr: SocketAddr, from_height: u64) {
+        if from_height < self.first_height() && !self.subscriptions.contains_key(&addr) {
+            return;
+        }
+        let expires = Instant::now() + self.subscription_timeout;
+        self.subscriptions
+            .entry(addr)
+            .or_insert(Subscription {
+                height: from_height,
+                blob_index: 0,
+                failures: 0,
+                expires,
+            })
+            .expires = expires;
+    }
+
+    /// Send each subscriber the entries it hasn't been sent, up to
+    /// `MAX_PUSH_ENTRIES` of them. Subscriptions that expired, failed
+    /// `MAX_PUSH_FAILURES` times in a row, or fell behind the oldest entry
+    /// the server keeps are dropped.
+    fn push_entries(&mut self, sock: &UdpSocket, recycler: &BlobRecycler) -> Result<()> {
+        self.sync();
+        let now = Instant::now();
+        let first_height = self.first_height();
+        self.subscriptions.retain(|_, sub| {
+            sub.expires > now && sub.failures < MAX_PUSH_FAILURES && sub.height >= first_height
+        });
+        let height = self.height();
+        let reads: Vec<_> = self.subscriptions
+            .iter()
+            .filter(|x| x.1.height < height)
+            .map(|(&addr, sub)| (addr, self.read_entries(sub.height, MAX_PUSH_ENTRIES)))
+            .collect();
+        for (addr, read) in reads {
+            let sub = self.subscriptions.get_mut(&addr).unwrap();
+            let entries = match read {
+                Ok((entries, _)) => entries,
+                Err(err) => {
+                    warn!("failed to read entries from the ledger: {:?}", err);
+                    sub.failures += 1;
+                    continue;
+                }
+            };
+            let mut blobs = VecDeque::new();
+            ledger::entries_to_blobs(&entries, recycler, &mut blobs)?;
+            for blob in blobs {
+                {
+                    let mut b = blob.write().unwrap();
+                    b.set_index(sub.blob_index)?;
+                    sub.blob_index += 1;
+                    match sock.send_to(&b.data[..b.meta.size], addr) {
+                        Ok(_) => sub.failures = 0,
+                        Err(err) => {
+                            warn!("failed to push entries to {}: {:?}", addr, err);
+                            sub.failures += 1;
+                        }
+                    }
+                }
+                recycler.recycle(blob);
+            }
+            sub.height += entries.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Every `interval` ticks, move the fees collected by `keypair`, the
+    /// accountant's fee collector, to `to`, and record the transfer in the
+    /// ledger.
+    pub fn set_fee_collection(&mut self, keypair: KeyPair, to: PublicKey, interval: u64) {
+        self.fee_collection = Some(FeeCollection {
+            keypair,
+            to,
+            interval,
+            num_ticks: 0,
+        });
+    }
+
+    /// Count a tick, collecting fees if it's time to. Returns whether the
+    /// collection was sent to the Historian.
+    fn tick_fee_collection(&mut self) -> Result<bool> {
+        let tr = match self.fee_collection {
+            Some(ref mut collection) => {
+                collection.num_ticks += 1;
+                if collection.num_ticks < collection.interval {
+                    return Ok(false);
+                }
+                collection.num_ticks = 0;
+                self.acc
+                    .collect_fees(&collection.keypair, collection.to, self.last_id)
+            }
+            None => return Ok(false),
+        };
+        match tr {
+            Ok(Some(tr)) => {
+                let event = Event::Transaction(tr);
+                self.historian.sender.send(Signal::Event(event))?;
+                return Ok(true);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("failed to collect fees: {:?}", err),
+        }
+        Ok(false)
+    }
+
     /// Process any Entry items that have been published by the Historian.
     pub fn sync(&mut self) -> Hash {
+        self.record_entries();
+        if self.persistence.as_ref().is_some_and(Persistence::snapshot_due) {
+            if let Err(err) = self.snapshot() {
+                warn!("failed to snapshot the accountant: {:?}", err);
+            }
+        }
+        self.last_id
+    }
+
+    /// Record the entries the Historian has published, and return whether
+    /// that sent it any events, which only a later entry will hold.
+    fn record_entries(&mut self) -> bool {
+        let mut sent_events = false;
         while let Ok(entry) = self.historian.receiver.try_recv() {
             self.last_id = entry.id;
             self.acc.register_entry_id(&self.last_id);
             writeln!(self.writer, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+            let is_tick = entry.events.is_empty();
+            match self.persistence {
+                Some(ref mut persistence) => persistence
+                    .write_entry(&entry)
+                    .expect("failed to append an entry to the ledger"),
+                None => self.keep_recent(entry),
+            }
+            if is_tick {
+                match self.tick_fee_collection() {
+                    Ok(sent) => sent_events |= sent,
+                    Err(_) => warn!("historian exited, can't record collected fees"),
+                }
+            }
+        }
+        sent_events
+    }
+
+    /// Snapshot the accountant alongside the ledger, if there is one. The
+    /// Historian is paused until it has recorded every event the accountant
+    /// processed, so that the snapshot matches the end of the ledger.
+    pub fn snapshot(&mut self) -> Result<()> {
+        if self.persistence.is_none() {
+            return Ok(());
+        }
+        loop {
+            let token = self.historian.pause().map_err(|_| Error::SendError)?;
+            if !self.record_entries() {
+                let result = match self.persistence {
+                    Some(ref mut persistence) => persistence.snapshot(&self.acc),
+                    None => Ok(()),
+                };
+                token.resume();
+                return result;
+            }
+            token.resume();
         }
-        self.last_id
     }
 
     /// Process Request items sent by clients.
@@ -88,52 +450,58 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
         rsp_addr: SocketAddr,
     ) -> Option<(Response, SocketAddr)> {
         match msg {
-            Request::GetBalance { key } => {
-                let val = self.acc.get_balance(&key);
-                Some((Response::Balance { key, val }, rsp_addr))
+            Request::GetBalance { key, req_id } => {
+                let val = self.reader.get_balance(&key);
+                Some((Response::Balance { key, val, req_id }, rsp_addr))
+            }
+            Request::GetLastId { req_id } => {
+                let id = self.sync();
+                Some((Response::LastId { id, req_id }, rsp_addr))
+            }
+            Request::CheckIds { ids, req_id } => {
+                self.sync();
+                let valid = self.acc.count_valid_ids(&ids);
+                Some((Response::ValidIds { valid, req_id }, rsp_addr))
+            }
+            Request::Subscribe { from_height } => {
+                self.subscribe(rsp_addr, from_height);
+                None
+            }
+            Request::GetRejections { limit, req_id } => {
+                let limit = min(limit, self.rejections.capacity() as u64) as usize;
+                let rejections = self.rejections.recent(limit);
+                Some((Response::Rejections { rejections, req_id }, rsp_addr))
+            }
+            Request::GetEntries {
+                start_height,
+                max_count,
+                req_id,
+            } => {
+                self.sync();
+                let first_height = self.first_height();
+                if start_height < first_height {
+                    let rsp = Response::EntriesTooOld {
+                        first_height,
+                        req_id,
+                    };
+                    return Some((rsp, rsp_addr));
+                }
+                let max_count = min(max_count, MAX_GET_ENTRIES) as usize;
+                match self.read_entries(start_height, max_count) {
+                    Ok((entries, height)) => {
+                        let rsp = Self::entry_range(entries, height, req_id);
+                        Some((rsp, rsp_addr))
+                    }
+                    Err(err) => {
+                        warn!("failed to read entries from the ledger: {:?}", err);
+                        None
+                    }
+                }
             }
-            Request::GetLastId => Some((Response::LastId { id: self.sync() }, rsp_addr)),
             Request::Transaction(_) => unreachable!(),
         }
     }
 
-    fn recv_batch(recvr: &streamer::PacketReceiver) -> Result<Vec<SharedPackets>> {
-        let timer = Duration::new(1, 0);
-        let msgs = recvr.recv_timeout(timer)?;
-        trace!("got msgs");
-        let mut batch = vec![msgs];
-        while let Ok(more) = recvr.try_recv() {
-            trace!("got more msgs");
-            batch.push(more);
-        }
-        info!("batch len {}", batch.len());
-        Ok(batch)
-    }
-
-    fn verify_batch(batch: Vec<SharedPackets>) -> Vec<Vec<(SharedPackets, Vec<u8>)>> {
-        let chunk_size = max(1, (batch.len() + 3) / 4);
-        let batches: Vec<_> = batch.chunks(chunk_size).map(|x| x.to_vec()).collect();
-        batches
-            .into_par_iter()
-            .map(|batch| {
-                let r = ecdsa::ed25519_verify(&batch);
-                batch.into_iter().zip(r).collect()
-            })
-            .collect()
-    }
-
-    fn verifier(
-        recvr: &streamer::PacketReceiver,
-        sendr: &Sender<Vec<(SharedPackets, Vec<u8>)>>,
-    ) -> Result<()> {
-        let batch = Self::recv_batch(recvr)?;
-        let verified_batches = Self::verify_batch(batch);
-        for xs in verified_batches {
-            sendr.send(xs)?;
-        }
-        Ok(())
-    }
-
     pub fn deserialize_packets(p: &packet::Packets) -> Vec<Option<(Request, SocketAddr)>> {
         p.packets
             .par_iter()
@@ -145,37 +513,55 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
             .collect()
     }
 
-    /// Split Request list into verified transactions and the rest
+    /// Split Request list into transactions and the rest
     fn partition_requests(
-        req_vers: Vec<(Request, SocketAddr, u8)>,
+        reqs: Vec<(Request, SocketAddr)>,
     ) -> (Vec<Transaction>, Vec<(Request, SocketAddr)>) {
         let mut trs = vec![];
-        let mut reqs = vec![];
-        for (msg, rsp_addr, verify) in req_vers {
+        let mut others = vec![];
+        for (msg, rsp_addr) in reqs {
             match msg {
-                Request::Transaction(tr) => {
-                    if verify != 0 {
-                        trs.push(tr);
-                    }
-                }
-                _ => reqs.push((msg, rsp_addr)),
+                Request::Transaction(tr) => trs.push(tr),
+                _ => others.push((msg, rsp_addr)),
             }
         }
-        (trs, reqs)
+        (trs, others)
     }
 
+    /// Process requests whose transactions' signatures are verified.
     fn process_packets(
         &mut self,
-        req_vers: Vec<(Request, SocketAddr, u8)>,
+        reqs: Vec<(Request, SocketAddr)>,
     ) -> Result<Vec<(Response, SocketAddr)>> {
-        let (trs, reqs) = Self::partition_requests(req_vers);
+        let (mut trs, reqs) = Self::partition_requests(reqs);
+
+        // Drop the transactions rather than apply them if the historian
+        // can't queue them and the tick that follows.
+        let sender = &self.historian.sender;
+        if sender.queue_depth() + trs.len() + 1 > sender.capacity() {
+            warn!("historian busy, dropping {} transactions", trs.len());
+            let metrics = self.historian.metrics();
+            metrics.events_dropped.fetch_add(trs.len(), Ordering::Relaxed);
+            for tr in trs.drain(..) {
+                let sig = Subject::Signature(tr.sig);
+                self.rejections.record(Stage::Historian, Reason::HistorianBusy, sig);
+            }
+        }
 
         // Process the transactions in parallel and then log the successful ones.
-        for result in self.acc.process_verified_transactions(trs) {
-            if let Ok(tr) = result {
-                self.historian
-                    .sender
-                    .send(Signal::Event(Event::Transaction(tr)))?;
+        let sigs: Vec<_> = trs.iter().map(|tr| tr.sig).collect();
+        let results = self.acc.process_verified_transactions(trs);
+        for (result, sig) in results.into_iter().zip(sigs) {
+            match result {
+                Ok(tr) => {
+                    self.historian
+                        .sender
+                        .send(Signal::Event(Event::Transaction(tr)))?;
+                }
+                Err(err) => {
+                    let sig = Subject::Signature(sig);
+                    self.rejections.record(Stage::Accountant, Reason::Accounting(err), sig);
+                }
             }
         }
 
@@ -183,6 +569,9 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
         // transactions in parallel.
         self.historian.sender.send(Signal::Tick)?;
 
+        // Serve the remaining requests' reads with the transactions applied.
+        self.acc.publish();
+
         // Process the remaining requests serially.
         let rsps = reqs.into_iter()
             .filter_map(|(req, rsp_addr)| self.process_request(req, rsp_addr))
@@ -191,6 +580,30 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
         Ok(rsps)
     }
 
+    /// Respond with as many of `entries` as fit in a datagram, and at least
+    /// the first, which is sent over TCP if it doesn't.
+    fn entry_range(mut entries: Vec<Entry>, height: u64, req_id: u64) -> Response {
+        let empty = Response::EntryRange {
+            entries: vec![],
+            height,
+            req_id,
+        };
+        let mut size = serialized_size(&empty).unwrap();
+        let fits = entries
+            .iter()
+            .position(|entry| {
+                size += serialized_size(entry).unwrap();
+                size > packet::MAX_BLOB_SEND_SIZE as u64
+            })
+            .unwrap_or(entries.len());
+        entries.truncate(fits.max(1));
+        Response::EntryRange {
+            entries,
+            height,
+            req_id,
+        }
+    }
+
     fn serialize_response(
         resp: Response,
         rsp_addr: SocketAddr,
@@ -199,7 +612,11 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
         let blob = blob_recycler.allocate();
         {
             let mut b = blob.write().unwrap();
-            let v = serialize(&resp)?;
+            let mut v = serialize(&resp)?;
+            if v.len() > packet::MAX_BLOB_SEND_SIZE {
+                let req_id = resp.req_id().unwrap_or(0);
+                v = serialize(&Response::TooLarge { req_id })?;
+            }
             let len = v.len();
             b.data[..len].copy_from_slice(&v);
             b.meta.size = len;
@@ -219,34 +636,84 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
         Ok(blobs)
     }
 
+    /// Return the requests that were decoded from packets from `sources` and
+    /// pass `Request::verify`, recording the rest in `rejections`.
+    fn check_requests(
+        reqs: Vec<Option<(Request, SocketAddr)>>,
+        sources: Vec<SocketAddr>,
+        rejections: &RejectLog,
+    ) -> Vec<(Request, SocketAddr)> {
+        let mut checked = vec![];
+        for (req, source) in reqs.into_iter().zip(sources) {
+            match req {
+                Some(req) => {
+                    if req.0.verify() {
+                        checked.push(req);
+                    } else if let Request::Transaction(ref tr) = req.0 {
+                        let reason = Reason::Accounting(AccountingError::InvalidPlan);
+                        rejections.record(Stage::Request, reason, Subject::Signature(tr.sig));
+                    }
+                }
+                None => {
+                    let source = Subject::Source(source);
+                    rejections.record(Stage::Request, Reason::Malformed, source);
+                }
+            }
+        }
+        checked
+    }
+
     fn process(
         obj: &Arc<Mutex<AccountantSkel<W>>>,
-        verified_receiver: &Receiver<Vec<(SharedPackets, Vec<u8>)>>,
+        verified_receiver: &Receiver<Vec<SharedPackets>>,
         blob_sender: &streamer::BlobSender,
         packet_recycler: &packet::PacketRecycler,
         blob_recycler: &packet::BlobRecycler,
+        rejections: &RejectLog,
     ) -> Result<()> {
         let timer = Duration::new(1, 0);
         let mms = verified_receiver.recv_timeout(timer)?;
-        for (msgs, vers) in mms {
-            let reqs = Self::deserialize_packets(&msgs.read().unwrap());
-            let req_vers = reqs.into_iter()
-                .zip(vers)
-                .filter_map(|(req, ver)| req.map(|(msg, addr)| (msg, addr, ver)))
-                .filter(|x| x.0.verify())
-                .collect();
-            let rsps = obj.lock().unwrap().process_packets(req_vers)?;
+        for msgs in mms {
+            let (reqs, sources) = {
+                let msgs = msgs.read().unwrap();
+                let sources = msgs.packets.iter().map(|x| x.meta.addr()).collect();
+                (Self::deserialize_packets(&msgs), sources)
+            };
+            packet_recycler.recycle(msgs);
+            let reqs = Self::check_requests(reqs, sources, rejections);
+            let rsps = obj.lock().unwrap().process_packets(reqs)?;
             let blobs = Self::serialize_responses(rsps, blob_recycler)?;
             if !blobs.is_empty() {
                 //don't wake up the other side if there is nothing
                 blob_sender.send(blobs)?;
             }
-            packet_recycler.recycle(msgs);
+        }
+        Ok(())
+    }
+
+    /// Answer the one request read from `stream` as a request over UDP is
+    /// answered. Transactions are ignored, since they must be sent over UDP
+    /// to have their signatures verified.
+    fn serve_tcp(obj: &Arc<Mutex<AccountantSkel<W>>>, mut stream: TcpStream) -> Result<()> {
+        let timeout = Some(Duration::new(1, 0));
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let req: Request = deserialize(&packet::read_frame(&mut stream)?)?;
+        if let Request::Transaction(_) = req {
+            return Ok(());
+        }
+        let rsp_addr = stream.peer_addr()?;
+        let rsp = obj.lock().unwrap().process_request(req, rsp_addr);
+        if let Some((rsp, _)) = rsp {
+            packet::write_frame(&mut stream, &serialize(&rsp)?)?;
         }
         Ok(())
     }
 
     /// Create a UDP microservice that forwards messages the given AccountantSkel.
+    /// Requests too large for a datagram, or with responses too large for
+    /// one, are accepted over TCP on the next port, one per connection.
     /// Set `exit` to shutdown its threads.
     pub fn serve(
         obj: &Arc<Mutex<AccountantSkel<W>>>,
@@ -256,25 +723,71 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
         let read = UdpSocket::bind(addr)?;
         // make sure we are on the same interface
         let mut local = read.local_addr()?;
+        let mut tcp_addr = local;
+        tcp_addr.set_port(local.port() + 1);
+        let listener = TcpListener::bind(tcp_addr)?;
+        listener.set_nonblocking(true)?;
         local.set_port(0);
         let write = UdpSocket::bind(local)?;
+        let push = write.try_clone()?;
 
         let packet_recycler = packet::PacketRecycler::default();
         let blob_recycler = packet::BlobRecycler::default();
         let (packet_sender, packet_receiver) = channel();
-        let t_receiver =
-            streamer::receiver(read, exit.clone(), packet_recycler.clone(), packet_sender)?;
+        let (intake, rejections) = {
+            let skel = obj.lock().unwrap();
+            (skel.intake, skel.rejections.clone())
+        };
+        let (t_receiver, intake_metrics) = streamer::limited_receiver(
+            read,
+            exit.clone(),
+            packet_recycler.clone(),
+            packet_sender,
+            intake,
+            rejections.clone(),
+        )?;
+        obj.lock().unwrap().intake_metrics = intake_metrics;
         let (blob_sender, blob_receiver) = channel();
         let t_responder =
             streamer::responder(write, exit.clone(), blob_recycler.clone(), blob_receiver);
         let (verified_sender, verified_receiver) = channel();
+        let (t_verifier, _) = sigverify::sigverify(
+            exit.clone(),
+            Ed25519Verifier,
+            packet_receiver,
+            verified_sender,
+            rejections.clone(),
+        );
+
+        let skel = obj.clone();
+        let exit_ = exit.clone();
+        let t_push = spawn(move || {
+            let recycler = BlobRecycler::default();
+            while !exit_.load(Ordering::Relaxed) {
+                if let Err(err) = skel.lock().unwrap().push_entries(&push, &recycler) {
+                    warn!("failed to push entries: {:?}", err);
+                }
+                sleep(Duration::from_millis(PUSH_INTERVAL_MS));
+            }
+        });
 
+        let skel = obj.clone();
         let exit_ = exit.clone();
-        let t_verifier = spawn(move || loop {
-            let e = Self::verifier(&packet_receiver, &verified_sender);
-            if e.is_err() && exit_.load(Ordering::Relaxed) {
+        let t_tcp = spawn(move || loop {
+            if exit_.load(Ordering::Relaxed) {
                 break;
             }
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let skel = skel.clone();
+                    spawn(move || {
+                        if let Err(err) = Self::serve_tcp(&skel, stream) {
+                            warn!("failed to serve a TCP request: {:?}", err);
+                        }
+                    });
+                }
+                Err(_) => sleep(Duration::from_millis(10)),
+            }
         });
 
         let skel = obj.clone();
@@ -285,12 +798,13 @@ impl<W: Write + Send + 'static> AccountantSkel<W> {
                 &blob_sender,
                 &packet_recycler,
                 &blob_recycler,
+                &rejections,
             );
             if e.is_err() && exit.load(Ordering::Relaxed) {
                 break;
             }
         });
-        Ok(vec![t_receiver, t_responder, t_server, t_verifier])
+        Ok(vec![t_receiver, t_responder, t_server, t_verifier, t_tcp, t_push])
     }
 }
 
@@ -316,22 +830,26 @@ pub fn to_packets(r: &packet::PacketRecycler, reqs: Vec<Request>) -> Vec<SharedP
 
 #[cfg(test)]
 mod tests {
-    use accountant_skel::{to_packets, Request};
-    use bincode::serialize;
+    use accountant_skel::{to_packets, Request, Response, MAX_GET_ENTRIES, MAX_PUSH_ENTRIES,
+                          MAX_PUSH_FAILURES, MAX_RECENT_ENTRIES};
+    use bincode::{deserialize, serialize};
     use ecdsa;
-    use packet::{PacketRecycler, NUM_PACKETS};
+    use packet::{BlobRecycler, PacketRecycler, MAX_BLOB_SEND_SIZE, NUM_PACKETS};
     use transaction::{memfind, test_tx};
 
-    use accountant::Accountant;
+    use accountant::{Accountant, AccountingError, Fees};
     use accountant_skel::AccountantSkel;
     use accountant_stub::AccountantStub;
-    use entry::Entry;
-    use historian::Historian;
+    use entry::{create_entry, next_tick, Entry};
+    use event::Event;
+    use hash::hash;
+    use historian::{Historian, HistorianConfig};
     use mint::Mint;
     use plan::Plan;
     use recorder::Signal;
+    use reject_log::{Reason, RejectLog, Stage, Subject};
     use signature::{KeyPair, KeyPairUtil};
-    use std::io::sink;
+    use std::io::{sink, Sink};
     use std::net::{SocketAddr, UdpSocket};
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Arc, Mutex};
@@ -339,6 +857,14 @@ mod tests {
     use std::time::Duration;
     use transaction::Transaction;
 
+    fn tick(skel: &mut AccountantSkel<Sink>) {
+        let height = skel.height();
+        skel.historian.sender.send(Signal::Tick).unwrap();
+        while skel.height() == height {
+            skel.sync();
+        }
+    }
+
     #[test]
     fn test_layout() {
         let tr = test_tx();
@@ -365,6 +891,20 @@ mod tests {
         assert_eq!(rv[1].read().unwrap().packets.len(), 1);
     }
 
+    #[test]
+    fn test_serialize_oversized_response() {
+        let rsp_addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
+        let valid = vec![(0, 0); MAX_BLOB_SEND_SIZE / 16];
+        let rsp = Response::ValidIds { valid, req_id: 3 };
+        let recycler = BlobRecycler::default();
+        let blob = AccountantSkel::<Sink>::serialize_response(rsp, rsp_addr, &recycler).unwrap();
+        let b = blob.read().unwrap();
+        assert_matches!(
+            deserialize(&b.data[..b.meta.size]),
+            Ok(Response::TooLarge { req_id: 3 })
+        );
+    }
+
     #[test]
     fn test_accounting_sequential_consistency() {
         // In this attack we'll demonstrate that a verifier can interpret the ledger
@@ -379,12 +919,12 @@ mod tests {
         // Process a batch that includes a transaction that receives two tokens.
         let alice = KeyPair::new();
         let tr = Transaction::new(&mint.keypair(), alice.pubkey(), 2, mint.last_id());
-        let req_vers = vec![(Request::Transaction(tr), rsp_addr, 1_u8)];
+        let req_vers = vec![(Request::Transaction(tr), rsp_addr)];
         assert!(skel.process_packets(req_vers).is_ok());
 
         // Process a second batch that spends one of those tokens.
         let tr = Transaction::new(&alice, mint.pubkey(), 1, mint.last_id());
-        let req_vers = vec![(Request::Transaction(tr), rsp_addr, 1_u8)];
+        let req_vers = vec![(Request::Transaction(tr), rsp_addr)];
         assert!(skel.process_packets(req_vers).is_ok());
 
         // Collect the ledger and feed it to a new accountant.
@@ -396,12 +936,294 @@ mod tests {
         // entry, then the second transaction will be rejected, because it drives
         // the account balance below zero before the credit is added.
         let acc = Accountant::new(&mint);
-        for entry in entries {
-            acc.process_verified_events(entry.events).unwrap();
-        }
+        acc.process_verified_entries(&entries);
         assert_eq!(acc.get_balance(&alice.pubkey()), Some(1));
     }
 
+    #[test]
+    fn test_fee_collection() {
+        let mint = Mint::new(100);
+        let collector = KeyPair::new();
+        let treasury = KeyPair::new().pubkey();
+        let mut acc = Accountant::new(&mint);
+        acc.set_fees(Some(Fees {
+            collector: collector.pubkey(),
+            per_transaction: 1,
+        }));
+        let historian = Historian::new(&mint.last_id(), None);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+        skel.set_fee_collection(collector, treasury, 2);
+
+        let rsp_addr: SocketAddr = "0.0.0.0:0".parse().expect("socket address");
+        let alice = KeyPair::new();
+        let tr = Transaction::new(&mint.keypair(), alice.pubkey(), 10, mint.last_id());
+        let req_vers = vec![(Request::Transaction(tr), rsp_addr)];
+        assert!(skel.process_packets(req_vers).is_ok());
+
+        // The fees are collected on the second tick.
+        skel.historian.sender.send(Signal::Tick).unwrap();
+        skel.historian.sender.send(Signal::Tick).unwrap();
+        while skel.acc.get_balance(&treasury).is_none() {
+            skel.sync();
+        }
+        assert_eq!(skel.acc.get_balance(&treasury), Some(1));
+
+        // The collection is recorded in the ledger.
+        skel.historian.sender.send(Signal::Tick).unwrap();
+        drop(skel.historian.sender);
+        let entries: Vec<Entry> = skel.historian.receiver.iter().collect();
+        let events: Vec<_> = entries.into_iter().flat_map(|x| x.events).collect();
+        assert_eq!(events.len(), 1);
+        assert_matches!(
+            events[0],
+            Event::Transaction(ref tr) if tr.from == skel.acc.fees().unwrap().collector
+        );
+    }
+
+    #[test]
+    fn test_subscription_expiry() {
+        let mint = Mint::new(1);
+        let acc = Accountant::new(&mint);
+        let historian = Historian::new(&mint.last_id(), None);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+        skel.set_subscription_timeout(Duration::from_millis(300));
+        let recycler = BlobRecycler::default();
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let subscriber = UdpSocket::bind("127.0.0.1:0").unwrap();
+        subscriber
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let addr = subscriber.local_addr().unwrap();
+        let req = Request::Subscribe { from_height: 1 };
+        assert!(skel.process_request(req, addr).is_none());
+
+        // The subscriber is sent the entries from its height on.
+        tick(&mut skel);
+        skel.push_entries(&sock, &recycler).unwrap();
+        assert_eq!(skel.subscriptions[&addr].height, 1);
+        tick(&mut skel);
+        skel.push_entries(&sock, &recycler).unwrap();
+        assert_eq!(skel.subscriptions[&addr].height, 2);
+        let mut buf = vec![0u8; MAX_BLOB_SEND_SIZE];
+        assert!(subscriber.recv_from(&mut buf).is_ok());
+
+        // And nothing once its subscription expires.
+        sleep(Duration::from_millis(400));
+        tick(&mut skel);
+        skel.push_entries(&sock, &recycler).unwrap();
+        assert!(skel.subscriptions.is_empty());
+        assert!(subscriber.recv_from(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_subscriber_eviction() {
+        let mint = Mint::new(1);
+        let acc = Accountant::new(&mint);
+        let historian = Historian::new(&mint.last_id(), None);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+        let recycler = BlobRecycler::default();
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        // An IPv4 socket can't send to an IPv6 address.
+        let addr: SocketAddr = "[::1]:9".parse().unwrap();
+        skel.process_request(Request::Subscribe { from_height: 0 }, addr);
+        for _ in 0..MAX_PUSH_FAILURES {
+            tick(&mut skel);
+            skel.push_entries(&sock, &recycler).unwrap();
+            assert_eq!(skel.subscriptions.len(), 1);
+        }
+        skel.push_entries(&sock, &recycler).unwrap();
+        assert!(skel.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_rejections() {
+        let mint = Mint::new(10);
+        let rsp_addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let historian = Historian::new(&mint.last_id(), None);
+        let acc = Accountant::new(&mint);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+
+        let broke = Transaction::new(&mint.keypair(), bob_pubkey, 11, mint.last_id());
+        let stale = Transaction::new(&mint.keypair(), bob_pubkey, 1, hash(b"stale"));
+        let reqs = vec![
+            (Request::Transaction(broke.clone()), rsp_addr),
+            (Request::Transaction(stale.clone()), rsp_addr),
+        ];
+        skel.process_packets(reqs).unwrap();
+        let rejections: Vec<_> = skel.reject_log()
+            .recent(2)
+            .into_iter()
+            .map(|x| (x.stage, x.reason, x.subject))
+            .collect();
+        assert_eq!(
+            rejections,
+            vec![
+                (
+                    Stage::Accountant,
+                    Reason::Accounting(AccountingError::LastIdNotFound),
+                    Subject::Signature(stale.sig),
+                ),
+                (
+                    Stage::Accountant,
+                    Reason::Accounting(AccountingError::InsufficientFunds),
+                    Subject::Signature(broke.sig),
+                ),
+            ]
+        );
+
+        // A historian that can't queue the batch and its tick drops it.
+        let config = HistorianConfig {
+            queue_capacity: Some(2),
+            ..HistorianConfig::default()
+        };
+        let historian = Historian::new_with_config(&mint.last_id(), config).unwrap();
+        let acc = Accountant::new(&mint);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+        let trs: Vec<_> = (1..3)
+            .map(|tokens| Transaction::new(&mint.keypair(), bob_pubkey, tokens, mint.last_id()))
+            .collect();
+        let reqs = trs.iter()
+            .map(|tr| (Request::Transaction(tr.clone()), rsp_addr))
+            .collect();
+        skel.process_packets(reqs).unwrap();
+        assert_eq!(skel.acc.get_balance(&bob_pubkey), None);
+        let rejections = skel.reject_log().recent(10);
+        assert_eq!(rejections.len(), 2);
+        assert!(rejections.iter().all(|x| x.reason == Reason::HistorianBusy));
+        assert_eq!(rejections[0].subject, Subject::Signature(trs[1].sig));
+    }
+
+    #[test]
+    fn test_get_entries() {
+        let mint = Mint::new(1_000);
+        let rsp_addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let bob_pubkey = KeyPair::new().pubkey();
+        let historian = Historian::new(&mint.last_id(), None);
+        let acc = Accountant::new(&mint);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+
+        // Entries of about 20KB, a few of which fill a datagram.
+        let mut id = mint.last_id();
+        for _ in 0..4 {
+            let events = (1..101)
+                .map(|tokens| Transaction::new(&mint.keypair(), bob_pubkey, tokens, id))
+                .map(Event::Transaction)
+                .collect();
+            let entry = create_entry(&id, 0, events);
+            id = entry.id;
+            skel.keep_recent(entry);
+        }
+        let mut get_entries = |start_height, max_count| {
+            let req = Request::GetEntries {
+                start_height,
+                max_count,
+                req_id: 7,
+            };
+            let (rsp, _) = skel.process_request(req, rsp_addr).unwrap();
+            assert!(serialize(&rsp).unwrap().len() <= MAX_BLOB_SEND_SIZE);
+            match rsp {
+                Response::EntryRange {
+                    entries,
+                    height,
+                    req_id: 7,
+                } => (entries, height),
+                rsp => panic!("unexpected response {:?}", rsp),
+            }
+        };
+
+        let (entries, height) = get_entries(0, MAX_GET_ENTRIES + 1);
+        assert_eq!(height, 4);
+        assert_eq!(entries.len(), 3);
+        let (rest, _) = get_entries(3, 10);
+        assert_eq!(rest.len(), 1);
+        assert!(rest[0].verify(&entries[2].id));
+        assert_eq!(get_entries(1, 1).0, &entries[1..2]);
+        assert_eq!(get_entries(4, 1), (vec![], 4));
+        assert_eq!(get_entries(u64::MAX, 1), (vec![], 4));
+    }
+
+    #[test]
+    fn test_recent_entries() {
+        let mint = Mint::new(1);
+        let rsp_addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let historian = Historian::new(&mint.last_id(), None);
+        let acc = Accountant::new(&mint);
+        let mut skel = AccountantSkel::new(acc, mint.last_id(), sink(), historian);
+        let mut id = mint.last_id();
+        let mut ticks = vec![];
+        for _ in 0..MAX_RECENT_ENTRIES + 2 {
+            let entry = next_tick(&id, 1);
+            id = entry.id;
+            ticks.push(entry.clone());
+            skel.keep_recent(entry);
+        }
+        assert_eq!(skel.recent.len(), MAX_RECENT_ENTRIES);
+
+        // The two oldest entries are forgotten.
+        let mut get_entries = |start_height| {
+            let req = Request::GetEntries {
+                start_height,
+                max_count: 1,
+                req_id: 3,
+            };
+            skel.process_request(req, rsp_addr).unwrap().0
+        };
+        assert_matches!(
+            get_entries(1),
+            Response::EntriesTooOld {
+                first_height: 2,
+                req_id: 3,
+            }
+        );
+        match get_entries(2) {
+            Response::EntryRange { entries, .. } => assert_eq!(entries, &ticks[2..3]),
+            rsp => panic!("unexpected response {:?}", rsp),
+        }
+
+        // And so are subscriptions from before what's kept.
+        skel.process_request(Request::Subscribe { from_height: 1 }, rsp_addr);
+        assert!(skel.subscriptions.is_empty());
+        skel.process_request(Request::Subscribe { from_height: 2 }, rsp_addr);
+        assert_eq!(skel.subscriptions.len(), 1);
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recycler = BlobRecycler::default();
+        skel.push_entries(&sock, &recycler).unwrap();
+        assert_eq!(skel.subscriptions[&rsp_addr].height, 2 + MAX_PUSH_ENTRIES as u64);
+        for entry in ticks.drain(..MAX_RECENT_ENTRIES) {
+            skel.keep_recent(entry);
+        }
+        skel.push_entries(&sock, &recycler).unwrap();
+        assert!(skel.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_check_requests() {
+        let mint = Mint::new(10);
+        let bob_pubkey = KeyPair::new().pubkey();
+        let sources: Vec<SocketAddr> = (1..4)
+            .map(|port| format!("127.0.0.1:{}", port).parse().unwrap())
+            .collect();
+        let mut bad_plan = Transaction::new(&mint.keypair(), bob_pubkey, 1, mint.last_id());
+        bad_plan.data.plan = Plan::new_payment(2, bob_pubkey);
+        let reqs = vec![
+            None,
+            Some((Request::Transaction(bad_plan.clone()), sources[1])),
+            Some((Request::GetLastId { req_id: 1 }, sources[2])),
+        ];
+        let rejections = RejectLog::new(4);
+        let checked = AccountantSkel::<Sink>::check_requests(reqs, sources.clone(), &rejections);
+        assert_eq!(checked.len(), 1);
+        let recent = rejections.recent(4);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].stage, Stage::Request);
+        assert_eq!(recent[0].reason, Reason::Accounting(AccountingError::InvalidPlan));
+        assert_eq!(recent[0].subject, Subject::Signature(bad_plan.sig));
+        assert_eq!(recent[1].reason, Reason::Malformed);
+        assert_eq!(recent[1].subject, Subject::Source(sources[0]));
+    }
+
     #[test]
     fn test_accountant_bad_sig() {
         let serve_port = 9002;
@@ -498,7 +1320,7 @@ mod bench {
 
         let req_vers = transactions
             .into_iter()
-            .map(|tr| (Request::Transaction(tr), rsp_addr, 1_u8))
+            .map(|tr| (Request::Transaction(tr), rsp_addr))
             .collect();
 
         let historian = Historian::new(&mint.last_id(), None);
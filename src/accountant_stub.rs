@@ -3,17 +3,124 @@
 //! this object instead of writing messages to the network directly. The binary
 //! encoding of its messages are unstable and may change in future releases.
 
-use accountant_skel::{Request, Response};
+use accountant_skel::{Request, Response, MAX_REQUEST_SIZE, SUBSCRIPTION_TIMEOUT_MS};
 use bincode::{deserialize, serialize};
+use byteorder::{ByteOrder, LittleEndian};
+use entry::{decode_entries, Entry};
 use hash::Hash;
+use packet::{read_frame, write_frame, BlobRecycler, MAX_BLOB_SEND_SIZE};
+use reject_log::Rejection;
 use signature::{KeyPair, PublicKey, Signature};
+use std::collections::HashMap;
+use std::fmt::Debug;
 use std::io;
-use std::net::UdpSocket;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+use streamer;
 use transaction::Transaction;
 
+/// The most entries the client accepts in a single response.
+pub const MAX_RESPONSE_ENTRIES: usize = 1024;
+
+/// The most ids `check_ids` puts in a single request, so that it fits in a
+/// packet. Each id takes 40 bytes.
+pub const MAX_CHECK_IDS: usize = 5;
+
+/// The number of blobs a subscription buffers while waiting on a missing one.
+const SUBSCRIPTION_WINDOW_SIZE: usize = 1024;
+
+/// The bincode tag of `Response::Entries`.
+const ENTRIES_TAG: u32 = 1;
+
+fn invalid_data<E: Debug>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", err))
+}
+
+/// Deserialize a response, decoding any entries it holds within
+/// `MAX_RESPONSE_ENTRIES` and the size of `buf`.
+fn decode_response(buf: &[u8]) -> io::Result<Response> {
+    if buf.len() >= 4 && LittleEndian::read_u32(buf) == ENTRIES_TAG {
+        let entries = decode_entries(&buf[4..], MAX_RESPONSE_ENTRIES, buf.len() - 4)
+            .map_err(invalid_data)?;
+        return Ok(Response::Entries { entries });
+    }
+    deserialize(buf).map_err(invalid_data)
+}
+
+/// A request sent with `AccountantStub::submit`, to pass to
+/// `AccountantStub::wait` for its response.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct RequestHandle(u64);
+
+#[derive(Debug)]
+pub enum StubError {
+    /// No response arrived in time. The request's handle is no longer valid.
+    Timeout,
+    /// The handle was already waited on, or isn't the stub's.
+    UnknownHandle,
+    /// The request was a transaction, which gets no response.
+    NoResponse,
+    /// The entry fetched for the given height doesn't chain from the one
+    /// before it.
+    VerificationFailed(u64),
+    /// The server no longer keeps the entries from the requested height. The
+    /// oldest it keeps is at the given height.
+    EntriesTooOld(u64),
+    IO(io::Error),
+}
+
+impl From<io::Error> for StubError {
+    fn from(err: io::Error) -> Self {
+        StubError::IO(err)
+    }
+}
+
+fn into_io_error(err: StubError) -> io::Error {
+    match err {
+        StubError::IO(err) => err,
+        StubError::Timeout => io::Error::new(io::ErrorKind::TimedOut, "request timed out"),
+        err => io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", err)),
+    }
+}
+
+/// Return `req` with its `req_id` replaced, or None if it gets no response.
+fn with_req_id(req: Request, req_id: u64) -> Option<Request> {
+    match req {
+        Request::GetBalance { key, .. } => Some(Request::GetBalance { key, req_id }),
+        Request::GetLastId { .. } => Some(Request::GetLastId { req_id }),
+        Request::CheckIds { ids, .. } => Some(Request::CheckIds { ids, req_id }),
+        Request::GetRejections { limit, .. } => Some(Request::GetRejections { limit, req_id }),
+        Request::GetEntries {
+            start_height,
+            max_count,
+            ..
+        } => Some(Request::GetEntries {
+            start_height,
+            max_count,
+            req_id,
+        }),
+        Request::Transaction(_) | Request::Subscribe { .. } => None,
+    }
+}
+
+/// The requests a stub is waiting on.
+#[derive(Default)]
+struct Requests {
+    next_id: u64,
+    /// The requests submitted and not yet waited on, by id.
+    pending: HashMap<u64, Request>,
+    /// Responses received for pending ids other than the one waited on.
+    responses: HashMap<u64, Response>,
+}
+
 pub struct AccountantStub {
     pub addr: String,
     pub socket: UdpSocket,
+    requests: Mutex<Requests>,
 }
 
 impl AccountantStub {
@@ -24,9 +131,114 @@ impl AccountantStub {
         AccountantStub {
             addr: addr.to_string(),
             socket,
+            requests: Mutex::new(Requests::default()),
+        }
+    }
+
+    /// Send `req` to the server with a new `req_id`, replacing its own, and
+    /// return a handle to wait on for the response. Any number of requests
+    /// can be submitted before waiting on them, in any order. A request too
+    /// large for a datagram is made over TCP before this method returns.
+    pub fn submit(&self, req: Request) -> Result<RequestHandle, StubError> {
+        let mut requests = self.requests.lock().unwrap();
+        let req_id = requests.next_id;
+        let req = with_req_id(req, req_id).ok_or(StubError::NoResponse)?;
+        let data = serialize(&req).expect("serialize Request");
+        if data.len() > MAX_REQUEST_SIZE {
+            let rsp = self.tcp_request(&data)?;
+            requests.responses.insert(req_id, rsp);
+        } else {
+            self.socket.send_to(&data, &self.addr)?;
+        }
+        requests.next_id += 1;
+        requests.pending.insert(req_id, req);
+        Ok(RequestHandle(req_id))
+    }
+
+    /// Make the request serialized in `data` over TCP, for when it or its
+    /// response doesn't fit in a datagram. The connection uses the socket's
+    /// read timeout.
+    fn tcp_request(&self, data: &[u8]) -> io::Result<Response> {
+        let mut addr = self.addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| invalid_data("no server address"))?;
+        let port = addr.port() + 1;
+        addr.set_port(port);
+        let mut stream = TcpStream::connect(addr)?;
+        let timeout = self.socket.read_timeout()?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        write_frame(&mut stream, data)?;
+        decode_response(&read_frame(&mut stream)?)
+    }
+
+    /// Wait up to `timeout` for the response to the request `handle` was
+    /// returned for. Responses to other pending requests that arrive first
+    /// are kept for their own handles.
+    pub fn wait(&self, handle: RequestHandle, timeout: Duration) -> Result<Response, StubError> {
+        let original_timeout = self.socket.read_timeout()?;
+        let result = self.wait_until(handle, Some(Instant::now() + timeout));
+        self.socket.set_read_timeout(original_timeout)?;
+        result
+    }
+
+    /// Wait for the response to `handle` as `wait` does, but until `deadline`,
+    /// or with the socket's own read timeout for each packet if None.
+    fn wait_until(
+        &self,
+        handle: RequestHandle,
+        deadline: Option<Instant>,
+    ) -> Result<Response, StubError> {
+        let RequestHandle(req_id) = handle;
+        let mut requests = self.requests.lock().unwrap();
+        let mut buf = vec![0u8; MAX_BLOB_SEND_SIZE];
+        loop {
+            if let Some(rsp) = requests.responses.remove(&req_id) {
+                let req = requests.pending.remove(&req_id).unwrap();
+                if let Response::TooLarge { .. } = rsp {
+                    let data = serialize(&req).expect("serialize Request");
+                    return Ok(self.tcp_request(&data)?);
+                }
+                return Ok(rsp);
+            }
+            if !requests.pending.contains_key(&req_id) {
+                return Err(StubError::UnknownHandle);
+            }
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    requests.pending.remove(&req_id);
+                    return Err(StubError::Timeout);
+                }
+                self.socket.set_read_timeout(Some(deadline - now))?;
+            }
+            let size = match self.socket.recv_from(&mut buf) {
+                Ok((size, _)) => size,
+                Err(ref err) if deadline.is_some() && is_timeout(err) => continue,
+                Err(err) => {
+                    requests.pending.remove(&req_id);
+                    return Err(StubError::IO(err));
+                }
+            };
+            // Drop packets that aren't a response to a pending request.
+            if let Ok(rsp) = decode_response(&buf[..size]) {
+                if let Some(id) = rsp.req_id() {
+                    if requests.pending.contains_key(&id) {
+                        requests.responses.insert(id, rsp);
+                    }
+                }
+            }
         }
     }
 
+    /// Send `req` and wait for its response, which, unless the socket has a
+    /// read timeout, blocks indefinitely if the response packet is dropped.
+    fn request(&self, req: Request) -> io::Result<Response> {
+        let handle = self.submit(req).map_err(into_io_error)?;
+        self.wait_until(handle, None).map_err(into_io_error)
+    }
+
     /// Send a signed Transaction to the server for processing. This method
     /// does not wait for a response.
     pub fn transfer_signed(&self, tr: Transaction) -> io::Result<usize> {
@@ -52,13 +264,11 @@ impl AccountantStub {
     /// until the server sends a response. If the response packet is dropped
     /// by the network, this method will hang indefinitely.
     pub fn get_balance(&self, pubkey: &PublicKey) -> io::Result<Option<i64>> {
-        let req = Request::GetBalance { key: *pubkey };
-        let data = serialize(&req).expect("serialize GetBalance");
-        self.socket.send_to(&data, &self.addr)?;
-        let mut buf = vec![0u8; 1024];
-        self.socket.recv_from(&mut buf)?;
-        let resp = deserialize(&buf).expect("deserialize balance");
-        if let Response::Balance { key, val } = resp {
+        let req = Request::GetBalance {
+            key: *pubkey,
+            req_id: 0,
+        };
+        if let Response::Balance { key, val, .. } = self.request(req)? {
             assert_eq!(key, *pubkey);
             return Ok(val);
         }
@@ -70,32 +280,183 @@ impl AccountantStub {
     /// it also has the side-effect of causing the server to log any
     /// entries that have been published by the Historian.
     pub fn get_last_id(&self) -> io::Result<Hash> {
-        let req = Request::GetLastId;
-        let data = serialize(&req).expect("serialize GetId");
-        self.socket.send_to(&data, &self.addr)?;
-        let mut buf = vec![0u8; 1024];
-        self.socket.recv_from(&mut buf)?;
-        let resp = deserialize(&buf).expect("deserialize Id");
-        if let Response::LastId { id } = resp {
+        if let Response::LastId { id, .. } = self.request(Request::GetLastId { req_id: 0 })? {
             return Ok(id);
         }
         Ok(Default::default())
     }
+
+    /// Ask the server which of `ids` a transaction can still use as its
+    /// `last_id`. Returns the index of each valid id and its age in entries.
+    /// This method sends a request per `MAX_CHECK_IDS` ids, and then blocks
+    /// until the server responds to each.
+    pub fn check_ids(&self, ids: &[Hash]) -> io::Result<Vec<(usize, u64)>> {
+        let handles = ids.chunks(MAX_CHECK_IDS)
+            .map(|chunk| {
+                let req = Request::CheckIds {
+                    ids: chunk.to_vec(),
+                    req_id: 0,
+                };
+                self.submit(req).map_err(into_io_error)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut valid_ids = vec![];
+        for (i, handle) in handles.into_iter().enumerate() {
+            let rsp = self.wait_until(handle, None).map_err(into_io_error)?;
+            if let Response::ValidIds { valid, .. } = rsp {
+                let offset = i * MAX_CHECK_IDS;
+                valid_ids.extend(valid.into_iter().map(|(j, age)| (offset + j, age)));
+            }
+        }
+        Ok(valid_ids)
+    }
+
+    /// Ask the server for up to `limit` of the latest work it dropped or
+    /// rejected, newest first, to find out why a transaction didn't land.
+    pub fn get_rejections(&self, limit: usize) -> io::Result<Vec<Rejection>> {
+        let req = Request::GetRejections {
+            limit: limit as u64,
+            req_id: 0,
+        };
+        match self.request(req)? {
+            Response::Rejections { rejections, .. } => Ok(rejections),
+            rsp => Err(invalid_data(rsp)),
+        }
+    }
+
+    /// Fetch the `count` entries from `start_height` on, or those up to the
+    /// end of the server's ledger if it ends first, and verify that they
+    /// chain from `start_hash`, the id of the entry before them. The server
+    /// responds with as many entries as fit in a datagram, so this method
+    /// requests the rest until it has them all.
+    pub fn get_entries(
+        &self,
+        start_height: u64,
+        count: u64,
+        start_hash: &Hash,
+    ) -> Result<Vec<Entry>, StubError> {
+        let mut entries = vec![];
+        while (entries.len() as u64) < count {
+            let next_height = start_height + entries.len() as u64;
+            let max_count = count - entries.len() as u64;
+            let req = Request::GetEntries {
+                start_height: next_height,
+                max_count,
+                req_id: 0,
+            };
+            let handle = self.submit(req)?;
+            let (range, height) = match self.wait_until(handle, None)? {
+                Response::EntryRange {
+                    entries, height, ..
+                } => (entries, height),
+                Response::EntriesTooOld { first_height, .. } => {
+                    return Err(StubError::EntriesTooOld(first_height))
+                }
+                rsp => return Err(invalid_data(rsp).into()),
+            };
+            if range.len() as u64 > max_count {
+                return Err(invalid_data("more entries than requested").into());
+            }
+            let fetched = range.len();
+            entries.extend(range);
+            if fetched == 0 || start_height + entries.len() as u64 >= height {
+                break;
+            }
+        }
+
+        let mut id = *start_hash;
+        for (height, entry) in (start_height..).zip(&entries) {
+            if !entry.verify(&id) {
+                return Err(StubError::VerificationFailed(height));
+            }
+            id = entry.id;
+        }
+        Ok(entries)
+    }
+
+    /// Subscribe to the entries the server records, from `from_height` on.
+    /// The entries arrive on a socket of their own, bound to the stub's IP
+    /// address, and the subscription is refreshed until the first entry to
+    /// arrive after the returned receiver is dropped. Entries lost in transit
+    /// are skipped.
+    pub fn subscribe_entries(&self, from_height: u64) -> io::Result<Receiver<Entry>> {
+        let mut local = self.socket.local_addr()?;
+        local.set_port(0);
+        let sock = UdpSocket::bind(local)?;
+        let req = serialize(&Request::Subscribe { from_height }).expect("serialize Request");
+        sock.send_to(&req, &self.addr)?;
+        let refresh_sock = sock.try_clone()?;
+        let addr = self.addr.clone();
+
+        let exit = Arc::new(AtomicBool::new(false));
+        let recycler = BlobRecycler::default();
+        let (blob_sender, blob_receiver) = channel();
+        streamer::blob_receiver(exit.clone(), recycler.clone(), sock, blob_sender)
+            .map_err(|err| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("{:?}", err)))?;
+        let (entry_sender, entry_receiver) = channel();
+        let (repair_sender, _) = channel();
+        let config = streamer::WindowConfig {
+            size: SUBSCRIPTION_WINDOW_SIZE,
+            overflow: streamer::WindowOverflow::DropOldest,
+            ..Default::default()
+        };
+        streamer::entry_window(
+            exit.clone(),
+            recycler,
+            blob_receiver,
+            entry_sender,
+            repair_sender,
+            config,
+        );
+
+        let (sender, receiver) = channel();
+        let refresh = Duration::from_millis(SUBSCRIPTION_TIMEOUT_MS / 5);
+        spawn(move || {
+            let mut last_refresh = Instant::now();
+            loop {
+                match entry_receiver.recv_timeout(refresh) {
+                    Ok(entry) => if sender.send(entry).is_err() {
+                        break;
+                    },
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if last_refresh.elapsed() >= refresh {
+                    last_refresh = Instant::now();
+                    if refresh_sock.send_to(&req, &addr).is_err() {
+                        break;
+                    }
+                }
+            }
+            exit.store(true, Ordering::Relaxed);
+        });
+        Ok(receiver)
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use accountant::Accountant;
+    use accountant::{Accountant, MAX_ENTRY_IDS};
     use accountant_skel::AccountantSkel;
+    use entry::next_tick;
+    use event::Event;
+    use hash::hash;
     use historian::Historian;
+    use ledger::Block;
     use mint::Mint;
     use signature::{KeyPair, KeyPairUtil};
+    use std::net::TcpListener;
     use std::io::sink;
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Arc, Mutex};
-    use std::thread::sleep;
+    use std::thread::{sleep, spawn};
     use std::time::Duration;
+    use transaction::test_tx;
 
     // TODO: Figure out why this test sometimes hangs on TravisCI.
     #[test]
@@ -126,4 +487,298 @@ mod tests {
         assert_eq!(acc.get_balance(&bob_pubkey).unwrap().unwrap(), 500);
         exit.store(true, Ordering::Relaxed);
     }
+
+    #[test]
+    fn test_pipelined_requests() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let acc = AccountantStub::new(&addr, UdpSocket::bind("127.0.0.1:0").unwrap());
+        let keys: Vec<_> = (0..4).map(|_| KeyPair::new().pubkey()).collect();
+
+        // A mock server that answers each key's request with the key's index,
+        // after a delay and in reverse order, except the first, which it
+        // never answers. It also sends a response to no request.
+        let server_keys = keys.clone();
+        let t_server = spawn(move || {
+            let mut buf = [0u8; 1024];
+            let mut reqs = vec![];
+            for _ in 0..server_keys.len() {
+                let (size, from) = server.recv_from(&mut buf).unwrap();
+                reqs.push((deserialize(&buf[..size]).unwrap(), from));
+            }
+            sleep(Duration::from_millis(100));
+            let stray = Response::Balance {
+                key: server_keys[0],
+                val: None,
+                req_id: 1000,
+            };
+            let mut rsps = vec![(stray, reqs[0].1)];
+            for (req, from) in reqs.into_iter().skip(1).rev() {
+                if let Request::GetBalance { key, req_id } = req {
+                    let i = server_keys.iter().position(|x| *x == key).unwrap();
+                    let val = Some(i as i64);
+                    rsps.push((Response::Balance { key, val, req_id }, from));
+                }
+            }
+            for (rsp, from) in rsps {
+                server.send_to(&serialize(&rsp).unwrap(), from).unwrap();
+            }
+        });
+
+        let handles: Vec<_> = keys.iter()
+            .map(|key| {
+                let req = Request::GetBalance {
+                    key: *key,
+                    req_id: 0,
+                };
+                acc.submit(req).unwrap()
+            })
+            .collect();
+        for i in 1..keys.len() {
+            assert_matches!(
+                acc.wait(handles[i], Duration::new(5, 0)),
+                Ok(Response::Balance { key, val: Some(val), .. })
+                    if key == keys[i] && val == i as i64
+            );
+        }
+        assert_matches!(
+            acc.wait(handles[0], Duration::from_millis(200)),
+            Err(StubError::Timeout)
+        );
+        assert_matches!(
+            acc.wait(handles[1], Duration::from_millis(200)),
+            Err(StubError::UnknownHandle)
+        );
+        assert_matches!(
+            acc.submit(Request::Transaction(test_tx())),
+            Err(StubError::NoResponse)
+        );
+        t_server.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_fallback() {
+        let addr = "127.0.0.1:9012";
+        let send_addr = "127.0.0.1:9013";
+        let mint = Mint::new(10);
+        let acc = Accountant::new(&mint);
+        let exit = Arc::new(AtomicBool::new(false));
+        let historian = Historian::new(&mint.last_id(), None);
+        let acc = Arc::new(Mutex::new(AccountantSkel::new(
+            acc,
+            mint.last_id(),
+            sink(),
+            historian,
+        )));
+        let _threads = AccountantSkel::serve(&acc, addr, exit.clone()).unwrap();
+        sleep(Duration::from_millis(300));
+
+        let socket = UdpSocket::bind(send_addr).unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let acc = AccountantStub::new(addr, socket);
+
+        // A small request gets the same response over either transport.
+        let req = Request::GetBalance {
+            key: mint.pubkey(),
+            req_id: 0,
+        };
+        let udp_rsp = acc.wait(acc.submit(req.clone()).unwrap(), Duration::new(5, 0))
+            .unwrap();
+        let tcp_rsp = acc.tcp_request(&serialize(&req).unwrap()).unwrap();
+        assert_eq!(serialize(&udp_rsp).unwrap(), serialize(&tcp_rsp).unwrap());
+
+        // Neither this request nor its response fit in a datagram.
+        let num_ids = MAX_BLOB_SEND_SIZE / 16 + 1;
+        let req = Request::CheckIds {
+            ids: vec![mint.last_id(); num_ids],
+            req_id: 0,
+        };
+        let rsp = acc.wait(acc.submit(req).unwrap(), Duration::new(5, 0));
+        assert_matches!(rsp, Ok(Response::ValidIds { ref valid, .. }) if valid.len() == num_ids);
+        exit.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_too_large_response() {
+        // A mock server that answers over UDP that the response is too large,
+        // and then answers the same request over TCP.
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut tcp_addr = server.local_addr().unwrap();
+        tcp_addr.set_port(tcp_addr.port() + 1);
+        let listener = TcpListener::bind(tcp_addr).unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let acc = AccountantStub::new(&addr, socket);
+        let t_server = spawn(move || {
+            let mut buf = [0u8; 1024];
+            let (size, from) = server.recv_from(&mut buf).unwrap();
+            let req_id = match deserialize(&buf[..size]).unwrap() {
+                Request::GetBalance { req_id, .. } => req_id,
+                req => panic!("unexpected request {:?}", req),
+            };
+            let rsp = serialize(&Response::TooLarge { req_id }).unwrap();
+            server.send_to(&rsp, from).unwrap();
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let req = deserialize(&read_frame(&mut stream).unwrap()).unwrap();
+            if let Request::GetBalance { key, req_id: tcp_req_id } = req {
+                assert_eq!(tcp_req_id, req_id);
+                let rsp = Response::Balance {
+                    key,
+                    val: Some(7),
+                    req_id,
+                };
+                write_frame(&mut stream, &serialize(&rsp).unwrap()).unwrap();
+            }
+        });
+        let pubkey = KeyPair::new().pubkey();
+        assert_eq!(acc.get_balance(&pubkey).unwrap(), Some(7));
+        t_server.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_rejections_unexpected_response() {
+        // A mock server that answers with a response to a different request.
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let acc = AccountantStub::new(&addr, socket);
+        let t_server = spawn(move || {
+            let mut buf = [0u8; 1024];
+            let (size, from) = server.recv_from(&mut buf).unwrap();
+            let req_id = match deserialize(&buf[..size]).unwrap() {
+                Request::GetRejections { req_id, .. } => req_id,
+                req => panic!("unexpected request {:?}", req),
+            };
+            let rsp = Response::LastId {
+                id: Hash::default(),
+                req_id,
+            };
+            server.send_to(&serialize(&rsp).unwrap(), from).unwrap();
+        });
+        let err = acc.get_rejections(10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        t_server.join().unwrap();
+    }
+
+    #[test]
+    fn test_subscribe_entries() {
+        let addr = "127.0.0.1:9014";
+        let send_addr = "127.0.0.1:9016";
+        let alice = Mint::new(10_000);
+        let acc = Accountant::new(&alice);
+        let exit = Arc::new(AtomicBool::new(false));
+        let historian = Historian::new(&alice.last_id(), Some(30));
+        let acc = Arc::new(Mutex::new(AccountantSkel::new(
+            acc,
+            alice.last_id(),
+            sink(),
+            historian,
+        )));
+        let _threads = AccountantSkel::serve(&acc, addr, exit.clone()).unwrap();
+        sleep(Duration::from_millis(300));
+
+        let socket = UdpSocket::bind(send_addr).unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let acc = AccountantStub::new(addr, socket);
+
+        // One transfer is recorded before the subscription and one after.
+        let bob_pubkey = KeyPair::new().pubkey();
+        let last_id = acc.get_last_id().unwrap();
+        let sig0 = acc.transfer(500, &alice.keypair(), bob_pubkey, &last_id)
+            .unwrap();
+        assert_eq!(acc.get_balance(&bob_pubkey).unwrap(), Some(500));
+        let entries = acc.subscribe_entries(0).unwrap();
+        sleep(Duration::from_millis(100));
+        let sig1 = acc.transfer(501, &alice.keypair(), bob_pubkey, &last_id)
+            .unwrap();
+
+        let mut received = vec![];
+        let mut sigs = vec![];
+        while sigs.len() < 2 {
+            let entry = entries.recv_timeout(Duration::new(5, 0)).unwrap();
+            for event in &entry.events {
+                if let Event::Transaction(ref tr) = *event {
+                    sigs.push(tr.sig);
+                }
+            }
+            received.push(entry);
+        }
+        assert_eq!(sigs, vec![sig0, sig1]);
+        assert!(received.verify(&alice.last_id()));
+        exit.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_check_ids_fit_in_packet() {
+        let ids = vec![Hash::default(); MAX_CHECK_IDS];
+        let data = serialize(&Request::CheckIds { ids, req_id: 0 }).unwrap();
+        assert!(data.len() <= MAX_REQUEST_SIZE);
+        let data = serialize(&Request::Transaction(test_tx())).unwrap();
+        assert!(data.len() <= MAX_REQUEST_SIZE);
+    }
+
+    #[test]
+    fn test_check_ids() {
+        let addr = "127.0.0.1:9010";
+        let send_addr = "127.0.0.1:9011";
+        let mint = Mint::new(1);
+        let acc = Accountant::new(&mint);
+        let ids: Vec<_> = (0..MAX_ENTRY_IDS)
+            .map(|i| hash(&serialize(&i).unwrap()))
+            .collect();
+        for id in &ids {
+            acc.register_entry_id(id);
+        }
+        let exit = Arc::new(AtomicBool::new(false));
+        let historian = Historian::new(&ids[MAX_ENTRY_IDS - 1], None);
+        let acc = Arc::new(Mutex::new(AccountantSkel::new(
+            acc,
+            ids[MAX_ENTRY_IDS - 1],
+            sink(),
+            historian,
+        )));
+        let _threads = AccountantSkel::serve(&acc, addr, exit.clone()).unwrap();
+        sleep(Duration::from_millis(300));
+
+        let socket = UdpSocket::bind(send_addr).unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let acc = AccountantStub::new(addr, socket);
+
+        // The mint's last id has rotated out of the window, and the
+        // queries span two requests.
+        let unknown = hash(&[1]);
+        let mut queries = vec![mint.last_id(), ids[10], ids[MAX_ENTRY_IDS - 1]];
+        queries.extend(vec![unknown; MAX_CHECK_IDS - 2]);
+        queries.push(ids[MAX_ENTRY_IDS - 3]);
+        let valid = acc.check_ids(&queries).unwrap();
+        let indexes: Vec<_> = valid.iter().map(|x| x.0).collect();
+        assert_eq!(indexes, vec![1, 2, MAX_CHECK_IDS + 1]);
+
+        // Each request also ends with a tick, which may be registered before
+        // the ids are checked.
+        let ages: Vec<_> = valid.iter().map(|x| x.1).collect();
+        assert_eq!(ages[0] - ages[1], MAX_ENTRY_IDS as u64 - 11);
+        assert!(ages[1] <= 1);
+        assert!(ages[2] >= 2 && ages[2] <= 4);
+        exit.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_decode_response_entries() {
+        let entries = vec![next_tick(&Hash::default(), 1)];
+        let data = serialize(&Response::Entries {
+            entries: entries.clone(),
+        }).unwrap();
+        assert_matches!(
+            decode_response(&data),
+            Ok(Response::Entries { entries: ref x }) if *x == entries
+        );
+
+        let mut data = serialize(&Response::Entries { entries: vec![] }).unwrap();
+        LittleEndian::write_u64(&mut data[4..], MAX_RESPONSE_ENTRIES as u64 + 1);
+        assert!(decode_response(&data).is_err());
+    }
 }
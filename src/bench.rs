@@ -0,0 +1,443 @@
+//! The `bench` module provides a load generator that submits transactions
+//! to a node as fast as it can and reports how many of them the node
+//! confirmed, and how quickly.
+
+use accountant::AccountingError;
+use accountant_stub::AccountantStub;
+use hash::Hash;
+use parallel::*;
+use signature::{KeyPair, KeyPairUtil, PublicKey};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::net::UdpSocket;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use transaction::Transaction;
+
+/// How long to wait on a response from the node.
+const READ_TIMEOUT_MS: u64 = 5000;
+
+/// How often balances are sampled while waiting on confirmations.
+const SAMPLE_INTERVAL_MS: u64 = 20;
+
+/// How long the confirmed balances must go unchanged to end a run.
+pub const SETTLE_TIMEOUT_MS: u64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    /// The number of keypairs the mint funds to send the load from. Each
+    /// sends its transactions to a keypair of its own.
+    pub keypairs: usize,
+    /// The number of transactions signed against each `last_id`.
+    pub batch_size: usize,
+    /// How long to keep submitting batches.
+    pub duration: Duration,
+    /// The number of sockets each batch is split across to submit it.
+    pub threads: usize,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            keypairs: 64,
+            batch_size: 1024,
+            duration: Duration::from_secs(10),
+            threads: 4,
+        }
+    }
+}
+
+/// Why a submitted transaction wasn't confirmed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FailureReason {
+    /// The accountant refused the transaction, or would have.
+    Accounting(AccountingError),
+    /// Nothing explains why the transaction wasn't confirmed, such as when
+    /// its packet was dropped.
+    Unconfirmed,
+}
+
+impl From<AccountingError> for FailureReason {
+    fn from(err: AccountingError) -> Self {
+        FailureReason::Accounting(err)
+    }
+}
+
+/// Transactions a second.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Tps {
+    /// Over the time it took to submit the transactions.
+    pub submitted: f64,
+    /// Over the whole run.
+    pub confirmed: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoadReport {
+    /// The transactions sent to the node.
+    pub submitted: u64,
+    /// The submitted transactions the node applied.
+    pub confirmed: u64,
+    /// From the first submission to the last confirmation.
+    pub duration: Duration,
+    pub tps: Tps,
+    /// The submitted transactions that weren't confirmed, by why not, in
+    /// order of reason. Pairs rather than a map, since json keys must be
+    /// strings.
+    pub failed_by_reason: Vec<(FailureReason, u64)>,
+}
+
+impl LoadReport {
+    /// The number of submitted transactions that weren't confirmed.
+    pub fn failed(&self) -> u64 {
+        self.failed_by_reason.iter().map(|x| x.1).sum()
+    }
+}
+
+/// A keypair the load is sent from, and what's been sent from it.
+struct Lane {
+    from: KeyPair,
+    /// The keypairs the lane sends to. A batch signs a single `last_id`, so
+    /// the lane needs one per transaction it sends in a batch for their
+    /// signatures to differ.
+    to: Vec<PublicKey>,
+    funds: i64,
+    sent: u64,
+    /// The transactions sent with a `last_id` the node no longer accepts.
+    sent_expired: u64,
+}
+
+/// The transactions of a batch sent from each lane, by the `last_id` they
+/// were signed against.
+struct Batch {
+    last_id: Hash,
+    sent: Vec<u64>,
+}
+
+pub struct LoadGenerator {
+    config: LoadConfig,
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn new_stub(addr: &str) -> io::Result<AccountantStub> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))?;
+    Ok(AccountantStub::new(addr, socket))
+}
+
+impl LoadGenerator {
+    pub fn new(config: LoadConfig) -> Self {
+        LoadGenerator { config }
+    }
+
+    /// Split the balance of `mint` across new keypairs, and then send one
+    /// token at a time from each to the node at `addr` until the configured
+    /// duration passes. Returns once the node stops confirming them.
+    pub fn run(&self, addr: &str, mint: &KeyPair) -> io::Result<LoadReport> {
+        let stub = new_stub(addr)?;
+        let mut lanes = self.fund_lanes(&stub, mint)?;
+        let stubs = (0..self.config.threads.max(1))
+            .map(|_| new_stub(addr))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let start = Instant::now();
+        let mut batches = vec![];
+        while batches.is_empty() || start.elapsed() < self.config.duration {
+            let prev_id = batches.last().map(|x: &Batch| x.last_id);
+            let batch = match self.send_batch(&stub, &stubs, &lanes, prev_id)? {
+                Some(batch) => batch,
+                None => continue,
+            };
+            for (lane, &sent) in lanes.iter_mut().zip(&batch.sent) {
+                lane.sent += sent;
+            }
+            batches.push(batch);
+        }
+        let submit_time = start.elapsed();
+
+        let confirmed = Self::wait_for_confirmations(&stub, &lanes)?;
+        let duration = start.elapsed();
+
+        let ids: Vec<_> = batches.iter().map(|x| x.last_id).collect();
+        let valid: HashSet<_> = stub.check_ids(&ids)?.into_iter().map(|x| x.0).collect();
+        let expired = batches.iter().enumerate().filter(|x| !valid.contains(&x.0));
+        for (_, batch) in expired {
+            for (lane, &sent) in lanes.iter_mut().zip(&batch.sent) {
+                lane.sent_expired += sent;
+            }
+        }
+
+        let submitted = lanes.iter().map(|x| x.sent).sum();
+        let confirmed_total = confirmed.iter().sum();
+        Ok(LoadReport {
+            submitted,
+            confirmed: confirmed_total,
+            duration,
+            tps: Tps {
+                submitted: submitted as f64 / seconds(submit_time),
+                confirmed: confirmed_total as f64 / seconds(duration),
+            },
+            failed_by_reason: failures(&lanes, &confirmed).into_iter().collect(),
+        })
+    }
+
+    /// Create the lanes, and wait until the node has confirmed their funds.
+    fn fund_lanes(&self, stub: &AccountantStub, mint: &KeyPair) -> io::Result<Vec<Lane>> {
+        let balance = stub.get_balance(&mint.pubkey())?.unwrap_or(0);
+        let num_lanes = self.config.keypairs.max(1);
+        let funds = balance / num_lanes as i64;
+        if funds <= 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the mint can't fund every keypair",
+            ));
+        }
+        let last_id = stub.get_last_id()?;
+        let per_lane = self.config.batch_size.div_ceil(num_lanes).max(1);
+        let lanes: Vec<_> = (0..num_lanes)
+            .map(|_| Lane {
+                from: KeyPair::new(),
+                to: (0..per_lane).map(|_| KeyPair::new().pubkey()).collect(),
+                funds,
+                sent: 0,
+                sent_expired: 0,
+            })
+            .collect();
+        for lane in &lanes {
+            stub.transfer(funds, mint, lane.from.pubkey(), &last_id)?;
+        }
+
+        let start = Instant::now();
+        for lane in &lanes {
+            while stub.get_balance(&lane.from.pubkey())? != Some(funds) {
+                if start.elapsed() > Duration::from_millis(READ_TIMEOUT_MS) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "the node didn't confirm the keypairs' funds",
+                    ));
+                }
+                sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+            }
+        }
+        Ok(lanes)
+    }
+
+    /// Sign a batch against the node's current `last_id` and submit it. A
+    /// batch signed against the same `last_id` as the one before would repeat
+    /// its signatures, so wait a while for the node to move past it first,
+    /// and return `None` without sending anything if it doesn't.
+    fn send_batch(
+        &self,
+        stub: &AccountantStub,
+        stubs: &[AccountantStub],
+        lanes: &[Lane],
+        prev_id: Option<Hash>,
+    ) -> io::Result<Option<Batch>> {
+        let start = Instant::now();
+        let mut last_id = stub.get_last_id()?;
+        while Some(last_id) == prev_id
+            && start.elapsed() < Duration::from_millis(READ_TIMEOUT_MS)
+        {
+            sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+            last_id = stub.get_last_id()?;
+        }
+        if Some(last_id) == prev_id {
+            return Ok(None);
+        }
+        let num_lanes = lanes.len();
+        let transactions: Vec<_> = (0..self.config.batch_size)
+            .into_par_iter()
+            .map(|i| {
+                let lane = &lanes[i % num_lanes];
+                let to = lane.to[(i / num_lanes) % lane.to.len()];
+                (i % num_lanes, Transaction::new(&lane.from, to, 1, last_id))
+            })
+            .collect();
+
+        let chunk_size = transactions.len().div_ceil(stubs.len());
+        let chunk_sent: Vec<Vec<u64>> = transactions
+            .par_chunks(chunk_size.max(1))
+            .zip(stubs)
+            .map(|(chunk, stub)| {
+                let mut sent = vec![0; lanes.len()];
+                for &(lane, ref tr) in chunk {
+                    if stub.transfer_signed(tr.clone()).is_ok() {
+                        sent[lane] += 1;
+                    }
+                }
+                sent
+            })
+            .collect();
+
+        let mut sent = vec![0; lanes.len()];
+        for chunk in chunk_sent {
+            for (x, y) in sent.iter_mut().zip(chunk) {
+                *x += y;
+            }
+        }
+        Ok(Some(Batch { last_id, sent }))
+    }
+
+    /// Sample the balances each lane sends to until none change for
+    /// `SETTLE_TIMEOUT_MS`, and return the transactions confirmed per lane.
+    fn wait_for_confirmations(stub: &AccountantStub, lanes: &[Lane]) -> io::Result<Vec<u64>> {
+        let mut confirmed = vec![0; lanes.len()];
+        let mut last_change = Instant::now();
+        while last_change.elapsed() < Duration::from_millis(SETTLE_TIMEOUT_MS) {
+            let mut changed = false;
+            for (lane, x) in lanes.iter().zip(&mut confirmed) {
+                let mut balance = 0;
+                for to in &lane.to {
+                    balance += stub.get_balance(to)?.unwrap_or(0) as u64;
+                }
+                let balance = balance.min(lane.sent);
+                if balance != *x {
+                    *x = balance;
+                    changed = true;
+                }
+            }
+            if changed {
+                last_change = Instant::now();
+            }
+            if confirmed.iter().zip(lanes).all(|(&x, lane)| x == lane.sent) {
+                break;
+            }
+            sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+        }
+        Ok(confirmed)
+    }
+}
+
+/// Explain the transactions each lane sent that weren't confirmed: those
+/// beyond the lane's funds couldn't be paid for, and of the rest, those
+/// signed against an expired `last_id` may have expired before they arrived.
+fn failures(lanes: &[Lane], confirmed: &[u64]) -> BTreeMap<FailureReason, u64> {
+    let mut reasons = BTreeMap::new();
+    for (lane, &confirmed) in lanes.iter().zip(confirmed) {
+        let mut unconfirmed = lane.sent - confirmed;
+        let unfunded = lane.sent.saturating_sub(lane.funds as u64).min(unconfirmed);
+        unconfirmed -= unfunded;
+        let expired = lane.sent_expired.min(unconfirmed);
+        unconfirmed -= expired;
+        let counts = [
+            (AccountingError::InsufficientFunds.into(), unfunded),
+            (AccountingError::LastIdNotFound.into(), expired),
+            (FailureReason::Unconfirmed, unconfirmed),
+        ];
+        for &(reason, n) in counts.iter().filter(|x| x.1 > 0) {
+            *reasons.entry(reason).or_insert(0) += n;
+        }
+    }
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use accountant::{Accountant, AccountingError};
+    use accountant_skel::AccountantSkel;
+    use bench::{failures, new_stub, FailureReason, Lane, LoadConfig, LoadGenerator, LoadReport};
+    use historian::Historian;
+    use mint::Mint;
+    use serde_json;
+    use signature::{KeyPair, KeyPairUtil};
+    use std::io::sink;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn test_failures() {
+        let lane = |funds, sent, sent_expired| Lane {
+            from: KeyPair::new(),
+            to: vec![KeyPair::new().pubkey()],
+            funds,
+            sent,
+            sent_expired,
+        };
+        let lanes = [lane(10, 15, 0), lane(10, 10, 4), lane(10, 10, 0)];
+        let reasons = failures(&lanes, &[8, 7, 9]);
+        let insufficient_funds = AccountingError::InsufficientFunds.into();
+        assert_eq!(reasons[&insufficient_funds], 5);
+        let last_id_not_found = AccountingError::LastIdNotFound.into();
+        assert_eq!(reasons[&last_id_not_found], 3);
+        assert_eq!(reasons[&FailureReason::Unconfirmed], 3);
+        assert_eq!(reasons.values().sum::<u64>(), 35 - 24);
+    }
+
+    #[test]
+    fn test_send_batch_stale_last_id() {
+        let addr = "127.0.0.1:9034";
+        let alice = Mint::new(1_000);
+        let acc = Accountant::new(&alice);
+        let exit = Arc::new(AtomicBool::new(false));
+        let historian = Historian::new(&alice.last_id(), None);
+        let skel = Arc::new(Mutex::new(AccountantSkel::new(
+            acc,
+            alice.last_id(),
+            sink(),
+            historian,
+        )));
+        let _threads = AccountantSkel::serve(&skel, addr, exit.clone()).unwrap();
+        sleep(Duration::from_millis(300));
+
+        // Without ticks or transactions, the node's last_id never moves.
+        let generator = LoadGenerator::new(LoadConfig::default());
+        let stub = new_stub(addr).unwrap();
+        let lanes = [Lane {
+            from: KeyPair::new(),
+            to: vec![KeyPair::new().pubkey()],
+            funds: 10,
+            sent: 0,
+            sent_expired: 0,
+        }];
+        let last_id = stub.get_last_id().unwrap();
+        let stubs = [new_stub(addr).unwrap()];
+        let batch = generator.send_batch(&stub, &stubs, &lanes, Some(last_id));
+        assert!(batch.unwrap().is_none());
+
+        exit.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_load_generator() {
+        let addr = "127.0.0.1:9020";
+        let alice = Mint::new(1_000_000);
+        let acc = Accountant::new(&alice);
+        let exit = Arc::new(AtomicBool::new(false));
+        let historian = Historian::new(&alice.last_id(), Some(30));
+        let skel = Arc::new(Mutex::new(AccountantSkel::new(
+            acc,
+            alice.last_id(),
+            sink(),
+            historian,
+        )));
+        let _threads = AccountantSkel::serve(&skel, addr, exit.clone()).unwrap();
+        sleep(Duration::from_millis(300));
+
+        let generator = LoadGenerator::new(LoadConfig {
+            keypairs: 4,
+            batch_size: 16,
+            duration: Duration::from_millis(200),
+            threads: 2,
+        });
+        let report = generator.run(addr, &alice.keypair()).unwrap();
+        assert!(report.submitted >= 16);
+        assert!(report.confirmed <= report.submitted);
+        assert!(report.confirmed > 0);
+        assert_eq!(report.confirmed + report.failed(), report.submitted);
+        assert!(report.tps.confirmed > 0.0);
+
+        // The rates needn't survive the round trip to the last bit.
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: LoadReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.submitted, report.submitted);
+        assert_eq!(parsed.confirmed, report.confirmed);
+        assert_eq!(parsed.failed_by_reason, report.failed_by_reason);
+
+        exit.store(true, Ordering::Relaxed);
+    }
+}
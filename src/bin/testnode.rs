@@ -4,22 +4,23 @@ extern crate serde_json;
 extern crate solana;
 
 use getopts::Options;
-use solana::accountant::Accountant;
-use solana::accountant_skel::AccountantSkel;
 use solana::entry::Entry;
-use solana::event::Event;
-use solana::historian::Historian;
+use solana::historian::HistorianConfig;
+use solana::mint::GenesisConfig;
+use solana::node::{Node, NodeConfig};
 use std::env;
 use std::io::{self, stdout, BufRead};
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 fn print_usage(program: &str, opts: Options) {
     let mut brief = format!("Usage: cat <transaction.log> | {} [options]\n\n", program);
     brief += "  Run a Solana node to handle transactions and\n";
     brief += "  write a new transaction log to stdout.\n";
-    brief += "  Takes existing transaction log from stdin.";
+    brief += "  Takes existing transaction log from stdin,\n";
+    brief += "  or starts a new one from a genesis config.\n";
+    brief += "  With a ledger directory that holds a ledger, resumes it instead.";
 
     print!("{}", opts.usage(&brief));
 }
@@ -29,6 +30,8 @@ fn main() {
     let mut port = 8000u16;
     let mut opts = Options::new();
     opts.optopt("p", "", "port", "port");
+    opts.optopt("g", "genesis", "start from a genesis config", "genesis.json");
+    opts.optopt("l", "ledger", "keep the ledger and state in a directory", "dir");
     opts.optflag("h", "help", "print help");
     let args: Vec<String> = env::args().collect();
     let matches = match opts.parse(&args[1..]) {
@@ -47,47 +50,62 @@ fn main() {
         port = matches.opt_str("p").unwrap().parse().expect("port");
     }
     let addr = format!("0.0.0.0:{}", port);
-    let stdin = io::stdin();
-    let mut entries = stdin.lock().lines().map(|line| {
-        serde_json::from_str(&line.unwrap()).unwrap_or_else(|e| {
-            eprintln!("failed to parse json: {}", e);
+    let genesis = matches.opt_str("g").map(|path| {
+        GenesisConfig::load(Path::new(&path)).unwrap_or_else(|e| {
+            eprintln!("failed to load genesis config: {:?}", e);
             exit(1);
         })
     });
-
-    // The first item in the ledger is required to be an entry with zero num_hashes,
-    // which implies its id can be used as the ledger's seed.
-    entries.next().unwrap();
-
-    // The second item in the ledger is a special transaction where the to and from
-    // fields are the same. That entry should be treated as a deposit, not a
-    // transfer to oneself.
-    let entry1: Entry = entries.next().unwrap();
-    let deposit = if let Event::Transaction(ref tr) = entry1.events[0] {
-        tr.data.plan.final_payment()
-    } else {
-        None
+    let historian = match genesis.as_ref().map(GenesisConfig::historian_config) {
+        Some(config) if config.tick_duration.is_some() || config.hashes_per_tick.is_some() => {
+            config
+        }
+        _ => HistorianConfig {
+            tick_duration: Some(Duration::from_millis(1000)),
+            ..HistorianConfig::default()
+        },
+    };
+    let config = NodeConfig {
+        ledger_path: matches.opt_str("l").map(PathBuf::from),
+        historian,
+        fees: genesis.as_ref().and_then(GenesisConfig::fees),
+        ..NodeConfig::default()
+    };
+    let entries: Vec<Entry> = match genesis {
+        _ if config.has_ledger() => vec![],
+        Some(ref config) => {
+            let entries = config.create_entries().unwrap();
+            for entry in &entries {
+                println!("{}", serde_json::to_string(entry).unwrap());
+            }
+            entries
+        }
+        None => {
+            let stdin = io::stdin();
+            let lines = stdin.lock().lines();
+            lines
+                .map(|line| {
+                    serde_json::from_str(&line.unwrap()).unwrap_or_else(|e| {
+                        eprintln!("failed to parse json: {}", e);
+                        exit(1);
+                    })
+                })
+                .collect()
+        }
     };
 
-    let acc = Accountant::new_from_deposit(&deposit.unwrap());
-
-    let mut last_id = entry1.id;
-    for entry in entries {
-        last_id = entry.id;
-        acc.process_verified_events(entry.events).unwrap();
+    // The first item in the ledger is required to be an entry with zero
+    // num_hashes, which implies its id can be used as the ledger's seed. The
+    // second is a special transaction where the to and from fields are the
+    // same, which the node treats as a deposit, not a transfer to oneself.
+    let mut node = Node::new(&config, &entries, stdout()).unwrap_or_else(|e| {
+        eprintln!("failed to start the node: {:?}", e);
+        exit(1);
+    });
+    if let Some(ref resumed) = node.resumed {
+        eprintln!("Resumed the ledger at height {}", resumed.height);
     }
-
-    let historian = Historian::new(&last_id, Some(1000));
-    let exit = Arc::new(AtomicBool::new(false));
-    let skel = Arc::new(Mutex::new(AccountantSkel::new(
-        acc,
-        last_id,
-        stdout(),
-        historian,
-    )));
     eprintln!("Listening on {}", addr);
-    let threads = AccountantSkel::serve(&skel, &addr, exit.clone()).unwrap();
-    for t in threads {
-        t.join().expect("join");
-    }
+    node.serve(&addr).unwrap();
+    node.join().expect("join");
 }
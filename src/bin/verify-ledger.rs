@@ -0,0 +1,89 @@
+//! A command-line executable for checking a ledger file, from its framing
+//! to replaying its events.
+
+extern crate getopts;
+extern crate solana;
+
+use getopts::Options;
+use solana::hash::Hash;
+use solana::hex;
+use solana::ledger::verify_ledger_file;
+use solana::mint::GenesisConfig;
+use std::env;
+use std::path::Path;
+use std::process::exit;
+
+fn print_usage(program: &str, opts: Options) {
+    let mut brief = format!("Usage: {} [options] <ledger>\n\n", program);
+    brief += "  Verify a ledger file and replay its transactions, and print what\n";
+    brief += "  was found. Exits with a nonzero status if the ledger is invalid.";
+
+    print!("{}", opts.usage(&brief));
+}
+
+/// Return the genesis id `arg` spells in hex, or else that of the genesis
+/// config at the path `arg`.
+fn genesis_id(arg: &str) -> Hash {
+    if let Some(bytes) = hex::decode(arg).filter(|x| x.len() == 32) {
+        return Hash::clone_from_slice(&bytes);
+    }
+    let config = GenesisConfig::load(Path::new(arg)).unwrap_or_else(|e| {
+        eprintln!("failed to load genesis config: {:?}", e);
+        exit(1);
+    });
+    let mint = config.mint().unwrap_or_else(|e| {
+        eprintln!("invalid genesis config: {:?}", e);
+        exit(1);
+    });
+    mint.seed()
+}
+
+fn main() {
+    let mut opts = Options::new();
+    opts.optopt(
+        "m",
+        "mint",
+        "require the genesis block of a genesis config, or with a hex id",
+        "genesis.json|ID",
+    );
+    opts.optflag("h", "help", "print help");
+    let args: Vec<String> = env::args().collect();
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+    if matches.opt_present("h") {
+        let program = args[0].clone();
+        print_usage(&program, opts);
+        return;
+    }
+    if matches.free.len() != 1 {
+        let program = args[0].clone();
+        print_usage(&program, opts);
+        exit(1);
+    }
+    let genesis = matches.opt_str("m").map(|arg| genesis_id(&arg));
+
+    let report = verify_ledger_file(Path::new(&matches.free[0]), genesis).unwrap_or_else(|e| {
+        eprintln!("failed to read the ledger: {:?}", e);
+        exit(1);
+    });
+    println!("entries verified: {}", report.height);
+    println!("last id:          {}", hex::encode(&report.last_id));
+    println!("entries replayed: {}", report.replayed_height);
+    if let Some(state_hash) = report.state_hash {
+        println!("state hash:       {}", hex::encode(&state_hash));
+    }
+    if let Some(ref err) = report.chain_error {
+        println!("chain error:      {:?}", err);
+    }
+    if let Some(ref err) = report.replay_error {
+        println!("replay error:     {:?}", err);
+    }
+    if !report.is_valid() {
+        exit(1);
+    }
+}
@@ -53,7 +53,7 @@ fn verify_packet(packet: &Packet) -> u8 {
 
 #[cfg(not(feature = "cuda"))]
 pub fn ed25519_verify(batches: &Vec<SharedPackets>) -> Vec<Vec<u8>> {
-    use rayon::prelude::*;
+    use parallel::*;
 
     batches
         .into_par_iter()
@@ -2,9 +2,13 @@
 //! unique ID that is the hash of the Entry before it, plus the hash of the
 //! transactions within it. Entries cannot be reordered, and its field `num_hashes`
 //! represents an approximate amount of time since the last Entry was created.
+use bincode::{deserialize_from, ErrorKind};
 use event::Event;
 use hash::{extend_and_hash, hash, Hash};
-use rayon::prelude::*;
+use hex;
+use parallel::*;
+use serde::de::DeserializeOwned;
+use std::io::{self, Read};
 
 /// Each Entry contains three pieces of data. The `num_hashes` field is the number
 /// of hashes performed since the previous entry.  The `id` field is the result
@@ -20,6 +24,7 @@ use rayon::prelude::*;
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Entry {
     pub num_hashes: u64,
+    #[serde(with = "hex")]
     pub id: Hash,
     pub events: Vec<Event>,
 }
@@ -38,11 +43,35 @@ impl Entry {
     /// Verifies self.id is the result of hashing a `start_hash` `self.num_hashes` times.
     /// If the event is not a Tick, then hash that as well.
     pub fn verify(&self, start_hash: &Hash) -> bool {
-        self.events.par_iter().all(|event| event.verify())
+        counts_event_hash(self.num_hashes, self.events.len() as u64)
+            && self.events.par_iter().all(|event| event.verify())
             && self.id == next_hash(start_hash, self.num_hashes, &self.events)
     }
 }
 
+pub trait Block {
+    /// Verifies the hashes and counts of a slice of events are all consistent.
+    fn verify(&self, start_hash: &Hash) -> bool;
+}
+
+impl Block for [Entry] {
+    fn verify(&self, start_hash: &Hash) -> bool {
+        let genesis = [Entry::new_tick(0, start_hash)];
+        genesis
+            .par_iter()
+            .chain(self)
+            .zip(self)
+            .all(|(x0, x1)| x1.verify(&x0.id))
+    }
+}
+
+/// Return whether `num_hashes` counts the hash that mixes in an entry's
+/// events, if it has any. Without this, an entry with events would verify
+/// the same with zero hashes as with one.
+pub fn counts_event_hash(num_hashes: u64, num_events: u64) -> bool {
+    num_events == 0 || num_hashes > 0
+}
+
 fn add_event_data(hash_data: &mut Vec<u8>, event: &Event) {
     match *event {
         Event::Transaction(ref tr) => {
@@ -57,6 +86,10 @@ fn add_event_data(hash_data: &mut Vec<u8>, event: &Event) {
             hash_data.push(2u8);
             hash_data.extend_from_slice(sig);
         }
+        Event::TimeSource { ref sig, .. } => {
+            hash_data.push(3u8);
+            hash_data.extend_from_slice(sig);
+        }
     }
 }
 
@@ -64,28 +97,41 @@ fn add_event_data(hash_data: &mut Vec<u8>, event: &Event) {
 /// signature, the final hash will be a hash of both the previous ID and
 /// the signature.
 pub fn next_hash(start_hash: &Hash, num_hashes: u64, events: &[Event]) -> Hash {
-    let mut id = *start_hash;
-    for _ in 1..num_hashes {
-        id = hash(&id);
-    }
-
     // Hash all the event data
     let mut hash_data = vec![];
     for event in events {
         add_event_data(&mut hash_data, event);
     }
 
+    next_hash_with_data(start_hash, num_hashes, &hash_data)
+}
+
+/// Creates the hash `num_hashes` after `start_hash`, where `hash_data` holds
+/// each event's type tag followed by its signature. Mixing in the event data
+/// counts as the last of the `num_hashes` hashes.
+pub fn next_hash_with_data(start_hash: &Hash, num_hashes: u64, hash_data: &[u8]) -> Hash {
+    let mut id = *start_hash;
+    let num_plain_hashes = if hash_data.is_empty() {
+        num_hashes
+    } else {
+        num_hashes.saturating_sub(1)
+    };
+    for _ in 0..num_plain_hashes {
+        id = hash(&id);
+    }
+
     if !hash_data.is_empty() {
-        return extend_and_hash(&id, &hash_data);
+        return extend_and_hash(&id, hash_data);
     }
 
     id
 }
 
-/// Creates the next Entry `num_hashes` after `start_hash`.
+/// Creates the next Entry `cur_hashes` hashes after `start_hash`, and one
+/// more to mix in `events`, if any.
 pub fn create_entry(start_hash: &Hash, cur_hashes: u64, events: Vec<Event>) -> Entry {
     let num_hashes = cur_hashes + if events.is_empty() { 0 } else { 1 };
-    let id = next_hash(start_hash, 0, &events);
+    let id = next_hash(start_hash, num_hashes, &events);
     Entry {
         num_hashes,
         id,
@@ -93,9 +139,16 @@ pub fn create_entry(start_hash: &Hash, cur_hashes: u64, events: Vec<Event>) -> E
     }
 }
 
-/// Creates the next Tick Entry `num_hashes` after `start_hash`.
+/// Creates the next Entry from `start_hash`, which is already `cur_hashes`
+/// hashes after the previous entry's id, as a recorder hashing in place
+/// holds it. Resets both for the entry after.
 pub fn create_entry_mut(start_hash: &mut Hash, cur_hashes: &mut u64, events: Vec<Event>) -> Entry {
-    let entry = create_entry(start_hash, *cur_hashes, events);
+    let num_hashes = *cur_hashes + if events.is_empty() { 0 } else { 1 };
+    let entry = Entry {
+        num_hashes,
+        id: next_hash(start_hash, 0, &events),
+        events,
+    };
     *start_hash = entry.id;
     *cur_hashes = 0;
     entry
@@ -110,9 +163,168 @@ pub fn next_tick(start_hash: &Hash, num_hashes: u64) -> Entry {
     }
 }
 
-#[cfg(test)]
+/// Counts over a run of entries, including the spacing of its ticks.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct EntrySummary {
+    pub num_entries: u64,
+    /// Entries without events.
+    pub num_ticks: u64,
+    pub num_events: u64,
+    pub num_hashes: u64,
+    /// The fewest and most hashes recorded by a single tick.
+    pub min_tick_hashes: Option<u64>,
+    pub max_tick_hashes: Option<u64>,
+}
+
+impl EntrySummary {
+    pub fn new(entries: &[Entry]) -> Self {
+        let mut summary = EntrySummary::default();
+        for entry in entries {
+            summary.num_entries += 1;
+            summary.num_events += entry.events.len() as u64;
+            summary.num_hashes += entry.num_hashes;
+            if entry.events.is_empty() {
+                summary.num_ticks += 1;
+                let hashes = Some(entry.num_hashes);
+                summary.min_tick_hashes = summary.min_tick_hashes.min(hashes).or(hashes);
+                summary.max_tick_hashes = summary.max_tick_hashes.max(hashes);
+            }
+        }
+        summary
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The stream claims `count` entries, more than `max_entries`.
+    TooManyEntries { count: u64, max_entries: usize },
+    /// Reading the next entry would consume more than `max_total_bytes`.
+    TooManyBytes { max_total_bytes: usize },
+    /// The stream ended in the middle of an entry.
+    Truncated,
+    /// The stream doesn't hold a list of entries.
+    Malformed(Box<ErrorKind>),
+}
+
+/// A reader that fails once more than `remaining` bytes are requested.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 && !buf.is_empty() {
+            self.exceeded = true;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "byte limit exceeded"));
+        }
+        let len = buf.len().min(self.remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+/// An iterator over the bincode-serialized `Vec<Entry>` in a reader. It fails
+/// before allocating if the stream claims more than `max_entries` entries or
+/// would take more than `max_total_bytes` bytes to read, and stops after the
+/// first error.
+pub struct EntryDecoder<R> {
+    reader: LimitedReader<R>,
+    max_entries: usize,
+    max_total_bytes: usize,
+    remaining: Option<u64>,
+    failed: bool,
+}
+
+impl<R: Read> EntryDecoder<R> {
+    pub fn new(reader: R, max_entries: usize, max_total_bytes: usize) -> Self {
+        EntryDecoder {
+            reader: LimitedReader {
+                inner: reader,
+                remaining: max_total_bytes,
+                exceeded: false,
+            },
+            max_entries,
+            max_total_bytes,
+            remaining: None,
+            failed: false,
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(&mut self) -> Result<T, DecodeError> {
+        deserialize_from(&mut self.reader).map_err(|err| {
+            if self.reader.exceeded {
+                return DecodeError::TooManyBytes {
+                    max_total_bytes: self.max_total_bytes,
+                };
+            }
+            match *err {
+                ErrorKind::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    DecodeError::Truncated
+                }
+                _ => DecodeError::Malformed(err),
+            }
+        })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Entry>, DecodeError> {
+        let remaining = match self.remaining {
+            Some(remaining) => remaining,
+            None => {
+                let count = self.deserialize()?;
+                if count > self.max_entries as u64 {
+                    return Err(DecodeError::TooManyEntries {
+                        count,
+                        max_entries: self.max_entries,
+                    });
+                }
+                count
+            }
+        };
+        if remaining == 0 {
+            self.remaining = Some(0);
+            return Ok(None);
+        }
+        let entry = self.deserialize()?;
+        self.remaining = Some(remaining - 1);
+        Ok(Some(entry))
+    }
+}
+
+impl<R: Read> Iterator for EntryDecoder<R> {
+    type Item = Result<Entry, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(entry) => entry.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Decode the bincode-serialized `Vec<Entry>` at the start of `bytes`,
+/// failing if it holds more than `max_entries` entries or takes more than
+/// `max_total_bytes` bytes.
+pub fn decode_entries(
+    bytes: &[u8],
+    max_entries: usize,
+    max_total_bytes: usize,
+) -> Result<Vec<Entry>, DecodeError> {
+    EntryDecoder::new(bytes, max_entries, max_total_bytes).collect()
+}
+
+#[cfg(all(test, feature = "native"))]
 mod tests {
     use super::*;
+    use bincode::serialize;
     use chrono::prelude::*;
     use entry::create_entry;
     use event::Event;
@@ -169,4 +381,83 @@ mod tests {
         let zero = Hash::default();
         assert_eq!(next_tick(&zero, 1).num_hashes, 1)
     }
+
+    #[test]
+    fn test_next_tick_hashes() {
+        let zero = Hash::default();
+        assert_eq!(next_tick(&zero, 1).id, hash(&zero));
+        assert_eq!(next_tick(&zero, 2).id, hash(&hash(&zero)));
+
+        // A tick recorded by hashing `num_hashes` times verifies.
+        let mut id = zero;
+        let mut num_hashes = 0;
+        for _ in 0..3 {
+            id = hash(&id);
+            num_hashes += 1;
+        }
+        let mut start = zero;
+        assert!(create_entry_mut(&mut id, &mut num_hashes, vec![]).verify(&start));
+        start = id;
+        assert!(create_entry_mut(&mut id, &mut num_hashes, vec![]).verify(&start));
+    }
+
+    fn test_entries(len: usize) -> Vec<Entry> {
+        let keypair = KeyPair::new();
+        let mut id = Hash::default();
+        (0..len)
+            .map(|i| {
+                let tr = Transaction::new(&keypair, keypair.pubkey(), i as i64, id);
+                let entry = create_entry(&id, 0, vec![Event::Transaction(tr)]);
+                id = entry.id;
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_entries_at_limits() {
+        let entries = test_entries(3);
+        let bytes = serialize(&entries).unwrap();
+        assert_eq!(decode_entries(&bytes, 3, bytes.len()).unwrap(), entries);
+
+        let decoder = EntryDecoder::new(&bytes[..], 3, bytes.len());
+        assert_eq!(decoder.map(|x| x.unwrap()).collect::<Vec<_>>(), entries);
+    }
+
+    #[test]
+    fn test_decode_entries_over_limits() {
+        let entries = test_entries(3);
+        let bytes = serialize(&entries).unwrap();
+        assert_matches!(
+            decode_entries(&bytes, 2, bytes.len()),
+            Err(DecodeError::TooManyEntries {
+                count: 3,
+                max_entries: 2,
+            })
+        );
+        assert_matches!(
+            decode_entries(&bytes, 3, bytes.len() - 1),
+            Err(DecodeError::TooManyBytes {
+                max_total_bytes,
+            }) if max_total_bytes == bytes.len() - 1
+        );
+
+        // A huge claimed length is rejected before anything is allocated.
+        let bytes = serialize(&u64::MAX).unwrap();
+        assert_matches!(
+            decode_entries(&bytes, 1024, 1 << 20),
+            Err(DecodeError::TooManyEntries { .. })
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated_entries() {
+        let entries = test_entries(3);
+        let bytes = serialize(&entries).unwrap();
+        let mut decoder = EntryDecoder::new(&bytes[..bytes.len() - 10], 3, bytes.len());
+        assert_eq!(decoder.next().unwrap().unwrap(), entries[0]);
+        assert_eq!(decoder.next().unwrap().unwrap(), entries[1]);
+        assert_matches!(decoder.next(), Some(Err(DecodeError::Truncated)));
+        assert!(decoder.next().is_none());
+    }
 }
@@ -0,0 +1,218 @@
+//! The `entry_broadcaster` module fans out a stream of entries, such as the
+//! Historian's output, to any number of subscribers. Every subscriber sees
+//! the entries it receives in the same order.
+
+use entry::Entry;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{spawn, JoinHandle};
+
+/// What to do with an entry for a subscriber whose buffer is full.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SlowSubscriberPolicy {
+    /// Skip the entry for that subscriber and count it as dropped.
+    Drop,
+    /// Count the entry as dropped and stop sending to that subscriber.
+    Disconnect,
+}
+
+/// Counts of what happened to the entries sent to one subscriber.
+#[derive(Debug, Default)]
+pub struct SubscriberCounters {
+    delivered: AtomicUsize,
+    dropped: AtomicUsize,
+    disconnected: AtomicBool,
+}
+
+impl SubscriberCounters {
+    pub fn delivered(&self) -> usize {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// True once the broadcaster has stopped sending to the subscriber, either
+    /// by policy or because it dropped its receiver.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+struct Subscriber {
+    sender: Option<SyncSender<Entry>>,
+    counters: Arc<SubscriberCounters>,
+}
+
+impl Subscriber {
+    fn send(&mut self, entry: &Entry, policy: SlowSubscriberPolicy) {
+        let result = match self.sender {
+            Some(ref sender) => sender.try_send(entry.clone()),
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                self.counters.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Full(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                if policy == SlowSubscriberPolicy::Disconnect {
+                    self.disconnect();
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => self.disconnect(),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.sender = None;
+        self.counters.disconnected.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct EntryBroadcaster {
+    receiver: Receiver<Entry>,
+    buffer_size: usize,
+    policy: SlowSubscriberPolicy,
+    subscribers: Vec<Subscriber>,
+}
+
+/// A running EntryBroadcaster. Its thread exits once the entry stream closes.
+pub struct BroadcastHandle {
+    pub thread_hdl: JoinHandle<()>,
+    counters: Vec<Arc<SubscriberCounters>>,
+}
+
+impl BroadcastHandle {
+    /// Return the counters of the subscriber created by the `index`th call to
+    /// `subscribe`.
+    pub fn counters(&self, index: usize) -> Arc<SubscriberCounters> {
+        self.counters[index].clone()
+    }
+}
+
+impl EntryBroadcaster {
+    /// Create a broadcaster of the entries from `receiver` that buffers up to
+    /// `buffer_size` entries for each subscriber, applying `policy` to
+    /// subscribers that fall further behind.
+    pub fn new(
+        receiver: Receiver<Entry>,
+        buffer_size: usize,
+        policy: SlowSubscriberPolicy,
+    ) -> Self {
+        EntryBroadcaster {
+            receiver,
+            buffer_size,
+            policy,
+            subscribers: vec![],
+        }
+    }
+
+    /// Return a new receiver of every entry broadcast after `start`.
+    pub fn subscribe(&mut self) -> Receiver<Entry> {
+        let (sender, receiver) = sync_channel(self.buffer_size);
+        self.subscribers.push(Subscriber {
+            sender: Some(sender),
+            counters: Arc::new(SubscriberCounters::default()),
+        });
+        receiver
+    }
+
+    /// Start broadcasting on a new thread.
+    pub fn start(self) -> BroadcastHandle {
+        let counters = self.subscribers
+            .iter()
+            .map(|x| x.counters.clone())
+            .collect();
+        let EntryBroadcaster {
+            receiver,
+            policy,
+            mut subscribers,
+            ..
+        } = self;
+        let thread_hdl = spawn(move || {
+            for entry in receiver.iter() {
+                for subscriber in &mut subscribers {
+                    subscriber.send(&entry, policy);
+                }
+            }
+        });
+        BroadcastHandle {
+            thread_hdl,
+            counters,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::next_tick;
+    use hash::Hash;
+    use ledger::next_ticks;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_broadcast_slow_subscriber() {
+        let (sender, receiver) = channel();
+        let mut broadcaster = EntryBroadcaster::new(receiver, 4, SlowSubscriberPolicy::Drop);
+        let fast0 = broadcaster.subscribe();
+        let fast1 = broadcaster.subscribe();
+        let slow = broadcaster.subscribe();
+        let handle = broadcaster.start();
+        let counters: Vec<_> = (0..3).map(|i| handle.counters(i)).collect();
+
+        // Pull each entry from the fast subscribers before sending the next.
+        let entries = next_ticks(&Hash::default(), 1, 10);
+        for entry in &entries {
+            sender.send(entry.clone()).unwrap();
+            assert_eq!(fast0.recv().unwrap(), *entry);
+            assert_eq!(fast1.recv().unwrap(), *entry);
+        }
+        drop(sender);
+        handle.thread_hdl.join().unwrap();
+
+        let slow_entries: Vec<_> = slow.iter().collect();
+        assert_eq!(slow_entries, &entries[..4]);
+        for fast in &counters[..2] {
+            assert_eq!(fast.delivered(), 10);
+            assert_eq!(fast.dropped(), 0);
+        }
+        assert_eq!(counters[2].delivered(), 4);
+        assert_eq!(counters[2].dropped(), 6);
+        assert!(!counters[2].is_disconnected());
+    }
+
+    #[test]
+    fn test_broadcast_disconnect_slow_subscriber() {
+        let (sender, receiver) = channel();
+        let mut broadcaster = EntryBroadcaster::new(receiver, 1, SlowSubscriberPolicy::Disconnect);
+        let fast = broadcaster.subscribe();
+        let slow = broadcaster.subscribe();
+        let handle = broadcaster.start();
+        let (fast_counters, slow_counters) = (handle.counters(0), handle.counters(1));
+
+        let entries = next_ticks(&Hash::default(), 1, 3);
+        for entry in &entries {
+            sender.send(entry.clone()).unwrap();
+            assert_eq!(fast.recv().unwrap(), *entry);
+        }
+
+        // The slow subscriber keeps the entry it had buffered.
+        assert_eq!(slow.recv().unwrap(), entries[0]);
+        assert!(slow.recv().is_err());
+        assert!(slow_counters.is_disconnected());
+        assert_eq!(slow_counters.delivered(), 1);
+        assert_eq!(slow_counters.dropped(), 1);
+
+        // A subscriber that drops its receiver is disconnected.
+        drop(fast);
+        sender.send(next_tick(&entries[2].id, 1)).unwrap();
+        drop(sender);
+        handle.thread_hdl.join().unwrap();
+        assert!(fast_counters.is_disconnected());
+        assert_eq!(fast_counters.delivered(), 3);
+    }
+}
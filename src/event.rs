@@ -3,26 +3,46 @@
 
 use bincode::serialize;
 use chrono::prelude::*;
-use signature::{KeyPair, KeyPairUtil, PublicKey, Signature, SignatureUtil};
+use hex;
+#[cfg(feature = "native")]
+use signature::{KeyPair, KeyPairUtil};
+use signature::{PublicKey, Signature, SignatureUtil};
 use transaction::Transaction;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum Event {
     Transaction(Transaction),
     Signature {
+        #[serde(with = "hex")]
         from: PublicKey,
+        #[serde(with = "hex")]
         tx_sig: Signature,
+        #[serde(with = "hex")]
         sig: Signature,
     },
     Timestamp {
+        #[serde(with = "hex")]
         from: PublicKey,
         dt: DateTime<Utc>,
+        #[serde(with = "hex")]
+        sig: Signature,
+    },
+    /// Name `source` the accountant's trusted source of Timestamps. Only
+    /// honored before the accountant trusts any other source, so it belongs
+    /// in the genesis block.
+    TimeSource {
+        #[serde(with = "hex")]
+        from: PublicKey,
+        #[serde(with = "hex")]
+        source: PublicKey,
+        #[serde(with = "hex")]
         sig: Signature,
     },
 }
 
 impl Event {
     /// Create and sign a new Witness Timestamp. Used for unit-testing.
+    #[cfg(feature = "native")]
     pub fn new_timestamp(from: &KeyPair, dt: DateTime<Utc>) -> Self {
         let sign_data = serialize(&dt).unwrap();
         let sig = Signature::clone_from_slice(from.sign(&sign_data).as_ref());
@@ -34,6 +54,7 @@ impl Event {
     }
 
     /// Create and sign a new Witness Signature. Used for unit-testing.
+    #[cfg(feature = "native")]
     pub fn new_signature(from: &KeyPair, tx_sig: Signature) -> Self {
         let sig = Signature::clone_from_slice(from.sign(&tx_sig).as_ref());
         Event::Signature {
@@ -43,6 +64,17 @@ impl Event {
         }
     }
 
+    /// Create and sign a new TimeSource designation.
+    #[cfg(feature = "native")]
+    pub fn new_time_source(from: &KeyPair, source: PublicKey) -> Self {
+        let sig = Signature::clone_from_slice(from.sign(&source).as_ref());
+        Event::TimeSource {
+            from: from.pubkey(),
+            source,
+            sig,
+        }
+    }
+
     /// Verify the Event's signature's are valid and if a transaction, that its
     /// spending plan is valid.
     pub fn verify(&self) -> bool {
@@ -50,11 +82,12 @@ impl Event {
             Event::Transaction(ref tr) => tr.verify_sig(),
             Event::Signature { from, tx_sig, sig } => sig.verify(&from, &tx_sig),
             Event::Timestamp { from, dt, sig } => sig.verify(&from, &serialize(&dt).unwrap()),
+            Event::TimeSource { from, source, sig } => sig.verify(&from, &source),
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "native"))]
 mod tests {
     use super::*;
     use signature::{KeyPair, KeyPairUtil};
@@ -63,5 +96,7 @@ mod tests {
     fn test_event_verify() {
         assert!(Event::new_timestamp(&KeyPair::new(), Utc::now()).verify());
         assert!(Event::new_signature(&KeyPair::new(), Signature::default()).verify());
+        let source = KeyPair::new().pubkey();
+        assert!(Event::new_time_source(&KeyPair::new(), source).verify());
     }
 }
@@ -0,0 +1,232 @@
+//! The `ffi` module exports entry verification to C, as declared in
+//! `include/solana.h`. Build it into a shared library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+//!
+//! Entries cross the boundary bincode-serialized, a single `Entry` for
+//! `solana_entry_decode` and a `Vec<Entry>` for
+//! `solana_entries_verify_chain`, and hashes as pointers to 32 bytes. Every
+//! function returns a negative code on error, including when it panics,
+//! and never unwinds into the caller.
+
+use bincode::{deserialize, serialized_size};
+use entry::{decode_entries, Entry};
+use hash::Hash;
+use ledger::Block;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+pub const SOLANA_OK: i32 = 0;
+/// A pointer argument is null.
+pub const SOLANA_ERR_NULL: i32 = -1;
+/// The bytes don't hold what the function expects.
+pub const SOLANA_ERR_MALFORMED: i32 = -2;
+/// The function panicked.
+pub const SOLANA_ERR_PANIC: i32 = -3;
+
+/// The size of the hashes passed as `start_hash`.
+pub const SOLANA_HASH_SIZE: usize = 32;
+
+/// A decoded entry, owned by the caller until passed to `solana_free`.
+pub struct SolanaEntry(Entry);
+
+/// Run `f`, turning a panic into `SOLANA_ERR_PANIC`.
+fn guard<F: FnOnce() -> i32>(f: F) -> i32 {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(SOLANA_ERR_PANIC)
+}
+
+/// Return the `len` bytes at `ptr`, or None if `ptr` is null. An empty
+/// slice may be passed as any pointer, including null.
+unsafe fn read_bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if ptr.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(ptr, len))
+    }
+}
+
+unsafe fn read_hash(ptr: *const u8) -> Option<Hash> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(Hash::clone_from_slice(slice::from_raw_parts(ptr, SOLANA_HASH_SIZE)))
+}
+
+/// Decode the entry in the `len` bytes at `bytes`, and store a handle to it
+/// in `out_handle`. The bytes must hold exactly one entry.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes, and `out_handle` to a
+/// writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn solana_entry_decode(
+    bytes: *const u8,
+    len: usize,
+    out_handle: *mut *mut SolanaEntry,
+) -> i32 {
+    guard(|| {
+        let data = match read_bytes(bytes, len) {
+            Some(data) if !out_handle.is_null() => data,
+            _ => return SOLANA_ERR_NULL,
+        };
+        let entry: Entry = match deserialize(data) {
+            Ok(entry) => entry,
+            Err(_) => return SOLANA_ERR_MALFORMED,
+        };
+        if serialized_size(&entry).ok() != Some(len as u64) {
+            return SOLANA_ERR_MALFORMED;
+        }
+        *out_handle = Box::into_raw(Box::new(SolanaEntry(entry)));
+        SOLANA_OK
+    })
+}
+
+/// Return 1 if the entry of `handle` follows from the 32 byte hash at
+/// `start_hash`, or 0 if it doesn't.
+///
+/// # Safety
+///
+/// `handle` must come from `solana_entry_decode` and not yet be freed, and
+/// `start_hash` must point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn solana_entry_verify(
+    handle: *const SolanaEntry,
+    start_hash: *const u8,
+) -> i32 {
+    guard(|| match (handle.as_ref(), read_hash(start_hash)) {
+        (Some(entry), Some(start_hash)) => entry.0.verify(&start_hash) as i32,
+        _ => SOLANA_ERR_NULL,
+    })
+}
+
+/// Return 1 if the entries in the `len` bytes at `bytes` each follow from
+/// the one before, and the first from the 32 byte hash at `start_hash`.
+/// Otherwise, return 0 and store the index of the first entry that doesn't
+/// in `out_failed_height`.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes, `start_hash` to 32 readable
+/// bytes, and `out_failed_height` to a writable u64.
+#[no_mangle]
+pub unsafe extern "C" fn solana_entries_verify_chain(
+    bytes: *const u8,
+    len: usize,
+    start_hash: *const u8,
+    out_failed_height: *mut u64,
+) -> i32 {
+    guard(|| {
+        let (data, start_hash) = match (read_bytes(bytes, len), read_hash(start_hash)) {
+            (Some(data), Some(start_hash)) if !out_failed_height.is_null() => (data, start_hash),
+            _ => return SOLANA_ERR_NULL,
+        };
+        // An entry takes more than a byte, so the length bounds the count.
+        let entries = match decode_entries(data, len, len) {
+            Ok(entries) => entries,
+            Err(_) => return SOLANA_ERR_MALFORMED,
+        };
+        if entries.verify(&start_hash) {
+            return 1;
+        }
+        let mut id = start_hash;
+        let failed = entries.iter().position(|entry| {
+            let valid = entry.verify(&id);
+            id = entry.id;
+            !valid
+        });
+        *out_failed_height = failed.unwrap_or(entries.len()) as u64;
+        0
+    })
+}
+
+/// Free a handle from `solana_entry_decode`. Null is ignored.
+///
+/// # Safety
+///
+/// `handle` must be null or come from `solana_entry_decode`, and not be used
+/// again.
+#[no_mangle]
+pub unsafe extern "C" fn solana_free(handle: *mut SolanaEntry) {
+    if !handle.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(handle))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::serialize;
+    use entry::next_tick;
+    use ffi::*;
+    use hash::{hash, Hash};
+    use ledger::next_ticks;
+    use std::ptr;
+
+    #[test]
+    fn test_entry_decode_and_verify() {
+        let zero = Hash::default();
+        let bytes = serialize(&next_tick(&zero, 2)).unwrap();
+        let mut handle = ptr::null_mut();
+        unsafe {
+            assert_eq!(solana_entry_decode(bytes.as_ptr(), bytes.len(), &mut handle), SOLANA_OK);
+            assert_eq!(solana_entry_verify(handle, zero.as_ptr()), 1);
+            assert_eq!(solana_entry_verify(handle, hash(&zero).as_ptr()), 0);
+            assert_eq!(solana_entry_verify(handle, ptr::null()), SOLANA_ERR_NULL);
+            assert_eq!(solana_entry_verify(ptr::null(), zero.as_ptr()), SOLANA_ERR_NULL);
+            solana_free(handle);
+            solana_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_entry_decode_errors() {
+        let bytes = serialize(&next_tick(&Hash::default(), 2)).unwrap();
+        let mut handle = ptr::null_mut();
+        unsafe {
+            let decode = |data: &[u8], out: *mut *mut SolanaEntry| {
+                solana_entry_decode(data.as_ptr(), data.len(), out)
+            };
+            assert_eq!(decode(&bytes[..bytes.len() - 1], &mut handle), SOLANA_ERR_MALFORMED);
+            let mut long = bytes.clone();
+            long.push(0);
+            assert_eq!(decode(&long, &mut handle), SOLANA_ERR_MALFORMED);
+            assert_eq!(decode(&[], &mut handle), SOLANA_ERR_MALFORMED);
+            assert_eq!(decode(&bytes, ptr::null_mut()), SOLANA_ERR_NULL);
+            let null_bytes = solana_entry_decode(ptr::null(), bytes.len(), &mut handle);
+            assert_eq!(null_bytes, SOLANA_ERR_NULL);
+        }
+        assert!(handle.is_null());
+    }
+
+    #[test]
+    fn test_entries_verify_chain() {
+        let zero = Hash::default();
+        let mut entries = next_ticks(&zero, 1, 4);
+        let mut failed = u64::MAX;
+        unsafe {
+            let verify = |entries: &[_], start_hash: &Hash, failed: *mut u64| {
+                let bytes = serialize(entries).unwrap();
+                let len = bytes.len();
+                solana_entries_verify_chain(bytes.as_ptr(), len, start_hash.as_ptr(), failed)
+            };
+            assert_eq!(verify(&entries, &zero, &mut failed), 1);
+            assert_eq!(failed, u64::MAX);
+            assert_eq!(verify(&entries, &hash(&zero), &mut failed), 0);
+            assert_eq!(failed, 0);
+            entries[2].id = zero;
+            assert_eq!(verify(&entries, &zero, &mut failed), 0);
+            assert_eq!(failed, 2);
+
+            assert_eq!(verify(&entries, &zero, ptr::null_mut()), SOLANA_ERR_NULL);
+            let bytes = serialize(&entries).unwrap();
+            let len = bytes.len();
+            let no_hash =
+                solana_entries_verify_chain(bytes.as_ptr(), len, ptr::null(), &mut failed);
+            assert_eq!(no_hash, SOLANA_ERR_NULL);
+            let truncated =
+                solana_entries_verify_chain(bytes.as_ptr(), len - 1, zero.as_ptr(), &mut failed);
+            assert_eq!(truncated, SOLANA_ERR_MALFORMED);
+        }
+    }
+}
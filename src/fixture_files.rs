@@ -0,0 +1,151 @@
+//! The `fixture_files` module reads back the golden fixtures the `fixtures`
+//! module pins, and checks that they verify the way a light client would
+//! check them: from the files alone, without signing anything or opening a
+//! ledger. Unlike `fixtures`, it builds without the `native` feature, so
+//! running it under `--no-default-features` checks the pure Rust signature
+//! backend and the sequential iterators against the same files.
+
+use bincode::deserialize;
+use byteorder::{ByteOrder, LittleEndian};
+use entry::{next_hash, Block, Entry};
+use event::Event;
+use hash::{hash, Hash};
+use hex;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::slice;
+use transaction::verify_signatures;
+
+/// The name of every golden fixture.
+pub const FIXTURE_NAMES: [&str; 6] = [
+    "tick",
+    "transaction",
+    "timestamp",
+    "signature",
+    "chained_ledger",
+    "genesis",
+];
+
+/// A ledger file's magic and seed.
+const HEADER_SIZE: usize = 40;
+/// A frame's entry length and checksum.
+const FRAME_HEADER_SIZE: usize = 16;
+
+/// Return the path of the golden fixture `name` in the crate's `fixtures`
+/// directory.
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(format!("{}.hex", name))
+}
+
+/// Return the bytes of the ledger file the golden fixture `name` dumps.
+pub fn read_fixture(name: &str) -> io::Result<Vec<u8>> {
+    let text = fs::read_to_string(fixture_path(name))?;
+    let mut bytes = vec![];
+    for line in text.lines().filter(|x| !x.starts_with('#')) {
+        let digits: String = line.split_once(": ").map_or("", |x| x.1).split_whitespace().collect();
+        let line_bytes = hex::decode(&digits)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad fixture"))?;
+        bytes.extend(line_bytes);
+    }
+    Ok(bytes)
+}
+
+/// Return the entries of the golden fixture `name`, checking each frame's
+/// checksum along the way.
+pub fn fixture_entries(name: &str) -> Vec<Entry> {
+    let bytes = read_fixture(name).unwrap();
+    let mut frames = &bytes[HEADER_SIZE..];
+    let mut entries = vec![];
+    while !frames.is_empty() {
+        let end = FRAME_HEADER_SIZE + LittleEndian::read_u64(&frames[..8]) as usize;
+        let data = &frames[FRAME_HEADER_SIZE..end];
+        let checksum = LittleEndian::read_u64(&frames[8..FRAME_HEADER_SIZE]);
+        assert_eq!(LittleEndian::read_u64(&hash(data)[..8]), checksum, "{}", name);
+        entries.push(deserialize(data).unwrap());
+        frames = &frames[end..];
+    }
+    entries
+}
+
+/// Return the hash the entries of fixture `name` follow from: the mint's
+/// seed for the genesis block, which starts with a tick of no hashes and so
+/// has the seed for its id, and the zero hash for the rest.
+fn start_hash(name: &str, entries: &[Entry]) -> Hash {
+    if name == "genesis" {
+        entries[0].id
+    } else {
+        Hash::default()
+    }
+}
+
+/// Return the index of the first of `entries` that doesn't follow from the
+/// one before, checking each in turn without any parallel iterator.
+fn first_invalid(entries: &[Entry], start_hash: &Hash) -> Option<usize> {
+    let mut id = *start_hash;
+    entries.iter().position(|entry| {
+        let valid = entry.events.iter().all(|event| event.verify())
+            && entry.id == next_hash(&id, entry.num_hashes, &entry.events);
+        id = entry.id;
+        !valid
+    })
+}
+
+/// Corrupt the signature that vouches for `event`.
+fn corrupt_signature(event: &mut Event) {
+    match *event {
+        Event::Transaction(ref mut tr) => tr.sig[0] ^= 1,
+        Event::Signature { ref mut sig, .. }
+        | Event::Timestamp { ref mut sig, .. }
+        | Event::TimeSource { ref mut sig, .. } => sig[0] ^= 1,
+    }
+}
+
+/// Verification uses rayon only with the `parallel` feature, and ring only
+/// with the `native` feature, so running this under `--no-default-features`
+/// too checks that both builds agree with a plain sequential pass, on each
+/// fixture and on each corruption of it.
+#[test]
+fn test_fixture_verification() {
+    let zero = Hash::default();
+    for &name in &FIXTURE_NAMES {
+        let entries = fixture_entries(name);
+        let start = start_hash(name, &entries);
+        assert_eq!(first_invalid(&entries, &start), None, "{}", name);
+        assert!(entries.verify(&start), "{}", name);
+        let transactions: Vec<_> = entries
+            .iter()
+            .flat_map(|x| &x.events)
+            .filter_map(|event| match event {
+                Event::Transaction(tr) => Some(tr.clone()),
+                _ => None,
+            })
+            .collect();
+        assert!(verify_signatures(&transactions), "{}", name);
+
+        for i in 0..entries.len() {
+            let mut bad_id = entries.clone();
+            bad_id[i].id = zero;
+            assert_eq!(first_invalid(&bad_id, &start), Some(i), "{} {}", name, i);
+            assert!(!bad_id.verify(&start), "{} {}", name, i);
+
+            let mut bad_event = entries.clone();
+            if let Some(Event::Transaction(tr)) = bad_event[i].events.first_mut() {
+                tr.data.tokens += 1;
+                assert!(!verify_signatures(slice::from_ref(tr)));
+                assert_eq!(first_invalid(&bad_event, &start), Some(i));
+                assert!(!bad_event.verify(&start));
+            }
+
+            for j in 0..entries[i].events.len() {
+                let mut bad_sig = entries.clone();
+                corrupt_signature(&mut bad_sig[i].events[j]);
+                assert!(!bad_sig[i].events[j].verify(), "{} {} {}", name, i, j);
+                assert_eq!(first_invalid(&bad_sig, &start), Some(i));
+                assert!(!bad_sig.verify(&start));
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! The `fixtures` module pins the serialized form of entries and ledger files
+//! to golden fixtures kept in the crate's `fixtures` directory, so that any
+//! change to the wire or disk format is caught.
+//!
+//! Every entry here is built from deterministically seeded keys and fixed
+//! dates. A change that alters the fixture bytes on purpose must regenerate
+//! them with `cargo test regen_fixtures -- --ignored` and commit the result.
+
+use chrono::prelude::*;
+use entry::{create_entry, next_tick, Entry};
+use event::Event;
+use fixture_files::{fixture_entries, fixture_path, read_fixture, FIXTURE_NAMES};
+use hash::Hash;
+use hex;
+use ledger::{hex_dump, serialize_frame, serialize_header, LedgerError};
+use mint::GenesisConfig;
+use result::{Error, Result};
+use serde_json;
+use signature::KeyPairUtil;
+use std::fs;
+use std::path::Path;
+use testing::seeded_keypair;
+use transaction::Transaction;
+
+/// Return the bytes of a ledger file holding `entries` and seeded with the
+/// zero hash, which is what golden fixtures pin.
+fn fixture_bytes(entries: &[Entry]) -> Result<Vec<u8>> {
+    let mut bytes = serialize_header(&Hash::default());
+    for entry in entries {
+        bytes.extend(serialize_frame(entry)?);
+    }
+    Ok(bytes)
+}
+
+/// Write the golden fixture `name` as a hex dump of `fixture_bytes(entries)`,
+/// preceded by a comment line with each entry's id.
+fn write_fixture(name: &str, entries: &[Entry]) -> Result<()> {
+    let path = fixture_path(name);
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut text = format!(
+        "# Golden fixture `{}`. Regenerate with `cargo test regen_fixtures -- --ignored`.\n",
+        name
+    );
+    for (i, entry) in entries.iter().enumerate() {
+        text.push_str(&format!("# id {}: {}\n", i, hex::encode(&entry.id)));
+    }
+    text.push_str(&hex_dump(&fixture_bytes(entries)?, 0));
+    text.push('\n');
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Compare `bytes` against the golden fixture `name`, failing with the offset
+/// of the first byte that differs.
+fn check_fixture(name: &str, bytes: &[u8]) -> Result<()> {
+    let expected = read_fixture(name)?;
+    let offset = expected
+        .iter()
+        .zip(bytes)
+        .position(|(x, y)| x != y)
+        .or_else(|| {
+            if expected.len() == bytes.len() {
+                None
+            } else {
+                Some(expected.len().min(bytes.len()))
+            }
+        });
+    match offset {
+        Some(offset) => Err(Error::Ledger(LedgerError::FixtureMismatch(offset as u64))),
+        None => Ok(()),
+    }
+}
+
+fn fixed_date() -> DateTime<Utc> {
+    "2018-04-01T08:09:10Z".parse().unwrap()
+}
+
+fn tick_entries() -> Vec<Entry> {
+    vec![next_tick(&Hash::default(), 3)]
+}
+
+fn transaction_entries() -> Vec<Entry> {
+    let bob = seeded_keypair(2).pubkey();
+    let tx = Transaction::new(&seeded_keypair(1), bob, 42, Hash::default());
+    vec![create_entry(&Hash::default(), 0, vec![Event::Transaction(tx)])]
+}
+
+fn timestamp_entries() -> Vec<Entry> {
+    let event = Event::new_timestamp(&seeded_keypair(1), fixed_date());
+    vec![create_entry(&Hash::default(), 0, vec![event])]
+}
+
+fn signature_entries() -> Vec<Entry> {
+    let alice = seeded_keypair(1);
+    let bob = seeded_keypair(2).pubkey();
+    let tx_sig = Transaction::new(&alice, bob, 42, Hash::default()).sig;
+    let event = Event::new_signature(&alice, tx_sig);
+    vec![create_entry(&Hash::default(), 0, vec![event])]
+}
+
+fn chained_entries() -> Vec<Entry> {
+    let alice = seeded_keypair(1);
+    let bob = seeded_keypair(2).pubkey();
+    let zero = Hash::default();
+    let tx = Transaction::new_on_date(&alice, bob, fixed_date(), 7, zero);
+    let tx_sig = tx.sig;
+    let e0 = create_entry(&zero, 0, vec![Event::Transaction(tx)]);
+    let e1 = next_tick(&e0.id, 2);
+    let e2 = create_entry(&e1.id, 0, vec![Event::new_signature(&alice, tx_sig)]);
+    vec![e0, e1, e2]
+}
+
+fn genesis_config() -> GenesisConfig {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/genesis/valid.json");
+    GenesisConfig::load(&path).unwrap()
+}
+
+/// The genesis block of the `valid` genesis config fixture.
+fn genesis_entries() -> Vec<Entry> {
+    genesis_config().create_entries().unwrap()
+}
+
+/// Every fixture's name and the entries it pins.
+fn fixtures() -> Vec<(&'static str, Vec<Entry>)> {
+    vec![
+        ("tick", tick_entries()),
+        ("transaction", transaction_entries()),
+        ("timestamp", timestamp_entries()),
+        ("signature", signature_entries()),
+        ("chained_ledger", chained_entries()),
+        ("genesis", genesis_entries()),
+    ]
+}
+
+#[test]
+fn test_fixtures() {
+    let names: Vec<_> = fixtures().iter().map(|x| x.0).collect();
+    assert_eq!(names, FIXTURE_NAMES);
+    for (name, entries) in fixtures() {
+        assert_eq!(fixture_entries(name), entries, "{}", name);
+        let bytes = fixture_bytes(&entries).unwrap();
+        if let Err(err) = check_fixture(name, &bytes) {
+            panic!(
+                "fixture `{}` changed ({:?}); if intended, regenerate with \
+                 `cargo test regen_fixtures -- --ignored`",
+                name, err
+            );
+        }
+    }
+}
+
+#[test]
+fn test_chained_fixture_verifies() {
+    let entries = chained_entries();
+    let mut id = Hash::default();
+    for entry in &entries {
+        assert!(entry.verify(&id));
+        id = entry.id;
+    }
+}
+
+/// `fixture_files` takes the genesis block's first id for the mint's seed.
+#[test]
+fn test_genesis_starts_with_seed() {
+    let seed = genesis_config().mint().unwrap().seed();
+    assert_eq!(genesis_entries()[0].id, seed);
+}
+
+#[test]
+fn test_json_round_trip() {
+    for (name, entries) in fixtures() {
+        let json = serde_json::to_string(&entries).unwrap();
+        assert!(json.contains(&hex::encode(&entries[0].id)), "{}", name);
+        let parsed: Vec<Entry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, entries, "{}", name);
+    }
+}
+
+/// JSON written before hashes, keys and signatures were hex strings, with
+/// each as an array of its bytes, still reads.
+#[test]
+fn test_json_from_arrays() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/chained_ledger_arrays.json");
+    let json = fs::read_to_string(path).unwrap();
+    let parsed: Vec<Entry> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, chained_entries());
+}
+
+#[test]
+fn test_check_fixture_mismatch() {
+    let mut bytes = fixture_bytes(&tick_entries()).unwrap();
+    bytes[50] ^= 1;
+    assert_matches!(
+        check_fixture("tick", &bytes),
+        Err(Error::Ledger(LedgerError::FixtureMismatch(50)))
+    );
+    bytes.truncate(50);
+    assert_matches!(
+        check_fixture("tick", &bytes),
+        Err(Error::Ledger(LedgerError::FixtureMismatch(50)))
+    );
+}
+
+#[test]
+#[ignore]
+fn regen_fixtures() {
+    for (name, entries) in fixtures() {
+        write_fixture(name, &entries).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+//! The `hex` module serializes hashes, public keys and signatures for
+//! `#[serde(with = "hex")]`: as a hex string for human-readable formats like
+//! JSON, and as the array's bytes, exactly as without it, for bincode.
+//!
+//! Either form deserializes from human-readable formats, so JSON written
+//! before hashes were strings still reads.
+
+use generic_array::{ArrayLength, GenericArray};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Return the lowercase hex digits of `bytes`.
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Return the bytes of the hex digits in `s`, or None if it holds anything
+/// else or an odd number of them.
+pub fn decode(s: &str) -> Option<Vec<u8>> {
+    // from_str_radix alone would accept a sign, as in "+f".
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+pub fn serialize<N, S>(bytes: &GenericArray<u8, N>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    N: ArrayLength<u8>,
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&encode(bytes))
+    } else {
+        bytes.serialize(serializer)
+    }
+}
+
+pub fn deserialize<'de, N, D>(deserializer: D) -> Result<GenericArray<u8, N>, D::Error>
+where
+    N: ArrayLength<u8>,
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(HexVisitor(PhantomData))
+    } else {
+        GenericArray::deserialize(deserializer)
+    }
+}
+
+struct HexVisitor<N>(PhantomData<N>);
+
+impl<'de, N: ArrayLength<u8>> Visitor<'de> for HexVisitor<N> {
+    type Value = GenericArray<u8, N>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} bytes as hex digits or an array", N::to_usize())
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        match decode(s) {
+            Some(ref bytes) if bytes.len() == N::to_usize() => {
+                Ok(GenericArray::clone_from_slice(bytes))
+            }
+            _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = GenericArray::default();
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(de::Error::invalid_length(N::to_usize() + 1, &self));
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::serialize;
+    use hash::{hash, Hash};
+    use hex;
+    use hex::*;
+    use serde_json;
+    #[cfg(feature = "native")]
+    use signature::{KeyPair, KeyPairUtil, PublicKey};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Hex {
+        #[serde(with = "hex")]
+        id: Hash,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Plain {
+        id: Hash,
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(encode(&[0, 0xab, 0x10]), "00ab10");
+        assert_eq!(decode("00ab10"), Some(vec![0, 0xab, 0x10]));
+        assert_eq!(decode("00AB10"), Some(vec![0, 0xab, 0x10]));
+        assert_eq!(decode("00ab1"), None);
+        assert_eq!(decode("00xb10"), None);
+        assert_eq!(decode("é0"), None);
+        assert_eq!(decode("+f"), None);
+        assert_eq!(decode("00-1"), None);
+    }
+
+    #[test]
+    fn test_bincode_unchanged() {
+        let id = hash(b"hello");
+        assert_eq!(serialize(&Hex { id }).unwrap(), serialize(&Plain { id }).unwrap());
+    }
+
+    #[test]
+    fn test_json_string() {
+        let id = hash(b"hello");
+        let json = serde_json::to_string(&Hex { id }).unwrap();
+        assert_eq!(json, format!("{{\"id\":\"{}\"}}", encode(&id)));
+        assert_eq!(serde_json::from_str::<Hex>(&json).unwrap(), Hex { id });
+    }
+
+    #[test]
+    fn test_json_array() {
+        let id = hash(b"hello");
+        let json = serde_json::to_string(&Plain { id }).unwrap();
+        assert_eq!(serde_json::from_str::<Hex>(&json).unwrap(), Hex { id });
+
+        let short = format!("{{\"id\":\"{}\"}}", encode(&id[1..]));
+        assert!(serde_json::from_str::<Hex>(&short).is_err());
+        let long = json.replace("]", ",0]");
+        assert!(serde_json::from_str::<Hex>(&long).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_public_key() {
+        #[derive(Serialize, Deserialize)]
+        struct Key {
+            #[serde(with = "hex")]
+            key: PublicKey,
+        }
+        let key = KeyPair::new().pubkey();
+        let json = serde_json::to_string(&Key { key }).unwrap();
+        assert!(json.contains(&encode(&key)));
+        assert_eq!(serde_json::from_str::<Key>(&json).unwrap().key, key);
+    }
+}
@@ -3,46 +3,529 @@
 
 use entry::Entry;
 use hash::Hash;
-use recorder::{ExitReason, Recorder, Signal};
-use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-use std::thread::{spawn, JoinHandle};
-use std::time::Instant;
+use ledger::verify_ledger;
+use recorder::{EntryTiming, ExitReason, Recorder, Signal};
+use result;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The number of signals, and of entries, the recorder's channels hold
+/// unless configured otherwise.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+
+/// How often the recorder ticks. With neither field set, it only records an
+/// entry when sent a `Signal::Tick`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct HistorianConfig {
+    /// Tick by wall-clock time, hashing continuously in between.
+    pub tick_duration: Option<Duration>,
+    /// Tick after exactly this many hashes, independent of wall-clock time.
+    pub hashes_per_tick: Option<u64>,
+    /// Capacity of the channels into and out of the recorder, or
+    /// `DEFAULT_QUEUE_CAPACITY`.
+    pub queue_capacity: Option<usize>,
+    /// Record an entry as soon as this many events are pending. Otherwise
+    /// the events between ticks share one entry, unless it would exceed
+    /// `ledger::MAX_ENTRY_SIZE`.
+    pub max_events_per_entry: Option<usize>,
+    /// Watch for the recorder going too long without recording an entry.
+    pub watchdog: Option<WatchdogConfig>,
+    /// Send an EntryTiming to `Historian::timings` for each entry recorded.
+    pub timing: bool,
+}
+
+/// What the watchdog does when the recorder stalls.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StallAction {
+    /// Notify, and have the recorder tick as soon as it resumes. Only allowed
+    /// with `tick_duration`, since an injected tick isn't deterministic.
+    InjectTick,
+    /// Only notify.
+    Notify,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WatchdogConfig {
+    /// How long without a new entry counts as a stall.
+    pub stall_threshold: Duration,
+    pub action: StallAction,
+}
+
+/// Notifications sent by the watchdog on `Historian::health`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Health {
+    /// No entry was recorded for the given duration.
+    Stalled(Duration),
+    /// An entry was recorded after a stall.
+    Resumed,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Both `tick_duration` and `hashes_per_tick` were set.
+    ConflictingTickModes,
+    /// `hashes_per_tick` was zero.
+    ZeroHashesPerTick,
+    /// `queue_capacity` was zero.
+    ZeroQueueCapacity,
+    /// `max_events_per_entry` was zero.
+    ZeroMaxEventsPerEntry,
+    /// A watchdog was configured for a recorder that never ticks on its own.
+    WatchdogWithoutTicks,
+    /// `StallAction::InjectTick` was configured along with `hashes_per_tick`.
+    InjectTickWithHashesPerTick,
+    /// `new_with_clock` was missing `tick_duration` or `hashes_per_tick`, or
+    /// was given a watchdog.
+    VirtualClock,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SendError {
+    /// The recorder's queue is full.
+    Busy,
+    /// The recorder has exited.
+    Disconnected,
+}
+
+/// Counters the recorder thread and senders update without locking.
+#[derive(Debug, Default)]
+pub struct HistorianMetrics {
+    /// Entries recorded without events.
+    pub ticks: AtomicUsize,
+    pub entries: AtomicUsize,
+    /// Events recorded in entries.
+    pub events: AtomicUsize,
+    /// Events refused because the recorder's queue was full.
+    pub events_dropped: AtomicUsize,
+    /// An exponentially weighted average of the recorder's hash rate.
+    pub hashes_per_sec: AtomicUsize,
+    /// Signals sent but not yet received by the recorder.
+    pub queue_depth: AtomicUsize,
+    /// Stalls detected by the watchdog.
+    pub stalls: AtomicUsize,
+    /// Whether the recorder is paused by `Historian::pause`.
+    pub paused: AtomicBool,
+}
+
+/// The values of a HistorianMetrics at one point in time.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct MetricsSnapshot {
+    pub ticks: usize,
+    pub entries: usize,
+    pub events: usize,
+    pub events_dropped: usize,
+    pub hashes_per_sec: usize,
+    pub queue_depth: usize,
+    pub stalls: usize,
+    pub paused: bool,
+}
+
+impl HistorianMetrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            ticks: self.ticks.load(Ordering::Relaxed),
+            entries: self.entries.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+            events_dropped: self.events_dropped.load(Ordering::Relaxed),
+            hashes_per_sec: self.hashes_per_sec.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+            paused: self.paused.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The sending half of the channel into the recorder, which keeps count of
+/// the signals the recorder hasn't received yet.
+#[derive(Clone)]
+pub struct SignalSender {
+    sender: SyncSender<Signal>,
+    metrics: Arc<HistorianMetrics>,
+    capacity: usize,
+}
+
+impl SignalSender {
+    /// Send `signal`, blocking while the queue is full.
+    #[allow(clippy::result_large_err)]
+    pub fn send(&self, signal: Signal) -> Result<(), mpsc::SendError<Signal>> {
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(signal).inspect_err(|_| {
+            self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+
+    /// Send `signal`, returning `SendError::Busy` instead of blocking if the
+    /// queue is full.
+    pub fn try_send(&self, signal: Signal) -> Result<(), SendError> {
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+        self.sender.try_send(signal).map_err(|err| {
+            self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            match err {
+                TrySendError::Full(Signal::Event(_))
+                | TrySendError::Full(Signal::TimedEvent(_)) => {
+                    self.metrics.events_dropped.fetch_add(1, Ordering::Relaxed);
+                    SendError::Busy
+                }
+                TrySendError::Full(_) => SendError::Busy,
+                TrySendError::Disconnected(_) => SendError::Disconnected,
+            }
+        })
+    }
+
+    /// Return the number of signals sent but not yet received by the recorder.
+    pub fn queue_depth(&self) -> usize {
+        self.metrics.queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of signals the queue holds before senders are busy.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExitError {
+    /// The recorder exited before it was stopped.
+    Exited(ExitReason),
+    /// The recorder thread panicked.
+    Panicked,
+}
+
+/// The clock of a Historian created by `new_with_clock`. Advancing it sends
+/// the recorder a signal, so the recorder sees the advance after every
+/// signal sent before it. While a VirtualClock exists, the recorder keeps
+/// running even if every other sender is dropped.
+#[derive(Clone)]
+pub struct VirtualClock {
+    elapsed: Arc<Mutex<Duration>>,
+    sender: SignalSender,
+}
+
+impl VirtualClock {
+    /// Return the virtual time passed since the Historian was created.
+    pub fn now(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Advance the clock by `duration`, recording a tick for each
+    /// `tick_duration` it passes.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+        // If the recorder exited, there's nothing left to tick.
+        let _ = self.sender.send(Signal::Advance(duration));
+    }
+}
+
+/// Where a Historian created by `from_ledger` continues the chain.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResumeInfo {
+    /// The number of entries already in the ledger.
+    pub height: u64,
+    /// The id of the ledger's last entry, which the first new entry chains
+    /// from.
+    pub last_id: Hash,
+    /// False if some entries were trusted from the ledger's checkpoint
+    /// rather than verified.
+    pub verified_fully: bool,
+}
+
+/// A paused Historian's recorder, returned by `Historian::pause`. The
+/// recorder stays paused until the token is resumed or dropped.
+pub struct PausedToken {
+    last_id: Hash,
+    resume: Sender<()>,
+}
+
+impl PausedToken {
+    /// Return the id of the last entry recorded before the pause, which the
+    /// first entry after it chains from.
+    pub fn last_id(&self) -> Hash {
+        self.last_id
+    }
+
+    /// Resume hashing, then record the signals queued during the pause.
+    pub fn resume(self) {
+        // The recorder resumes either way, even if it already exited.
+        let _ = self.resume.send(());
+    }
+}
+
+/// Sets its flag when dropped, including when the recorder thread panics.
+struct ExitFlag(Arc<AtomicBool>);
+
+impl Drop for ExitFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
 
 pub struct Historian {
-    pub sender: SyncSender<Signal>,
+    pub sender: SignalSender,
     pub receiver: Receiver<Entry>,
     pub thread_hdl: JoinHandle<ExitReason>,
+    /// Notifications from the watchdog, if one is configured.
+    pub health: Receiver<Health>,
+    /// The timing of each entry, if `HistorianConfig::timing` is set.
+    pub timings: Receiver<EntryTiming>,
+    watchdog_hdl: Option<JoinHandle<()>>,
 }
 
 impl Historian {
     pub fn new(start_hash: &Hash, ms_per_tick: Option<u64>) -> Self {
-        let (sender, event_receiver) = sync_channel(10_000);
-        let (entry_sender, receiver) = sync_channel(10_000);
-        let thread_hdl =
-            Historian::create_recorder(*start_hash, ms_per_tick, event_receiver, entry_sender);
+        let config = HistorianConfig {
+            tick_duration: ms_per_tick.map(Duration::from_millis),
+            ..HistorianConfig::default()
+        };
+        Historian::spawn(start_hash, config, None)
+    }
+
+    /// Create a Historian that ticks as described by `config`.
+    pub fn new_with_config(
+        start_hash: &Hash,
+        config: HistorianConfig,
+    ) -> Result<Self, ConfigError> {
+        Historian::validate(&config)?;
+        Ok(Historian::spawn(start_hash, config, None))
+    }
+
+    /// Create a Historian whose clock only moves when the returned
+    /// VirtualClock is advanced. `config` must set both `tick_duration` and
+    /// `hashes_per_tick`: each `tick_duration` of virtual time records a tick
+    /// after exactly `hashes_per_tick` hashes, and there is no hashing in
+    /// between.
+    pub fn new_with_clock(
+        start_hash: &Hash,
+        config: HistorianConfig,
+    ) -> Result<(Self, VirtualClock), ConfigError> {
+        let virtual_ticks = match (config.tick_duration, config.hashes_per_tick) {
+            (Some(_), Some(0)) => return Err(ConfigError::ZeroHashesPerTick),
+            (Some(tick_duration), Some(hashes_per_tick)) if config.watchdog.is_none() => {
+                (tick_duration, hashes_per_tick)
+            }
+            _ => return Err(ConfigError::VirtualClock),
+        };
+        let config = HistorianConfig {
+            tick_duration: None,
+            hashes_per_tick: None,
+            ..config
+        };
+        Historian::validate(&config)?;
+        let hist = Historian::spawn(start_hash, config, Some(virtual_ticks));
+        let clock = VirtualClock {
+            elapsed: Arc::new(Mutex::new(Duration::default())),
+            sender: hist.sender.clone(),
+        };
+        Ok((hist, clock))
+    }
+
+    fn validate(config: &HistorianConfig) -> Result<(), ConfigError> {
+        if config.tick_duration.is_some() && config.hashes_per_tick.is_some() {
+            return Err(ConfigError::ConflictingTickModes);
+        }
+        if config.hashes_per_tick == Some(0) {
+            return Err(ConfigError::ZeroHashesPerTick);
+        }
+        if config.queue_capacity == Some(0) {
+            return Err(ConfigError::ZeroQueueCapacity);
+        }
+        if config.max_events_per_entry == Some(0) {
+            return Err(ConfigError::ZeroMaxEventsPerEntry);
+        }
+        if let Some(watchdog) = config.watchdog {
+            if config.tick_duration.is_none() && config.hashes_per_tick.is_none() {
+                return Err(ConfigError::WatchdogWithoutTicks);
+            }
+            if watchdog.action == StallAction::InjectTick && config.hashes_per_tick.is_some() {
+                return Err(ConfigError::InjectTickWithHashesPerTick);
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a Historian that continues the chain ending in `last_id`, such
+    /// as the one returned by `join`.
+    pub fn resume(last_id: &Hash, config: HistorianConfig) -> Result<Self, ConfigError> {
+        Historian::new_with_config(last_id, config)
+    }
+
+    /// Create a Historian that continues the chain of the ledger file at
+    /// `path`, after verifying it with `ledger::verify_ledger`. Refuses to
+    /// resume a ledger that fails to verify.
+    pub fn from_ledger(
+        path: &Path,
+        config: HistorianConfig,
+    ) -> result::Result<(Historian, ResumeInfo)> {
+        let verified = verify_ledger(path)?;
+        let historian = Historian::resume(&verified.last_id, config)?;
+        let info = ResumeInfo {
+            height: verified.height,
+            last_id: verified.last_id,
+            verified_fully: verified.trusted_height == 0,
+        };
+        Ok((historian, info))
+    }
+
+    /// Stop the recorder once it has processed every signal already sent,
+    /// recording any pending events in a final entry. Returns the id of the
+    /// last entry, the number of hashes since that no entry includes, and
+    /// the entries not yet pulled from `receiver`.
+    pub fn join(self) -> Result<(Hash, u64, Vec<Entry>), ExitError> {
+        // Keep pulling entries while the queue is full, as the recorder may
+        // be blocked sending one. If it already exited, the join below
+        // reports why.
+        let mut entries = vec![];
+        while self.sender.try_send(Signal::Stop) == Err(SendError::Busy) {
+            if let Ok(entry) = self.receiver.recv_timeout(Duration::from_millis(1)) {
+                entries.push(entry);
+            }
+        }
+        entries.extend(self.receiver.iter());
+        let result = self.thread_hdl.join();
+        if let Some(watchdog_hdl) = self.watchdog_hdl {
+            watchdog_hdl.join().or(Err(ExitError::Panicked))?;
+        }
+        match result {
+            Ok(ExitReason::Stopped {
+                last_id,
+                num_hashes,
+            }) => Ok((last_id, num_hashes, entries)),
+            Ok(reason) => Err(ExitError::Exited(reason)),
+            Err(_) => Err(ExitError::Panicked),
+        }
+    }
+
+    /// Pause the recorder once it has processed every signal already sent,
+    /// recording any pending events in an entry. While paused, the recorder
+    /// doesn't hash or tick and signals queue up, so `try_send` returns
+    /// `SendError::Busy` once the queue is full. `join` blocks until the
+    /// returned token is resumed or dropped.
+    pub fn pause(&self) -> Result<PausedToken, SendError> {
+        let (paused_sender, paused_receiver) = channel();
+        let (resume, resume_receiver) = channel();
+        self.sender
+            .send(Signal::Pause(paused_sender, resume_receiver))
+            .or(Err(SendError::Disconnected))?;
+        let last_id = paused_receiver.recv().or(Err(SendError::Disconnected))?;
+        Ok(PausedToken { last_id, resume })
+    }
+
+    /// Return the number of signals sent but not yet received by the recorder.
+    pub fn queue_depth(&self) -> usize {
+        self.sender.queue_depth()
+    }
+
+    pub fn metrics(&self) -> Arc<HistorianMetrics> {
+        self.sender.metrics.clone()
+    }
+
+    fn spawn(
+        start_hash: &Hash,
+        config: HistorianConfig,
+        virtual_ticks: Option<(Duration, u64)>,
+    ) -> Self {
+        let capacity = config.queue_capacity.unwrap_or(DEFAULT_QUEUE_CAPACITY);
+        let (sender, event_receiver) = sync_channel(capacity);
+        let (entry_sender, receiver) = sync_channel(capacity);
+        let mut recorder = Recorder::new(event_receiver, entry_sender, *start_hash);
+        recorder.set_max_events_per_entry(config.max_events_per_entry);
+        recorder.set_max_signals(Some(capacity));
+        if let Some((tick_duration, hashes_per_tick)) = virtual_ticks {
+            recorder.set_virtual_clock(tick_duration, hashes_per_tick);
+        }
+        let (timing_sender, timings) = channel();
+        if config.timing {
+            recorder.set_timing(timing_sender);
+        }
+        let sender = SignalSender {
+            sender,
+            metrics: recorder.metrics(),
+            capacity,
+        };
+        let exited = Arc::new(AtomicBool::new(false));
+        let (health_sender, health) = channel();
+        let watchdog_hdl = config.watchdog.map(|watchdog| {
+            Historian::create_watchdog(
+                watchdog,
+                recorder.metrics(),
+                recorder.tick_request(),
+                exited.clone(),
+                health_sender,
+            )
+        });
+        let thread_hdl = Historian::create_recorder(recorder, config, exited);
         Historian {
             sender,
             receiver,
             thread_hdl,
+            health,
+            timings,
+            watchdog_hdl,
         }
     }
 
+    /// A background thread that checks for new entries until the recorder
+    /// exits, acting as `config` describes when there are none for
+    /// `config.stall_threshold`. Each stall is acted on once.
+    fn create_watchdog(
+        config: WatchdogConfig,
+        metrics: Arc<HistorianMetrics>,
+        tick_request: Arc<AtomicBool>,
+        exited: Arc<AtomicBool>,
+        health: Sender<Health>,
+    ) -> JoinHandle<()> {
+        spawn(move || {
+            let poll_interval = (config.stall_threshold / 4).max(Duration::from_millis(1));
+            let mut num_entries = metrics.entries.load(Ordering::Relaxed);
+            let mut last_entry = Instant::now();
+            let mut stalled = false;
+            while !exited.load(Ordering::Relaxed) {
+                sleep(poll_interval);
+                let entries = metrics.entries.load(Ordering::Relaxed);
+                if metrics.paused.load(Ordering::Relaxed) {
+                    // A paused recorder isn't stalled.
+                    last_entry = Instant::now();
+                } else if entries != num_entries {
+                    num_entries = entries;
+                    last_entry = Instant::now();
+                    if stalled {
+                        stalled = false;
+                        let _ = health.send(Health::Resumed);
+                    }
+                } else if !stalled && last_entry.elapsed() > config.stall_threshold {
+                    stalled = true;
+                    metrics.stalls.fetch_add(1, Ordering::Relaxed);
+                    if config.action == StallAction::InjectTick {
+                        tick_request.store(true, Ordering::Relaxed);
+                    }
+                    let _ = health.send(Health::Stalled(last_entry.elapsed()));
+                }
+            }
+        })
+    }
+
     /// A background thread that will continue tagging received Event messages and
     /// sending back Entry messages until either the receiver or sender channel is closed.
     fn create_recorder(
-        start_hash: Hash,
-        ms_per_tick: Option<u64>,
-        receiver: Receiver<Signal>,
-        sender: SyncSender<Entry>,
+        mut recorder: Recorder,
+        config: HistorianConfig,
+        exited: Arc<AtomicBool>,
     ) -> JoinHandle<ExitReason> {
         spawn(move || {
-            let mut recorder = Recorder::new(receiver, sender, start_hash);
+            let _exited = ExitFlag(exited);
             let now = Instant::now();
             loop {
-                if let Err(err) = recorder.process_events(now, ms_per_tick) {
+                if let Err(err) = recorder.process_events(now, config.tick_duration) {
                     return err;
                 }
-                if ms_per_tick.is_some() {
+                if let Some(hashes_per_tick) = config.hashes_per_tick {
+                    if let Err(err) = recorder.hash_and_tick(hashes_per_tick) {
+                        return err;
+                    }
+                } else if config.tick_duration.is_some() {
                     recorder.hash();
                 }
             }
@@ -53,7 +536,17 @@ impl Historian {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ledger::Block;
+    use bincode::serialize;
+    use chrono::prelude::*;
+    use entry::EntrySummary;
+    use event::Event;
+    use ledger::{checkpoint_path, next_ticks, read_ledger, tmp_ledger_path, write_ledger, Block,
+                 LedgerError, LedgerWriter};
+    use recorder::TimedEvent;
+    use result::Error;
+    use std::fs;
+    use serde_json;
+    use signature::{KeyPair, KeyPairUtil};
     use std::thread::sleep;
     use std::time::Duration;
 
@@ -110,4 +603,651 @@ mod tests {
         // Ensure the ID is not the seed.
         assert_ne!(entries[0].id, zero);
     }
+
+    #[test]
+    fn test_historian_hashes_per_tick() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: None,
+            hashes_per_tick: Some(1_000),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        sleep(Duration::from_millis(30));
+        drop(hist.sender);
+        let entries: Vec<Entry> = hist.receiver.iter().collect();
+
+        let summary = EntrySummary::new(&entries);
+        assert!(summary.num_ticks > 0);
+        assert_eq!(summary.num_ticks, summary.num_entries);
+        assert_eq!(summary.min_tick_hashes, Some(1_000));
+        assert_eq!(summary.max_tick_hashes, Some(1_000));
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_tick_duration() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(20)),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        sleep(Duration::from_millis(210));
+        drop(hist.sender);
+        let entries: Vec<Entry> = hist.receiver.iter().collect();
+
+        let summary = EntrySummary::new(&entries);
+        assert!(summary.num_ticks >= 5 && summary.num_ticks <= 11);
+        assert!(summary.min_tick_hashes.unwrap() > 0);
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_bad_config() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(20)),
+            hashes_per_tick: Some(1_000),
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_config(&zero, config).err(),
+            Some(ConfigError::ConflictingTickModes)
+        );
+        let config = HistorianConfig {
+            tick_duration: None,
+            hashes_per_tick: Some(0),
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_config(&zero, config).err(),
+            Some(ConfigError::ZeroHashesPerTick)
+        );
+        let config = HistorianConfig {
+            queue_capacity: Some(0),
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_config(&zero, config).err(),
+            Some(ConfigError::ZeroQueueCapacity)
+        );
+        let config = HistorianConfig {
+            max_events_per_entry: Some(0),
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_config(&zero, config).err(),
+            Some(ConfigError::ZeroMaxEventsPerEntry)
+        );
+        let watchdog = Some(WatchdogConfig {
+            stall_threshold: Duration::from_millis(50),
+            action: StallAction::InjectTick,
+        });
+        let config = HistorianConfig {
+            watchdog,
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_config(&zero, config).err(),
+            Some(ConfigError::WatchdogWithoutTicks)
+        );
+        let config = HistorianConfig {
+            hashes_per_tick: Some(1_000),
+            watchdog,
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_config(&zero, config).err(),
+            Some(ConfigError::InjectTickWithHashesPerTick)
+        );
+    }
+
+    #[test]
+    fn test_historian_flush() {
+        let zero = Hash::default();
+        let hist = Historian::new(&zero, None);
+        let keypair = KeyPair::new();
+        let events: Vec<_> = (0..3)
+            .map(|_| Event::new_timestamp(&keypair, Utc::now()))
+            .collect();
+
+        let (flush_sender, flush_receiver) = channel();
+        hist.sender.send(Signal::Event(events[0].clone())).unwrap();
+        hist.sender.send(Signal::Event(events[1].clone())).unwrap();
+        hist.sender.send(Signal::Flush(flush_sender.clone())).unwrap();
+        hist.sender.send(Signal::Event(events[2].clone())).unwrap();
+        hist.sender.send(Signal::Flush(flush_sender.clone())).unwrap();
+        hist.sender.send(Signal::Flush(flush_sender)).unwrap();
+
+        let id0 = flush_receiver.recv().unwrap().unwrap();
+        let id1 = flush_receiver.recv().unwrap().unwrap();
+        assert_eq!(flush_receiver.recv().unwrap(), None);
+
+        drop(hist.sender);
+        let entries: Vec<Entry> = hist.receiver.iter().collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, id0);
+        assert_eq!(entries[0].events, &events[..2]);
+        assert_eq!(entries[1].id, id1);
+        assert_eq!(entries[1].events, &events[2..]);
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_join_and_resume() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: None,
+            hashes_per_tick: Some(1_000),
+            ..HistorianConfig::default()
+        };
+        let keypair = KeyPair::new();
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        sleep(Duration::from_millis(10));
+        let event0 = Event::new_timestamp(&keypair, Utc::now());
+        hist.sender.send(Signal::Event(event0.clone())).unwrap();
+        let mut entries = vec![hist.receiver.recv().unwrap()];
+        let (last_id, num_hashes, rest) = hist.join().unwrap();
+        entries.extend(rest);
+
+        // The event is recorded even if no tick came after it.
+        assert_eq!(entries.last().unwrap().id, last_id);
+        assert!(entries.iter().any(|x| x.events == vec![event0.clone()]));
+        assert!(num_hashes < 1_000);
+
+        let hist = Historian::resume(&last_id, config).unwrap();
+        sleep(Duration::from_millis(10));
+        let event1 = Event::new_timestamp(&keypair, Utc::now());
+        hist.sender.send(Signal::Event(event1.clone())).unwrap();
+        let (last_id, _, rest) = hist.join().unwrap();
+        assert_eq!(rest.last().unwrap().id, last_id);
+        assert!(rest.iter().any(|x| x.events == vec![event1.clone()]));
+        entries.extend(rest);
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_join_records_pending_events() {
+        let zero = Hash::default();
+        let hist = Historian::new(&zero, None);
+        let event = Event::new_timestamp(&KeyPair::new(), Utc::now());
+        hist.sender.send(Signal::Tick).unwrap();
+        hist.sender.send(Signal::Event(event.clone())).unwrap();
+        let (last_id, num_hashes, entries) = hist.join().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].events, vec![event]);
+        assert_eq!(entries[1].id, last_id);
+        assert_eq!(num_hashes, 0);
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_busy() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            queue_capacity: Some(2),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        let keypair = KeyPair::new();
+
+        // Nothing pulls entries, so the recorder blocks once the entry
+        // channel fills and then the signal queue fills too.
+        let mut accepted = vec![];
+        let mut busy = false;
+        for _ in 0..1_000 {
+            let event = Event::new_timestamp(&keypair, Utc::now());
+            match hist.sender.try_send(Signal::Event(event.clone())) {
+                Ok(()) => accepted.push(event),
+                Err(err) => {
+                    assert_eq!(err, SendError::Busy);
+                    busy = true;
+                    break;
+                }
+            }
+            if hist.sender.try_send(Signal::Tick).is_err() {
+                sleep(Duration::from_millis(1));
+            }
+        }
+        assert!(busy);
+        assert!(hist.queue_depth() <= 2);
+        assert_eq!(hist.metrics().snapshot().events_dropped, 1);
+
+        let (_, _, entries) = hist.join().unwrap();
+        let events: Vec<_> = entries.into_iter().flat_map(|x| x.events).collect();
+        assert_eq!(events, accepted);
+    }
+
+    #[test]
+    fn test_recorder_max_signals() {
+        let (sender, receiver) = sync_channel(8);
+        let (entry_sender, entry_receiver) = sync_channel(8);
+        let mut recorder = Recorder::new(receiver, entry_sender, Hash::default());
+        recorder.set_max_signals(Some(3));
+        for _ in 0..5 {
+            sender.send(Signal::Tick).unwrap();
+        }
+        recorder.process_events(Instant::now(), None).unwrap();
+        assert_eq!(entry_receiver.try_iter().count(), 3);
+        recorder.process_events(Instant::now(), None).unwrap();
+        assert_eq!(entry_receiver.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_historian_batches_events() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(10)),
+            max_events_per_entry: Some(64),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        let keypair = KeyPair::new();
+        let events: Vec<_> = (0..1_000)
+            .map(|_| Event::new_timestamp(&keypair, Utc::now()))
+            .collect();
+        for event in &events {
+            hist.sender.send(Signal::Event(event.clone())).unwrap();
+        }
+        let (_, _, entries) = hist.join().unwrap();
+
+        assert!(entries.len() < 200);
+        assert!(entries.iter().all(|x| x.events.len() <= 64));
+        let recorded: Vec<_> = entries.iter().flat_map(|x| x.events.clone()).collect();
+        assert_eq!(recorded, events);
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_metrics() {
+        let zero = Hash::default();
+        let hist = Historian::new(&zero, None);
+        let metrics = hist.metrics();
+        let keypair = KeyPair::new();
+        for i in 0..15 {
+            let event = Event::new_timestamp(&keypair, Utc::now());
+            hist.sender.send(Signal::Event(event)).unwrap();
+            if i == 9 {
+                hist.sender.send(Signal::Tick).unwrap();
+            }
+        }
+        hist.sender.send(Signal::Tick).unwrap();
+        hist.sender.send(Signal::Tick).unwrap();
+        hist.sender.send(Signal::Tick).unwrap();
+        hist.join().unwrap();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.entries, 4);
+        assert_eq!(snapshot.ticks, 2);
+        assert_eq!(snapshot.events, 15);
+        assert_eq!(snapshot.events_dropped, 0);
+        assert_eq!(snapshot.queue_depth, 0);
+        assert_eq!(snapshot.hashes_per_sec, 0);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<MetricsSnapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_historian_hash_rate() {
+        let zero = Hash::default();
+        let hist = Historian::new(&zero, Some(10));
+        sleep(Duration::from_millis(50));
+        let snapshot = hist.metrics().snapshot();
+        assert!(snapshot.hashes_per_sec > 0);
+        assert!(snapshot.ticks > 0);
+        assert_eq!(snapshot.ticks, snapshot.entries);
+    }
+
+    /// Pull entries from `hist` for `duration`.
+    fn drain_for(hist: &Historian, duration: Duration) -> Vec<Entry> {
+        let start = Instant::now();
+        let mut entries = vec![];
+        while start.elapsed() < duration {
+            if let Ok(entry) = hist.receiver.recv_timeout(Duration::from_millis(1)) {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    #[test]
+    fn test_watchdog_injects_tick() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(5)),
+            queue_capacity: Some(2),
+            watchdog: Some(WatchdogConfig {
+                stall_threshold: Duration::from_millis(50),
+                action: StallAction::InjectTick,
+            }),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        let metrics = hist.metrics();
+
+        // Nothing pulls entries, so the recorder blocks once the entry
+        // channel fills.
+        sleep(Duration::from_millis(300));
+        assert_eq!(metrics.snapshot().stalls, 1);
+        assert_matches!(hist.health.try_recv(), Ok(Health::Stalled(_)));
+        assert!(hist.health.try_recv().is_err());
+
+        let mut entries = drain_for(&hist, Duration::from_millis(200));
+        assert_eq!(hist.health.try_recv(), Ok(Health::Resumed));
+        assert_eq!(metrics.snapshot().stalls, 1);
+
+        // ...and again.
+        sleep(Duration::from_millis(300));
+        assert_eq!(metrics.snapshot().stalls, 2);
+        assert_matches!(hist.health.try_recv(), Ok(Health::Stalled(_)));
+
+        let (_, _, rest) = hist.join().unwrap();
+        entries.extend(rest);
+        assert!(entries.len() > 10);
+    }
+
+    #[test]
+    fn test_watchdog_notifies_deterministic() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            hashes_per_tick: Some(100),
+            queue_capacity: Some(2),
+            watchdog: Some(WatchdogConfig {
+                stall_threshold: Duration::from_millis(50),
+                action: StallAction::Notify,
+            }),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        let mut entries = vec![];
+        for _ in 0..2 {
+            sleep(Duration::from_millis(200));
+            assert_matches!(hist.health.try_recv(), Ok(Health::Stalled(_)));
+            assert!(hist.health.try_recv().is_err());
+            entries.extend(drain_for(&hist, Duration::from_millis(100)));
+            assert_eq!(hist.health.try_recv(), Ok(Health::Resumed));
+        }
+        let metrics = hist.metrics();
+        let (_, _, rest) = hist.join().unwrap();
+        entries.extend(rest);
+        assert_eq!(metrics.snapshot().stalls, 2);
+
+        // No ticks were injected.
+        let summary = EntrySummary::new(&entries);
+        assert_eq!(summary.min_tick_hashes, Some(100));
+        assert_eq!(summary.max_tick_hashes, Some(100));
+        assert!(entries.verify(&zero));
+    }
+
+    #[test]
+    fn test_historian_from_ledger() {
+        let path = tmp_ledger_path("historian_from_ledger");
+        let zero = Hash::default();
+        let keypair = KeyPair::new();
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(5)),
+            ..HistorianConfig::default()
+        };
+
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        hist.sender
+            .send(Signal::Event(Event::new_timestamp(&keypair, Utc::now())))
+            .unwrap();
+        sleep(Duration::from_millis(20));
+        let (_, _, entries) = hist.join().unwrap();
+        write_ledger(&path, &zero, &entries).unwrap();
+
+        let (hist, info) = Historian::from_ledger(&path, config).unwrap();
+        assert_eq!(info.height, entries.len() as u64);
+        assert_eq!(info.last_id, entries.last().unwrap().id);
+        assert!(info.verified_fully);
+        hist.sender
+            .send(Signal::Event(Event::new_timestamp(&keypair, Utc::now())))
+            .unwrap();
+        sleep(Duration::from_millis(20));
+        let (_, _, more) = hist.join().unwrap();
+        LedgerWriter::open(&path)
+            .unwrap()
+            .write_entries(&more)
+            .unwrap();
+
+        let (seed, all) = read_ledger(&path).unwrap();
+        assert_eq!(all.len(), entries.len() + more.len());
+        assert!(all.verify(&seed));
+
+        // The checkpoint left by the first resume is trusted by the next.
+        let (hist, info) = Historian::from_ledger(&path, config).unwrap();
+        assert_eq!(info.height, all.len() as u64);
+        assert!(!info.verified_fully);
+        hist.join().unwrap();
+
+        // A tail that doesn't chain is refused.
+        LedgerWriter::open(&path)
+            .unwrap()
+            .write_entries(&next_ticks(&zero, 1, 1))
+            .unwrap();
+        assert_matches!(
+            Historian::from_ledger(&path, config).err(),
+            Some(Error::Ledger(LedgerError::VerificationFailed(_)))
+        );
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(checkpoint_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_historian_pause() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            hashes_per_tick: Some(100),
+            queue_capacity: Some(4),
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        let keypair = KeyPair::new();
+        let event0 = Event::new_timestamp(&keypair, Utc::now());
+        hist.sender.send(Signal::Event(event0.clone())).unwrap();
+
+        let token = hist.pause().unwrap();
+        assert!(hist.metrics().snapshot().paused);
+        let before: Vec<_> = hist.receiver.try_iter().collect();
+        assert!(before.verify(&zero));
+        let sealed = before.last().unwrap();
+        assert_eq!(sealed.events, vec![event0]);
+        assert_eq!(token.last_id(), sealed.id);
+
+        // Signals queue up to the channel's capacity while paused.
+        let events: Vec<_> = (0..4)
+            .map(|_| Event::new_timestamp(&keypair, Utc::now()))
+            .collect();
+        for event in &events {
+            hist.sender.try_send(Signal::Event(event.clone())).unwrap();
+        }
+        let event = Event::new_timestamp(&keypair, Utc::now());
+        assert_eq!(
+            hist.sender.try_send(Signal::Event(event)),
+            Err(SendError::Busy)
+        );
+        assert_eq!(hist.metrics().snapshot().events_dropped, 1);
+        sleep(Duration::from_millis(20));
+        assert!(hist.receiver.try_recv().is_err());
+
+        let last_id = token.last_id();
+        token.resume();
+        let (_, _, after) = hist.join().unwrap();
+        assert!(after.verify(&last_id));
+        assert_eq!(after[0].events, events);
+    }
+
+    #[test]
+    fn test_historian_drop_paused_token() {
+        let zero = Hash::default();
+        let hist = Historian::new(&zero, None);
+        drop(hist.pause().unwrap());
+        hist.sender.send(Signal::Tick).unwrap();
+        let entry = hist.receiver.recv().unwrap();
+        assert!(entry.verify(&zero));
+        assert!(!hist.metrics().snapshot().paused);
+    }
+
+    /// Wait for the recorder to process every signal sent so far, and return
+    /// the entries it recorded. There must be no pending events.
+    fn settle(hist: &Historian) -> Vec<Entry> {
+        let (sender, receiver) = channel();
+        hist.sender.send(Signal::Flush(sender)).unwrap();
+        assert_eq!(receiver.recv().unwrap(), None);
+        hist.receiver.try_iter().collect()
+    }
+
+    fn virtual_ledger(events: &[Event]) -> Vec<Entry> {
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(10)),
+            hashes_per_tick: Some(50),
+            ..HistorianConfig::default()
+        };
+        let (hist, clock) = Historian::new_with_clock(&Hash::default(), config).unwrap();
+        let mut entries = vec![];
+
+        hist.sender.send(Signal::Event(events[0].clone())).unwrap();
+        hist.sender.send(Signal::Event(events[1].clone())).unwrap();
+        clock.advance(Duration::from_millis(10));
+        let tick = settle(&hist);
+        assert_eq!(tick.len(), 1);
+        assert_eq!(tick[0].events, &events[..2]);
+        assert_eq!(tick[0].num_hashes, 51);
+        entries.extend(tick);
+
+        clock.advance(Duration::from_millis(5));
+        assert!(settle(&hist).is_empty());
+        clock.advance(Duration::from_millis(5));
+        entries.extend(settle(&hist));
+        assert_eq!(entries.len(), 2);
+
+        hist.sender.send(Signal::Event(events[2].clone())).unwrap();
+        clock.advance(Duration::from_millis(30));
+        assert_eq!(clock.now(), Duration::from_millis(50));
+        let (_, num_hashes, rest) = hist.join().unwrap();
+        assert_eq!(num_hashes, 0);
+        assert_eq!(rest.len(), 3);
+        entries.extend(rest);
+        entries
+    }
+
+    #[test]
+    fn test_virtual_clock_is_deterministic() {
+        let keypair = KeyPair::new();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let events: Vec<_> = (0..3)
+            .map(|_| Event::new_timestamp(&keypair, dt))
+            .collect();
+        let entries = virtual_ledger(&events);
+        assert!(entries.verify(&Hash::default()));
+        let summary = EntrySummary::new(&entries);
+        assert_eq!(summary.num_ticks, 3);
+        assert_eq!(summary.min_tick_hashes, Some(50));
+        assert_eq!(summary.max_tick_hashes, Some(50));
+        assert_eq!(
+            serialize(&entries).unwrap(),
+            serialize(&virtual_ledger(&events)).unwrap()
+        );
+    }
+
+    fn timed_ledger(event: &Event, timing: bool) -> (Vec<Entry>, Vec<EntryTiming>) {
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(10)),
+            hashes_per_tick: Some(10),
+            timing,
+            ..HistorianConfig::default()
+        };
+        let (hist, clock) = Historian::new_with_clock(&Hash::default(), config).unwrap();
+        hist.sender.send(Signal::Event(event.clone())).unwrap();
+        clock.advance(Duration::from_millis(4));
+        hist.sender.send(Signal::Event(event.clone())).unwrap();
+        clock.advance(Duration::from_millis(6));
+        clock.advance(Duration::from_millis(10));
+        let entries = settle(&hist);
+        let timings = hist.timings.try_iter().collect();
+        (entries, timings)
+    }
+
+    #[test]
+    fn test_entry_timing() {
+        let event = Event::new_timestamp(&KeyPair::new(), Utc::now());
+        let (entries, timings) = timed_ledger(&event, true);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].events.len(), 2);
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].num_events, 2);
+        assert_eq!(timings[0].max_wait, Duration::from_millis(10));
+        assert_eq!(timings[0].mean_wait, Duration::from_millis(8));
+        assert_eq!(timings[1].num_events, 0);
+        assert_eq!(timings[1].max_wait, Duration::default());
+        assert_eq!(
+            timings[1].sealed_at - timings[0].sealed_at,
+            Duration::from_millis(10)
+        );
+
+        // Timing doesn't change the entries.
+        let (untimed_entries, untimed_timings) = timed_ledger(&event, false);
+        assert_eq!(untimed_entries, entries);
+        assert!(untimed_timings.is_empty());
+    }
+
+    #[test]
+    fn test_timed_event() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            timing: true,
+            ..HistorianConfig::default()
+        };
+        let hist = Historian::new_with_config(&zero, config).unwrap();
+        let keypair = KeyPair::new();
+        let timed = TimedEvent {
+            recv_at: Instant::now() - Duration::from_millis(50),
+            event: Event::new_timestamp(&keypair, Utc::now()),
+        };
+        hist.sender.send(Signal::TimedEvent(timed)).unwrap();
+        hist.sender.send(Signal::Tick).unwrap();
+        assert_eq!(hist.receiver.recv().unwrap().events.len(), 1);
+        let timing = hist.timings.recv().unwrap();
+        assert_eq!(timing.num_events, 1);
+        assert!(timing.max_wait >= Duration::from_millis(50));
+        assert_eq!(timing.mean_wait, timing.max_wait);
+    }
+
+    #[test]
+    fn test_virtual_clock_bad_config() {
+        let zero = Hash::default();
+        let config = HistorianConfig {
+            tick_duration: Some(Duration::from_millis(10)),
+            ..HistorianConfig::default()
+        };
+        assert_eq!(
+            Historian::new_with_clock(&zero, config).err(),
+            Some(ConfigError::VirtualClock)
+        );
+
+        let config = HistorianConfig {
+            hashes_per_tick: Some(0),
+            ..config
+        };
+        assert_eq!(
+            Historian::new_with_clock(&zero, config).err(),
+            Some(ConfigError::ZeroHashesPerTick)
+        );
+
+        let config = HistorianConfig {
+            hashes_per_tick: Some(50),
+            watchdog: Some(WatchdogConfig {
+                stall_threshold: Duration::from_millis(10),
+                action: StallAction::Notify,
+            }),
+            ..config
+        };
+        assert_eq!(
+            Historian::new_with_clock(&zero, config).err(),
+            Some(ConfigError::VirtualClock)
+        );
+    }
 }
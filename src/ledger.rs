@@ -1,21 +1,103 @@
 //! The `ledger` module provides functions for parallel verification of the
-//! Proof of History ledger.
+//! Proof of History ledger, and for storing it on disk.
+//!
+//! A ledger file starts with a header holding the hash that the first entry
+//! verifies against, followed by one frame per entry. Each frame is the
+//! entry's length and checksum (both little-endian u64s) and then the
+//! bincode-serialized entry. A sidecar file next to the ledger records the
+//! last height known to verify, so that restarts don't need to replay the
+//! whole chain, and another, kept by the node, indexes each frame's offset
+//! by height.
 
-use entry::{next_tick, Entry};
-use hash::Hash;
-use rayon::prelude::*;
+use accountant::{Accountant, AccountingError, AuditError};
+use bincode::{deserialize, serialize, serialized_size};
+use byteorder::{ByteOrder, LittleEndian};
+use entry::{counts_event_hash, decode_entries, next_hash_with_data, next_tick, Entry};
+use hash::{hash, Hash};
+use libc;
+use packet::{Blob, BlobRecycler, SharedBlob, MAX_BLOB_SEND_SIZE};
+use parallel::*;
+use result::{Error, Result};
+use signature::{Signature, SignatureUtil};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
 
-pub trait Block {
-    /// Verifies the hashes and counts of a slice of events are all consistent.
-    fn verify(&self, start_hash: &Hash) -> bool;
+pub use entry::Block;
+
+/// The bytes of a blob before its entries: its index.
+const BLOB_INDEX_SIZE: usize = 8;
+
+/// Pack `entries`, in order, into as few blobs as hold them, and append the
+/// blobs to `q`. A blob's data is its index, left for the caller to set,
+/// followed by a bincode-serialized list of entries, and fits in a UDP
+/// datagram.
+pub fn entries_to_blobs(
+    entries: &[Entry],
+    recycler: &BlobRecycler,
+    q: &mut VecDeque<SharedBlob>,
+) -> Result<()> {
+    let max_size = (MAX_BLOB_SEND_SIZE - BLOB_INDEX_SIZE) as u64;
+    let mut start = 0;
+    while start < entries.len() {
+        // A list starts with its length.
+        let mut size = serialized_size(&0u64)?;
+        let mut end = start;
+        while end < entries.len() {
+            let entry_size = serialized_size(&entries[end])?;
+            if size + entry_size > max_size {
+                break;
+            }
+            size += entry_size;
+            end += 1;
+        }
+        if end == start {
+            return Err(Error::Ledger(LedgerError::EntryTooLarge(start as u64)));
+        }
+        let data = serialize(&entries[start..end])?;
+        let blob = recycler.allocate();
+        {
+            let mut b = blob.write().unwrap();
+            b.data_mut()[..data.len()].copy_from_slice(&data);
+            b.meta.size = BLOB_INDEX_SIZE + data.len();
+        }
+        q.push_back(blob);
+        start = end;
+    }
+    Ok(())
+}
+
+/// Return the entries packed into `blobs` by `entries_to_blobs`, in the order
+/// of the blobs.
+pub fn blobs_to_entries(blobs: &VecDeque<SharedBlob>) -> Result<Vec<Entry>> {
+    let mut entries = vec![];
+    for blob in blobs {
+        let b = blob.read().unwrap();
+        if b.meta.size < BLOB_INDEX_SIZE {
+            return Err(Error::Ledger(LedgerError::BadBlob));
+        }
+        // Every entry takes at least a byte, so a blob can't hold more
+        // entries than bytes.
+        let data = &b.data()[..b.meta.size - BLOB_INDEX_SIZE];
+        let blob_entries = decode_entries(data, data.len(), data.len())
+            .map_err(|_| Error::Ledger(LedgerError::BadBlob))?;
+        entries.extend(blob_entries);
+    }
+    Ok(entries)
 }
 
-impl Block for [Entry] {
-    fn verify(&self, start_hash: &Hash) -> bool {
-        let genesis = [Entry::new_tick(0, start_hash)];
-        let entry_pairs = genesis.par_iter().chain(self).zip(self);
-        entry_pairs.all(|(x0, x1)| x1.verify(&x0.id))
+/// The number of entries packed into `blob`, read from the length of its
+/// list of entries without decoding them.
+pub fn num_blob_entries(blob: &Blob) -> Result<u64> {
+    if blob.meta.size < BLOB_INDEX_SIZE + 8 {
+        return Err(Error::Ledger(LedgerError::BadBlob));
     }
+    Ok(LittleEndian::read_u64(&blob.data()[..8]))
 }
 
 /// Create a vector of Ticks of length `len` from `start_hash` hash and `num_hashes`.
@@ -30,10 +112,1730 @@ pub fn next_ticks(start_hash: &Hash, num_hashes: u64, len: usize) -> Vec<Entry>
     ticks
 }
 
+/// Identifies a ledger file. The trailing digit is the format version.
+pub const LEDGER_MAGIC: [u8; 8] = *b"SOLEDGR1";
+/// Magic, followed by the 32 byte hash the first entry verifies against.
+pub const HEADER_SIZE: usize = 40;
+/// Entry length and checksum.
+pub const FRAME_HEADER_SIZE: usize = 16;
+/// Frames claiming to be larger than this are treated as corrupt rather than
+/// allocated.
+pub const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+/// The little-endian u64 offset of a frame in a ledger's index.
+const INDEX_ENTRY_SIZE: u64 = 8;
+
+/// Number of entries verified in parallel at a time when checking a file.
+const VERIFY_CHUNK_SIZE: usize = 1024;
+
+/// Times a follower rereads a complete last frame whose checksum fails, once
+/// per poll, before deciding it's corrupt rather than still being written.
+const MAX_BAD_CHECKSUM_READS: u32 = 8;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LedgerError {
+    /// The file doesn't start with a ledger header.
+    BadHeader,
+    /// The frame at the given byte offset is truncated or fails its checksum.
+    BadFrame(u64),
+    /// The entry at the given height doesn't chain from the one before it.
+    VerificationFailed(u64),
+    /// The ledger has fewer entries than the given height.
+    HeightOutOfRange(u64),
+    /// The bytes differ from a golden fixture at the given offset.
+    FixtureMismatch(u64),
+    /// The entry at the given position of a slice doesn't fit in a blob.
+    EntryTooLarge(u64),
+    /// A blob's data isn't a list of entries.
+    BadBlob,
+    /// The ledger's seed isn't the expected genesis id, but this one.
+    GenesisMismatch(Hash),
+}
+
+/// Return the checksum stored in the header of the frame holding `data`.
+pub fn frame_checksum(data: &[u8]) -> u64 {
+    LittleEndian::read_u64(&hash(data)[..8])
+}
+
+/// Serialize `entry` along with its frame header.
+pub fn serialize_frame(entry: &Entry) -> Result<Vec<u8>> {
+    let data = serialize(entry)?;
+    let mut frame = vec![0u8; FRAME_HEADER_SIZE];
+    LittleEndian::write_u64(&mut frame[..8], data.len() as u64);
+    LittleEndian::write_u64(&mut frame[8..], frame_checksum(&data));
+    frame.extend_from_slice(&data);
+    Ok(frame)
+}
+
+/// Serialize the header of a ledger whose first entry verifies against `seed`.
+pub fn serialize_header(seed: &Hash) -> Vec<u8> {
+    let mut header = LEDGER_MAGIC.to_vec();
+    header.extend_from_slice(seed);
+    header
+}
+
+fn deserialize_header(header: &[u8]) -> Result<Hash> {
+    if header.len() < HEADER_SIZE || header[..8] != LEDGER_MAGIC {
+        return Err(Error::Ledger(LedgerError::BadHeader));
+    }
+    Ok(Hash::clone_from_slice(&header[8..HEADER_SIZE]))
+}
+
+/// Appends entries to a ledger file.
+pub struct LedgerWriter {
+    file: File,
+    seed: Hash,
+    height: u64,
+    offset: u64,
+    last_id: Hash,
+}
+
+impl LedgerWriter {
+    /// Create a new ledger file at `path` whose first entry will verify
+    /// against `seed`. An existing file is overwritten.
+    pub fn create(path: &Path, seed: &Hash) -> Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(&serialize_header(seed))?;
+        Ok(LedgerWriter {
+            file,
+            seed: *seed,
+            height: 0,
+            offset: HEADER_SIZE as u64,
+            last_id: *seed,
+        })
+    }
+
+    /// Open an existing ledger file for appending. Every frame is read to
+    /// find the end of the ledger, so a file with a corrupt frame is refused.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut reader = LedgerReader::open(path)?;
+        let mut last_id = reader.seed();
+        while let Some(entry) = reader.read_entry()? {
+            last_id = entry.id;
+        }
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(reader.offset()))?;
+        Ok(LedgerWriter {
+            file,
+            seed: reader.seed(),
+            height: reader.height(),
+            offset: reader.offset(),
+            last_id,
+        })
+    }
+
+    /// Append `entry` to the ledger. The frame is written with a single
+    /// write so that a concurrent reader sees either all of it or a prefix.
+    pub fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let frame = serialize_frame(entry)?;
+        self.file.write_all(&frame)?;
+        self.height += 1;
+        self.offset += frame.len() as u64;
+        self.last_id = entry.id;
+        Ok(())
+    }
+
+    /// Append each of `entries` to the ledger.
+    pub fn write_entries(&mut self, entries: &[Entry]) -> Result<()> {
+        for entry in entries {
+            self.write_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Flush written entries to disk.
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// The hash the first entry verifies against.
+    pub fn seed(&self) -> Hash {
+        self.seed
+    }
+
+    /// The number of entries in the ledger.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// The byte offset at which the next entry will be written.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The id of the last entry, or the seed if the ledger is empty.
+    pub fn last_id(&self) -> Hash {
+        self.last_id
+    }
+}
+
+/// Reads entries from a ledger file in order.
+pub struct LedgerReader {
+    reader: BufReader<File>,
+    seed: Hash,
+    height: u64,
+    offset: u64,
+}
+
+impl LedgerReader {
+    /// Open the ledger at `path` and read its header.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; HEADER_SIZE];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| Error::Ledger(LedgerError::BadHeader))?;
+        let seed = deserialize_header(&header)?;
+        Ok(LedgerReader {
+            reader,
+            seed,
+            height: 0,
+            offset: HEADER_SIZE as u64,
+        })
+    }
+
+    /// The hash the first entry verifies against.
+    pub fn seed(&self) -> Hash {
+        self.seed
+    }
+
+    /// The number of entries read so far.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// The byte offset of the next frame.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Position the reader at the frame starting at `offset`, which holds the
+    /// entry at `height`.
+    pub fn seek(&mut self, offset: u64, height: u64) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Read the next entry, or None at the end of the ledger.
+    pub fn read_entry(&mut self) -> Result<Option<Entry>> {
+        let bad_frame = Error::Ledger(LedgerError::BadFrame(self.offset));
+        let mut frame_header = [0u8; FRAME_HEADER_SIZE];
+        let mut len = 0;
+        while len < FRAME_HEADER_SIZE {
+            match self.reader.read(&mut frame_header[len..]) {
+                Ok(0) if len == 0 => return Ok(None),
+                Ok(0) => return Err(bad_frame),
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(Error::IO(e)),
+            }
+        }
+        let size = LittleEndian::read_u64(&frame_header[..8]);
+        if size > MAX_ENTRY_SIZE {
+            return Err(bad_frame);
+        }
+        let mut data = vec![0u8; size as usize];
+        if self.reader.read_exact(&mut data).is_err()
+            || frame_checksum(&data) != LittleEndian::read_u64(&frame_header[8..])
+        {
+            return Err(bad_frame);
+        }
+        let entry = deserialize(&data).map_err(|_| bad_frame)?;
+        self.height += 1;
+        self.offset += (FRAME_HEADER_SIZE + data.len()) as u64;
+        Ok(Some(entry))
+    }
+}
+
+impl Iterator for LedgerReader {
+    type Item = Result<Entry>;
+    fn next(&mut self) -> Option<Result<Entry>> {
+        match self.read_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Write `entries` to a new ledger file at `path`.
+pub fn write_ledger(path: &Path, seed: &Hash, entries: &[Entry]) -> Result<()> {
+    let mut writer = LedgerWriter::create(path, seed)?;
+    writer.write_entries(entries)?;
+    writer.sync()
+}
+
+/// Read the seed and all entries of the ledger file at `path`.
+pub fn read_ledger(path: &Path) -> Result<(Hash, Vec<Entry>)> {
+    let reader = LedgerReader::open(path)?;
+    let seed = reader.seed();
+    let entries = reader.collect::<Result<_>>()?;
+    Ok((seed, entries))
+}
+
+/// Return the path of the sidecar file that indexes the frames of the
+/// ledger at `path`.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".index");
+    path.with_file_name(name)
+}
+
+/// The offset of each frame of a ledger file by height, kept in a sidecar
+/// file, so that entries can be read from any height without scanning the
+/// frames before it.
+pub struct LedgerIndex {
+    path: PathBuf,
+    file: File,
+    height: u64,
+}
+
+impl LedgerIndex {
+    /// Open the index of the ledger at `path`, which holds `height` entries.
+    /// An index that's missing or doesn't hold exactly `height` offsets is
+    /// rebuilt.
+    pub fn open(path: &Path, height: u64) -> Result<Self> {
+        let index = index_path(path);
+        match fs::metadata(&index) {
+            Ok(ref metadata) if metadata.len() == height * INDEX_ENTRY_SIZE => Ok(LedgerIndex {
+                path: path.to_path_buf(),
+                file: OpenOptions::new().read(true).append(true).open(&index)?,
+                height,
+            }),
+            _ => LedgerIndex::rebuild(path),
+        }
+    }
+
+    /// Replace the index of the ledger at `path` with one built by reading
+    /// every frame. Fails if any of them are unreadable.
+    pub fn rebuild(path: &Path) -> Result<Self> {
+        let mut data = vec![];
+        let reader = scan_ledger(path, |_, offset, _| {
+            let mut bytes = [0u8; INDEX_ENTRY_SIZE as usize];
+            LittleEndian::write_u64(&mut bytes, offset);
+            data.extend_from_slice(&bytes);
+            Ok(())
+        })?;
+
+        let index = index_path(path);
+        let mut tmp_name = index.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = index.with_file_name(tmp_name);
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_data()?;
+        }
+        fs::rename(&tmp, &index)?;
+        Ok(LedgerIndex {
+            path: path.to_path_buf(),
+            file: OpenOptions::new().read(true).append(true).open(&index)?,
+            height: reader.height(),
+        })
+    }
+
+    /// The number of entries indexed.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Index the entry at `height()`, just appended to the ledger in the
+    /// frame at `offset`.
+    pub fn append(&mut self, offset: u64) -> Result<()> {
+        let mut bytes = [0u8; INDEX_ENTRY_SIZE as usize];
+        LittleEndian::write_u64(&mut bytes, offset);
+        self.file.write_all(&bytes)?;
+        self.height += 1;
+        Ok(())
+    }
+
+    /// Read up to `max_count` entries from `start_height` on, or none if the
+    /// ledger doesn't reach `start_height`.
+    pub fn read_entries(&self, start_height: u64, max_count: usize) -> Result<Vec<Entry>> {
+        if start_height >= self.height {
+            return Ok(vec![]);
+        }
+        let mut bytes = [0u8; INDEX_ENTRY_SIZE as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(start_height * INDEX_ENTRY_SIZE))?;
+        file.read_exact(&mut bytes)?;
+        let mut reader = LedgerReader::open(&self.path)?;
+        reader.seek(LittleEndian::read_u64(&bytes), start_height)?;
+
+        let count = (max_count as u64).min(self.height - start_height);
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            match reader.read_entry()? {
+                Some(entry) => entries.push(entry),
+                // The index outlived frames truncated from the ledger.
+                None => return Err(Error::Ledger(LedgerError::BadFrame(reader.offset()))),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The last verified position in a ledger file.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Checkpoint {
+    /// The number of entries known to verify.
+    pub height: u64,
+    /// The id of the entry at `height - 1`.
+    pub id: Hash,
+    /// The byte offset of the frame holding that entry.
+    pub offset: u64,
+}
+
+/// Return the path of the sidecar file that holds the checkpoint for the
+/// ledger at `path`.
+pub fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".trusted");
+    path.with_file_name(name)
+}
+
+/// Read the checkpoint for the ledger at `path`. A missing or corrupt
+/// sidecar yields None.
+pub fn read_checkpoint(path: &Path) -> Option<Checkpoint> {
+    let mut data = vec![];
+    File::open(checkpoint_path(path))
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .ok()?;
+    if data.len() < 8 {
+        return None;
+    }
+    let (data, checksum) = data.split_at(data.len() - 8);
+    if frame_checksum(data) != LittleEndian::read_u64(checksum) {
+        return None;
+    }
+    deserialize(data).ok()
+}
+
+/// Record `checkpoint` for the ledger at `path`. The sidecar is replaced
+/// atomically so that a crash leaves either the old or new checkpoint.
+pub fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let mut data = serialize(checkpoint)?;
+    let mut checksum = [0u8; 8];
+    LittleEndian::write_u64(&mut checksum, frame_checksum(&data));
+    data.extend_from_slice(&checksum);
+
+    let sidecar = checkpoint_path(path);
+    let mut tmp_name = sidecar.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = sidecar.with_file_name(tmp_name);
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+    }
+    fs::rename(&tmp, &sidecar)?;
+    Ok(())
+}
+
+/// Position `reader` just past the entry recorded in `checkpoint`, if that
+/// entry is where the checkpoint says and carries the recorded id.
+fn seek_checkpoint(reader: &mut LedgerReader, checkpoint: &Checkpoint) -> bool {
+    if checkpoint.height == 0 || checkpoint.offset < HEADER_SIZE as u64 {
+        return false;
+    }
+    if reader
+        .seek(checkpoint.offset, checkpoint.height - 1)
+        .is_err()
+    {
+        return false;
+    }
+    match reader.read_entry() {
+        Ok(Some(entry)) => entry.id == checkpoint.id,
+        _ => false,
+    }
+}
+
+/// The end of a verified ledger.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VerifiedLedger {
+    /// The number of entries in the ledger.
+    pub height: u64,
+    /// The id of the last entry, or the seed if there are none.
+    pub last_id: Hash,
+    /// The number of entries trusted from the checkpoint rather than
+    /// verified.
+    pub trusted_height: u64,
+}
+
+/// Verify the ledger at `path` and return the id of its last entry. If the
+/// sidecar holds a checkpoint that matches the ledger, only entries after
+/// it are verified. Otherwise, the whole ledger is verified. On success, the
+/// checkpoint is advanced to the end of the ledger.
+pub fn verify_from_trusted(path: &Path) -> Result<Hash> {
+    verify_ledger(path).map(|verified| verified.last_id)
+}
+
+/// Verify the ledger at `path` as `verify_from_trusted` does, describing
+/// its end.
+pub fn verify_ledger(path: &Path) -> Result<VerifiedLedger> {
+    let mut reader = LedgerReader::open(path)?;
+    let seed = reader.seed();
+    let mut checkpoint = match read_checkpoint(path) {
+        Some(ref checkpoint) if seek_checkpoint(&mut reader, checkpoint) => checkpoint.clone(),
+        _ => {
+            reader.seek(HEADER_SIZE as u64, 0)?;
+            Checkpoint {
+                height: 0,
+                id: seed,
+                offset: HEADER_SIZE as u64,
+            }
+        }
+    };
+    let trusted_height = checkpoint.height;
+
+    loop {
+        let mut entries = vec![];
+        let mut offset = checkpoint.offset;
+        while entries.len() < VERIFY_CHUNK_SIZE {
+            let next_offset = reader.offset();
+            match reader.read_entry()? {
+                Some(entry) => {
+                    offset = next_offset;
+                    entries.push(entry);
+                }
+                None => break,
+            }
+        }
+        if entries.is_empty() {
+            break;
+        }
+        if !entries.verify(&checkpoint.id) {
+            let height = checkpoint.height + first_invalid(&entries, &checkpoint.id);
+            return Err(Error::Ledger(LedgerError::VerificationFailed(height)));
+        }
+        checkpoint = Checkpoint {
+            height: checkpoint.height + entries.len() as u64,
+            id: entries[entries.len() - 1].id,
+            offset,
+        };
+    }
+
+    if checkpoint.height > trusted_height {
+        write_checkpoint(path, &checkpoint)?;
+    }
+    Ok(VerifiedLedger {
+        height: checkpoint.height,
+        last_id: checkpoint.id,
+        trusted_height,
+    })
+}
+
+/// Return the index of the first entry that doesn't chain from its
+/// predecessor.
+fn first_invalid(entries: &[Entry], start_hash: &Hash) -> u64 {
+    let mut id = *start_hash;
+    for (i, entry) in entries.iter().enumerate() {
+        if !entry.verify(&id) {
+            return i as u64;
+        }
+        id = entry.id;
+    }
+    entries.len() as u64
+}
+
+/// Why the events of a valid chain don't replay.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The ledger doesn't start with a seed entry and a deposit.
+    BadGenesis,
+    /// The accountant rejected the event at index `event` of the entry at
+    /// `height`.
+    Rejected {
+        height: u64,
+        event: usize,
+        error: AccountingError,
+    },
+    /// The accountant ends up without the tokens the genesis block deposits.
+    Audit(AuditError),
+}
+
+/// What `verify_ledger_file` found.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The number of entries whose frames and ids were checked and found
+    /// valid.
+    pub height: u64,
+    /// The id of the last of those entries, or the seed if there are none.
+    pub last_id: Hash,
+    /// The number of entries the accountant replayed without error.
+    pub replayed_height: u64,
+    /// The hash of the accountant's snapshot after replaying the whole
+    /// ledger, if it's valid.
+    pub state_hash: Option<Hash>,
+    /// Why the chain stops at `height`, if it does before the end of the
+    /// file: the file is corrupt, or doesn't start from the expected genesis.
+    pub chain_error: Option<LedgerError>,
+    /// Why replay stops at `replayed_height`, if it does. The chain up to
+    /// `height` is intact either way.
+    pub replay_error: Option<ReplayError>,
+}
+
+impl VerifyReport {
+    pub fn is_valid(&self) -> bool {
+        self.chain_error.is_none() && self.replay_error.is_none()
+    }
+}
+
+/// Replay `entries`, the first of which is at `height`, with `acc` and the
+/// supply it must hold. The accountant is created from the genesis block if
+/// there isn't one yet.
+fn replay_chunk(
+    acc: &mut Option<(Accountant, i64)>,
+    height: u64,
+    entries: &[Entry],
+) -> ::std::result::Result<(), ReplayError> {
+    let mut skip = 0;
+    if acc.is_none() {
+        let genesis = Accountant::new_from_genesis(entries).ok_or(ReplayError::BadGenesis)?;
+        let supply = genesis.total_supply();
+        *acc = Some((genesis, supply));
+        skip = 2;
+    }
+    let acc = &acc.as_ref().unwrap().0;
+    for (i, entry) in entries.iter().enumerate().skip(skip) {
+        let results = acc.process_verified_entries(::std::slice::from_ref(entry));
+        let rejected = results[0].iter().position(|x| x.is_err());
+        if let Some(event) = rejected {
+            let error = results.into_iter().next().unwrap().remove(event).unwrap_err();
+            return Err(ReplayError::Rejected {
+                height: height + i as u64,
+                event,
+                error,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check every frame of the ledger at `path`, verify that its entries chain
+/// from one another, in parallel, and from `expected_genesis` if given,
+/// which is the id of the first entry of the genesis block, and replay
+/// their events with an accountant, auditing its supply at the end.
+/// The chain is verified to its end even if replay fails part way. Only
+/// fails if the file can't be read.
+pub fn verify_ledger_file(path: &Path, expected_genesis: Option<Hash>) -> Result<VerifyReport> {
+    let mut report = VerifyReport {
+        height: 0,
+        last_id: Hash::default(),
+        replayed_height: 0,
+        state_hash: None,
+        chain_error: None,
+        replay_error: None,
+    };
+    let mut reader = match LedgerReader::open(path) {
+        Ok(reader) => reader,
+        Err(Error::Ledger(err)) => {
+            report.chain_error = Some(err);
+            return Ok(report);
+        }
+        Err(err) => return Err(err),
+    };
+    report.last_id = reader.seed();
+    if let Some(expected) = expected_genesis {
+        if expected != reader.seed() {
+            report.chain_error = Some(LedgerError::GenesisMismatch(reader.seed()));
+            return Ok(report);
+        }
+    }
+
+    let mut acc = None;
+    while report.chain_error.is_none() {
+        let mut entries = vec![];
+        while entries.len() < VERIFY_CHUNK_SIZE {
+            match reader.read_entry() {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => break,
+                Err(Error::Ledger(err)) => {
+                    report.chain_error = Some(err);
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if !entries.verify(&report.last_id) {
+            let valid = first_invalid(&entries, &report.last_id);
+            entries.truncate(valid as usize);
+            let height = report.height + valid;
+            report.chain_error = Some(LedgerError::VerificationFailed(height));
+        }
+        if entries.is_empty() {
+            break;
+        }
+
+        if report.replay_error.is_none() {
+            match replay_chunk(&mut acc, report.height, &entries) {
+                Ok(()) => report.replayed_height = report.height + entries.len() as u64,
+                Err(err) => {
+                    if let ReplayError::Rejected { height, .. } = err {
+                        report.replayed_height = height;
+                    }
+                    report.replay_error = Some(err);
+                }
+            }
+        }
+        report.height += entries.len() as u64;
+        report.last_id = entries[entries.len() - 1].id;
+    }
+
+    if report.replay_error.is_none() {
+        match acc {
+            None => report.replay_error = Some(ReplayError::BadGenesis),
+            Some((ref acc, supply)) => match acc.audit(supply) {
+                Err(err) => report.replay_error = Some(ReplayError::Audit(err)),
+                Ok(()) if report.chain_error.is_none() => {
+                    report.state_hash = Some(acc.snapshot_hash());
+                }
+                Ok(()) => {}
+            },
+        }
+    }
+    Ok(report)
+}
+
+/// Reads fields of a bincode-serialized value in place. Every multi-byte
+/// integer is read from the byte slice as little-endian, so values need no
+/// particular alignment within the file.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Cursor { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return None;
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(LittleEndian::read_u32)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes(8).map(LittleEndian::read_u64)
+    }
+
+    /// A fixed-size array, which bincode prefixes with its length.
+    fn array(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.u64()? != len as u64 {
+            return None;
+        }
+        self.bytes(len)
+    }
+
+    /// A string, returned along with its length prefix.
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let start = self.pos;
+        let len = self.u64()?;
+        if len > (self.data.len() - self.pos) as u64 {
+            return None;
+        }
+        self.bytes(len as usize)?;
+        Some(&self.data[start..self.pos])
+    }
+
+    fn condition(&mut self) -> Option<()> {
+        match self.u32()? {
+            0 => self.string().map(|_| ()),
+            1 => self.array(32).map(|_| ()),
+            _ => None,
+        }
+    }
+
+    fn payment(&mut self) -> Option<()> {
+        self.u64()?;
+        self.array(32).map(|_| ())
+    }
+
+    fn plan(&mut self) -> Option<()> {
+        match self.u32()? {
+            0 => self.payment(),
+            1 => {
+                self.condition()?;
+                self.payment()
+            }
+            2 => {
+                self.condition()?;
+                self.payment()?;
+                self.condition()?;
+                self.payment()
+            }
+            _ => None,
+        }
+    }
+
+    fn event(&mut self) -> Option<EventView<'a>> {
+        match self.u32()? {
+            0 => {
+                let sig = self.array(64)?;
+                let from = self.array(32)?;
+                let start = self.pos;
+                self.u64()?;
+                self.array(32)?;
+                self.plan()?;
+                let sign_data = &self.data[start..self.pos];
+                Some(EventView::Transaction {
+                    sig,
+                    from,
+                    sign_data,
+                })
+            }
+            1 => Some(EventView::Signature {
+                from: self.array(32)?,
+                tx_sig: self.array(64)?,
+                sig: self.array(64)?,
+            }),
+            2 => Some(EventView::Timestamp {
+                from: self.array(32)?,
+                sign_data: self.string()?,
+                sig: self.array(64)?,
+            }),
+            3 => Some(EventView::TimeSource {
+                from: self.array(32)?,
+                source: self.array(32)?,
+                sig: self.array(64)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An event borrowed from the bytes of a serialized entry. Each variant holds
+/// the public key, the signature, and the bytes that signature covers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EventView<'a> {
+    Transaction {
+        sig: &'a [u8],
+        from: &'a [u8],
+        sign_data: &'a [u8],
+    },
+    Signature {
+        from: &'a [u8],
+        tx_sig: &'a [u8],
+        sig: &'a [u8],
+    },
+    Timestamp {
+        from: &'a [u8],
+        sign_data: &'a [u8],
+        sig: &'a [u8],
+    },
+    TimeSource {
+        from: &'a [u8],
+        source: &'a [u8],
+        sig: &'a [u8],
+    },
+}
+
+impl<'a> EventView<'a> {
+    /// Verify the event's signature, as `Event::verify` does.
+    pub fn verify(&self) -> bool {
+        let (from, sign_data, sig) = match *self {
+            EventView::Transaction {
+                from,
+                sign_data,
+                sig,
+            }
+            | EventView::Timestamp {
+                from,
+                sign_data,
+                sig,
+            } => (from, sign_data, sig),
+            EventView::Signature { from, tx_sig, sig } => (from, tx_sig, sig),
+            EventView::TimeSource { from, source, sig } => (from, source, sig),
+        };
+        Signature::clone_from_slice(sig).verify(from, sign_data)
+    }
+
+    /// Append the data this event contributes to its entry's id.
+    fn add_hash_data(&self, hash_data: &mut Vec<u8>) {
+        let (tag, sig) = match *self {
+            EventView::Transaction { sig, .. } => (0u8, sig),
+            EventView::Signature { sig, .. } => (1u8, sig),
+            EventView::Timestamp { sig, .. } => (2u8, sig),
+            EventView::TimeSource { sig, .. } => (3u8, sig),
+        };
+        hash_data.push(tag);
+        hash_data.extend_from_slice(sig);
+    }
+}
+
+/// An entry borrowed from the bytes of a ledger file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EntryView<'a> {
+    pub num_hashes: u64,
+    pub id: &'a [u8],
+    num_events: u64,
+    events: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> EntryView<'a> {
+    /// Borrow the entry serialized in `data`, or None if `data` isn't
+    /// exactly one serialized entry.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut cursor = Cursor::new(data);
+        let num_hashes = cursor.u64()?;
+        let id = cursor.array(32)?;
+        let num_events = cursor.u64()?;
+        let start = cursor.pos;
+        for _ in 0..num_events {
+            cursor.event()?;
+        }
+        if cursor.pos != data.len() {
+            return None;
+        }
+        Some(EntryView {
+            num_hashes,
+            id,
+            num_events,
+            events: &data[start..],
+            data,
+        })
+    }
+
+    /// Iterate over the entry's events.
+    pub fn events(&self) -> EventViews<'a> {
+        EventViews {
+            cursor: Cursor::new(self.events),
+            remaining: self.num_events,
+        }
+    }
+
+    pub fn num_events(&self) -> u64 {
+        self.num_events
+    }
+
+    /// Verifies self.id is the result of hashing a `start_hash` `self.num_hashes`
+    /// times, as `Entry::verify` does.
+    pub fn verify(&self, start_hash: &Hash) -> bool {
+        if !counts_event_hash(self.num_hashes, self.num_events) {
+            return false;
+        }
+        let mut hash_data = vec![];
+        for event in self.events() {
+            if !event.verify() {
+                return false;
+            }
+            event.add_hash_data(&mut hash_data);
+        }
+        *self.id == next_hash_with_data(start_hash, self.num_hashes, &hash_data)[..]
+    }
+
+    /// Copy the view into an owned Entry.
+    pub fn to_entry(&self) -> Entry {
+        deserialize(self.data).expect("deserialize parsed entry")
+    }
+}
+
+/// Iterates over the events of an `EntryView`.
+pub struct EventViews<'a> {
+    cursor: Cursor<'a>,
+    remaining: u64,
+}
+
+impl<'a> Iterator for EventViews<'a> {
+    type Item = EventView<'a>;
+    fn next(&mut self) -> Option<EventView<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.cursor.event()
+    }
+}
+
+/// A read-only memory map of a ledger file. Entries are read in place
+/// rather than copied into owned `Entry` values.
+#[cfg(unix)]
+pub struct MappedLedger {
+    ptr: *mut libc::c_void,
+    len: usize,
+    seed: Hash,
+}
+
+// The mapping is read-only and owned by the MappedLedger.
+#[cfg(unix)]
+unsafe impl Send for MappedLedger {}
+#[cfg(unix)]
+unsafe impl Sync for MappedLedger {}
+
+#[cfg(unix)]
+impl MappedLedger {
+    /// Map the ledger file at `path`. Entries appended to the file after it
+    /// is mapped are not visible.
+    pub fn open(path: &Path) -> Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        use std::ptr;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < HEADER_SIZE {
+            return Err(Error::Ledger(LedgerError::BadHeader));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::IO(io::Error::last_os_error()));
+        }
+        let mut ledger = MappedLedger {
+            ptr,
+            len,
+            seed: Hash::default(),
+        };
+        ledger.seed = deserialize_header(ledger.bytes())?;
+        Ok(ledger)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    /// The hash the first entry verifies against.
+    pub fn seed(&self) -> Hash {
+        self.seed
+    }
+
+    /// Iterate over the ledger's entries.
+    pub fn iter(&self) -> MappedEntries<'_> {
+        MappedEntries {
+            data: self.bytes(),
+            offset: HEADER_SIZE,
+        }
+    }
+
+    /// Verify the whole ledger and return the id of its last entry.
+    pub fn verify(&self) -> Result<Hash> {
+        let mut id = self.seed;
+        let mut height = 0;
+        let mut entries = self.iter().peekable();
+        while entries.peek().is_some() {
+            let chunk = entries
+                .by_ref()
+                .take(VERIFY_CHUNK_SIZE)
+                .collect::<Result<Vec<_>>>()?;
+            let mut ids = vec![id];
+            ids.extend(chunk.iter().map(|x| Hash::clone_from_slice(x.id)));
+            if !ids.par_iter().zip(&chunk).all(|(id, x)| x.verify(id)) {
+                let mut prev = id;
+                for x in &chunk {
+                    if !x.verify(&prev) {
+                        break;
+                    }
+                    prev = Hash::clone_from_slice(x.id);
+                    height += 1;
+                }
+                return Err(Error::Ledger(LedgerError::VerificationFailed(height)));
+            }
+            id = Hash::clone_from_slice(chunk[chunk.len() - 1].id);
+            height += chunk.len() as u64;
+        }
+        Ok(id)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MappedLedger {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// Iterates over the entries of a `MappedLedger`.
+pub struct MappedEntries<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for MappedEntries<'a> {
+    type Item = Result<EntryView<'a>>;
+    fn next(&mut self) -> Option<Result<EntryView<'a>>> {
+        if self.offset == self.data.len() {
+            return None;
+        }
+        let bad_frame = Err(Error::Ledger(LedgerError::BadFrame(self.offset as u64)));
+        let rest = &self.data[self.offset..];
+        if rest.len() < FRAME_HEADER_SIZE {
+            self.offset = self.data.len();
+            return Some(bad_frame);
+        }
+        let size = LittleEndian::read_u64(&rest[..8]);
+        if size > (rest.len() - FRAME_HEADER_SIZE) as u64 {
+            self.offset = self.data.len();
+            return Some(bad_frame);
+        }
+        let data = &rest[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + size as usize];
+        let view = if frame_checksum(data) == LittleEndian::read_u64(&rest[8..16]) {
+            EntryView::parse(data)
+        } else {
+            None
+        };
+        match view {
+            Some(view) => {
+                self.offset += FRAME_HEADER_SIZE + data.len();
+                Some(Ok(view))
+            }
+            None => {
+                self.offset = self.data.len();
+                Some(bad_frame)
+            }
+        }
+    }
+}
+
+/// Reads complete frames from a ledger file that may still be being written.
+struct Follower {
+    file: File,
+    height: u64,
+    offset: u64,
+    bad_checksum_reads: u32,
+}
+
+impl Follower {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|_| Error::Ledger(LedgerError::BadHeader))?;
+        deserialize_header(&header)?;
+        Ok(Follower {
+            file,
+            height: 0,
+            offset: HEADER_SIZE as u64,
+            bad_checksum_reads: 0,
+        })
+    }
+
+    /// Read the next entry, or None if it hasn't been completely written yet.
+    fn read_entry(&mut self) -> Result<Option<Entry>> {
+        let len = self.file.metadata()?.len();
+        if len < self.offset + FRAME_HEADER_SIZE as u64 {
+            return Ok(None);
+        }
+        let mut frame_header = [0u8; FRAME_HEADER_SIZE];
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        self.file.read_exact(&mut frame_header)?;
+        let size = LittleEndian::read_u64(&frame_header[..8]);
+        if size > MAX_ENTRY_SIZE {
+            return Err(Error::Ledger(LedgerError::BadFrame(self.offset)));
+        }
+        if len < self.offset + (FRAME_HEADER_SIZE as u64) + size {
+            return Ok(None);
+        }
+        let mut data = vec![0u8; size as usize];
+        self.file.read_exact(&mut data)?;
+
+        // The length may land before the rest of the frame, so a bad
+        // checksum is treated as a frame still being written, unless more
+        // has been written after it or it stays bad.
+        let end = self.offset + (FRAME_HEADER_SIZE as u64) + size;
+        if frame_checksum(&data) != LittleEndian::read_u64(&frame_header[8..]) {
+            self.bad_checksum_reads += 1;
+            if len > end || self.bad_checksum_reads > MAX_BAD_CHECKSUM_READS {
+                return Err(Error::Ledger(LedgerError::BadFrame(self.offset)));
+            }
+            return Ok(None);
+        }
+        self.bad_checksum_reads = 0;
+        let entry =
+            deserialize(&data).map_err(|_| Error::Ledger(LedgerError::BadFrame(self.offset)))?;
+        self.height += 1;
+        self.offset += (FRAME_HEADER_SIZE as u64) + size;
+        Ok(Some(entry))
+    }
+}
+
+/// Stops the thread started by `follow`.
+pub struct FollowHandle {
+    exit: Sender<()>,
+    thread_hdl: JoinHandle<Result<()>>,
+}
+
+impl FollowHandle {
+    /// Stop following the ledger and wait for the background thread to
+    /// exit. Returns the error that stopped the thread early, if any.
+    pub fn stop(self) -> Result<()> {
+        let _ = self.exit.send(());
+        self.thread_hdl.join()?
+    }
+}
+
+/// Watch the ledger at `path` and send each entry appended after the current
+/// end of the ledger. The file is checked for new entries every
+/// `poll_interval`.
+pub fn follow(path: &Path, poll_interval: Duration) -> Result<(Receiver<Entry>, FollowHandle)> {
+    let mut follower = Follower::open(path)?;
+    while follower.read_entry()?.is_some() {}
+    let height = follower.height;
+    Ok(spawn_follower(follower, height, poll_interval))
+}
+
+/// Watch the ledger at `path` and send each entry starting from the entry
+/// at `height`, waiting for it to be written if necessary.
+pub fn follow_from(
+    path: &Path,
+    height: u64,
+    poll_interval: Duration,
+) -> Result<(Receiver<Entry>, FollowHandle)> {
+    let follower = Follower::open(path)?;
+    Ok(spawn_follower(follower, height, poll_interval))
+}
+
+fn spawn_follower(
+    mut follower: Follower,
+    start_height: u64,
+    poll_interval: Duration,
+) -> (Receiver<Entry>, FollowHandle) {
+    let (exit, exit_receiver) = channel();
+    let (sender, receiver) = channel();
+    let thread_hdl = spawn(move || loop {
+        while let Some(entry) = follower.read_entry()? {
+            if follower.height > start_height && sender.send(entry).is_err() {
+                return Ok(());
+            }
+        }
+        match exit_receiver.recv_timeout(poll_interval) {
+            Err(RecvTimeoutError::Timeout) => (),
+            _ => return Ok(()),
+        }
+    });
+    (receiver, FollowHandle { exit, thread_hdl })
+}
+
+/// Read every frame of the ledger at `path`, calling `f` with each entry's
+/// height, frame offset, and the entry itself. Fails if any frame is
+/// unreadable.
+fn scan_ledger<F>(path: &Path, mut f: F) -> Result<LedgerReader>
+where
+    F: FnMut(u64, u64, Entry) -> Result<()>,
+{
+    let mut reader = LedgerReader::open(path)?;
+    loop {
+        let (height, offset) = (reader.height(), reader.offset());
+        match reader.read_entry()? {
+            Some(entry) => f(height, offset, entry)?,
+            None => return Ok(reader),
+        }
+    }
+}
+
+/// Truncate the ledger at `path` so that exactly `height` entries remain,
+/// lowering its checkpoint if it recorded a greater height and dropping the
+/// offsets of the removed frames from its index. The ledger is
+/// left untouched if any of its frames are unreadable.
+pub fn truncate(path: &Path, height: u64) -> Result<()> {
+    let mut end = None;
+    let mut last = None;
+    let reader = scan_ledger(path, |h, offset, entry| {
+        if h + 1 == height {
+            last = Some((offset, entry.id));
+        }
+        if h == height {
+            end = Some(offset);
+        }
+        Ok(())
+    })?;
+    if height > reader.height() {
+        return Err(Error::Ledger(LedgerError::HeightOutOfRange(height)));
+    }
+    let end = end.unwrap_or_else(|| reader.offset());
+
+    match read_checkpoint(path) {
+        Some(ref checkpoint) if checkpoint.height > height => match last {
+            Some((offset, id)) => write_checkpoint(path, &Checkpoint { height, id, offset })?,
+            None => fs::remove_file(checkpoint_path(path))?,
+        },
+        _ => (),
+    }
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(end)?;
+    file.sync_all()?;
+    if let Ok(index) = OpenOptions::new().write(true).open(index_path(path)) {
+        if index.metadata()?.len() > height * INDEX_ENTRY_SIZE {
+            index.set_len(height * INDEX_ENTRY_SIZE)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the entries from `start_height` up to but not including
+/// `end_height` from the ledger at `src` into a new ledger at `dst`. The new
+/// ledger's seed is the id of the entry before `start_height`, so it
+/// verifies on its own. Fails without writing `dst` if any of the frames of
+/// `src` are unreadable.
+pub fn copy_range(src: &Path, dst: &Path, start_height: u64, end_height: u64) -> Result<()> {
+    let mut seed = None;
+    let mut entries = vec![];
+    let reader = scan_ledger(src, |height, _, entry| {
+        if height + 1 == start_height {
+            seed = Some(entry.id);
+        }
+        if height >= start_height && height < end_height {
+            entries.push(entry);
+        }
+        Ok(())
+    })?;
+    if start_height > end_height {
+        return Err(Error::Ledger(LedgerError::HeightOutOfRange(start_height)));
+    }
+    if end_height > reader.height() {
+        return Err(Error::Ledger(LedgerError::HeightOutOfRange(end_height)));
+    }
+    let seed = seed.unwrap_or_else(|| reader.seed());
+
+    let mut tmp_name = dst.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = dst.with_file_name(tmp_name);
+    write_ledger(&tmp, &seed, &entries)?;
+    fs::rename(&tmp, dst)?;
+    Ok(())
+}
+
+/// Where two chains of entries stop agreeing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Divergence {
+    /// Both chains hold the same `len` entries.
+    Identical { len: u64 },
+    /// One chain holds the first `shorter_len` entries of the other.
+    Prefix { shorter_len: u64 },
+    /// The chains agree up to `height`, where their entries' ids differ.
+    Forked { height: u64, a_id: Hash, b_id: Hash },
+}
+
+/// Compare the chains entry by entry until their ids differ or either ends.
+fn diverge<A, B, E>(mut a: A, mut b: B) -> ::std::result::Result<Divergence, E>
+where
+    A: Iterator<Item = ::std::result::Result<Entry, E>>,
+    B: Iterator<Item = ::std::result::Result<Entry, E>>,
+{
+    let mut height = 0;
+    loop {
+        match (a.next().transpose()?, b.next().transpose()?) {
+            (Some(x), Some(y)) if x.id == y.id => height += 1,
+            (Some(x), Some(y)) => {
+                return Ok(Divergence::Forked {
+                    height,
+                    a_id: x.id,
+                    b_id: y.id,
+                })
+            }
+            (None, None) => return Ok(Divergence::Identical { len: height }),
+            _ => return Ok(Divergence::Prefix { shorter_len: height }),
+        }
+    }
+}
+
+/// Find where the chains `a` and `b` diverge. Since each id hashes the
+/// entries before it, only the ids are compared, and the first differing
+/// pair marks the fork.
+pub fn find_divergence(
+    a: impl Iterator<Item = Entry>,
+    b: impl Iterator<Item = Entry>,
+) -> Divergence {
+    match diverge::<_, _, Infallible>(a.map(Ok), b.map(Ok)) {
+        Ok(divergence) => divergence,
+        Err(never) => match never {},
+    }
+}
+
+/// Find where the ledgers at `a` and `b` diverge, reading only as far as the
+/// fork. Their seeds aren't compared, but an entry's id follows from its
+/// ledger's seed, so ledgers with different seeds fork at height 0. Fails if
+/// a frame before the fork is unreadable.
+pub fn find_ledger_divergence(a: &Path, b: &Path) -> Result<Divergence> {
+    diverge(LedgerReader::open(a)?, LedgerReader::open(b)?)
+}
+
+/// Number of bytes before a corrupt record included in its report.
+const CONTEXT_SIZE: usize = 32;
+/// Number of bytes of a corrupt record included in its report.
+const MAX_DUMP_SIZE: usize = 256;
+
+/// Why a record in a ledger file couldn't be used.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CorruptionKind {
+    /// The file doesn't start with a ledger header.
+    BadHeader,
+    /// The file ends partway through the frame.
+    TruncatedFrame,
+    /// The frame's length prefix exceeds `MAX_ENTRY_SIZE`.
+    BadLength,
+    /// The frame's data doesn't match its checksum.
+    BadChecksum,
+    /// The frame's data matches its checksum but isn't an entry.
+    BadEntry,
+    /// The entry deserializes but doesn't chain from the previous one.
+    VerificationFailed,
+}
+
+impl CorruptionKind {
+    /// True if the record's frame is damaged, as opposed to the record being
+    /// a well-formed entry that fails verification.
+    pub fn is_framing(&self) -> bool {
+        *self != CorruptionKind::VerificationFailed
+    }
+}
+
+/// The first record of a ledger file that couldn't be used.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct CorruptRecord {
+    pub kind: CorruptionKind,
+    /// Byte offset of the record's frame.
+    pub offset: u64,
+    /// The record's position in the ledger.
+    pub height: u64,
+    /// The id of the last entry that verified, or the seed.
+    pub last_good_id: Hash,
+    /// Hex dump of the bytes preceding the record.
+    pub context: String,
+    /// Hex dump of the record itself, cut off at `MAX_DUMP_SIZE` bytes.
+    pub record: String,
+}
+
+/// Where usable entries resume after a corrupt record.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Resync {
+    /// Byte offset of the first usable frame after the corruption.
+    pub offset: u64,
+    /// Number of bytes between the corrupt record and `offset`.
+    pub skipped_bytes: u64,
+    /// Number of entries that failed verification before `offset`. Unknown
+    /// when the framing is damaged.
+    pub affected_entries: Option<u64>,
+    /// Number of readable entries from `offset` to the end of the file.
+    pub entries_after: u64,
+}
+
+/// The result of `diagnose`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct DiagnosisReport {
+    /// The hash the first entry verifies against, if the header is intact.
+    pub seed: Option<Hash>,
+    /// Number of entries that verified before the first corrupt record.
+    pub verified_entries: u64,
+    pub corruption: Option<CorruptRecord>,
+    pub resync: Option<Resync>,
+}
+
+impl DiagnosisReport {
+    pub fn is_healthy(&self) -> bool {
+        self.corruption.is_none()
+    }
+}
+
+/// Format `bytes`, which start at `offset` in the file, as lines of 16
+/// bytes each.
+pub fn hex_dump(bytes: &[u8], offset: u64) -> String {
+    let lines: Vec<_> = bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<_> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{:08x}: {}", offset as usize + i * 16, hex.join(" "))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Number of bytes after a record with damaged framing that `diagnose`
+/// searches for the next readable frame.
+const RESYNC_WINDOW: u64 = 1024 * 1024;
+
+/// Reads the frames of a ledger file at any offset, for `diagnose`. Holds at
+/// most one frame in memory at a time, besides the resync window.
+struct FrameScanner {
+    reader: BufReader<File>,
+    len: u64,
+    pos: u64,
+}
+
+impl FrameScanner {
+    fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(FrameScanner {
+            reader: BufReader::new(file),
+            len,
+            pos: 0,
+        })
+    }
+
+    /// Read the `len` bytes at `offset`, which must be within the file.
+    fn read_bytes(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if offset != self.pos {
+            self.reader.seek(SeekFrom::Start(offset))?;
+        }
+        let mut bytes = vec![0u8; len];
+        self.reader.read_exact(&mut bytes)?;
+        self.pos = offset + len as u64;
+        Ok(bytes)
+    }
+
+    /// Parse the frame at `offset`, returning its entry and length.
+    fn frame_at(
+        &mut self,
+        offset: u64,
+    ) -> io::Result<::std::result::Result<(Entry, u64), CorruptionKind>> {
+        let rest = self.len.saturating_sub(offset);
+        if rest < FRAME_HEADER_SIZE as u64 {
+            return Ok(Err(CorruptionKind::TruncatedFrame));
+        }
+        let frame_header = self.read_bytes(offset, FRAME_HEADER_SIZE)?;
+        let size = LittleEndian::read_u64(&frame_header[..8]);
+        if size > MAX_ENTRY_SIZE {
+            return Ok(Err(CorruptionKind::BadLength));
+        }
+        if size > rest - FRAME_HEADER_SIZE as u64 {
+            return Ok(Err(CorruptionKind::TruncatedFrame));
+        }
+        let data = self.read_bytes(offset + FRAME_HEADER_SIZE as u64, size as usize)?;
+        Ok(decode_frame(&frame_header, &data))
+    }
+
+    /// Count the readable frames from `offset` on.
+    fn count_frames(&mut self, mut offset: u64) -> io::Result<u64> {
+        let mut count = 0;
+        while let Ok((_, len)) = self.frame_at(offset)? {
+            offset += len;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Search the `RESYNC_WINDOW` bytes after the corrupt record at `offset`
+    /// for the first readable frame, and report how many entries follow it.
+    fn resync_framing(&mut self, offset: u64) -> io::Result<Option<Resync>> {
+        let start = offset + 1;
+        let end = self.len.min(start + RESYNC_WINDOW);
+        if start >= end {
+            return Ok(None);
+        }
+        let window = self.read_bytes(start, (end - start) as usize)?;
+        for i in 0..window.len() {
+            let candidate = start + i as u64;
+            // Check the frames that fit in the window from it, and read only
+            // those that extend past it.
+            let frame = match window.get(i..i + FRAME_HEADER_SIZE) {
+                Some(frame_header) => {
+                    let size = LittleEndian::read_u64(&frame_header[..8]);
+                    if size > MAX_ENTRY_SIZE
+                        || candidate + FRAME_HEADER_SIZE as u64 + size > self.len
+                    {
+                        continue;
+                    }
+                    let data_start = i + FRAME_HEADER_SIZE;
+                    match window.get(data_start..data_start + size as usize) {
+                        Some(data) => decode_frame(frame_header, data),
+                        None => self.frame_at(candidate)?,
+                    }
+                }
+                None => self.frame_at(candidate)?,
+            };
+            if frame.is_ok() {
+                return Ok(Some(Resync {
+                    offset: candidate,
+                    skipped_bytes: candidate - offset,
+                    affected_entries: None,
+                    entries_after: self.count_frames(candidate)?,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Skip past the run of entries that fail verification starting with
+    /// `entry`, the one at `offset`, and report where verification resumes.
+    fn resync_semantic(
+        &mut self,
+        offset: u64,
+        len: u64,
+        entry: Entry,
+    ) -> io::Result<Option<Resync>> {
+        let mut prev = entry;
+        let mut next = offset + len;
+        let mut affected = 1;
+        while let Ok((entry, len)) = self.frame_at(next)? {
+            if entry.verify(&prev.id) {
+                return Ok(Some(Resync {
+                    offset: next,
+                    skipped_bytes: next - offset,
+                    affected_entries: Some(affected),
+                    entries_after: self.count_frames(next)?,
+                }));
+            }
+            prev = entry;
+            next += len;
+            affected += 1;
+        }
+        Ok(None)
+    }
+}
+
+/// Check the data of a frame against its header's checksum and decode its
+/// entry, returning it with the frame's length.
+fn decode_frame(
+    frame_header: &[u8],
+    data: &[u8],
+) -> ::std::result::Result<(Entry, u64), CorruptionKind> {
+    if frame_checksum(data) != LittleEndian::read_u64(&frame_header[8..]) {
+        return Err(CorruptionKind::BadChecksum);
+    }
+    let len = (FRAME_HEADER_SIZE + data.len()) as u64;
+    deserialize(data)
+        .map(|entry| (entry, len))
+        .map_err(|_| CorruptionKind::BadEntry)
+}
+
+/// Examine the ledger at `path` and report the first record that is
+/// corrupt, distinguishing damaged framing from entries that don't verify.
+/// If `resync` is set, also scan past the corruption to report where usable
+/// entries resume, if they do within `RESYNC_WINDOW` bytes of damaged
+/// framing. The file is read a frame at a time.
+pub fn diagnose(path: &Path, resync: bool) -> Result<DiagnosisReport> {
+    let mut scanner = FrameScanner::open(path)?;
+    let header_len = scanner.len.min(HEADER_SIZE as u64) as usize;
+    let header = scanner.read_bytes(0, header_len)?;
+    let mut report = DiagnosisReport {
+        seed: deserialize_header(&header).ok(),
+        verified_entries: 0,
+        corruption: None,
+        resync: None,
+    };
+    let seed = match report.seed {
+        Some(seed) => seed,
+        None => {
+            report.corruption = Some(CorruptRecord {
+                kind: CorruptionKind::BadHeader,
+                offset: 0,
+                height: 0,
+                last_good_id: Hash::default(),
+                context: String::new(),
+                record: hex_dump(&header, 0),
+            });
+            return Ok(report);
+        }
+    };
+
+    let mut offset = HEADER_SIZE as u64;
+    let mut last_id = seed;
+    while offset < scanner.len {
+        let (kind, len, entry) = match scanner.frame_at(offset)? {
+            Ok((ref entry, len)) if entry.verify(&last_id) => {
+                last_id = entry.id;
+                offset += len;
+                report.verified_entries += 1;
+                continue;
+            }
+            Ok((entry, len)) => (CorruptionKind::VerificationFailed, len, Some(entry)),
+            Err(kind) => (kind, scanner.len - offset, None),
+        };
+        let context_start = offset
+            .saturating_sub(CONTEXT_SIZE as u64)
+            .max(HEADER_SIZE as u64);
+        let context = scanner.read_bytes(context_start, (offset - context_start) as usize)?;
+        let record = scanner.read_bytes(offset, len.min(MAX_DUMP_SIZE as u64) as usize)?;
+        report.corruption = Some(CorruptRecord {
+            kind,
+            offset,
+            height: report.verified_entries,
+            last_good_id: last_id,
+            context: hex_dump(&context, context_start),
+            record: hex_dump(&record, offset),
+        });
+        if resync {
+            report.resync = match entry {
+                Some(entry) => scanner.resync_semantic(offset, len, entry)?,
+                None => scanner.resync_framing(offset)?,
+            };
+        }
+        break;
+    }
+    Ok(report)
+}
+
+/// Return a path in the system's temporary directory that is unique to this
+/// process and `name`, removing any ledger and sidecar left there by an
+/// earlier run.
+#[cfg(test)]
+pub fn tmp_ledger_path(name: &str) -> PathBuf {
+    use std::env;
+    use std::process;
+
+    let path = env::temp_dir().join(format!("solana-ledger-{}-{}", name, process::id()));
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(checkpoint_path(&path));
+    let _ = fs::remove_file(index_path(&path));
+    path
+}
+
+/// Create a chain of `len` entries from `start_hash` holding each kind of
+/// event and spending plan.
+#[cfg(test)]
+pub fn test_entries(start_hash: &Hash, len: usize) -> Vec<Entry> {
+    use chrono::prelude::*;
+    use entry::create_entry;
+    use event::Event;
+    use signature::{KeyPair, KeyPairUtil};
+    use transaction::Transaction;
+
+    let keypair = KeyPair::new();
+    let pubkey = keypair.pubkey();
+    let dt: DateTime<Utc> = "2018-04-01T08:09:10Z".parse().unwrap();
+    let mut id = *start_hash;
+    let mut entries = vec![];
+    for i in 0..len {
+        let tr = Transaction::new(&keypair, pubkey, i as i64, id);
+        let events = match i % 4 {
+            0 => vec![],
+            1 => vec![Event::Transaction(tr)],
+            2 => vec![
+                Event::Transaction(Transaction::new_on_date(&keypair, pubkey, dt, 1, id)),
+                Event::new_timestamp(&keypair, dt),
+            ],
+            _ => vec![Event::new_signature(&keypair, tr.sig)],
+        };
+        let entry = create_entry(&id, 0, events);
+        id = entry.id;
+        entries.push(entry);
+    }
+    entries
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use entry::create_entry;
+    use event::Event;
     use hash::hash;
+    use mint::Mint;
+    use serde_json;
+    use signature::{KeyPair, KeyPairUtil};
+    use transaction::Transaction;
+    use std::thread::sleep;
+    use std::time::Instant;
 
     #[test]
     fn test_verify_slice() {
@@ -48,6 +1850,851 @@ mod tests {
         bad_ticks[1].id = one;
         assert!(!bad_ticks.verify(&zero)); // inductive step, bad
     }
+
+    #[test]
+    fn test_entries_to_blobs() {
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 8);
+        let recycler = BlobRecycler::default();
+        let mut blobs = VecDeque::new();
+        entries_to_blobs(&entries, &recycler, &mut blobs).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs_to_entries(&blobs).unwrap(), entries);
+        assert_eq!(num_blob_entries(&blobs[0].read().unwrap()).unwrap(), 8);
+
+        // An entry too large for a datagram can't be sent.
+        let mut big = entries[1].clone();
+        let event = big.events[0].clone();
+        big.events = vec![event; MAX_BLOB_SEND_SIZE / 100];
+        let mut blobs = VecDeque::new();
+        assert_matches!(
+            entries_to_blobs(&[entries[0].clone(), big], &recycler, &mut blobs),
+            Err(Error::Ledger(LedgerError::EntryTooLarge(1)))
+        );
+    }
+
+    #[test]
+    fn test_write_read_ledger() {
+        let path = tmp_ledger_path("write_read");
+        let zero = Hash::default();
+        let entries = next_ticks(&zero, 2, 3);
+        write_ledger(&path, &zero, &entries).unwrap();
+        assert_eq!(read_ledger(&path).unwrap(), (zero, entries.clone()));
+
+        let mut writer = LedgerWriter::open(&path).unwrap();
+        assert_eq!(writer.height(), 3);
+        assert_eq!(writer.last_id(), entries[2].id);
+        let more = next_ticks(&entries[2].id, 2, 2);
+        writer.write_entries(&more).unwrap();
+        let (_, all) = read_ledger(&path).unwrap();
+        assert_eq!(all.len(), 5);
+        assert!(all.verify(&zero));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_bad_ledger() {
+        let path = tmp_ledger_path("bad_ledger");
+        File::create(&path).unwrap().write_all(b"not a ledger").unwrap();
+        assert_matches!(
+            read_ledger(&path),
+            Err(Error::Ledger(LedgerError::BadHeader))
+        );
+
+        let zero = Hash::default();
+        write_ledger(&path, &zero, &next_ticks(&zero, 2, 2)).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        let frame_size = (len - HEADER_SIZE as u64) / 2;
+        let offset = HEADER_SIZE as u64 + frame_size;
+        assert_matches!(
+            read_ledger(&path),
+            Err(Error::Ledger(LedgerError::BadFrame(o))) if o == offset
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_from_trusted() {
+        let path = tmp_ledger_path("trusted");
+        let zero = Hash::default();
+        let entries = next_ticks(&zero, 2, 5);
+        write_ledger(&path, &zero, &entries).unwrap();
+        assert_eq!(read_checkpoint(&path), None);
+        assert_eq!(verify_from_trusted(&path).unwrap(), entries[4].id);
+        let checkpoint = read_checkpoint(&path).unwrap();
+        assert_eq!(checkpoint.height, 5);
+        assert_eq!(checkpoint.id, entries[4].id);
+
+        // Replace an entry before the checkpoint with one that doesn't verify.
+        // Only entries after the checkpoint are replayed, so the bad entry
+        // goes unnoticed.
+        let mut bad_entries = entries.clone();
+        bad_entries[2].id = hash(&zero);
+        write_ledger(&path, &zero, &bad_entries).unwrap();
+        assert_eq!(verify_from_trusted(&path).unwrap(), entries[4].id);
+
+        // Without the checkpoint, the whole ledger is verified.
+        fs::remove_file(checkpoint_path(&path)).unwrap();
+        assert_matches!(
+            verify_from_trusted(&path),
+            Err(Error::Ledger(LedgerError::VerificationFailed(2)))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_from_lying_checkpoint() {
+        let path = tmp_ledger_path("lying");
+        let zero = Hash::default();
+        let entries = next_ticks(&zero, 2, 5);
+        let mut bad_entries = entries.clone();
+        bad_entries[2].id = hash(&zero);
+        write_ledger(&path, &zero, &bad_entries).unwrap();
+
+        // A checkpoint whose id doesn't match the entry at its height isn't
+        // trusted.
+        let mut checkpoint = Checkpoint {
+            height: 5,
+            id: hash(&entries[4].id),
+            offset: (HEADER_SIZE + 4 * serialize_frame(&entries[0]).unwrap().len()) as u64,
+        };
+        write_checkpoint(&path, &checkpoint).unwrap();
+        assert_matches!(
+            verify_from_trusted(&path),
+            Err(Error::Ledger(LedgerError::VerificationFailed(2)))
+        );
+
+        // Nor is one that points past the end of the ledger, which falls
+        // back to full verification.
+        write_ledger(&path, &zero, &entries).unwrap();
+        checkpoint.id = entries[4].id;
+        checkpoint.offset += 1_000;
+        write_checkpoint(&path, &checkpoint).unwrap();
+        let verified = verify_ledger(&path).unwrap();
+        assert_eq!(verified.height, 5);
+        assert_eq!(verified.last_id, entries[4].id);
+        assert_eq!(verified.trusted_height, 0);
+
+        // A corrupt sidecar falls back to full verification.
+        File::create(checkpoint_path(&path))
+            .unwrap()
+            .write_all(b"garbage")
+            .unwrap();
+        assert_eq!(read_checkpoint(&path), None);
+        assert_eq!(verify_from_trusted(&path).unwrap(), entries[4].id);
+        assert_eq!(read_checkpoint(&path).unwrap().height, 5);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(checkpoint_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_verify_appended_after_checkpoint() {
+        let path = tmp_ledger_path("appended");
+        let zero = Hash::default();
+        let entries = next_ticks(&zero, 2, 3);
+        write_ledger(&path, &zero, &entries).unwrap();
+        assert_eq!(verify_from_trusted(&path).unwrap(), entries[2].id);
+
+        let more = next_ticks(&entries[2].id, 2, 2);
+        LedgerWriter::open(&path)
+            .unwrap()
+            .write_entries(&more)
+            .unwrap();
+        assert_eq!(verify_from_trusted(&path).unwrap(), more[1].id);
+        assert_eq!(read_checkpoint(&path).unwrap().height, 5);
+
+        // An appended entry that doesn't chain is caught.
+        let bad = next_ticks(&zero, 2, 1);
+        LedgerWriter::open(&path)
+            .unwrap()
+            .write_entries(&bad)
+            .unwrap();
+        assert_matches!(
+            verify_from_trusted(&path),
+            Err(Error::Ledger(LedgerError::VerificationFailed(5)))
+        );
+        assert_eq!(read_checkpoint(&path).unwrap().height, 5);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(checkpoint_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_mapped_ledger() {
+        let path = tmp_ledger_path("mapped");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 64);
+        write_ledger(&path, &zero, &entries).unwrap();
+
+        let mapped = MappedLedger::open(&path).unwrap();
+        assert_eq!(mapped.seed(), zero);
+        let views: Vec<_> = mapped.iter().map(|x| x.unwrap()).collect();
+        assert_eq!(views.len(), entries.len());
+        for (view, entry) in views.iter().zip(&entries) {
+            assert_eq!(view.num_hashes, entry.num_hashes);
+            assert_eq!(view.id, &entry.id[..]);
+            assert_eq!(view.num_events(), entry.events.len() as u64);
+            assert_eq!(view.events().count(), entry.events.len());
+            assert_eq!(view.to_entry(), *entry);
+        }
+
+        // The mapped and buffered readers agree on the verified chain.
+        let (seed, buffered) = read_ledger(&path).unwrap();
+        assert!(buffered.verify(&seed));
+        assert_eq!(mapped.verify().unwrap(), buffered[buffered.len() - 1].id);
+        assert_eq!(
+            verify_from_trusted(&path).unwrap(),
+            mapped.verify().unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(checkpoint_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_mapped_ledger_time_source() {
+        let path = tmp_ledger_path("mapped_time_source");
+        let oracle = KeyPair::new().pubkey();
+        let dt = "2018-04-01T08:09:10Z".parse().unwrap();
+        let shares = [(KeyPair::new().pubkey(), 50)];
+        let grants = [(KeyPair::new().pubkey(), 50, dt)];
+        let mint = Mint::new_with_grants(100, &shares, &grants, oracle).unwrap();
+        let entries = mint.create_entries();
+        write_ledger(&path, &mint.seed(), &entries).unwrap();
+
+        let mapped = MappedLedger::open(&path).unwrap();
+        let views: Vec<_> = mapped.iter().map(|x| x.unwrap()).collect();
+        assert!(views[2].events().any(|event| match event {
+            EventView::TimeSource { source, .. } => source == &oracle[..],
+            _ => false,
+        }));
+        for (view, entry) in views.iter().zip(&entries) {
+            assert_eq!(view.to_entry(), *entry);
+        }
+        let (seed, buffered) = read_ledger(&path).unwrap();
+        assert!(buffered.verify(&seed));
+        assert_eq!(mapped.verify().unwrap(), buffered[buffered.len() - 1].id);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mapped_ledger_bad_entries() {
+        let path = tmp_ledger_path("mapped_bad");
+        let zero = Hash::default();
+        let mut entries = test_entries(&zero, 8);
+        entries[6].id = zero;
+        write_ledger(&path, &zero, &entries).unwrap();
+        assert_matches!(
+            MappedLedger::open(&path).unwrap().verify(),
+            Err(Error::Ledger(LedgerError::VerificationFailed(6)))
+        );
+
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        let mapped = MappedLedger::open(&path).unwrap();
+        let results: Vec<_> = mapped.iter().collect();
+        assert_eq!(results.len(), 8);
+        assert!(results[..7].iter().all(|x| x.is_ok()));
+        assert_matches!(results[7], Err(Error::Ledger(LedgerError::BadFrame(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow() {
+        let path = tmp_ledger_path("follow");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 20);
+        let mut writer = LedgerWriter::create(&path, &zero).unwrap();
+        let (receiver, handle) = follow_from(&path, 0, Duration::from_millis(1)).unwrap();
+
+        let entries_ = entries.clone();
+        let path_ = path.clone();
+        let t_writer = spawn(move || {
+            for (i, entry) in entries_.iter().enumerate() {
+                if i == 10 {
+                    // Leave a partially written frame at the tail for a while.
+                    let frame = serialize_frame(entry).unwrap();
+                    let mut file = OpenOptions::new().append(true).open(&path_).unwrap();
+                    file.write_all(&frame[..frame.len() / 2]).unwrap();
+                    sleep(Duration::from_millis(20));
+                    file.write_all(&frame[frame.len() / 2..]).unwrap();
+                    writer = LedgerWriter::open(&path_).unwrap();
+                } else {
+                    writer.write_entry(entry).unwrap();
+                }
+                if i % 5 == 0 {
+                    writer.sync().unwrap();
+                }
+                sleep(Duration::from_millis(2));
+            }
+        });
+
+        let followed: Vec<Entry> = receiver.iter().take(entries.len()).collect();
+        t_writer.join().unwrap();
+        handle.stop().unwrap();
+        assert_eq!(followed, entries);
+        assert!(followed.verify(&zero));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_from_end() {
+        let path = tmp_ledger_path("follow_end");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 5);
+        write_ledger(&path, &zero, &entries[..3]).unwrap();
+
+        // Entries already in the ledger when following began are skipped.
+        let (receiver, handle) = follow(&path, Duration::from_millis(1)).unwrap();
+        LedgerWriter::open(&path)
+            .unwrap()
+            .write_entries(&entries[3..])
+            .unwrap();
+        let followed: Vec<Entry> = receiver.iter().take(2).collect();
+        assert_eq!(followed, &entries[3..]);
+        handle.stop().unwrap();
+
+        // A long poll interval doesn't delay stopping.
+        let (_receiver, handle) = follow(&path, Duration::from_secs(10)).unwrap();
+        let start = Instant::now();
+        handle.stop().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_corrupt_ledger() {
+        let path = tmp_ledger_path("follow_corrupt");
+        let zero = Hash::default();
+        write_ledger(&path, &zero, &[]).unwrap();
+        let (receiver, handle) = follow_from(&path, 0, Duration::from_millis(1)).unwrap();
+        let mut frame = vec![0u8; FRAME_HEADER_SIZE];
+        LittleEndian::write_u64(&mut frame[..8], 4);
+        LittleEndian::write_u64(&mut frame[8..], frame_checksum(b"junk"));
+        frame.extend_from_slice(b"junk");
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&frame)
+            .unwrap();
+        assert!(receiver.recv().is_err());
+        assert_matches!(
+            handle.stop(),
+            Err(Error::Ledger(LedgerError::BadFrame(o))) if o == HEADER_SIZE as u64
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_bad_checksum() {
+        let path = tmp_ledger_path("follow_bad_checksum");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 3);
+        write_ledger(&path, &zero, &entries).unwrap();
+
+        // A frame in the middle with a bad checksum is corrupt right away,
+        // and so is the last one once it stays bad.
+        for height in 1..3 {
+            let offset = frame_offset(&entries, height);
+            let data_offset = offset + FRAME_HEADER_SIZE as u64;
+            let byte = fs::read(&path).unwrap()[data_offset as usize];
+            write_byte(&path, data_offset, !byte);
+            let (receiver, handle) = follow_from(&path, 0, Duration::from_millis(1)).unwrap();
+            let followed: Vec<Entry> = receiver.iter().collect();
+            assert_eq!(followed, &entries[..height]);
+            assert_matches!(
+                handle.stop(),
+                Err(Error::Ledger(LedgerError::BadFrame(o))) if o == offset
+            );
+            write_byte(&path, data_offset, byte);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn frame_offset(entries: &[Entry], height: usize) -> u64 {
+        let frames: usize = entries[..height]
+            .iter()
+            .map(|x| serialize_frame(x).unwrap().len())
+            .sum();
+        (HEADER_SIZE + frames) as u64
+    }
+
+    fn write_byte(path: &Path, offset: u64, byte: u8) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[byte]).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_healthy() {
+        let path = tmp_ledger_path("diagnose_healthy");
+        let zero = Hash::default();
+        write_ledger(&path, &zero, &test_entries(&zero, 6)).unwrap();
+        let report = diagnose(&path, true).unwrap();
+        assert!(report.is_healthy());
+        assert_eq!(report.seed, Some(zero));
+        assert_eq!(report.verified_entries, 6);
+        assert_eq!(report.resync, None);
+
+        File::create(&path).unwrap().write_all(b"junk").unwrap();
+        let report = diagnose(&path, true).unwrap();
+        assert_eq!(report.seed, None);
+        assert_eq!(report.corruption.unwrap().kind, CorruptionKind::BadHeader);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_bad_checksum() {
+        let path = tmp_ledger_path("diagnose_checksum");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 6);
+        write_ledger(&path, &zero, &entries).unwrap();
+        let offset = frame_offset(&entries, 2);
+        write_byte(&path, offset + FRAME_HEADER_SIZE as u64 + 3, 0xff);
+
+        let report = diagnose(&path, true).unwrap();
+        assert_eq!(report.verified_entries, 2);
+        let corruption = report.corruption.unwrap();
+        assert_eq!(corruption.kind, CorruptionKind::BadChecksum);
+        assert!(corruption.kind.is_framing());
+        assert_eq!(corruption.offset, offset);
+        assert_eq!(corruption.height, 2);
+        assert_eq!(corruption.last_good_id, entries[1].id);
+        assert!(corruption.record.starts_with(&format!("{:08x}: ", offset)));
+        assert!(!corruption.context.is_empty());
+
+        let resync = report.resync.unwrap();
+        assert_eq!(resync.offset, frame_offset(&entries, 3));
+        assert_eq!(resync.skipped_bytes, resync.offset - offset);
+        assert_eq!(resync.affected_entries, None);
+        assert_eq!(resync.entries_after, 3);
+
+        // Resyncing is optional.
+        assert_eq!(diagnose(&path, false).unwrap().resync, None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_bad_length() {
+        let path = tmp_ledger_path("diagnose_length");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 4);
+        write_ledger(&path, &zero, &entries).unwrap();
+        let offset = frame_offset(&entries, 1);
+        write_byte(&path, offset + 7, 0xff);
+        let report = diagnose(&path, true).unwrap();
+        let corruption = report.corruption.unwrap();
+        assert_eq!(corruption.kind, CorruptionKind::BadLength);
+        assert_eq!(corruption.offset, offset);
+        assert_eq!(report.resync.unwrap().entries_after, 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_resync_window() {
+        let path = tmp_ledger_path("diagnose_window");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 3);
+        write_ledger(&path, &zero, &entries[..1]).unwrap();
+        let offset = frame_offset(&entries, 1);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&vec![0xff; RESYNC_WINDOW as usize + 1]).unwrap();
+        file.write_all(&serialize_frame(&entries[2]).unwrap()).unwrap();
+
+        // The next frame starts just past the window.
+        let report = diagnose(&path, true).unwrap();
+        assert_eq!(report.corruption.unwrap().offset, offset);
+        assert_eq!(report.resync, None);
+
+        // Within it, it's found.
+        file.set_len(offset + 16).unwrap();
+        file.write_all(&serialize_frame(&entries[2]).unwrap()).unwrap();
+        let resync = diagnose(&path, true).unwrap().resync.unwrap();
+        assert_eq!(resync.offset, offset + 16);
+        assert_eq!(resync.entries_after, 1);
+
+        // So is one that starts in it and ends past it, whether its header
+        // or only its entry crosses the window's end.
+        for &junk_len in &[RESYNC_WINDOW - 4, RESYNC_WINDOW - 20] {
+            file.set_len(offset).unwrap();
+            file.write_all(&vec![0xff; junk_len as usize]).unwrap();
+            file.write_all(&serialize_frame(&entries[2]).unwrap()).unwrap();
+            let resync = diagnose(&path, true).unwrap().resync.unwrap();
+            assert_eq!(resync.offset, offset + junk_len);
+            assert_eq!(resync.entries_after, 1);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_truncated() {
+        let path = tmp_ledger_path("diagnose_truncated");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 4);
+        write_ledger(&path, &zero, &entries).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 10)
+            .unwrap();
+        let report = diagnose(&path, true).unwrap();
+        let corruption = report.corruption.unwrap();
+        assert_eq!(corruption.kind, CorruptionKind::TruncatedFrame);
+        assert_eq!(corruption.offset, frame_offset(&entries, 3));
+        assert_eq!(corruption.height, 3);
+        assert_eq!(report.resync, None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_semantic() {
+        let path = tmp_ledger_path("diagnose_semantic");
+        let zero = Hash::default();
+        let mut entries = test_entries(&zero, 6);
+        entries[3].id = zero;
+        write_ledger(&path, &zero, &entries).unwrap();
+
+        let report = diagnose(&path, true).unwrap();
+        assert_eq!(report.verified_entries, 3);
+        let corruption = report.corruption.clone().unwrap();
+        assert_eq!(corruption.kind, CorruptionKind::VerificationFailed);
+        assert!(!corruption.kind.is_framing());
+        assert_eq!(corruption.offset, frame_offset(&entries, 3));
+        assert_eq!(corruption.height, 3);
+        assert_eq!(corruption.last_good_id, entries[2].id);
+
+        // The entry after the bad one chained from its original id, so it
+        // fails too.
+        let resync = report.resync.clone().unwrap();
+        assert_eq!(resync.offset, frame_offset(&entries, 5));
+        assert_eq!(resync.affected_entries, Some(2));
+        assert_eq!(resync.entries_after, 1);
+
+        let json = serde_json::to_string(&report).unwrap();
+        let report1: DiagnosisReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(report1, report);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_entry_view_rejects_trailing_bytes() {
+        let entry = test_entries(&Hash::default(), 2).pop().unwrap();
+        let mut data = serialize(&entry).unwrap();
+        assert!(EntryView::parse(&data).is_some());
+        data.push(0);
+        assert!(EntryView::parse(&data).is_none());
+        assert!(EntryView::parse(&data[..10]).is_none());
+    }
+
+    #[test]
+    fn test_truncate_then_append() {
+        let path = tmp_ledger_path("truncate");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 8);
+        write_ledger(&path, &zero, &entries).unwrap();
+        assert_eq!(verify_from_trusted(&path).unwrap(), entries[7].id);
+
+        truncate(&path, 3).unwrap();
+        assert_eq!(read_ledger(&path).unwrap().1, &entries[..3]);
+        assert_eq!(read_checkpoint(&path).unwrap().height, 3);
+        assert_matches!(
+            truncate(&path, 4),
+            Err(Error::Ledger(LedgerError::HeightOutOfRange(4)))
+        );
+
+        let mut writer = LedgerWriter::open(&path).unwrap();
+        assert_eq!(writer.last_id(), entries[2].id);
+        let more = next_ticks(&writer.last_id(), 2, 2);
+        writer.write_entries(&more).unwrap();
+        assert_eq!(verify_from_trusted(&path).unwrap(), more[1].id);
+        assert_eq!(read_checkpoint(&path).unwrap().height, 5);
+
+        truncate(&path, 0).unwrap();
+        assert!(read_checkpoint(&path).is_none());
+        assert_eq!(read_ledger(&path).unwrap(), (zero, vec![]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_truncate_corrupt_ledger() {
+        let path = tmp_ledger_path("truncate_corrupt");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 4);
+        write_ledger(&path, &zero, &entries).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+        write_byte(&path, frame_offset(&entries, 3) + 20, 0xff);
+        assert_matches!(truncate(&path, 1), Err(Error::Ledger(LedgerError::BadFrame(_))));
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+
+        let dst = tmp_ledger_path("copy_corrupt");
+        assert_matches!(
+            copy_range(&path, &dst, 0, 2),
+            Err(Error::Ledger(LedgerError::BadFrame(_)))
+        );
+        assert!(!dst.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_index() {
+        let path = tmp_ledger_path("index");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 8);
+        write_ledger(&path, &zero, &entries[..6]).unwrap();
+        assert!(!index_path(&path).exists());
+
+        let mut index = LedgerIndex::open(&path, 6).unwrap();
+        assert_eq!(index.height(), 6);
+        let mut writer = LedgerWriter::open(&path).unwrap();
+        for entry in &entries[6..] {
+            let offset = writer.offset();
+            writer.write_entry(entry).unwrap();
+            index.append(offset).unwrap();
+        }
+        assert_eq!(index.height(), 8);
+        assert_eq!(index.read_entries(0, 8).unwrap(), entries);
+        assert_eq!(index.read_entries(2, 3).unwrap(), &entries[2..5]);
+        assert_eq!(index.read_entries(7, 5).unwrap(), &entries[7..]);
+        assert!(index.read_entries(3, 0).unwrap().is_empty());
+        assert!(index.read_entries(8, 1).unwrap().is_empty());
+        assert!(index.read_entries(u64::MAX, 1).unwrap().is_empty());
+
+        // An index that's missing or doesn't match the ledger is rebuilt.
+        fs::remove_file(index_path(&path)).unwrap();
+        let index = LedgerIndex::open(&path, 8).unwrap();
+        assert_eq!(index.read_entries(5, 8).unwrap(), &entries[5..]);
+        let index = LedgerIndex::open(&path, 9).unwrap();
+        assert_eq!(index.height(), 8);
+        assert_eq!(fs::metadata(index_path(&path)).unwrap().len(), 64);
+
+        truncate(&path, 3).unwrap();
+        assert_eq!(fs::metadata(index_path(&path)).unwrap().len(), 24);
+        let index = LedgerIndex::open(&path, 3).unwrap();
+        assert_eq!(index.read_entries(0, 8).unwrap(), &entries[..3]);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(index_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_copy_range() {
+        let src = tmp_ledger_path("copy_src");
+        let dst = tmp_ledger_path("copy_dst");
+        let zero = Hash::default();
+        let entries = test_entries(&zero, 8);
+        write_ledger(&src, &zero, &entries).unwrap();
+
+        copy_range(&src, &dst, 2, 6).unwrap();
+        let (seed, excerpt) = read_ledger(&dst).unwrap();
+        assert_eq!(seed, entries[1].id);
+        assert_eq!(excerpt, &entries[2..6]);
+        assert_eq!(verify_from_trusted(&dst).unwrap(), entries[5].id);
+        fs::remove_file(checkpoint_path(&dst)).unwrap();
+
+        copy_range(&src, &dst, 0, 8).unwrap();
+        assert_eq!(read_ledger(&dst).unwrap(), (zero, entries.clone()));
+
+        assert_matches!(
+            copy_range(&src, &dst, 0, 9),
+            Err(Error::Ledger(LedgerError::HeightOutOfRange(9)))
+        );
+        assert_matches!(
+            copy_range(&src, &dst, 3, 2),
+            Err(Error::Ledger(LedgerError::HeightOutOfRange(3)))
+        );
+        fs::remove_file(&src).unwrap();
+        fs::remove_file(&dst).unwrap();
+    }
+
+    /// Return `prefix` followed by an entry holding a transaction from a new
+    /// keypair, which no other fork shares, and then two ticks.
+    fn fork(prefix: &[Entry], start_hash: &Hash) -> Vec<Entry> {
+        let id = prefix.last().map_or(*start_hash, |entry| entry.id);
+        let keypair = KeyPair::new();
+        let event = Event::Transaction(Transaction::new(&keypair, keypair.pubkey(), 1, id));
+        let mut entries = prefix.to_vec();
+        entries.push(create_entry(&id, 0, vec![event]));
+        entries.extend(next_ticks(&entries.last().unwrap().id, 1, 2));
+        entries
+    }
+
+    #[test]
+    fn test_find_divergence() {
+        let zero = Hash::default();
+        let prefix = test_entries(&zero, 5);
+        let diverge = |a: &[Entry], b: &[Entry]| {
+            find_divergence(a.iter().cloned(), b.iter().cloned())
+        };
+
+        let (a, b) = (fork(&prefix, &zero), fork(&prefix, &zero));
+        assert_eq!(
+            diverge(&a, &b),
+            Divergence::Forked {
+                height: 5,
+                a_id: a[5].id,
+                b_id: b[5].id,
+            }
+        );
+        let (a, b) = (fork(&[], &zero), fork(&[], &zero));
+        assert_eq!(
+            diverge(&a, &b),
+            Divergence::Forked {
+                height: 0,
+                a_id: a[0].id,
+                b_id: b[0].id,
+            }
+        );
+
+        assert_eq!(diverge(&prefix, &prefix), Divergence::Identical { len: 5 });
+        assert_eq!(diverge(&[], &[]), Divergence::Identical { len: 0 });
+        assert_eq!(diverge(&a, &a[..2]), Divergence::Prefix { shorter_len: 2 });
+        assert_eq!(diverge(&a[..1], &a), Divergence::Prefix { shorter_len: 1 });
+        assert_eq!(diverge(&[], &a), Divergence::Prefix { shorter_len: 0 });
+    }
+
+    #[test]
+    fn test_find_ledger_divergence() {
+        let path_a = tmp_ledger_path("divergence_a");
+        let path_b = tmp_ledger_path("divergence_b");
+        let zero = Hash::default();
+        let prefix = test_entries(&zero, 6);
+        let (a, b) = (fork(&prefix, &zero), fork(&prefix, &zero));
+        write_ledger(&path_a, &zero, &a).unwrap();
+        write_ledger(&path_b, &zero, &b).unwrap();
+        assert_eq!(
+            find_ledger_divergence(&path_a, &path_b).unwrap(),
+            Divergence::Forked {
+                height: 6,
+                a_id: a[6].id,
+                b_id: b[6].id,
+            }
+        );
+
+        write_ledger(&path_b, &zero, &a[..7]).unwrap();
+        assert_eq!(
+            find_ledger_divergence(&path_a, &path_b).unwrap(),
+            Divergence::Prefix { shorter_len: 7 }
+        );
+        assert_eq!(
+            find_ledger_divergence(&path_a, &path_a).unwrap(),
+            Divergence::Identical { len: 9 }
+        );
+
+        // Only the frames before the fork are read.
+        let mut data = fs::read(&path_a).unwrap();
+        let len = data.len();
+        data[len - 1] ^= 1;
+        fs::write(&path_a, &data).unwrap();
+        assert_eq!(
+            find_ledger_divergence(&path_a, &path_b).unwrap(),
+            Divergence::Prefix { shorter_len: 7 }
+        );
+        assert_matches!(
+            find_ledger_divergence(&path_a, &path_a),
+            Err(Error::Ledger(LedgerError::BadFrame(_)))
+        );
+        fs::remove_file(&path_a).unwrap();
+        fs::remove_file(&path_b).unwrap();
+    }
+
+    /// A genesis block, a transfer, and a tick, and the Accountant they
+    /// leave behind.
+    fn transfer_entries() -> (Vec<Entry>, Transaction, Accountant) {
+        let mint = Mint::new(1_000);
+        let mut entries = mint.create_entries();
+        let to = KeyPair::new().pubkey();
+        let tr = Transaction::new(&mint.keypair(), to, 100, entries[1].id);
+        let entry = create_entry(&entries[1].id, 0, vec![Event::Transaction(tr.clone())]);
+        entries.push(next_tick(&entry.id, 1));
+        entries.insert(2, entry);
+        let acc = Accountant::new(&mint);
+        acc.process_verified_entries(&entries[2..]);
+        (entries, tr, acc)
+    }
+
+    #[test]
+    fn test_verify_ledger_file() {
+        let path = tmp_ledger_path("verify_file");
+        let (entries, _, acc) = transfer_entries();
+        let seed = entries[0].id;
+        write_ledger(&path, &seed, &entries).unwrap();
+
+        let report = verify_ledger_file(&path, Some(seed)).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.height, 4);
+        assert_eq!(report.replayed_height, 4);
+        assert_eq!(report.last_id, entries[3].id);
+        assert_eq!(report.state_hash, Some(acc.snapshot_hash()));
+
+        let report = verify_ledger_file(&path, Some(hash(&seed))).unwrap();
+        assert_eq!(report.chain_error, Some(LedgerError::GenesisMismatch(seed)));
+        assert_eq!(report.height, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_ledger_file_corrupt_chain() {
+        let path = tmp_ledger_path("verify_file_chain");
+        let (mut entries, _, _) = transfer_entries();
+        let seed = entries[0].id;
+        entries[3].id = hash(&entries[3].id);
+        write_ledger(&path, &seed, &entries).unwrap();
+
+        let report = verify_ledger_file(&path, None).unwrap();
+        assert_eq!(report.chain_error, Some(LedgerError::VerificationFailed(3)));
+        assert_eq!(report.replay_error, None);
+        assert_eq!((report.height, report.replayed_height), (3, 3));
+        assert_eq!(report.last_id, entries[2].id);
+        assert_eq!(report.state_hash, None);
+
+        // A frame that fails its checksum ends the chain too.
+        entries[3].id = next_tick(&entries[2].id, 1).id;
+        write_ledger(&path, &seed, &entries).unwrap();
+        let offset = frame_offset(&entries, 2);
+        write_byte(&path, offset + FRAME_HEADER_SIZE as u64 + 3, 0xff);
+        let report = verify_ledger_file(&path, None).unwrap();
+        assert_eq!(report.chain_error, Some(LedgerError::BadFrame(offset)));
+        assert_eq!(report.height, 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_ledger_file_double_spend() {
+        let path = tmp_ledger_path("verify_file_double_spend");
+        let (mut entries, tr, _) = transfer_entries();
+        let seed = entries[0].id;
+        let entry = create_entry(&entries[3].id, 0, vec![Event::Transaction(tr)]);
+        entries.push(next_tick(&entry.id, 1));
+        entries.insert(4, entry);
+        write_ledger(&path, &seed, &entries).unwrap();
+
+        let report = verify_ledger_file(&path, Some(seed)).unwrap();
+        assert_eq!(report.chain_error, None);
+        assert_eq!(report.height, 6);
+        assert_eq!(report.last_id, entries[5].id);
+        assert_eq!(report.replayed_height, 4);
+        assert_eq!(
+            report.replay_error,
+            Some(ReplayError::Rejected {
+                height: 4,
+                event: 0,
+                error: AccountingError::InvalidTransferSignature,
+            })
+        );
+        assert_eq!(report.state_hash, None);
+        fs::remove_file(&path).unwrap();
+    }
 }
 
 #[cfg(all(feature = "unstable", test))]
@@ -64,4 +2711,25 @@ mod bench {
             assert!(entries.verify(&start_hash));
         });
     }
+
+    #[bench]
+    fn read_ledger_bench(bencher: &mut Bencher) {
+        let path = tmp_ledger_path("read_bench");
+        let zero = Hash::default();
+        write_ledger(&path, &zero, &test_entries(&zero, 1024)).unwrap();
+        bencher.iter(|| {
+            let (seed, entries) = read_ledger(&path).unwrap();
+            assert!(entries.verify(&seed));
+        });
+    }
+
+    #[bench]
+    fn mapped_ledger_bench(bencher: &mut Bencher) {
+        let path = tmp_ledger_path("mapped_bench");
+        let zero = Hash::default();
+        write_ledger(&path, &zero, &test_entries(&zero, 1024)).unwrap();
+        bencher.iter(|| {
+            assert!(MappedLedger::open(&path).unwrap().verify().is_ok());
+        });
+    }
 }
@@ -1,40 +1,85 @@
 #![cfg_attr(feature = "unstable", feature(test))]
+#[cfg(feature = "native")]
 pub mod accountant;
+#[cfg(feature = "native")]
 pub mod accountant_skel;
+#[cfg(feature = "native")]
 pub mod accountant_stub;
+#[cfg(feature = "native")]
+pub mod bench;
+#[cfg(feature = "native")]
 pub mod ecdsa;
 pub mod entry;
+#[cfg(feature = "native")]
+pub mod entry_broadcaster;
 pub mod event;
+#[cfg(test)]
+mod fixture_files;
+#[cfg(all(test, feature = "native"))]
+mod fixtures;
 pub mod hash;
+pub mod hex;
+#[cfg(feature = "native")]
 pub mod historian;
+#[cfg(feature = "native")]
 pub mod ledger;
+#[cfg(feature = "native")]
 pub mod mint;
+#[cfg(feature = "native")]
+pub mod node;
+#[cfg(feature = "native")]
 pub mod packet;
+pub mod parallel;
 pub mod plan;
+#[cfg(feature = "native")]
+pub mod rate_limiter;
+#[cfg(feature = "native")]
 pub mod recorder;
+#[cfg(feature = "native")]
+pub mod reject_log;
+#[cfg(feature = "native")]
+pub mod repair;
+#[cfg(feature = "native")]
 pub mod result;
 pub mod signature;
+#[cfg(feature = "native")]
+pub mod sigverify;
+#[cfg(feature = "native")]
 pub mod streamer;
+#[cfg(feature = "native")]
 pub mod subscribers;
+#[cfg(any(all(test, feature = "native"), feature = "testing"))]
+pub mod testing;
 pub mod transaction;
 #[cfg(feature = "erasure")]
 pub mod erasure;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 extern crate bincode;
 extern crate byteorder;
 extern crate chrono;
+#[cfg(any(test, not(feature = "native")))]
+extern crate ed25519_compact;
 extern crate generic_array;
 extern crate libc;
+#[cfg(feature = "native")]
 #[macro_use]
 extern crate log;
+#[cfg(feature = "parallel")]
 extern crate rayon;
+#[cfg(feature = "native")]
 extern crate ring;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
 extern crate sha2;
+#[cfg(feature = "native")]
 extern crate untrusted;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
+extern crate proptest;
+
+#[cfg(all(test, feature = "native"))]
 #[macro_use]
 extern crate matches;
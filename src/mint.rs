@@ -1,40 +1,213 @@
-//! The `mint` module is a library for generating the chain's genesis block.
+//! The `mint` module is a library for generating the chain's genesis block,
+//! either directly or from a `GenesisConfig` file describing it.
 
+use accountant::Fees;
+use bincode::serialize;
+use chrono::prelude::*;
 use entry::create_entry;
 use entry::Entry;
 use event::Event;
-use hash::{hash, Hash};
-use ring::rand::SystemRandom;
+use hash::{extend_and_hash, hash, Hash};
+use historian::HistorianConfig;
+use plan::Plan;
+use ring::error::Unspecified;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::Ed25519KeyPair;
+use serde_json;
 use signature::{KeyPair, KeyPairUtil, PublicKey};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
 use transaction::Transaction;
 use untrusted::Input;
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum MintError {
+    /// The shares and grants add up to `sum` rather than the mint's `total`.
+    ShareSumMismatch { total: i64, sum: i128 },
+    NonPositiveShare(PublicKey),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Mint {
     pub pkcs8: Vec<u8>,
     pubkey: PublicKey,
     pub tokens: i64,
+    /// How the genesis block distributes the tokens, sorted by public key.
+    /// If empty, the mint keeps them all.
+    #[serde(default)]
+    shares: Vec<(PublicKey, i64)>,
+    /// Tokens the genesis block holds for a public key until a date, sorted
+    /// by public key.
+    #[serde(default)]
+    grants: Vec<(PublicKey, i64, DateTime<Utc>)>,
+    /// The source of Timestamps that unlock the grants.
+    #[serde(default)]
+    time_source: Option<PublicKey>,
+}
+
+/// A source of randomness that yields a fixed seed, to generate the keypair
+/// it seeds.
+struct SeedRandom(Hash);
+
+impl SecureRandom for SeedRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), Unspecified> {
+        if dest.len() != self.0.len() {
+            return Err(Unspecified);
+        }
+        dest.copy_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+/// Return the keypair seeded with the hash of `phrase`.
+pub fn seeded_keypair(phrase: &str) -> KeyPair {
+    Ed25519KeyPair::from_seed_unchecked(Input::from(&hash(phrase.as_bytes()))).unwrap()
 }
 
 impl Mint {
     pub fn new(tokens: i64) -> Self {
-        let rnd = SystemRandom::new();
-        let pkcs8 = KeyPair::generate_pkcs8(&rnd).unwrap().to_vec();
+        Mint::new_with_rng(&SystemRandom::new(), tokens)
+    }
+
+    /// Create a Mint whose keypair is `seeded_keypair(phrase)`, so that the
+    /// same phrase always creates the same genesis block.
+    pub fn new_from_phrase(phrase: &str, tokens: i64) -> Self {
+        Mint::new_with_rng(&SeedRandom(hash(phrase.as_bytes())), tokens)
+    }
+
+    fn new_with_rng(rnd: &dyn SecureRandom, tokens: i64) -> Self {
+        let pkcs8 = KeyPair::generate_pkcs8(rnd).unwrap().to_vec();
         let keypair = KeyPair::from_pkcs8(Input::from(&pkcs8)).unwrap();
         let pubkey = keypair.pubkey();
         Mint {
             pkcs8,
             pubkey,
             tokens,
+            shares: vec![],
+            grants: vec![],
+            time_source: None,
+        }
+    }
+
+    /// Create a Mint whose genesis block issues each share of `total`
+    /// tokens to its public key. Every share must be positive and the shares
+    /// must add up to `total`.
+    pub fn new_with_distribution(
+        total: i64,
+        shares: &[(PublicKey, i64)],
+    ) -> Result<Self, MintError> {
+        let mut mint = Mint::new(total);
+        mint.set_shares(shares)?;
+        Ok(mint)
+    }
+
+    /// Create a Mint that splits `total` tokens into `amounts`, each issued
+    /// to a keypair derived from the mint's seed. See `keypairs`.
+    pub fn new_with_derived_keypairs(total: i64, amounts: &[i64]) -> Result<Self, MintError> {
+        let mut mint = Mint::new(total);
+        let shares: Vec<_> = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, tokens)| (mint.derived_keypair(i).pubkey(), *tokens))
+            .collect();
+        mint.set_shares(&shares)?;
+        Ok(mint)
+    }
+
+    /// Create a Mint whose genesis block issues each share of `total` tokens
+    /// and holds each grant until `time_source` witnesses its unlock date.
+    /// Every share and grant must be positive and together they must add up
+    /// to `total`.
+    pub fn new_with_grants(
+        total: i64,
+        shares: &[(PublicKey, i64)],
+        grants: &[(PublicKey, i64, DateTime<Utc>)],
+        time_source: PublicKey,
+    ) -> Result<Self, MintError> {
+        let mut mint = Mint::new(total);
+        mint.set_distribution(shares, grants)?;
+        mint.time_source = Some(time_source);
+        Ok(mint)
+    }
+
+    fn set_shares(&mut self, shares: &[(PublicKey, i64)]) -> Result<(), MintError> {
+        self.set_distribution(shares, &[])
+    }
+
+    fn set_distribution(
+        &mut self,
+        shares: &[(PublicKey, i64)],
+        grants: &[(PublicKey, i64, DateTime<Utc>)],
+    ) -> Result<(), MintError> {
+        let amounts = shares
+            .iter()
+            .map(|x| (x.0, x.1))
+            .chain(grants.iter().map(|x| (x.0, x.1)));
+        if let Some(amount) = amounts.clone().find(|x| x.1 <= 0) {
+            return Err(MintError::NonPositiveShare(amount.0));
+        }
+        let sum = amounts.map(|x| i128::from(x.1)).sum();
+        if sum != i128::from(self.tokens) {
+            return Err(MintError::ShareSumMismatch {
+                total: self.tokens,
+                sum,
+            });
+        }
+        self.shares = shares.to_vec();
+        self.shares.sort();
+        self.grants = grants.to_vec();
+        self.grants.sort();
+        Ok(())
+    }
+
+    fn derived_keypair(&self, index: usize) -> KeyPair {
+        let seed = extend_and_hash(&self.seed(), &serialize(&(index as u64)).unwrap());
+        Ed25519KeyPair::from_seed_unchecked(Input::from(&seed)).unwrap()
+    }
+
+    /// Return the keypairs of the shares of a Mint created by
+    /// `new_with_derived_keypairs`, in the order of its `amounts`, or `None`
+    /// if the shares' keys weren't derived from the mint's seed.
+    pub fn keypairs(&self) -> Option<Vec<KeyPair>> {
+        let keypairs: Vec<_> = (0..self.shares.len())
+            .map(|i| self.derived_keypair(i))
+            .collect();
+        let all_derived = keypairs
+            .iter()
+            .all(|keypair| self.shares.iter().any(|x| x.0 == keypair.pubkey()));
+        if all_derived && !keypairs.is_empty() {
+            Some(keypairs)
+        } else {
+            None
         }
     }
 
+    /// Return the public keys and tokens the genesis block issues, sorted by
+    /// public key. Empty if the mint keeps all the tokens.
+    pub fn shares(&self) -> &[(PublicKey, i64)] {
+        &self.shares
+    }
+
+    /// Return the public keys, tokens, and unlock dates of the grants the
+    /// genesis block holds, sorted by public key.
+    pub fn grants(&self) -> &[(PublicKey, i64, DateTime<Utc>)] {
+        &self.grants
+    }
+
+    /// Return the source of Timestamps the genesis block names, if any.
+    pub fn time_source(&self) -> Option<PublicKey> {
+        self.time_source
+    }
+
     pub fn seed(&self) -> Hash {
         hash(&self.pkcs8)
     }
 
     pub fn last_id(&self) -> Hash {
-        self.create_entries()[1].id
+        self.create_entries().last().unwrap().id
     }
 
     pub fn keypair(&self) -> KeyPair {
@@ -51,18 +224,383 @@ impl Mint {
         vec![Event::Transaction(tr)]
     }
 
+    /// Return the events that issue each share, spending from the deposit in
+    /// the entry with id `last_id`.
+    pub fn create_share_events(&self, last_id: &Hash) -> Vec<Event> {
+        let keypair = self.keypair();
+        self.shares
+            .iter()
+            .map(|&(to, tokens)| {
+                let tr = Transaction::new(&keypair, to, tokens, *last_id);
+                Event::Transaction(tr)
+            })
+            .collect()
+    }
+
+    /// Return the events that name the mint's time source and hold each
+    /// grant until its unlock date, spending from the deposit in the entry
+    /// with id `last_id`.
+    pub fn create_grant_events(&self, last_id: &Hash) -> Vec<Event> {
+        let keypair = self.keypair();
+        let time_source = self.time_source
+            .map(|source| Event::new_time_source(&keypair, source));
+        let grants = self.grants.iter().map(|&(to, tokens, dt)| {
+            let plan = Plan::new_future_payment(dt, tokens, to);
+            let tr = Transaction::new_with_plan(&keypair, plan, tokens, *last_id);
+            Event::Transaction(tr)
+        });
+        time_source.into_iter().chain(grants).collect()
+    }
+
+    /// Return the genesis block: an empty entry whose id is the seed, an
+    /// entry depositing every token with the mint, and, if the tokens are
+    /// distributed, an entry issuing the shares and grants.
     pub fn create_entries(&self) -> Vec<Entry> {
         let e0 = create_entry(&self.seed(), 0, vec![]);
         let e1 = create_entry(&e0.id, 0, self.create_events());
-        vec![e0, e1]
+        let mut events = self.create_share_events(&e1.id);
+        events.extend(self.create_grant_events(&e1.id));
+        if events.is_empty() {
+            return vec![e0, e1];
+        }
+        let e2 = create_entry(&e1.id, 0, events);
+        vec![e0, e1, e2]
+    }
+}
+
+/// A public key in a `GenesisConfig`. In JSON, either `{"pubkey": [...]}`
+/// with the key's 32 bytes or `{"seed": "..."}`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Account {
+    Pubkey(PublicKey),
+    /// The key of `seeded_keypair` of the phrase.
+    Seed(String),
+}
+
+impl Account {
+    pub fn pubkey(&self) -> PublicKey {
+        match *self {
+            Account::Pubkey(pubkey) => pubkey,
+            Account::Seed(ref phrase) => seeded_keypair(phrase).pubkey(),
+        }
+    }
+}
+
+/// Tokens the genesis block issues to an account.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Allocation {
+    pub account: Account,
+    pub tokens: i64,
+}
+
+/// Tokens the genesis block holds for an account until a date.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Grant {
+    pub account: Account,
+    pub tokens: i64,
+    pub unlock: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FeeConfig {
+    pub collector: Account,
+    pub per_transaction: i64,
+}
+
+/// The parameters of a new chain, as read from a JSON file.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisConfig {
+    /// The phrase the mint's keypair is seeded with.
+    pub mint_seed: String,
+    pub total_supply: i64,
+    /// The tokens issued to each account. The mint keeps what's left of the
+    /// supply after the distribution and grants.
+    #[serde(default)]
+    pub distribution: Vec<Allocation>,
+    #[serde(default)]
+    pub grants: Vec<Grant>,
+    /// The source of the Timestamps that unlock the grants.
+    #[serde(default)]
+    pub time_source: Option<Account>,
+    #[serde(default)]
+    pub hashes_per_tick: Option<u64>,
+    #[serde(default)]
+    pub tick_duration_ms: Option<u64>,
+    #[serde(default)]
+    pub fees: Option<FeeConfig>,
+}
+
+/// The part of a `GenesisConfig` an error is in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Location {
+    Field(&'static str),
+    /// An element of `distribution`, by index.
+    Distribution(usize),
+    /// An element of `grants`, by index.
+    Grant(usize),
+}
+
+/// An inconsistency in a `GenesisConfig`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GenesisConfigError {
+    NonPositiveSupply,
+    NonPositiveAmount(Location),
+    /// An account was in the distribution, or the grants, twice.
+    DuplicateKey(Location),
+    /// The distribution and grants up to `location` add up to `sum`, more
+    /// than the supply.
+    ExceedsSupply {
+        location: Location,
+        total: i64,
+        sum: i128,
+    },
+    GrantsWithoutTimeSource,
+    ConflictingTickModes,
+    ZeroHashesPerTick,
+    NegativeFee,
+}
+
+impl GenesisConfigError {
+    pub fn location(&self) -> Location {
+        match *self {
+            GenesisConfigError::NonPositiveSupply => Location::Field("total_supply"),
+            GenesisConfigError::NonPositiveAmount(location)
+            | GenesisConfigError::DuplicateKey(location)
+            | GenesisConfigError::ExceedsSupply { location, .. } => location,
+            GenesisConfigError::GrantsWithoutTimeSource => Location::Field("grants"),
+            GenesisConfigError::ConflictingTickModes => Location::Field("tick_duration_ms"),
+            GenesisConfigError::ZeroHashesPerTick => Location::Field("hashes_per_tick"),
+            GenesisConfigError::NegativeFee => Location::Field("fees"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GenesisError {
+    IO(io::Error),
+    /// The file isn't a genesis config. The error holds the line and column.
+    Parse(serde_json::Error),
+    /// The config is inconsistent at `line` of the file, if it's there.
+    Invalid {
+        line: Option<usize>,
+        error: GenesisConfigError,
+    },
+}
+
+/// Return the line of `text`, a JSON object, that names its field `key` or,
+/// with `index`, that starts the element at `index` of the array in that
+/// field. Lines count from 1.
+fn json_line(text: &str, key: &str, index: Option<usize>) -> Option<usize> {
+    let mut line = 1;
+    let mut depth = 0;
+    let mut expect_key = false;
+    let mut found = false;
+    let mut in_array = false;
+    let mut element_next = false;
+    let mut elements = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        if in_array && depth == 2 && element_next && c != ']' {
+            if Some(elements) == index {
+                return Some(line);
+            }
+            elements += 1;
+            element_next = false;
+        }
+        match c {
+            '"' => {
+                let mut s = String::new();
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => (),
+                    }
+                    s.push(c);
+                }
+                if depth == 1 && expect_key {
+                    expect_key = false;
+                    if s == key {
+                        if index.is_none() {
+                            return Some(line);
+                        }
+                        found = true;
+                    }
+                }
+            }
+            '{' | '[' => {
+                depth += 1;
+                expect_key = depth == 1;
+                if c == '[' && depth == 2 && found {
+                    in_array = true;
+                    element_next = true;
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                if in_array && depth == 1 {
+                    return None;
+                }
+            }
+            ',' if depth == 1 => {
+                if found {
+                    return None;
+                }
+                expect_key = true;
+            }
+            ',' if in_array && depth == 2 => element_next = true,
+            _ => (),
+        }
+    }
+    None
+}
+
+impl GenesisConfig {
+    /// Parse and validate the config in `text`.
+    pub fn from_json(text: &str) -> Result<Self, GenesisError> {
+        let config: GenesisConfig = serde_json::from_str(text).map_err(GenesisError::Parse)?;
+        config.validate().map_err(|error| {
+            let line = match error.location() {
+                Location::Field(name) => json_line(text, name, None),
+                Location::Distribution(i) => json_line(text, "distribution", Some(i)),
+                Location::Grant(i) => json_line(text, "grants", Some(i)),
+            };
+            GenesisError::Invalid { line, error }
+        })?;
+        Ok(config)
+    }
+
+    /// Read, parse, and validate the config at `path`.
+    pub fn load(path: &Path) -> Result<Self, GenesisError> {
+        let text = fs::read_to_string(path).map_err(GenesisError::IO)?;
+        GenesisConfig::from_json(&text)
+    }
+
+    /// Check that the config describes a genesis block.
+    pub fn validate(&self) -> Result<(), GenesisConfigError> {
+        if self.total_supply <= 0 {
+            return Err(GenesisConfigError::NonPositiveSupply);
+        }
+        let amounts = self.distribution
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (Location::Distribution(i), &x.account, x.tokens))
+            .chain(
+                self.grants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| (Location::Grant(i), &x.account, x.tokens)),
+            );
+        let mut shares = HashSet::new();
+        let mut grants = HashSet::new();
+        let mut sum = 0;
+        for (location, account, tokens) in amounts {
+            if tokens <= 0 {
+                return Err(GenesisConfigError::NonPositiveAmount(location));
+            }
+            let keys = match location {
+                Location::Grant(_) => &mut grants,
+                _ => &mut shares,
+            };
+            if !keys.insert(account.pubkey()) {
+                return Err(GenesisConfigError::DuplicateKey(location));
+            }
+            sum += i128::from(tokens);
+            if sum > i128::from(self.total_supply) {
+                return Err(GenesisConfigError::ExceedsSupply {
+                    location,
+                    total: self.total_supply,
+                    sum,
+                });
+            }
+        }
+        if !self.grants.is_empty() && self.time_source.is_none() {
+            return Err(GenesisConfigError::GrantsWithoutTimeSource);
+        }
+        if self.hashes_per_tick.is_some() && self.tick_duration_ms.is_some() {
+            return Err(GenesisConfigError::ConflictingTickModes);
+        }
+        if self.hashes_per_tick == Some(0) {
+            return Err(GenesisConfigError::ZeroHashesPerTick);
+        }
+        if self.fees.as_ref().is_some_and(|x| x.per_transaction < 0) {
+            return Err(GenesisConfigError::NegativeFee);
+        }
+        Ok(())
+    }
+
+    /// Return the mint the config describes.
+    pub fn mint(&self) -> Result<Mint, GenesisConfigError> {
+        self.validate()?;
+        let mut mint = Mint::new_from_phrase(&self.mint_seed, self.total_supply);
+        let mut shares: Vec<_> = self.distribution
+            .iter()
+            .map(|x| (x.account.pubkey(), x.tokens))
+            .collect();
+        let grants: Vec<_> = self.grants
+            .iter()
+            .map(|x| (x.account.pubkey(), x.tokens, x.unlock))
+            .collect();
+        let issued: i64 = shares.iter().map(|x| x.1).chain(grants.iter().map(|x| x.1)).sum();
+        let remainder = self.total_supply - issued;
+        if remainder > 0 && !(shares.is_empty() && grants.is_empty()) {
+            let pubkey = mint.pubkey();
+            match shares.iter().position(|x| x.0 == pubkey) {
+                Some(i) => shares[i].1 += remainder,
+                None => shares.push((pubkey, remainder)),
+            }
+        }
+        mint.set_distribution(&shares, &grants)
+            .expect("a validated config adds up");
+        mint.time_source = self.time_source.as_ref().map(Account::pubkey);
+        Ok(mint)
+    }
+
+    /// Return the genesis block the config describes.
+    pub fn create_entries(&self) -> Result<Vec<Entry>, GenesisConfigError> {
+        Ok(self.mint()?.create_entries())
+    }
+
+    /// Return how the chain ticks. With neither `hashes_per_tick` nor
+    /// `tick_duration_ms` set, it only ticks when told to.
+    pub fn historian_config(&self) -> HistorianConfig {
+        HistorianConfig {
+            tick_duration: self.tick_duration_ms.map(Duration::from_millis),
+            hashes_per_tick: self.hashes_per_tick,
+            ..HistorianConfig::default()
+        }
+    }
+
+    /// Return the fees the chain charges, if any.
+    pub fn fees(&self) -> Option<Fees> {
+        self.fees.as_ref().map(|x| Fees {
+            collector: x.collector.pubkey(),
+            per_transaction: x.per_transaction,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use accountant::Accountant;
     use ledger::Block;
     use plan::Plan;
+    use serde_json;
+    use std::path::PathBuf;
 
     #[test]
     fn test_create_events() {
@@ -80,4 +618,240 @@ mod tests {
         let entries = Mint::new(100).create_entries();
         assert!(entries[..].verify(&entries[0].id));
     }
+
+    #[test]
+    fn test_distribution() {
+        let pubkeys: Vec<_> = (0..3).map(|_| KeyPair::new().pubkey()).collect();
+        let shares = vec![(pubkeys[0], 50), (pubkeys[1], 30), (pubkeys[2], 20)];
+        let mint = Mint::new_with_distribution(100, &shares).unwrap();
+        let entries = mint.create_entries();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[..].verify(&entries[0].id));
+        assert_eq!(entries[2].events.len(), 3);
+        assert_eq!(mint.last_id(), entries[2].id);
+        assert!(mint.keypairs().is_none());
+
+        let acc = Accountant::new(&mint);
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(0));
+        for &(pubkey, tokens) in &shares {
+            assert_eq!(acc.get_balance(&pubkey), Some(tokens));
+        }
+    }
+
+    #[test]
+    fn test_bad_distribution() {
+        let pubkey = KeyPair::new().pubkey();
+        let shares = vec![(pubkey, 50), (KeyPair::new().pubkey(), 30)];
+        assert_eq!(
+            Mint::new_with_distribution(100, &shares).err(),
+            Some(MintError::ShareSumMismatch {
+                total: 100,
+                sum: 80,
+            })
+        );
+        let shares = vec![(KeyPair::new().pubkey(), 101), (pubkey, -1)];
+        assert_eq!(
+            Mint::new_with_distribution(100, &shares).err(),
+            Some(MintError::NonPositiveShare(pubkey))
+        );
+    }
+
+    #[test]
+    fn test_derived_keypairs() {
+        let mint = Mint::new_with_derived_keypairs(100, &[10, 60, 30]).unwrap();
+        let keypairs = mint.keypairs().unwrap();
+        assert_eq!(keypairs.len(), 3);
+        let acc = Accountant::new(&mint);
+        for (keypair, tokens) in keypairs.iter().zip(&[10, 60, 30]) {
+            assert_eq!(acc.get_balance(&keypair.pubkey()), Some(*tokens));
+        }
+
+        // The derived keys can spend from the genesis block.
+        let to = KeyPair::new().pubkey();
+        acc.transfer(10, &keypairs[1], to, mint.last_id()).unwrap();
+        assert_eq!(acc.get_balance(&to), Some(10));
+    }
+
+    #[test]
+    fn test_distribution_is_deterministic() {
+        let mint = Mint::new_with_derived_keypairs(100, &[1, 2, 97]).unwrap();
+        let json = serde_json::to_string(&mint).unwrap();
+        let copy: Mint = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.shares(), mint.shares());
+        assert_eq!(copy.create_entries(), mint.create_entries());
+
+        // Shares are issued in the same order however they're given.
+        let pubkeys: Vec<_> = (0..3).map(|_| KeyPair::new().pubkey()).collect();
+        let shares = vec![(pubkeys[0], 1), (pubkeys[1], 2), (pubkeys[2], 3)];
+        let mut reversed = shares.clone();
+        reversed.reverse();
+        let mint = Mint::new_with_distribution(6, &shares).unwrap();
+        let mut mint_reversed = Mint::new_with_distribution(6, &reversed).unwrap();
+        mint_reversed.pkcs8 = mint.pkcs8.clone();
+        mint_reversed.pubkey = mint.pubkey;
+        assert_eq!(mint_reversed.create_entries(), mint.create_entries());
+    }
+
+    fn total_supply(acc: &Accountant) -> i64 {
+        let balances: i64 = acc.collect_balances().iter().map(|x| x.1).sum();
+        balances + acc.pending_tokens()
+    }
+
+    #[test]
+    fn test_grants() {
+        let oracle = KeyPair::new();
+        let (alice, bob) = (KeyPair::new().pubkey(), KeyPair::new().pubkey());
+        let date = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let grants = vec![
+            (alice, 30, date("2019-01-01T00:00:00Z")),
+            (bob, 20, date("2020-01-01T00:00:00Z")),
+        ];
+        let mint = Mint::new_with_grants(100, &[(alice, 50)], &grants, oracle.pubkey()).unwrap();
+        let entries = mint.create_entries();
+        assert!(entries[..].verify(&entries[0].id));
+        assert_eq!(entries[2].events.len(), 4);
+        assert_eq!(mint.create_entries(), entries);
+
+        let acc = Accountant::new(&mint);
+        assert_eq!(acc.get_balance(&alice), Some(50));
+        assert_eq!(acc.get_balance(&bob), None);
+        assert_eq!(acc.pending_tokens(), 50);
+        assert_eq!(total_supply(&acc), 100);
+
+        // Only the named time source can unlock the grants.
+        let event = Event::new_timestamp(&mint.keypair(), date("2021-01-01T00:00:00Z"));
+        assert!(acc.process_witness(&event).is_empty());
+        assert_eq!(acc.get_balance(&alice), Some(50));
+
+        let event = Event::new_timestamp(&oracle, date("2019-06-01T00:00:00Z"));
+        acc.process_verified_event(&event).unwrap();
+        assert_eq!(acc.get_balance(&alice), Some(80));
+        assert_eq!(acc.get_balance(&bob), None);
+        assert_eq!(total_supply(&acc), 100);
+
+        let event = Event::new_timestamp(&oracle, date("2020-01-01T00:00:00Z"));
+        acc.process_verified_event(&event).unwrap();
+        assert_eq!(acc.get_balance(&bob), Some(20));
+        assert_eq!(acc.pending_tokens(), 0);
+        assert_eq!(total_supply(&acc), 100);
+    }
+
+    #[test]
+    fn test_bad_grants() {
+        let pubkey = KeyPair::new().pubkey();
+        let dt = Utc::now();
+        let oracle = KeyPair::new().pubkey();
+        assert_eq!(
+            Mint::new_with_grants(100, &[(pubkey, 50)], &[(pubkey, 40, dt)], oracle).err(),
+            Some(MintError::ShareSumMismatch {
+                total: 100,
+                sum: 90,
+            })
+        );
+        assert_eq!(
+            Mint::new_with_grants(100, &[(pubkey, 100)], &[(oracle, 0, dt)], oracle).err(),
+            Some(MintError::NonPositiveShare(oracle))
+        );
+    }
+
+    fn genesis_fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/genesis")
+            .join(format!("{}.json", name))
+    }
+
+    #[test]
+    fn test_genesis_config() {
+        let config = GenesisConfig::load(&genesis_fixture("valid")).unwrap();
+        let entries = config.create_entries().unwrap();
+        assert!(entries[..].verify(&entries[0].id));
+        let again = GenesisConfig::load(&genesis_fixture("valid")).unwrap();
+        assert_eq!(again.create_entries().unwrap(), entries);
+        let json = serde_json::to_string(&config).unwrap();
+        let copy = GenesisConfig::from_json(&json).unwrap();
+        assert_eq!(copy, config);
+        assert_eq!(copy.create_entries().unwrap(), entries);
+
+        let mint = config.mint().unwrap();
+        assert_eq!(mint.pubkey(), seeded_keypair("testnet mint").pubkey());
+        let acc = Accountant::new(&mint);
+        let pubkey = PublicKey::clone_from_slice(&(1..33).collect::<Vec<u8>>());
+        assert_eq!(acc.get_balance(&seeded_keypair("alice").pubkey()), Some(500_000));
+        assert_eq!(acc.get_balance(&pubkey), Some(200_000));
+        assert_eq!(acc.get_balance(&mint.pubkey()), Some(200_000));
+        assert_eq!(acc.get_balance(&seeded_keypair("bob").pubkey()), None);
+        assert_eq!(acc.pending_tokens(), 100_000);
+        assert_eq!(mint.time_source(), Some(seeded_keypair("oracle").pubkey()));
+
+        assert_eq!(config.historian_config().hashes_per_tick, Some(1000));
+        assert_eq!(config.historian_config().tick_duration, None);
+        let fees = config.fees().unwrap();
+        assert_eq!(fees.collector, seeded_keypair("collector").pubkey());
+        assert_eq!(fees.per_transaction, 1);
+    }
+
+    #[test]
+    fn test_bad_genesis_configs() {
+        use self::GenesisConfigError::*;
+        let cases = vec![
+            (
+                "exceeds_supply",
+                9,
+                ExceedsSupply {
+                    location: Location::Grant(0),
+                    total: 1000,
+                    sum: 1100,
+                },
+            ),
+            ("duplicate_key", 7, DuplicateKey(Location::Distribution(2))),
+            ("non_positive_amount", 5, NonPositiveAmount(Location::Distribution(0))),
+            ("non_positive_supply", 3, NonPositiveSupply),
+            ("grants_without_time_source", 4, GrantsWithoutTimeSource),
+            ("conflicting_tick_modes", 5, ConflictingTickModes),
+            ("zero_hashes_per_tick", 4, ZeroHashesPerTick),
+            ("negative_fee", 4, NegativeFee),
+        ];
+        for (name, line, error) in cases {
+            match GenesisConfig::load(&genesis_fixture(name)) {
+                Err(GenesisError::Invalid { line: l, error: e }) => {
+                    assert_eq!((name, l, e), (name, Some(line), error))
+                }
+                x => panic!("{}: {:?}", name, x),
+            }
+        }
+        assert_matches!(
+            GenesisConfig::load(&genesis_fixture("unknown_field")),
+            Err(GenesisError::Parse(ref e)) if e.line() == 4
+        );
+        assert_matches!(
+            GenesisConfig::load(&genesis_fixture("missing")),
+            Err(GenesisError::IO(_))
+        );
+    }
+
+    #[test]
+    fn test_json_line() {
+        let text = "{\n  \"a\": \"[b\\\",\",\n  \"b\": [1,\n    {\"b\": 2}, [3,\n 4]]\n}";
+        assert_eq!(json_line(text, "a", None), Some(2));
+        assert_eq!(json_line(text, "b", None), Some(3));
+        assert_eq!(json_line(text, "b", Some(0)), Some(3));
+        assert_eq!(json_line(text, "b", Some(1)), Some(4));
+        assert_eq!(json_line(text, "b", Some(2)), Some(4));
+        assert_eq!(json_line(text, "b", Some(3)), None);
+        assert_eq!(json_line(text, "a", Some(0)), None);
+        assert_eq!(json_line(text, "c", None), None);
+    }
+
+    #[test]
+    fn test_mint_without_shares_field() {
+        let mint = Mint::new(100);
+        let json = format!(
+            "{{\"pkcs8\":{:?},\"pubkey\":{:?},\"tokens\":100}}",
+            mint.pkcs8,
+            mint.pubkey.to_vec()
+        );
+        let copy: Mint = serde_json::from_str(&json).unwrap();
+        assert!(copy.shares().is_empty());
+        assert_eq!(copy.create_entries(), mint.create_entries());
+    }
 }
@@ -0,0 +1,563 @@
+//! The `node` module starts an accountant server from a genesis block, or,
+//! if it keeps its state on disk, from where the node last left off.
+
+use accountant::{Accountant, AccountantSnapshot, Fees};
+use accountant_skel::AccountantSkel;
+use byteorder::{ByteOrder, LittleEndian};
+use entry::Entry;
+use hash::Hash;
+use historian::{Historian, HistorianConfig, ResumeInfo};
+use ledger::{self, frame_checksum, LedgerIndex, LedgerReader, LedgerWriter};
+use reject_log::{RejectLog, Rejection};
+use result::{Error, Result};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// The name of the ledger file in a node's ledger directory.
+pub const LEDGER_FILE: &str = "ledger";
+/// The name of the accountant snapshot in a node's ledger directory.
+pub const SNAPSHOT_FILE: &str = "snapshot";
+/// The number of entries appended between snapshots by default.
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 1024;
+/// The ledger height the snapshot was taken at and the id of the entry
+/// before it, followed by the accountant snapshot and a checksum.
+const SNAPSHOT_PREFIX_SIZE: usize = 40;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NodeError {
+    /// The genesis entries don't start with a seed entry and a deposit.
+    BadGenesis,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// The directory to append the node's entries and snapshot its
+    /// accountant to, and to resume from on startup. With none, the node
+    /// only keeps its state in memory.
+    pub ledger_path: Option<PathBuf>,
+    /// The number of entries appended to the ledger between snapshots.
+    pub snapshot_interval: u64,
+    pub historian: HistorianConfig,
+    /// The fees the accountant charges, which must be the same each time
+    /// a ledger is resumed.
+    pub fees: Option<Fees>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            ledger_path: None,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            historian: HistorianConfig::default(),
+            fees: None,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Return whether there's a ledger to resume from, in which case the
+    /// node ignores the genesis block it's given.
+    pub fn has_ledger(&self) -> bool {
+        self.ledger_file().is_some_and(|path| path.exists())
+    }
+
+    fn ledger_file(&self) -> Option<PathBuf> {
+        self.ledger_path.as_ref().map(|dir| dir.join(LEDGER_FILE))
+    }
+}
+
+/// The state a node keeps on disk: a ledger of every entry, indexed by
+/// height, and a recent snapshot of its accountant.
+pub struct Persistence {
+    writer: LedgerWriter,
+    index: LedgerIndex,
+    snapshot_path: PathBuf,
+    interval: u64,
+    /// The height of the ledger at the last snapshot.
+    snapshot_height: u64,
+}
+
+impl Persistence {
+    /// Append `entry` to the ledger.
+    pub fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let offset = self.writer.offset();
+        self.writer.write_entry(entry)?;
+        self.index.append(offset)
+    }
+
+    /// The number of entries in the ledger.
+    pub fn height(&self) -> u64 {
+        self.writer.height()
+    }
+
+    /// Read up to `max_count` of the ledger's entries from `start_height` on.
+    pub fn read_entries(&self, start_height: u64, max_count: usize) -> Result<Vec<Entry>> {
+        self.index.read_entries(start_height, max_count)
+    }
+
+    /// Return whether enough entries were appended since the last snapshot
+    /// to take another.
+    pub fn snapshot_due(&self) -> bool {
+        self.writer.height() >= self.snapshot_height + self.interval
+    }
+
+    /// Flush the ledger to disk and then snapshot `acc`, which must have
+    /// processed exactly the events of the ledger's entries.
+    pub fn snapshot(&mut self, acc: &Accountant) -> Result<()> {
+        self.writer.sync()?;
+        let height = self.writer.height();
+        write_snapshot(
+            &self.snapshot_path,
+            height,
+            &self.writer.last_id(),
+            &acc.snapshot(),
+        )?;
+        self.snapshot_height = height;
+        Ok(())
+    }
+
+    /// The id of the last entry appended.
+    pub fn last_id(&self) -> Hash {
+        self.writer.last_id()
+    }
+}
+
+/// Record `snapshot` of an accountant that has processed the first `height`
+/// entries of a ledger, the last of which is `last_id`. The file is replaced
+/// atomically so that a crash leaves either the old or new snapshot.
+pub fn write_snapshot(
+    path: &Path,
+    height: u64,
+    last_id: &Hash,
+    snapshot: &AccountantSnapshot,
+) -> Result<()> {
+    let mut data = vec![0u8; 8];
+    LittleEndian::write_u64(&mut data, height);
+    data.extend_from_slice(last_id);
+    data.extend(snapshot.to_bytes());
+    let mut checksum = [0u8; 8];
+    LittleEndian::write_u64(&mut checksum, frame_checksum(&data));
+    data.extend_from_slice(&checksum);
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Read the snapshot at `path` along with the height and last id of the
+/// ledger it was taken at. A missing or corrupt snapshot yields None.
+pub fn read_snapshot(path: &Path) -> Option<(u64, Hash, AccountantSnapshot)> {
+    let mut data = vec![];
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .ok()?;
+    if data.len() < SNAPSHOT_PREFIX_SIZE + 8 {
+        return None;
+    }
+    let (data, checksum) = data.split_at(data.len() - 8);
+    if frame_checksum(data) != LittleEndian::read_u64(checksum) {
+        return None;
+    }
+    let height = LittleEndian::read_u64(&data[..8]);
+    let last_id = Hash::clone_from_slice(&data[8..SNAPSHOT_PREFIX_SIZE]);
+    let snapshot = AccountantSnapshot::from_bytes(&data[SNAPSHOT_PREFIX_SIZE..]).ok()?;
+    Some((height, last_id, snapshot))
+}
+
+/// Create an accountant from a genesis block and the entries after it.
+/// The first entry is the seed, and the second deposits the mint's tokens.
+fn replay_genesis(entries: &[Entry], fees: Option<Fees>) -> Result<Accountant> {
+    let mut acc =
+        Accountant::new_from_genesis(entries).ok_or(Error::Node(NodeError::BadGenesis))?;
+    acc.set_fees(fees);
+    acc.process_verified_entries(&entries[2..]);
+    Ok(acc)
+}
+
+/// Read the first `height` entries of `reader` and return the id of the
+/// last, or None if the ledger has fewer.
+fn skip_entries(reader: &mut LedgerReader, height: u64) -> Result<Option<Hash>> {
+    let mut last_id = None;
+    for _ in 0..height {
+        match reader.read_entry()? {
+            Some(entry) => last_id = Some(entry.id),
+            None => return Ok(None),
+        }
+    }
+    Ok(last_id)
+}
+
+/// Restore the accountant the verified ledger at `path` describes, from
+/// the snapshot at `snapshot_path` if it matches the ledger. Returns the
+/// accountant and the height of the snapshot it was restored from.
+fn restore(path: &Path, snapshot_path: &Path, fees: Option<Fees>) -> Result<(Accountant, u64)> {
+    let mut reader = LedgerReader::open(path)?;
+    if let Some((height, last_id, snapshot)) = read_snapshot(snapshot_path) {
+        if skip_entries(&mut reader, height)? == Some(last_id) {
+            let entries: Vec<Entry> = reader.collect::<Result<_>>()?;
+            let mut acc = Accountant::from_snapshot(snapshot);
+            acc.set_fees(fees);
+            acc.process_verified_entries(&entries);
+            return Ok((acc, height));
+        }
+        warn!("snapshot doesn't match the ledger, replaying the whole ledger");
+    }
+    let (_, entries) = ledger::read_ledger(path)?;
+    Ok((replay_genesis(&entries, fees)?, 0))
+}
+
+pub struct Node<W: Write + Send + 'static> {
+    pub skel: Arc<Mutex<AccountantSkel<W>>>,
+    /// Where the node resumed its ledger, if it did.
+    pub resumed: Option<ResumeInfo>,
+    rejections: Arc<RejectLog>,
+    exit: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<W: Write + Send + 'static> Node<W> {
+    /// Create a node as `config` describes, writing its entries to `writer`.
+    /// If its ledger directory holds a ledger, the node verifies it, trusting
+    /// the entries up to its checkpoint, and restores the accountant from
+    /// the latest snapshot and the entries after it. Otherwise the node
+    /// starts from `genesis`, and begins a new ledger with it if it has a
+    /// ledger directory.
+    pub fn new(config: &NodeConfig, genesis: &[Entry], writer: W) -> Result<Self> {
+        let ledger_file = match config.ledger_file() {
+            Some(path) => path,
+            None => {
+                let acc = replay_genesis(genesis, config.fees)?;
+                let last_id = genesis[genesis.len() - 1].id;
+                let historian = Historian::new_with_config(&last_id, config.historian)?;
+                let skel = AccountantSkel::new(acc, last_id, writer, historian);
+                return Ok(Node::from_skel(skel, None));
+            }
+        };
+        let snapshot_path = ledger_file.with_file_name(SNAPSHOT_FILE);
+
+        let (acc, snapshot_height, resumed) = if ledger_file.exists() {
+            let (historian, info) = Historian::from_ledger(&ledger_file, config.historian)?;
+            let (acc, snapshot_height) = restore(&ledger_file, &snapshot_path, config.fees)?;
+            (acc, snapshot_height, Some((historian, info)))
+        } else {
+            let acc = replay_genesis(genesis, config.fees)?;
+            fs::create_dir_all(ledger_file.parent().unwrap())?;
+            let mut writer = LedgerWriter::create(&ledger_file, &genesis[0].id)?;
+            writer.write_entries(genesis)?;
+            writer.sync()?;
+            (acc, 0, None)
+        };
+        let writer_ = LedgerWriter::open(&ledger_file)?;
+        let last_id = writer_.last_id();
+        let (historian, resumed) = match resumed {
+            Some((historian, info)) => (historian, Some(info)),
+            None => (Historian::new_with_config(&last_id, config.historian)?, None),
+        };
+        let index = LedgerIndex::open(&ledger_file, writer_.height())?;
+        let mut skel = AccountantSkel::new(acc, last_id, writer, historian);
+        skel.set_persistence(Persistence {
+            writer: writer_,
+            index,
+            snapshot_path,
+            interval: config.snapshot_interval,
+            snapshot_height,
+        });
+        Ok(Node::from_skel(skel, resumed))
+    }
+
+    fn from_skel(skel: AccountantSkel<W>, resumed: Option<ResumeInfo>) -> Self {
+        Node {
+            rejections: skel.reject_log(),
+            skel: Arc::new(Mutex::new(skel)),
+            resumed,
+            exit: Arc::new(AtomicBool::new(false)),
+            threads: vec![],
+        }
+    }
+
+    /// Serve requests on `addr` until the node is shut down.
+    pub fn serve(&mut self, addr: &str) -> Result<()> {
+        let threads = AccountantSkel::serve(&self.skel, addr, self.exit.clone())?;
+        self.threads.extend(threads);
+        Ok(())
+    }
+
+    /// Return up to `limit` of the latest work the node dropped or rejected,
+    /// newest first.
+    pub fn recent_rejections(&self, limit: usize) -> Vec<Rejection> {
+        self.rejections.recent(limit)
+    }
+
+    /// Block until the node's threads exit.
+    pub fn join(self) -> Result<()> {
+        for t in self.threads {
+            t.join()?;
+        }
+        Ok(())
+    }
+
+    /// Stop serving requests, and then snapshot the accountant if the node
+    /// keeps its state on disk. Returns the id of the last entry recorded.
+    pub fn shutdown(self) -> Result<Hash> {
+        self.exit.store(true, Ordering::Relaxed);
+        for t in self.threads {
+            t.join()?;
+        }
+        let mut skel = self.skel.lock().unwrap();
+        skel.snapshot()?;
+        Ok(skel.sync())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use accountant::AccountingError;
+    use accountant_stub::{AccountantStub, StubError};
+    use bincode::serialized_size;
+    use ledger::tmp_ledger_path;
+    use mint::Mint;
+    use packet::{MAX_BLOB_SEND_SIZE, PACKET_DATA_SIZE};
+    use reject_log::{Reason, Stage, Subject};
+    use signature::{KeyPair, KeyPairUtil, PublicKey};
+    use std::io::sink;
+    use std::net::UdpSocket;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use transaction::Transaction;
+
+    fn tmp_node_dir(name: &str) -> PathBuf {
+        let path = tmp_ledger_path(name);
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    fn balance_of(addr: &str, pubkey: &PublicKey) -> Option<i64> {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        AccountantStub::new(addr, socket).get_balance(pubkey).unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_file() {
+        let path = tmp_node_dir("test_snapshot_file");
+        let snapshot = Accountant::new(&Mint::new(100)).snapshot();
+        let id = Hash::default();
+        write_snapshot(&path, 2, &id, &snapshot).unwrap();
+        assert_eq!(read_snapshot(&path), Some((2, id, snapshot)));
+
+        let mut data = fs::read(&path).unwrap();
+        data[9] ^= 1;
+        fs::write(&path, &data).unwrap();
+        assert_eq!(read_snapshot(&path), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_node_restart() {
+        let dir = tmp_node_dir("test_node_restart");
+        let alice = Mint::new(10_000);
+        let bob_pubkey = KeyPair::new().pubkey();
+        let config = NodeConfig {
+            ledger_path: Some(dir.clone()),
+            snapshot_interval: 4,
+            ..NodeConfig::default()
+        };
+        assert!(!config.has_ledger());
+
+        let addr = "127.0.0.1:9022";
+        let mut node = Node::new(&config, &alice.create_entries(), sink()).unwrap();
+        assert_eq!(node.resumed, None);
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let stub = AccountantStub::new(addr, socket);
+        for tokens in 1..11 {
+            let last_id = stub.get_last_id().unwrap();
+            stub.transfer(tokens, &alice.keypair(), bob_pubkey, &last_id)
+                .unwrap();
+        }
+        while balance_of(addr, &bob_pubkey) != Some(55) {
+            sleep(Duration::from_millis(10));
+        }
+        let last_id = node.shutdown().unwrap();
+        assert!(config.has_ledger());
+        assert!(dir.join(SNAPSHOT_FILE).exists());
+
+        // The genesis block is ignored once there's a ledger to resume.
+        let addr = "127.0.0.1:9024";
+        let mut node = Node::new(&config, &[], sink()).unwrap();
+        assert_eq!(node.resumed.as_ref().unwrap().last_id, last_id);
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+        assert_eq!(balance_of(addr, &bob_pubkey), Some(55));
+        assert_eq!(balance_of(addr, &alice.pubkey()), Some(9_945));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let stub = AccountantStub::new(addr, socket);
+        assert_eq!(stub.check_ids(&[last_id]).unwrap().len(), 1);
+        node.shutdown().unwrap();
+
+        // Without the snapshot, the whole ledger is replayed.
+        fs::remove_file(dir.join(SNAPSHOT_FILE)).unwrap();
+        let addr = "127.0.0.1:9026";
+        let mut node = Node::new(&config, &[], sink()).unwrap();
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+        assert_eq!(balance_of(addr, &bob_pubkey), Some(55));
+        node.shutdown().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_entries() {
+        let dir = tmp_node_dir("test_get_entries");
+        let path = dir.join(LEDGER_FILE);
+        let alice = Mint::new(100_000);
+        let bob_pubkey = KeyPair::new().pubkey();
+        let config = NodeConfig {
+            ledger_path: Some(dir.clone()),
+            ..NodeConfig::default()
+        };
+        let addr = "127.0.0.1:9030";
+        let mut node = Node::new(&config, &alice.create_entries(), sink()).unwrap();
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let stub = AccountantStub::new(addr, socket);
+
+        // Enough transactions that the ledger doesn't fit in one response.
+        let last_id = stub.get_last_id().unwrap();
+        for tokens in 1..401 {
+            stub.transfer(tokens, &alice.keypair(), bob_pubkey, &last_id)
+                .unwrap();
+        }
+        let seed = LedgerReader::open(&path).unwrap().seed();
+        let num_events = |entries: &[Entry]| -> usize {
+            entries.iter().map(|x| x.events.len()).sum()
+        };
+        // Each request is followed by a tick, so the ledger keeps growing.
+        let mut entries = vec![];
+        while num_events(&entries) < 401 {
+            sleep(Duration::from_millis(10));
+            entries = stub.get_entries(0, 10_000, &seed).unwrap();
+        }
+        assert!(ledger::read_ledger(&path).unwrap().1.starts_with(&entries));
+        assert!(serialized_size(&entries).unwrap() > MAX_BLOB_SEND_SIZE as u64);
+
+        let height = entries.len();
+        let tail = stub.get_entries(height as u64 - 1, 1, &entries[height - 2].id);
+        assert_eq!(tail.unwrap(), &entries[height - 1..]);
+        assert_eq!(stub.get_entries(0, 3, &seed).unwrap(), &entries[..3]);
+        assert!(stub.get_entries(1_000_000, 5, &seed).unwrap().is_empty());
+        assert_matches!(
+            stub.get_entries(1, 3, &Hash::default()),
+            Err(StubError::VerificationFailed(1))
+        );
+        node.shutdown().unwrap();
+
+        // The index is rebuilt when the node resumes without it.
+        fs::remove_file(ledger::index_path(&path)).unwrap();
+        let addr = "127.0.0.1:9032";
+        let mut node = Node::new(&config, &[], sink()).unwrap();
+        assert!(ledger::index_path(&path).exists());
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let stub = AccountantStub::new(addr, socket);
+        let fetched = stub.get_entries(0, 10_000, &seed).unwrap();
+        assert!(fetched.starts_with(&entries));
+        assert!(ledger::read_ledger(&path).unwrap().1.starts_with(&fetched));
+        node.shutdown().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_subscribe_from_ledger() {
+        let dir = tmp_node_dir("test_subscribe_from_ledger");
+        let alice = Mint::new(100);
+        let config = NodeConfig {
+            ledger_path: Some(dir.clone()),
+            ..NodeConfig::default()
+        };
+        let addr = "127.0.0.1:9035";
+        let genesis = alice.create_entries();
+        let mut node = Node::new(&config, &genesis, sink()).unwrap();
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let stub = AccountantStub::new(addr, socket);
+
+        // Subscribers are sent the ledger on disk, genesis block and all.
+        let entries = stub.subscribe_entries(0).unwrap();
+        for entry in &genesis {
+            assert_eq!(&entries.recv_timeout(Duration::new(5, 0)).unwrap(), entry);
+        }
+        node.shutdown().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recent_rejections() {
+        let alice = Mint::new(10_000);
+        let bob_pubkey = KeyPair::new().pubkey();
+        let addr = "127.0.0.1:9028";
+        let mut node = Node::new(&NodeConfig::default(), &alice.create_entries(), sink()).unwrap();
+        node.serve(addr).unwrap();
+        sleep(Duration::from_millis(300));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let source = Subject::Source(socket.local_addr().unwrap());
+        let stub = AccountantStub::new(addr, socket.try_clone().unwrap());
+        let last_id = stub.get_last_id().unwrap();
+
+        socket.send_to(&[0; PACKET_DATA_SIZE], addr).unwrap();
+        socket.send_to(&[0; 2], addr).unwrap();
+        socket.send_to(&[9, 0, 0, 0, 0, 0, 0, 0], addr).unwrap();
+        let mut forged = Transaction::new(&alice.keypair(), bob_pubkey, 1, last_id);
+        forged.data.tokens = 2;
+        stub.transfer_signed(forged.clone()).unwrap();
+        let broke = Transaction::new(&alice.keypair(), bob_pubkey, 10_001, last_id);
+        stub.transfer_signed(broke.clone()).unwrap();
+
+        let expected = [
+            (Stage::Intake, Reason::Oversized, source),
+            (Stage::SigVerify, Reason::Malformed, source),
+            (Stage::Request, Reason::Malformed, source),
+            (Stage::SigVerify, Reason::InvalidSignature, Subject::Signature(forged.sig)),
+            (
+                Stage::Accountant,
+                Reason::Accounting(AccountingError::InsufficientFunds),
+                Subject::Signature(broke.sig),
+            ),
+        ];
+        while node.recent_rejections(10).len() < expected.len() {
+            sleep(Duration::from_millis(10));
+        }
+        // The stages run concurrently, so only their own records are ordered.
+        let rejections = stub.get_rejections(10).unwrap();
+        assert_eq!(rejections, node.recent_rejections(10));
+        assert_eq!(rejections.len(), expected.len());
+        for x in rejections {
+            assert!(expected.contains(&(x.stage, x.reason, x.subject)), "{:?}", x);
+        }
+        assert_eq!(stub.get_rejections(1).unwrap().len(), 1);
+        node.shutdown().unwrap();
+    }
+}
@@ -4,6 +4,7 @@ use result::{Error, Result};
 use std::collections::VecDeque;
 use std::fmt;
 use std::io;
+use std::io::{Read, Write};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
 use std::sync::{Arc, Mutex, RwLock};
 
@@ -13,9 +14,38 @@ pub type PacketRecycler = Recycler<Packets>;
 pub type BlobRecycler = Recycler<Blob>;
 
 pub const NUM_PACKETS: usize = 1024 * 8;
-const BLOB_SIZE: usize = 64 * 1024;
+pub const BLOB_SIZE: usize = 64 * 1024;
+/// The most bytes of a blob that fit in a single UDP datagram.
+pub const MAX_BLOB_SEND_SIZE: usize = 65_507;
 pub const PACKET_DATA_SIZE: usize = 256;
 pub const NUM_BLOBS: usize = (NUM_PACKETS * PACKET_DATA_SIZE) / BLOB_SIZE;
+/// The most buffers a recycler keeps for reuse; any more are dropped.
+pub const RECYCLER_LIMIT: usize = 256;
+/// The most bytes a message sent over TCP can have.
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Write `data` to `stream`, prefixed with its length, as one message.
+pub fn write_frame<W: Write>(stream: &mut W, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_FRAME_SIZE {
+        let err = format!("a {} byte frame exceeds MAX_FRAME_SIZE", data.len());
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+    }
+    stream.write_u64::<LittleEndian>(data.len() as u64)?;
+    stream.write_all(data)
+}
+
+/// Read a message written by `write_frame`, refusing to read a message of
+/// more than `MAX_FRAME_SIZE` bytes.
+pub fn read_frame<R: Read>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let len = stream.read_u64::<LittleEndian>()?;
+    if len > MAX_FRAME_SIZE as u64 {
+        let err = format!("a {} byte frame exceeds MAX_FRAME_SIZE", len);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+    }
+    let mut data = vec![0u8; len as usize];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
 
 #[derive(Clone, Default)]
 #[repr(C)]
@@ -130,35 +160,78 @@ impl Default for Blob {
     }
 }
 
+/// A buffer a `Recycler` can hand out again.
+pub trait Reset {
+    /// Return the buffer to the state of a new one, except for the contents
+    /// of its data, which are only valid up to a size that is reset to 0.
+    fn reset(&mut self);
+}
+
+impl Reset for Packets {
+    fn reset(&mut self) {
+        self.packets.resize(NUM_PACKETS, Packet::default());
+        for p in &mut self.packets {
+            p.meta = Meta::default();
+        }
+    }
+}
+
+impl Reset for Blob {
+    fn reset(&mut self) {
+        self.meta = Meta::default();
+        self.data[..8].copy_from_slice(&[0u8; 8]);
+    }
+}
+
+/// A free-list of buffers, so that the network stages don't allocate a
+/// buffer per datagram.
 pub struct Recycler<T> {
     gc: Arc<Mutex<Vec<Arc<RwLock<T>>>>>,
+    limit: usize,
 }
 
-impl<T: Default> Default for Recycler<T> {
+impl<T: Default + Reset> Default for Recycler<T> {
     fn default() -> Recycler<T> {
-        Recycler {
-            gc: Arc::new(Mutex::new(vec![])),
-        }
+        Recycler::with_limit(RECYCLER_LIMIT)
     }
 }
 
-impl<T: Default> Clone for Recycler<T> {
+impl<T: Default + Reset> Clone for Recycler<T> {
     fn clone(&self) -> Recycler<T> {
         Recycler {
             gc: self.gc.clone(),
+            limit: self.limit,
         }
     }
 }
 
-impl<T: Default> Recycler<T> {
+impl<T: Default + Reset> Recycler<T> {
+    /// Create a recycler that keeps at most `limit` buffers for reuse.
+    pub fn with_limit(limit: usize) -> Recycler<T> {
+        Recycler {
+            gc: Arc::new(Mutex::new(vec![])),
+            limit,
+        }
+    }
+
+    /// Return a recycled buffer, reset, or a new one if there are none.
     pub fn allocate(&self) -> Arc<RwLock<T>> {
-        let mut gc = self.gc.lock().expect("recycler lock");
-        gc.pop()
-            .unwrap_or_else(|| Arc::new(RwLock::new(Default::default())))
+        let x = self.gc.lock().expect("recycler lock").pop();
+        match x {
+            Some(x) => {
+                x.write().unwrap().reset();
+                x
+            }
+            None => Arc::new(RwLock::new(Default::default())),
+        }
     }
+
+    /// Keep `msgs` for reuse, unless the recycler is already full.
     pub fn recycle(&self, msgs: Arc<RwLock<T>>) {
         let mut gc = self.gc.lock().expect("recycler lock");
-        gc.push(msgs);
+        if gc.len() < self.limit {
+            gc.push(msgs);
+        }
     }
 }
 
@@ -268,25 +341,32 @@ impl Blob {
         socket: &UdpSocket,
         v: &mut VecDeque<SharedBlob>,
     ) -> Result<()> {
+        // Recycle every blob, even after a failed send.
+        let mut result = Ok(());
         while let Some(r) = v.pop_front() {
-            {
+            if result.is_ok() {
                 let p = r.read().unwrap();
                 let a = p.meta.addr();
-                socket.send_to(&p.data[..p.meta.size], &a)?;
+                if let Err(e) = socket.send_to(&p.data[..p.meta.size], a) {
+                    result = Err(Error::IO(e));
+                }
             }
             re.recycle(r);
         }
-        Ok(())
+        result
     }
 }
 
 #[cfg(test)]
 mod test {
-    use packet::{Blob, BlobRecycler, Packet, PacketRecycler, Packets};
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use packet::{read_frame, write_frame, Blob, BlobRecycler, Packet, PacketRecycler, Packets,
+                 Recycler, MAX_FRAME_SIZE, NUM_PACKETS, RECYCLER_LIMIT};
     use std::collections::VecDeque;
     use std::io;
     use std::io::Write;
-    use std::net::UdpSocket;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::thread::spawn;
     #[test]
     pub fn packet_recycler_test() {
         let r = PacketRecycler::default();
@@ -306,6 +386,96 @@ mod test {
         assert_eq!(r.gc.lock().unwrap().len(), 0);
     }
     #[test]
+    pub fn recycler_reset_test() {
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let r = PacketRecycler::default();
+        let p = r.allocate();
+        {
+            let mut msgs = p.write().unwrap();
+            msgs.packets.resize(1, Packet::default());
+            msgs.packets[0].meta.size = 10;
+            msgs.packets[0].meta.set_addr(&addr);
+        }
+        r.recycle(p);
+        let p = r.allocate();
+        let msgs = p.read().unwrap();
+        assert_eq!(msgs.packets.len(), NUM_PACKETS);
+        assert_eq!(msgs.packets[0].meta.size, 0);
+        assert_ne!(msgs.packets[0].meta.addr(), addr);
+
+        let r = BlobRecycler::default();
+        let b = r.allocate();
+        {
+            let mut blob = b.write().unwrap();
+            blob.set_index(7).unwrap();
+            blob.meta.size = 10;
+            blob.meta.set_addr(&addr);
+        }
+        r.recycle(b);
+        let b = r.allocate();
+        let blob = b.read().unwrap();
+        assert_eq!(blob.get_index().unwrap(), 0);
+        assert_eq!(blob.meta.size, 0);
+        assert_ne!(blob.meta.addr(), addr);
+    }
+    #[test]
+    pub fn recycler_limit_test() {
+        let r = BlobRecycler::default();
+        let blobs: Vec<_> = (0..RECYCLER_LIMIT + 1).map(|_| r.allocate()).collect();
+        for b in blobs {
+            r.recycle(b);
+        }
+        assert_eq!(r.gc.lock().unwrap().len(), RECYCLER_LIMIT);
+
+        let r = BlobRecycler::with_limit(0);
+        let b = r.allocate();
+        r.recycle(b);
+        assert_eq!(r.gc.lock().unwrap().len(), 0);
+    }
+    #[test]
+    pub fn recycler_concurrency_test() {
+        let r: Recycler<Blob> = Recycler::with_limit(2);
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let r = r.clone();
+                spawn(move || {
+                    for j in 0..100 {
+                        let b = r.allocate();
+                        {
+                            let mut blob = b.write().unwrap();
+                            assert_eq!(blob.meta.size, 0);
+                            assert_eq!(blob.get_index().unwrap(), 0);
+                            blob.meta.size = i * 100 + j + 1;
+                            blob.set_index(1).unwrap();
+                        }
+                        r.recycle(b);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(r.gc.lock().unwrap().len() <= 2);
+    }
+    #[test]
+    pub fn frame_test() {
+        let mut buf = vec![];
+        write_frame(&mut buf, &[1, 2, 3]).unwrap();
+        write_frame(&mut buf, &[]).unwrap();
+        let mut cursor = io::Cursor::new(&buf);
+        assert_eq!(read_frame(&mut cursor).unwrap(), vec![1, 2, 3]);
+        assert!(read_frame(&mut cursor).unwrap().is_empty());
+        assert!(read_frame(&mut cursor).is_err());
+
+        assert!(write_frame(&mut vec![], &vec![0; MAX_FRAME_SIZE + 1]).is_err());
+        let mut buf = vec![];
+        buf.write_u64::<LittleEndian>(MAX_FRAME_SIZE as u64 + 1)
+            .unwrap();
+        let err = read_frame(&mut io::Cursor::new(&buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    #[test]
     pub fn packet_send_recv() {
         let reader = UdpSocket::bind("127.0.0.1:0").expect("bind");
         let addr = reader.local_addr().unwrap();
@@ -4,6 +4,7 @@
 //! `Payment`, the payment is executed.
 
 use chrono::prelude::*;
+use hex;
 use signature::PublicKey;
 use std::mem;
 
@@ -15,7 +16,7 @@ pub enum Witness {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum Condition {
     Timestamp(DateTime<Utc>),
-    Signature(PublicKey),
+    Signature(#[serde(with = "hex")] PublicKey),
 }
 
 impl Condition {
@@ -32,6 +33,7 @@ impl Condition {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Payment {
     pub tokens: i64,
+    #[serde(with = "hex")]
     pub to: PublicKey,
 }
 
@@ -81,18 +83,23 @@ impl Plan {
         }
     }
 
-    /// Return true if the plan spends exactly `spendable_tokens`.
-    pub fn verify(&self, spendable_tokens: i64) -> bool {
+    /// Return the payment of each way the plan can be reduced.
+    pub fn payments(&self) -> Vec<&Payment> {
         match *self {
-            Plan::Pay(ref payment) | Plan::After(_, ref payment) => {
-                payment.tokens == spendable_tokens
-            }
-            Plan::Race(ref a, ref b) => {
-                a.1.tokens == spendable_tokens && b.1.tokens == spendable_tokens
-            }
+            Plan::Pay(ref payment) | Plan::After(_, ref payment) => vec![payment],
+            Plan::Race(ref a, ref b) => vec![&a.1, &b.1],
         }
     }
 
+    /// Return true if `spendable_tokens` isn't negative and the plan spends
+    /// exactly that many, however it's reduced.
+    pub fn verify(&self, spendable_tokens: i64) -> bool {
+        spendable_tokens >= 0
+            && self.payments()
+                .iter()
+                .all(|payment| payment.tokens == spendable_tokens)
+    }
+
     /// Apply a witness to the spending plan to see if the plan can be reduced.
     /// If so, modify the plan in-place.
     pub fn apply_witness(&mut self, witness: &Witness) {
@@ -139,6 +146,47 @@ mod tests {
         assert!(Plan::new_cancelable_future_payment(dt, from, 42, to).verify(42));
     }
 
+    #[test]
+    fn test_verify_plan_imbalance() {
+        let dt = Utc.ymd(2014, 11, 14).and_hms(8, 9, 10);
+        let from = PublicKey::default();
+        let to = PublicKey::default();
+        for tokens in &[41, 43] {
+            assert!(!Plan::new_payment(42, to).verify(*tokens));
+            assert!(!Plan::new_authorized_payment(from, 42, to).verify(*tokens));
+            assert!(!Plan::new_future_payment(dt, 42, to).verify(*tokens));
+            assert!(!Plan::new_cancelable_future_payment(dt, from, 42, to).verify(*tokens));
+        }
+        assert!(!Plan::new_payment(-1, to).verify(-1));
+
+        // Each branch of a race must spend everything.
+        let overpaid_cancel = Plan::Race(
+            (Condition::Timestamp(dt), Payment { tokens: 42, to }),
+            (Condition::Signature(from), Payment { tokens: 43, to: from }),
+        );
+        assert!(!overpaid_cancel.verify(42));
+        let underpaid_payment = Plan::Race(
+            (Condition::Timestamp(dt), Payment { tokens: 41, to }),
+            (Condition::Signature(from), Payment { tokens: 42, to: from }),
+        );
+        assert!(!underpaid_payment.verify(42));
+    }
+
+    #[test]
+    fn test_final_payment() {
+        let dt = Utc.ymd(2014, 11, 14).and_hms(8, 9, 10);
+        let from = PublicKey::default();
+        let to = PublicKey::default();
+        let payment = Payment { tokens: 42, to };
+        assert_eq!(Plan::new_payment(42, to).final_payment(), Some(payment));
+        assert_eq!(Plan::new_authorized_payment(from, 42, to).final_payment(), None);
+        assert_eq!(Plan::new_future_payment(dt, 42, to).final_payment(), None);
+        assert_eq!(
+            Plan::new_cancelable_future_payment(dt, from, 42, to).final_payment(),
+            None
+        );
+    }
+
     #[test]
     fn test_authorized_payment() {
         let from = PublicKey::default();
@@ -0,0 +1,121 @@
+//! The `rate_limiter` module provides a token bucket for each of a bounded
+//! number of keys, such as the addresses requests come from.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::Instant;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the key was last used, by the limiter's count of uses.
+    used: u64,
+}
+
+/// Tokens for each key that refill at a steady rate up to a burst. Only the
+/// most recently used keys are tracked, and a key that's forgotten starts
+/// again with a full bucket.
+pub struct RateLimiter<K: Hash + Eq + Clone> {
+    rate: f64,
+    burst: f64,
+    max_keys: usize,
+    buckets: HashMap<K, Bucket>,
+    /// The keys by when they were last used.
+    lru: BTreeMap<u64, K>,
+    uses: u64,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    /// Create a limiter whose buckets hold up to `burst` tokens and gain
+    /// `rate` tokens a second, for at most `max_keys` keys at once.
+    pub fn new(rate: u64, burst: u64, max_keys: usize) -> Self {
+        RateLimiter {
+            rate: rate as f64,
+            burst: burst as f64,
+            max_keys,
+            buckets: HashMap::new(),
+            lru: BTreeMap::new(),
+            uses: 0,
+        }
+    }
+
+    /// The number of keys tracked.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Take up to `n` tokens from the bucket of `key`, and return how many
+    /// were taken.
+    pub fn take(&mut self, key: &K, n: u64) -> u64 {
+        let now = Instant::now();
+        self.uses += 1;
+        if !self.buckets.contains_key(key) {
+            while self.buckets.len() >= self.max_keys.max(1) {
+                let (&used, _) = self.lru.iter().next().expect("lru tracks every key");
+                let old = self.lru.remove(&used).unwrap();
+                self.buckets.remove(&old);
+            }
+            self.buckets.insert(
+                key.clone(),
+                Bucket {
+                    tokens: self.burst,
+                    updated: now,
+                    used: self.uses,
+                },
+            );
+        }
+        let bucket = self.buckets.get_mut(key).unwrap();
+        self.lru.remove(&bucket.used);
+        bucket.used = self.uses;
+        self.lru.insert(bucket.used, key.clone());
+
+        let elapsed = now.duration_since(bucket.updated);
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        bucket.tokens = (bucket.tokens + secs * self.rate).min(self.burst);
+        bucket.updated = now;
+        let taken = (bucket.tokens as u64).min(n);
+        bucket.tokens -= taken as f64;
+        taken
+    }
+
+    /// Take a token from the bucket of `key`, if it has one.
+    pub fn try_take(&mut self, key: &K) -> bool {
+        self.take(key, 1) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rate_limiter::RateLimiter;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_and_refill() {
+        let mut limiter = RateLimiter::new(100, 3, 16);
+        assert_eq!(limiter.take(&1, 2), 2);
+        assert_eq!(limiter.take(&1, 2), 1);
+        assert!(!limiter.try_take(&1));
+        assert!(limiter.try_take(&2));
+        sleep(Duration::from_millis(50));
+        assert_eq!(limiter.take(&1, 5), 3);
+    }
+
+    #[test]
+    fn test_lru_cap() {
+        let mut limiter = RateLimiter::new(0, 1, 2);
+        assert!(limiter.try_take(&1));
+        assert!(limiter.try_take(&2));
+        assert!(!limiter.try_take(&1));
+
+        // Key 2 is the least recently used, so it's forgotten for key 3.
+        assert!(limiter.try_take(&3));
+        assert_eq!(limiter.len(), 2);
+        assert!(!limiter.try_take(&1));
+        assert!(limiter.try_take(&2));
+    }
+}
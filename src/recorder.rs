@@ -5,32 +5,131 @@
 //! Event, the latest hash, and the number of hashes since the last event.
 //! The resulting stream of entries represents ordered events in time.
 
+use bincode::serialized_size;
 use entry::{create_entry_mut, Entry};
 use event::Event;
 use hash::{hash, Hash};
+use historian::HistorianMetrics;
+use ledger::MAX_ENTRY_SIZE;
 use std::mem;
-use std::sync::mpsc::{Receiver, SyncSender, TryRecvError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+/// Bytes of a serialized entry other than its events: `num_hashes`, the `id`
+/// and its length, and the length of `events`.
+const ENTRY_OVERHEAD_SIZE: u64 = 56;
+
+/// The number of hashes between updates of the hash rate metric.
+const HASH_RATE_SAMPLE_SIZE: u64 = 1024;
+
 #[cfg_attr(feature = "cargo-clippy", allow(large_enum_variant))]
 pub enum Signal {
     Tick,
     Event(Event),
+    /// An event along with when it was received, for senders that want its
+    /// `EntryTiming` wait to include time spent before it was sent.
+    TimedEvent(TimedEvent),
+    /// Record an entry with the pending events right away and send back its
+    /// id, or `None` if there were no pending events. Events received after
+    /// this signal go into a later entry.
+    Flush(Sender<Option<Hash>>),
+    /// Record an entry with any pending events and exit with
+    /// `ExitReason::Stopped`.
+    Stop,
+    /// Advance the recorder's virtual clock, if it has one.
+    Advance(Duration),
+    /// Record an entry with any pending events, send back the id of the last
+    /// entry, and stop hashing until the receiver is sent `()` or closed.
+    /// Signals sent meanwhile stay queued.
+    Pause(Sender<Hash>, Receiver<()>),
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExitReason {
     RecvDisconnected,
     SendDisconnected,
+    /// Sent `Signal::Stop`. `last_id` is the id of the last entry recorded and
+    /// `num_hashes` the number of hashes since, which no entry includes.
+    Stopped { last_id: Hash, num_hashes: u64 },
+}
+
+/// An event and when it was received.
+pub struct TimedEvent {
+    pub recv_at: Instant,
+    pub event: Event,
+}
+
+/// How long the events of a recorded entry waited to be recorded, measured
+/// from when they were received.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EntryTiming {
+    pub sealed_at: Instant,
+    pub max_wait: Duration,
+    pub mean_wait: Duration,
+    pub num_events: usize,
+}
+
+/// How a recorder with a virtual clock ticks.
+struct VirtualTicks {
+    tick_duration: Duration,
+    hashes_per_tick: u64,
+    epoch: Instant,
+    elapsed: Duration,
+}
+
+/// Return the current time, by `virtual_clock` if there is one.
+fn now(virtual_clock: &Option<VirtualTicks>) -> Instant {
+    match *virtual_clock {
+        Some(ref clock) => clock.epoch + clock.elapsed,
+        None => Instant::now(),
+    }
+}
+
+/// Where a recorder with timing enabled sends an EntryTiming for each entry.
+struct Timing {
+    sender: Sender<EntryTiming>,
+    recv_times: Vec<Instant>,
+}
+
+impl Timing {
+    /// Send the timing of an entry sealed at `sealed_at` holding the events
+    /// received since the last one.
+    fn send(&mut self, sealed_at: Instant) {
+        let waits: Vec<_> = self.recv_times
+            .drain(..)
+            .map(|recv_at| sealed_at.saturating_duration_since(recv_at))
+            .collect();
+        let total: Duration = waits.iter().sum();
+        let timing = EntryTiming {
+            sealed_at,
+            max_wait: waits.iter().max().cloned().unwrap_or_default(),
+            mean_wait: total.checked_div(waits.len() as u32).unwrap_or_default(),
+            num_events: waits.len(),
+        };
+        // Timing is best effort; nobody may be listening.
+        let _ = self.sender.send(timing);
+    }
 }
 
 pub struct Recorder {
     sender: SyncSender<Entry>,
     receiver: Receiver<Signal>,
     last_hash: Hash,
+    last_id: Hash,
     events: Vec<Event>,
+    events_size: u64,
+    max_events_per_entry: Option<usize>,
+    max_signals: Option<usize>,
+    virtual_clock: Option<VirtualTicks>,
+    timing: Option<Timing>,
     num_hashes: u64,
     num_ticks: u64,
+    metrics: Arc<HistorianMetrics>,
+    tick_request: Arc<AtomicBool>,
+    sample_start: Instant,
+    sample_hashes: u64,
 }
 
 impl Recorder {
@@ -39,46 +138,238 @@ impl Recorder {
             receiver,
             sender,
             last_hash,
+            last_id: last_hash,
             events: vec![],
+            events_size: 0,
+            max_events_per_entry: None,
+            max_signals: None,
+            virtual_clock: None,
+            timing: None,
             num_hashes: 0,
             num_ticks: 0,
+            metrics: Arc::new(HistorianMetrics::default()),
+            tick_request: Arc::new(AtomicBool::new(false)),
+            sample_start: Instant::now(),
+            sample_hashes: 0,
+        }
+    }
+
+    /// Return the recorder's metrics. Senders are expected to increment
+    /// `queue_depth` before sending each signal; the recorder decrements it
+    /// as it receives them.
+    pub fn metrics(&self) -> Arc<HistorianMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Return a flag that, once set, makes the recorder record an entry the
+    /// next time it processes events.
+    pub fn tick_request(&self) -> Arc<AtomicBool> {
+        self.tick_request.clone()
+    }
+
+    /// Record an entry as soon as `max` events are pending, rather than
+    /// waiting for the next tick.
+    pub fn set_max_events_per_entry(&mut self, max: Option<usize>) {
+        self.max_events_per_entry = max;
+    }
+
+    /// Process at most `max` signals each time `process_events` is called,
+    /// so senders that keep the queue full can't keep the recorder from
+    /// hashing.
+    pub fn set_max_signals(&mut self, max: Option<usize>) {
+        self.max_signals = max;
+    }
+
+    /// Tick only when sent `Signal::Advance`, once for each `tick_duration`
+    /// of virtual time passed, after hashing exactly `hashes_per_tick` times.
+    pub fn set_virtual_clock(&mut self, tick_duration: Duration, hashes_per_tick: u64) {
+        self.virtual_clock = Some(VirtualTicks {
+            tick_duration,
+            hashes_per_tick,
+            epoch: Instant::now(),
+            elapsed: Duration::default(),
+        });
+    }
+
+    /// Send an EntryTiming to `sender` after recording each entry, in the
+    /// same order as the entries.
+    pub fn set_timing(&mut self, sender: Sender<EntryTiming>) {
+        self.timing = Some(Timing {
+            sender,
+            recv_times: vec![],
+        });
+    }
+
+    /// Advance the virtual clock by `duration`, recording the ticks it passes.
+    fn advance(&mut self, duration: Duration) -> Result<(), ExitReason> {
+        let (tick_duration, hashes_per_tick, elapsed) = match self.virtual_clock {
+            Some(ref mut clock) => {
+                clock.elapsed += duration;
+                (clock.tick_duration, clock.hashes_per_tick, clock.elapsed)
+            }
+            None => return Ok(()),
+        };
+        while elapsed.as_nanos() >= tick_duration.as_nanos() * u128::from(self.num_ticks + 1) {
+            for _ in 0..hashes_per_tick {
+                self.hash();
+            }
+            self.record_entry()?;
+            self.num_ticks += 1;
         }
+        Ok(())
     }
 
     pub fn hash(&mut self) {
         self.last_hash = hash(&self.last_hash);
         self.num_hashes += 1;
+        self.sample_hashes += 1;
+        if self.sample_hashes == HASH_RATE_SAMPLE_SIZE {
+            self.sample_hash_rate();
+        }
+    }
+
+    /// Fold the hash rate since the last sample into the metrics' average.
+    fn sample_hash_rate(&mut self) {
+        let nanos = self.sample_start.elapsed().as_nanos().max(1);
+        let rate = (u128::from(self.sample_hashes) * 1_000_000_000 / nanos) as usize;
+        let average = match self.metrics.hashes_per_sec.load(Ordering::Relaxed) {
+            0 => rate,
+            average => (average * 4 + rate) / 5,
+        };
+        self.metrics.hashes_per_sec.store(average, Ordering::Relaxed);
+        self.sample_start = Instant::now();
+        self.sample_hashes = 0;
     }
 
     pub fn record_entry(&mut self) -> Result<(), ExitReason> {
         let events = mem::replace(&mut self.events, vec![]);
+        let num_events = events.len();
+        self.events_size = 0;
         let entry = create_entry_mut(&mut self.last_hash, &mut self.num_hashes, events);
+        self.last_id = entry.id;
         self.sender
             .send(entry)
             .or(Err(ExitReason::SendDisconnected))?;
+        self.metrics.entries.fetch_add(1, Ordering::Relaxed);
+        if num_events == 0 {
+            self.metrics.ticks.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.metrics.events.fetch_add(num_events, Ordering::Relaxed);
+        }
+        if let Some(ref mut timing) = self.timing {
+            timing.send(now(&self.virtual_clock));
+        }
+        Ok(())
+    }
+
+    /// Hash once, recording an entry if that makes `hashes_per_tick` hashes
+    /// since the last one.
+    pub fn hash_and_tick(&mut self, hashes_per_tick: u64) -> Result<(), ExitReason> {
+        self.hash();
+        if self.num_hashes >= hashes_per_tick {
+            self.record_entry()?;
+        }
+        Ok(())
+    }
+
+    /// Add `event` to the pending events, first recording an entry if the
+    /// event would make it exceed `MAX_ENTRY_SIZE`. With timing enabled, the
+    /// event's wait starts at `recv_at`, or now if `None`.
+    fn add_event(&mut self, event: Event, recv_at: Option<Instant>) -> Result<(), ExitReason> {
+        let size = serialized_size(&event).unwrap();
+        if !self.events.is_empty()
+            && ENTRY_OVERHEAD_SIZE + self.events_size + size > MAX_ENTRY_SIZE
+        {
+            self.record_entry()?;
+        }
+        if let Some(ref mut timing) = self.timing {
+            let recv_at = match recv_at {
+                Some(recv_at) => recv_at,
+                None => now(&self.virtual_clock),
+            };
+            timing.recv_times.push(recv_at);
+        }
+        self.events.push(event);
+        self.events_size += size;
+        if let Some(max) = self.max_events_per_entry {
+            if self.events.len() >= max {
+                self.record_entry()?;
+            }
+        }
         Ok(())
     }
 
     pub fn process_events(
         &mut self,
         epoch: Instant,
-        ms_per_tick: Option<u64>,
+        tick_duration: Option<Duration>,
     ) -> Result<(), ExitReason> {
+        if self.tick_request.swap(false, Ordering::Relaxed) {
+            self.record_entry()?;
+        }
+
+        // Drain the queued signals, up to `max_signals`, before returning to
+        // the hash loop, ticking on time while there are many.
+        let mut num_signals = 0;
         loop {
-            if let Some(ms) = ms_per_tick {
-                if epoch.elapsed() > Duration::from_millis((self.num_ticks + 1) * ms) {
+            if let Some(duration) = tick_duration {
+                let deadline = duration.as_nanos() * u128::from(self.num_ticks + 1);
+                if epoch.elapsed().as_nanos() > deadline {
                     self.record_entry()?;
                     self.num_ticks += 1;
                 }
             }
-
-            match self.receiver.try_recv() {
+            if self.max_signals.is_some_and(|max| num_signals >= max) {
+                return Ok(());
+            }
+            let signal = self.receiver.try_recv();
+            if signal.is_ok() {
+                num_signals += 1;
+                self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            }
+            match signal {
                 Ok(signal) => match signal {
                     Signal::Tick => {
                         self.record_entry()?;
                     }
                     Signal::Event(event) => {
-                        self.events.push(event);
+                        self.add_event(event, None)?;
+                    }
+                    Signal::TimedEvent(timed) => {
+                        self.add_event(timed.event, Some(timed.recv_at))?;
+                    }
+                    Signal::Flush(sender) => {
+                        let id = if self.events.is_empty() {
+                            None
+                        } else {
+                            self.record_entry()?;
+                            Some(self.last_id)
+                        };
+                        // The caller may have stopped waiting for the id.
+                        let _ = sender.send(id);
+                    }
+                    Signal::Advance(duration) => {
+                        self.advance(duration)?;
+                    }
+                    Signal::Pause(sender, resume) => {
+                        if !self.events.is_empty() {
+                            self.record_entry()?;
+                        }
+                        self.metrics.paused.store(true, Ordering::Relaxed);
+                        // Don't wait on a caller that stopped waiting for the id.
+                        if sender.send(self.last_id).is_ok() {
+                            let _ = resume.recv();
+                        }
+                        self.metrics.paused.store(false, Ordering::Relaxed);
+                    }
+                    Signal::Stop => {
+                        if !self.events.is_empty() {
+                            self.record_entry()?;
+                        }
+                        return Err(ExitReason::Stopped {
+                            last_id: self.last_id,
+                            num_hashes: self.num_hashes,
+                        });
                     }
                 },
                 Err(TryRecvError::Empty) => return Ok(()),
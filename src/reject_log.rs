@@ -0,0 +1,195 @@
+//! The `reject_log` module keeps a bounded record of the recent work a
+//! node's stages dropped or rejected, so that a client can ask why a
+//! transaction didn't land.
+//!
+//! The log is a ring buffer of slots, each behind its own lock. Appending
+//! takes the next slot with an atomic counter, so appends from different
+//! stages only contend when the log wraps around onto a slot being read.
+
+use accountant::AccountingError;
+use chrono::prelude::*;
+use hex;
+use signature::Signature;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The number of rejections a node keeps.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// The stage that dropped or rejected the work.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stage {
+    /// The receiver, applying its limits before anything reads a packet.
+    Intake,
+    SigVerify,
+    /// The server, decoding and checking requests.
+    Request,
+    Accountant,
+    Historian,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Reason {
+    /// The source exceeded its rate.
+    RateLimited,
+    /// The packet was larger than the receiver accepts.
+    Oversized,
+    /// The packet doesn't hold a request.
+    Malformed,
+    InvalidSignature,
+    /// The accountant refused the transaction, or would have.
+    Accounting(AccountingError),
+    /// The historian couldn't queue the transaction.
+    HistorianBusy,
+}
+
+/// What was rejected: the transaction with a signature, or, before its
+/// signature can be read, the packet from a source.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Subject {
+    Signature(#[serde(with = "hex")] Signature),
+    Source(SocketAddr),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Rejection {
+    pub timestamp: DateTime<Utc>,
+    pub stage: Stage,
+    pub reason: Reason,
+    pub subject: Subject,
+}
+
+pub struct RejectLog {
+    /// Each slot holds the rejection appended with the sequence number whose
+    /// remainder is its index, if that's still the latest one.
+    slots: Vec<Mutex<Option<(usize, Rejection)>>>,
+    /// The sequence number of the next rejection.
+    next: AtomicUsize,
+}
+
+impl Default for RejectLog {
+    fn default() -> Self {
+        RejectLog::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl RejectLog {
+    /// Create a log that keeps the last `capacity` rejections.
+    pub fn new(capacity: usize) -> Self {
+        RejectLog {
+            slots: (0..capacity).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Append a rejection, overwriting the oldest one if the log is full.
+    pub fn record(&self, stage: Stage, reason: Reason, subject: Subject) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let rejection = Rejection {
+            timestamp: Utc::now(),
+            stage,
+            reason,
+            subject,
+        };
+        let mut slot = self.slots[seq % self.slots.len()].lock().unwrap();
+        // A record that took long enough to be lapped is already stale.
+        if slot.as_ref().is_none_or(|x| x.0 < seq) {
+            *slot = Some((seq, rejection));
+        }
+    }
+
+    /// Return up to `limit` of the latest rejections, newest first. Those
+    /// still being appended are left out.
+    pub fn recent(&self, limit: usize) -> Vec<Rejection> {
+        let next = self.next.load(Ordering::Relaxed);
+        let len = limit.min(self.slots.len()).min(next);
+        let mut rejections = Vec::with_capacity(len);
+        for seq in (next - len..next).rev() {
+            let slot = self.slots[seq % self.slots.len()].lock().unwrap();
+            match *slot {
+                Some((x, ref rejection)) if x == seq => rejections.push(rejection.clone()),
+                _ => {}
+            }
+        }
+        rejections
+    }
+
+    /// The number of rejections appended, including those since overwritten.
+    pub fn total(&self) -> usize {
+        self.next.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use accountant::AccountingError;
+    use bincode::{deserialize, serialize};
+    use reject_log::{Reason, RejectLog, Rejection, Stage, Subject};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::thread::spawn;
+
+    fn source(port: u16) -> Subject {
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        Subject::Source(addr)
+    }
+
+    #[test]
+    fn test_recent_is_bounded_and_newest_first() {
+        let log = RejectLog::new(4);
+        assert!(log.recent(10).is_empty());
+        for port in 0..6 {
+            log.record(Stage::Intake, Reason::Oversized, source(port));
+        }
+        assert_eq!(log.total(), 6);
+        let subjects: Vec<_> = log.recent(10).into_iter().map(|x| x.subject).collect();
+        assert_eq!(subjects, vec![source(5), source(4), source(3), source(2)]);
+        assert_eq!(log.recent(1)[0].subject, source(5));
+        assert!(log.recent(0).is_empty());
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let log = RejectLog::new(0);
+        log.record(Stage::Intake, Reason::Oversized, source(1));
+        assert!(log.recent(1).is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_records() {
+        let log = Arc::new(RejectLog::new(64));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let log = log.clone();
+                spawn(move || {
+                    for _ in 0..100 {
+                        log.record(Stage::Intake, Reason::RateLimited, source(i));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(log.total(), 400);
+        assert_eq!(log.recent(100).len(), 64);
+    }
+
+    #[test]
+    fn test_serialize_rejection() {
+        let log = RejectLog::new(1);
+        let reason = Reason::Accounting(AccountingError::InsufficientFunds);
+        log.record(Stage::Accountant, reason, source(1));
+        let rejection = log.recent(1).pop().unwrap();
+        let bytes = serialize(&rejection).unwrap();
+        assert_eq!(deserialize::<Rejection>(&bytes).unwrap(), rejection);
+    }
+}
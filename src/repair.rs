@@ -0,0 +1,374 @@
+//! The `repair` module provides a service that answers `RepairRequest`s with
+//! the blobs a broadcaster sent, rebuilding the ones that have left its
+//! window from the ledger. Each peer is sent at most a burst of blobs that
+//! refills over time, so that a small request can't be used to flood the
+//! network.
+
+use bincode::{deserialize, serialize};
+use ledger::{entries_to_blobs, LedgerReader};
+use packet::{Blob, BlobRecycler, SharedBlob, MAX_BLOB_SEND_SIZE};
+use rate_limiter::RateLimiter;
+use result::{Error, Result};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+use streamer::{find_broadcast_blob, BroadcastWindow, RepairRequest, SharedBroadcastHistory,
+               TOO_OLD_INDEX};
+
+/// The most peers whose allowances are tracked. The one heard from least
+/// recently is forgotten to make room for another.
+pub const MAX_REPAIR_PEERS: usize = 1024;
+
+/// How a `RepairServer` finds and rations blobs.
+#[derive(Debug, Clone)]
+pub struct RepairConfig {
+    /// The most blobs a peer is sent at once.
+    pub burst: u64,
+    /// How many blobs a peer's allowance grows by each second, up to `burst`.
+    pub blobs_per_sec: u64,
+    /// The ledger the broadcast entries are recorded in, and the height in it
+    /// of the first entry broadcast. Without it, only the blobs still in the
+    /// broadcaster's window are sent.
+    pub ledger: Option<(PathBuf, u64)>,
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        RepairConfig {
+            burst: 64,
+            blobs_per_sec: 256,
+            ledger: None,
+        }
+    }
+}
+
+/// What a `RepairServer` found for a request.
+#[derive(Default)]
+pub struct Repair {
+    /// The blobs found. Those still in the broadcaster's window are shared
+    /// with it.
+    pub blobs: Vec<SharedBlob>,
+    /// The indices of the blobs too old to rebuild.
+    pub too_old: Vec<u64>,
+}
+
+pub struct RepairServer {
+    window: BroadcastWindow,
+    history: SharedBroadcastHistory,
+    config: RepairConfig,
+    allowances: RateLimiter<SocketAddr>,
+}
+
+impl RepairServer {
+    /// Create a server for the blobs of the broadcaster with `window` and
+    /// `history`.
+    pub fn new(
+        window: BroadcastWindow,
+        history: SharedBroadcastHistory,
+        config: RepairConfig,
+    ) -> Self {
+        let allowances = RateLimiter::new(config.blobs_per_sec, config.burst, MAX_REPAIR_PEERS);
+        RepairServer {
+            window,
+            history,
+            config,
+            allowances,
+        }
+    }
+
+    /// Find the blobs `req` asks for, for as many of its indices as the
+    /// allowance of `peer` covers. Indices yet to be broadcast are skipped.
+    pub fn repair(
+        &mut self,
+        peer: SocketAddr,
+        req: &RepairRequest,
+        recycler: &BlobRecycler,
+    ) -> Result<Repair> {
+        let n = self.allowances
+            .take(&peer, req.missing_indices.len() as u64);
+        let mut repair = Repair::default();
+        let mut rebuilds = vec![];
+        {
+            let history = self.history.read().unwrap();
+            for &ix in req.missing_indices.iter().take(n as usize) {
+                if let Some(blob) = find_broadcast_blob(&self.window, ix) {
+                    repair.blobs.push(blob);
+                } else if let Some(entries) = history.entries(ix) {
+                    rebuilds.push((ix, entries));
+                } else if ix < history.first_index() {
+                    repair.too_old.push(ix);
+                }
+            }
+        }
+        match self.config.ledger {
+            Some((ref path, start_height)) => {
+                rebuild(path, start_height, rebuilds, recycler, &mut repair)?
+            }
+            None => repair.too_old.extend(rebuilds.into_iter().map(|x| x.0)),
+        }
+        Ok(repair)
+    }
+}
+
+/// Rebuild each blob in `rebuilds`, the index of a blob and the position in
+/// the broadcast of its first entry and its number of entries, from the
+/// ledger at `path`. The broadcast starts at `start_height` in the ledger.
+fn rebuild(
+    path: &Path,
+    start_height: u64,
+    mut rebuilds: Vec<(u64, (u64, u64))>,
+    recycler: &BlobRecycler,
+    repair: &mut Repair,
+) -> Result<()> {
+    if rebuilds.is_empty() {
+        return Ok(());
+    }
+    rebuilds.sort_by_key(|x| (x.1).0);
+    let mut reader = LedgerReader::open(path)?;
+    for (ix, (first, num_entries)) in rebuilds {
+        let first = start_height + first;
+        let mut entries = vec![];
+        while reader.height() < first + num_entries {
+            let height = reader.height();
+            match reader.read_entry()? {
+                Some(entry) if height >= first => entries.push(entry),
+                Some(_) => (),
+                None => break,
+            }
+        }
+        let mut blobs = VecDeque::new();
+        if entries.len() as u64 == num_entries {
+            entries_to_blobs(&entries, recycler, &mut blobs)?;
+        }
+        // The entries of a blob pack into one blob again, unless the ledger
+        // doesn't hold them.
+        if blobs.len() != 1 {
+            repair.too_old.push(ix);
+            continue;
+        }
+        let blob = blobs.pop_front().unwrap();
+        blob.write().unwrap().set_index(ix)?;
+        repair.blobs.push(blob);
+    }
+    Ok(())
+}
+
+/// Return the indices held by a blob answering a `RepairRequest` that can no
+/// longer be repaired, or None if it's a blob of entries.
+pub fn too_old_indices(blob: &Blob) -> Option<Vec<u64>> {
+    if blob.meta.size < 8 || blob.get_index().ok()? != TOO_OLD_INDEX {
+        return None;
+    }
+    deserialize(&blob.data()[..blob.meta.size - 8]).ok()
+}
+
+fn answer(
+    server: &mut RepairServer,
+    sock: &UdpSocket,
+    buf: &mut [u8],
+    recycler: &BlobRecycler,
+) -> Result<()> {
+    let (size, peer) = sock.recv_from(buf)?;
+    let req: RepairRequest = deserialize(&buf[..size])?;
+    let repair = server.repair(peer, &req, recycler)?;
+    for blob in &repair.blobs {
+        let b = blob.read().unwrap();
+        sock.send_to(&b.data[..b.meta.size], peer)?;
+    }
+    if !repair.too_old.is_empty() {
+        // The request held at least as many indices, so they fit.
+        let data = serialize(&repair.too_old)?;
+        let blob = recycler.allocate();
+        {
+            let mut b = blob.write().unwrap();
+            b.set_index(TOO_OLD_INDEX)?;
+            b.data_mut()[..data.len()].copy_from_slice(&data);
+            b.meta.size = 8 + data.len();
+            sock.send_to(&b.data[..b.meta.size], peer)?;
+        }
+        recycler.recycle(blob);
+    }
+    Ok(())
+}
+
+/// Service to answer the `RepairRequest`s received on `sock` with `server`.
+/// The blobs found are sent to the requester, followed by a blob with index
+/// `TOO_OLD_INDEX` listing the indices too old to rebuild, if any. Set `exit`
+/// to shut it down.
+pub fn repair_server(
+    exit: Arc<AtomicBool>,
+    sock: UdpSocket,
+    server: RepairServer,
+) -> Result<JoinHandle<()>> {
+    sock.set_read_timeout(Some(Duration::new(1, 0)))?;
+    Ok(spawn(move || {
+        let mut server = server;
+        let recycler = BlobRecycler::default();
+        let mut buf = vec![0u8; MAX_BLOB_SEND_SIZE];
+        while !exit.load(Ordering::Relaxed) {
+            match answer(&mut server, &sock, &mut buf, &recycler) {
+                Ok(()) => (),
+                Err(Error::IO(ref err))
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut => {}
+                Err(err) => warn!("failed to answer a repair request: {:?}", err),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::{serialize, serialized_size};
+    use entry::{create_entry, Entry};
+    use event::Event;
+    use hash::Hash;
+    use ledger::{blobs_to_entries, tmp_ledger_path, write_ledger};
+    use packet::{Blob, BlobRecycler, SharedBlob, MAX_BLOB_SEND_SIZE};
+    use repair::{repair_server, too_old_indices, RepairConfig, RepairServer};
+    use std::collections::VecDeque;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use streamer::{broadcaster, BroadcastWindow, RepairRequest, SharedBroadcastHistory,
+                   TOO_OLD_INDEX};
+    use transaction::test_tx;
+
+    /// Return a chain of `len` entries, each too large to share a blob.
+    fn big_entries(len: usize) -> Vec<Entry> {
+        let event = Event::Transaction(test_tx());
+        let num_events = MAX_BLOB_SEND_SIZE / 2 / serialized_size(&event).unwrap() as usize + 1;
+        let mut id = Hash::default();
+        (0..len)
+            .map(|_| {
+                let entry = create_entry(&id, 0, vec![event.clone(); num_events]);
+                id = entry.id;
+                entry
+            })
+            .collect()
+    }
+
+    /// Broadcast `entries` to no one, one per blob.
+    fn broadcast(entries: &[Entry]) -> (BroadcastWindow, SharedBroadcastHistory) {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (s_entries, r_entries) = channel();
+        let (t_broadcaster, window, history) = broadcaster(r_entries, sock, vec![]);
+        for entry in entries {
+            s_entries.send(entry.clone()).unwrap();
+        }
+        drop(s_entries);
+        t_broadcaster.join().unwrap();
+        (window, history)
+    }
+
+    fn indices(blobs: &[SharedBlob]) -> Vec<u64> {
+        blobs
+            .iter()
+            .map(|b| b.read().unwrap().get_index().unwrap())
+            .collect()
+    }
+
+    fn peer() -> SocketAddr {
+        "127.0.0.1:9".parse().unwrap()
+    }
+
+    #[test]
+    fn test_repair_from_window_and_ledger() {
+        let entries = big_entries(40);
+        let path = tmp_ledger_path("repair");
+        write_ledger(&path, &Hash::default(), &entries[..4]).unwrap();
+        let (window, history) = broadcast(&entries);
+        let config = RepairConfig {
+            ledger: Some((path, 0)),
+            ..RepairConfig::default()
+        };
+        let mut server = RepairServer::new(window, history, config);
+        let recycler = BlobRecycler::default();
+
+        // Blob 39 is in the window, 2 and 5 have left it, but only 2 is in
+        // the ledger, and 45 is yet to be broadcast.
+        let req = RepairRequest {
+            missing_indices: vec![39, 2, 45, 5],
+        };
+        let repair = server.repair(peer(), &req, &recycler).unwrap();
+        assert_eq!(indices(&repair.blobs), vec![39, 2]);
+        assert_eq!(repair.too_old, vec![5]);
+        for (blob, ix) in repair.blobs.iter().zip(&[39, 2]) {
+            let q: VecDeque<_> = vec![blob.clone()].into_iter().collect();
+            assert_eq!(blobs_to_entries(&q).unwrap(), vec![entries[*ix].clone()]);
+        }
+    }
+
+    #[test]
+    fn test_repair_too_old() {
+        let entries = big_entries(36);
+        let (window, history) = broadcast(&entries);
+        let mut server = RepairServer::new(window, history, RepairConfig::default());
+        let recycler = BlobRecycler::default();
+        let req = RepairRequest {
+            missing_indices: vec![1, 35, 3],
+        };
+        let repair = server.repair(peer(), &req, &recycler).unwrap();
+        assert_eq!(indices(&repair.blobs), vec![35]);
+        assert_eq!(repair.too_old, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_repair_rate_limit() {
+        let entries = big_entries(8);
+        let (window, history) = broadcast(&entries);
+        let config = RepairConfig {
+            burst: 4,
+            blobs_per_sec: 1,
+            ledger: None,
+        };
+        let mut server = RepairServer::new(window, history, config);
+        let recycler = BlobRecycler::default();
+        let req = RepairRequest {
+            missing_indices: (0..8).collect(),
+        };
+        let repair = server.repair(peer(), &req, &recycler).unwrap();
+        assert_eq!(indices(&repair.blobs), vec![0, 1, 2, 3]);
+        let repair = server.repair(peer(), &req, &recycler).unwrap();
+        assert!(repair.blobs.is_empty());
+
+        // Other peers have allowances of their own.
+        let other: SocketAddr = "127.0.0.1:10".parse().unwrap();
+        let repair = server.repair(other, &req, &recycler).unwrap();
+        assert_eq!(repair.blobs.len(), 4);
+    }
+
+    #[test]
+    fn test_repair_server() {
+        let entries = big_entries(36);
+        let (window, history) = broadcast(&entries);
+        let exit = Arc::new(AtomicBool::new(false));
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = sock.local_addr().unwrap();
+        let server = RepairServer::new(window, history, RepairConfig::default());
+        let t_server = repair_server(exit.clone(), sock, server).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        let req = RepairRequest {
+            missing_indices: vec![0, 34],
+        };
+        client.send_to(&serialize(&req).unwrap(), addr).unwrap();
+        let mut blob = Blob::default();
+        for ix in &[34, TOO_OLD_INDEX] {
+            let (size, _) = client.recv_from(&mut blob.data).unwrap();
+            blob.meta.size = size;
+            assert_eq!(blob.get_index().unwrap(), *ix);
+        }
+        assert_eq!(too_old_indices(&blob), Some(vec![0]));
+        exit.store(true, Ordering::Relaxed);
+        t_server.join().unwrap();
+    }
+}
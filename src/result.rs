@@ -1,6 +1,10 @@
 //! The `result` module exposes a Result type that propagates one of many different Error types.
 
+use accountant;
 use bincode;
+use historian;
+use ledger;
+use node;
 use serde_json;
 use std;
 use std::any::Any;
@@ -14,6 +18,10 @@ pub enum Error {
     RecvError(std::sync::mpsc::RecvError),
     RecvTimeoutError(std::sync::mpsc::RecvTimeoutError),
     Serialize(std::boxed::Box<bincode::ErrorKind>),
+    Ledger(ledger::LedgerError),
+    HistorianConfig(historian::ConfigError),
+    Snapshot(accountant::SnapshotError),
+    Node(node::NodeError),
     SendError,
     Services,
 }
@@ -61,6 +69,26 @@ impl std::convert::From<std::boxed::Box<bincode::ErrorKind>> for Error {
         Error::Serialize(e)
     }
 }
+impl std::convert::From<ledger::LedgerError> for Error {
+    fn from(e: ledger::LedgerError) -> Error {
+        Error::Ledger(e)
+    }
+}
+impl std::convert::From<historian::ConfigError> for Error {
+    fn from(e: historian::ConfigError) -> Error {
+        Error::HistorianConfig(e)
+    }
+}
+impl std::convert::From<accountant::SnapshotError> for Error {
+    fn from(e: accountant::SnapshotError) -> Error {
+        Error::Snapshot(e)
+    }
+}
+impl std::convert::From<node::NodeError> for Error {
+    fn from(e: node::NodeError) -> Error {
+        Error::Node(e)
+    }
+}
 
 #[cfg(test)]
 mod tests {
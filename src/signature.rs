@@ -1,20 +1,32 @@
 //! The `signature` module provides functionality for public, and private keys.
+//! With the default `native` feature, keys are made and signatures checked
+//! with ring. Without it, there are no private keys, and signatures are
+//! checked with ed25519-compact, which is pure Rust and builds for targets
+//! ring doesn't, such as `wasm32-unknown-unknown`.
 
+#[cfg(any(test, not(feature = "native")))]
+use ed25519_compact;
 use generic_array::typenum::{U32, U64};
 use generic_array::GenericArray;
+#[cfg(feature = "native")]
 use ring::signature::Ed25519KeyPair;
+#[cfg(feature = "native")]
 use ring::{rand, signature};
+#[cfg(feature = "native")]
 use untrusted;
 
+#[cfg(feature = "native")]
 pub type KeyPair = Ed25519KeyPair;
 pub type PublicKey = GenericArray<u8, U32>;
 pub type Signature = GenericArray<u8, U64>;
 
+#[cfg(feature = "native")]
 pub trait KeyPairUtil {
     fn new() -> Self;
     fn pubkey(&self) -> PublicKey;
 }
 
+#[cfg(feature = "native")]
 impl KeyPairUtil for Ed25519KeyPair {
     /// Return a new ED25519 keypair
     fn new() -> Self {
@@ -34,10 +46,56 @@ pub trait SignatureUtil {
 }
 
 impl SignatureUtil for GenericArray<u8, U64> {
+    #[cfg(feature = "native")]
     fn verify(&self, peer_public_key_bytes: &[u8], msg_bytes: &[u8]) -> bool {
         let peer_public_key = untrusted::Input::from(peer_public_key_bytes);
         let msg = untrusted::Input::from(msg_bytes);
         let sig = untrusted::Input::from(self);
         signature::verify(&signature::ED25519, peer_public_key, msg, sig).is_ok()
     }
+
+    #[cfg(not(feature = "native"))]
+    fn verify(&self, peer_public_key_bytes: &[u8], msg_bytes: &[u8]) -> bool {
+        verify_portable(self, peer_public_key_bytes, msg_bytes)
+    }
+}
+
+/// Check `sig` with ed25519-compact rather than ring.
+#[cfg(any(test, not(feature = "native")))]
+fn verify_portable(sig: &[u8], peer_public_key_bytes: &[u8], msg_bytes: &[u8]) -> bool {
+    let peer_public_key = match ed25519_compact::PublicKey::from_slice(peer_public_key_bytes) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    match ed25519_compact::Signature::from_slice(sig) {
+        Ok(sig) => peer_public_key.verify(msg_bytes, &sig).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_portable() {
+        let keypair = KeyPair::new();
+        let pubkey = keypair.pubkey();
+        let msg = b"hello";
+        let sig = Signature::clone_from_slice(keypair.sign(msg).as_ref());
+        let other_pubkey = KeyPair::new().pubkey();
+        let mut bad_sig = sig;
+        bad_sig[0] ^= 1;
+        let cases: [(&Signature, &[u8], &[u8]); 5] = [
+            (&sig, &pubkey, msg),
+            (&sig, &pubkey, b"hellp"),
+            (&sig, &other_pubkey, msg),
+            (&bad_sig, &pubkey, msg),
+            (&sig, &pubkey[..31], msg),
+        ];
+        for &(sig, pubkey, msg) in &cases {
+            assert_eq!(verify_portable(sig, pubkey, msg), sig.verify(pubkey, msg));
+        }
+        assert!(verify_portable(&sig, &pubkey, msg));
+    }
 }
@@ -0,0 +1,288 @@
+//! The `sigverify` module provides a stage that verifies the signatures of
+//! the transactions in batches of packets, so that only transactions with
+//! valid signatures reach the accountant.
+
+use byteorder::{ByteOrder, LittleEndian};
+use ecdsa::{self, TX_OFFSET};
+use packet::{Packet, SharedPackets};
+use reject_log::{Reason, RejectLog, Stage, Subject};
+use result::Result;
+use signature::Signature;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+use streamer::PacketReceiver;
+use transaction::{SIGNED_DATA_OFFSET, SIG_OFFSET};
+
+/// The bincode tag of `Request::Transaction`.
+const TRANSACTION_TAG: u32 = 0;
+
+/// A backend that verifies signatures in bulk.
+pub trait SigVerifier: Send + Sync {
+    /// Return, for each packet of each of `batches` in order, 1 if it holds
+    /// a transaction with a valid signature and 0 if not.
+    fn verify(&self, batches: &[SharedPackets]) -> Vec<Vec<u8>>;
+}
+
+/// Verifies ed25519 signatures with `ecdsa::ed25519_verify`: on the GPU when
+/// built with the `cuda` feature, and otherwise on the CPU, across the rayon
+/// thread pool when built with the `parallel` feature.
+pub struct Ed25519Verifier;
+
+impl SigVerifier for Ed25519Verifier {
+    fn verify(&self, batches: &[SharedPackets]) -> Vec<Vec<u8>> {
+        ecdsa::ed25519_verify(&batches.to_vec())
+    }
+}
+
+/// Counters the stage updates as it verifies packets.
+#[derive(Debug, Default)]
+pub struct SigVerifyMetrics {
+    /// Transactions with valid signatures.
+    pub verified: AtomicUsize,
+    /// Transactions dropped for invalid signatures.
+    pub failed: AtomicUsize,
+    /// Packets dropped for being too short to hold what they claim to.
+    pub malformed: AtomicUsize,
+}
+
+/// What a packet holds, as far as its signature is concerned.
+enum Contents<'a> {
+    /// A transaction, with its signature.
+    Transaction(&'a [u8]),
+    /// A request without a signature.
+    Unsigned,
+    Malformed,
+}
+
+/// Read the signature of the transaction in `packet`, without deserializing
+/// the rest of the packet.
+fn extract(packet: &Packet) -> Contents<'_> {
+    let size = packet.meta.size;
+    if size < TX_OFFSET {
+        return Contents::Malformed;
+    }
+    if LittleEndian::read_u32(&packet.data[..TX_OFFSET]) != TRANSACTION_TAG {
+        return Contents::Unsigned;
+    }
+    let msg_start = TX_OFFSET + SIGNED_DATA_OFFSET;
+    if size <= msg_start {
+        return Contents::Malformed;
+    }
+    let sig_start = TX_OFFSET + SIG_OFFSET;
+    Contents::Transaction(&packet.data[sig_start..sig_start + size_of::<Signature>()])
+}
+
+/// Drop the packets of `batches` that are malformed or hold a transaction
+/// with an invalid signature, keeping the rest in their original order, and
+/// record each one dropped in `rejections`.
+pub fn verify_packets<V: SigVerifier>(
+    verifier: &V,
+    batches: &[SharedPackets],
+    metrics: &SigVerifyMetrics,
+    rejections: &RejectLog,
+) {
+    let valid = verifier.verify(batches);
+    for (batch, valid) in batches.iter().zip(valid) {
+        let keep: Vec<bool> = {
+            let msgs = batch.read().unwrap();
+            msgs.packets
+                .iter()
+                .zip(valid)
+                .map(|(packet, valid)| match extract(packet) {
+                    Contents::Transaction(sig) => {
+                        if valid != 0 {
+                            metrics.verified.fetch_add(1, Ordering::Relaxed);
+                        } else {
+                            metrics.failed.fetch_add(1, Ordering::Relaxed);
+                            let sig = Subject::Signature(Signature::clone_from_slice(sig));
+                            rejections.record(Stage::SigVerify, Reason::InvalidSignature, sig);
+                        }
+                        valid != 0
+                    }
+                    Contents::Unsigned => true,
+                    Contents::Malformed => {
+                        metrics.malformed.fetch_add(1, Ordering::Relaxed);
+                        let source = Subject::Source(packet.meta.addr());
+                        rejections.record(Stage::SigVerify, Reason::Malformed, source);
+                        false
+                    }
+                })
+                .collect()
+        };
+        let mut msgs = batch.write().unwrap();
+        let mut keep = keep.into_iter();
+        msgs.packets.retain(|_| keep.next().unwrap());
+    }
+}
+
+fn recv_verify<V: SigVerifier>(
+    verifier: &V,
+    r: &PacketReceiver,
+    s: &Sender<Vec<SharedPackets>>,
+    metrics: &SigVerifyMetrics,
+    rejections: &RejectLog,
+) -> Result<()> {
+    let timer = Duration::new(1, 0);
+    let mut batch = vec![r.recv_timeout(timer)?];
+    while let Ok(more) = r.try_recv() {
+        batch.push(more);
+    }
+    verify_packets(verifier, &batch, metrics, rejections);
+    s.send(batch)?;
+    Ok(())
+}
+
+/// Service to verify the packets from `r` with `verifier`, sending what
+/// survives to `s`, in order, and recording what doesn't in `rejections`.
+/// Set `exit` to shut it down.
+pub fn sigverify<V: SigVerifier + 'static>(
+    exit: Arc<AtomicBool>,
+    verifier: V,
+    r: PacketReceiver,
+    s: Sender<Vec<SharedPackets>>,
+    rejections: Arc<RejectLog>,
+) -> (JoinHandle<()>, Arc<SigVerifyMetrics>) {
+    let metrics = Arc::new(SigVerifyMetrics::default());
+    let metrics_ = metrics.clone();
+    let t = spawn(move || loop {
+        let e = recv_verify(&verifier, &r, &s, &metrics_, &rejections);
+        if e.is_err() && exit.load(Ordering::Relaxed) {
+            break;
+        }
+    });
+    (t, metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use accountant_skel::Request;
+    use bincode::{deserialize, serialize};
+    use hash::Hash;
+    use packet::{Packet, Packets, SharedPackets};
+    use reject_log::{Reason, RejectLog, Stage, Subject};
+    use signature::{KeyPair, KeyPairUtil};
+    use sigverify::{sigverify, verify_packets, Ed25519Verifier, SigVerifyMetrics};
+    use std::slice;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+    use transaction::Transaction;
+
+    fn make_packet(req: &Request) -> Packet {
+        let data = serialize(req).unwrap();
+        let mut packet = Packet::default();
+        packet.meta.size = data.len();
+        packet.data[..data.len()].copy_from_slice(&data);
+        packet
+    }
+
+    /// Return a batch of every kind of packet, and the packets of it that
+    /// should survive verification, in order.
+    fn mixed_batch() -> (SharedPackets, Vec<Packet>) {
+        let keypair = KeyPair::new();
+        let to = KeyPair::new().pubkey();
+        let tx = |tokens| {
+            let tr = Transaction::new(&keypair, to, tokens, Hash::default());
+            make_packet(&Request::Transaction(tr))
+        };
+        let mut bad_sig = tx(2);
+        bad_sig.data[bad_sig.meta.size - 1] ^= 1;
+        let mut truncated = tx(3);
+        truncated.meta.size = 40;
+        let mut too_short = tx(4);
+        too_short.meta.size = 2;
+        let query = make_packet(&Request::GetBalance {
+            key: to,
+            req_id: 0,
+        });
+
+        let packets = vec![
+            tx(1),
+            bad_sig,
+            query.clone(),
+            too_short,
+            tx(5),
+            truncated,
+            tx(6),
+        ];
+        let survivors = vec![packets[0].clone(), query, packets[4].clone(), packets[6].clone()];
+        let batch = Arc::new(RwLock::new(Packets { packets }));
+        (batch, survivors)
+    }
+
+    fn assert_packets_eq(batch: &SharedPackets, expected: &[Packet]) {
+        let msgs = batch.read().unwrap();
+        assert_eq!(msgs.packets.len(), expected.len());
+        for (x, y) in msgs.packets.iter().zip(expected) {
+            assert_eq!(x.meta.size, y.meta.size);
+            assert_eq!(&x.data[..x.meta.size], &y.data[..y.meta.size]);
+        }
+    }
+
+    #[test]
+    fn test_verify_packets() {
+        let (batch, survivors) = mixed_batch();
+        let bad_sig = {
+            let p = &batch.read().unwrap().packets[1];
+            match deserialize(&p.data[..p.meta.size]).unwrap() {
+                Request::Transaction(tr) => tr.sig,
+                _ => unreachable!(),
+            }
+        };
+        let metrics = SigVerifyMetrics::default();
+        let rejections = RejectLog::default();
+        verify_packets(&Ed25519Verifier, slice::from_ref(&batch), &metrics, &rejections);
+        assert_packets_eq(&batch, &survivors);
+        assert_eq!(metrics.verified.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.failed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.malformed.load(Ordering::Relaxed), 2);
+
+        let recent: Vec<_> = rejections
+            .recent(10)
+            .into_iter()
+            .map(|x| (x.stage, x.reason, x.subject))
+            .collect();
+        let source = Subject::Source(Packet::default().meta.addr());
+        let expected = vec![
+            (Stage::SigVerify, Reason::Malformed, source),
+            (Stage::SigVerify, Reason::Malformed, source),
+            (Stage::SigVerify, Reason::InvalidSignature, Subject::Signature(bad_sig)),
+        ];
+        assert_eq!(recent, expected);
+    }
+
+    #[test]
+    fn test_sigverify_stage() {
+        let exit = Arc::new(AtomicBool::new(false));
+        let (s_packets, r_packets) = channel();
+        let (s_verified, r_verified) = channel();
+        let rejections = Arc::new(RejectLog::default());
+        let (t_verifier, metrics) = sigverify(
+            exit.clone(),
+            Ed25519Verifier,
+            r_packets,
+            s_verified,
+            rejections.clone(),
+        );
+        let batches: Vec<_> = (0..3).map(|_| mixed_batch()).collect();
+        for (batch, _) in &batches {
+            s_packets.send(batch.clone()).unwrap();
+        }
+        let mut verified = vec![];
+        while verified.len() < batches.len() {
+            verified.extend(r_verified.recv_timeout(Duration::new(5, 0)).unwrap());
+        }
+        for (batch, (_, survivors)) in verified.iter().zip(&batches) {
+            assert_packets_eq(batch, survivors);
+        }
+        assert_eq!(metrics.malformed.load(Ordering::Relaxed), 6);
+        assert_eq!(rejections.total(), 9);
+        exit.store(true, Ordering::Relaxed);
+        t_verifier.join().unwrap();
+    }
+}
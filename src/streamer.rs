@@ -1,13 +1,20 @@
 //! The `streamer` module defines a set of services for effecently pulling data from udp sockets.
-use packet::{Blob, BlobRecycler, PacketRecycler, SharedBlob, SharedPackets, NUM_BLOBS};
-use result::Result;
+use entry::Entry;
+use ledger::{blobs_to_entries, entries_to_blobs, num_blob_entries};
+use packet::{Blob, BlobRecycler, PacketRecycler, Packets, SharedBlob, SharedPackets, NUM_BLOBS,
+             PACKET_DATA_SIZE};
+use rate_limiter::RateLimiter;
+use reject_log::{Reason, RejectLog, Stage, Subject};
+use result::{Error, Result};
+use std::cmp::{max, min};
 use std::collections::VecDeque;
-use std::net::UdpSocket;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, RwLock};
 use std::thread::{spawn, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use subscribers;
 
 pub type PacketReceiver = mpsc::Receiver<SharedPackets>;
@@ -15,21 +22,101 @@ pub type PacketSender = mpsc::Sender<SharedPackets>;
 pub type BlobSender = mpsc::Sender<VecDeque<SharedBlob>>;
 pub type BlobReceiver = mpsc::Receiver<VecDeque<SharedBlob>>;
 
+/// Limits on the packets a `limited_receiver` accepts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ReceiverConfig {
+    /// The packets a source may send each second, on average.
+    pub packets_per_sec: u64,
+    /// The most packets a source may send at once.
+    pub burst: u64,
+    /// The most sources tracked. The one heard from least recently is
+    /// forgotten to make room for another.
+    pub max_sources: usize,
+    /// The largest packet accepted, in bytes. A datagram too large for a
+    /// packet is truncated to `PACKET_DATA_SIZE` bytes, so to drop it rather
+    /// than pass it on truncated, this must be less than `PACKET_DATA_SIZE`.
+    pub max_packet_size: usize,
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        ReceiverConfig {
+            packets_per_sec: 50_000,
+            burst: 10_000,
+            max_sources: 16_384,
+            max_packet_size: PACKET_DATA_SIZE - 1,
+        }
+    }
+}
+
+/// Counters a `limited_receiver` updates as it drops packets.
+#[derive(Debug, Default)]
+pub struct ReceiverMetrics {
+    /// Packets dropped for exceeding their source's rate.
+    pub rate_limited: AtomicUsize,
+    /// Packets dropped for exceeding `max_packet_size`.
+    pub oversized: AtomicUsize,
+}
+
+/// The state of a `limited_receiver`.
+struct Intake {
+    config: ReceiverConfig,
+    limiter: RateLimiter<SocketAddr>,
+    metrics: Arc<ReceiverMetrics>,
+    rejections: Arc<RejectLog>,
+}
+
+impl Intake {
+    /// Drop the packets of `msgs` that are too large or exceed their source's
+    /// rate, before anything reads them.
+    fn filter(&mut self, msgs: &mut Packets) {
+        let max_packet_size = self.config.max_packet_size;
+        let limiter = &mut self.limiter;
+        let metrics = &self.metrics;
+        let rejections = &self.rejections;
+        msgs.packets.retain(|p| {
+            let source = Subject::Source(p.meta.addr());
+            if p.meta.size > max_packet_size {
+                metrics.oversized.fetch_add(1, Ordering::Relaxed);
+                rejections.record(Stage::Intake, Reason::Oversized, source);
+                false
+            } else if !limiter.try_take(&p.meta.addr()) {
+                metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+                rejections.record(Stage::Intake, Reason::RateLimited, source);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
 fn recv_loop(
     sock: &UdpSocket,
     exit: &Arc<AtomicBool>,
     re: &PacketRecycler,
     channel: &PacketSender,
+    mut intake: Option<Intake>,
 ) -> Result<()> {
     loop {
         let msgs = re.allocate();
         let msgs_ = msgs.clone();
         loop {
-            match msgs.write().unwrap().recv_from(sock) {
-                Ok(()) => {
+            let received = {
+                let mut m = msgs.write().unwrap();
+                m.recv_from(sock).map(|()| {
+                    if let Some(ref mut intake) = intake {
+                        intake.filter(&mut m);
+                    }
+                    !m.packets.is_empty()
+                })
+            };
+            match received {
+                Ok(true) => {
                     channel.send(msgs_)?;
                     break;
                 }
+                Ok(false) => (),
                 Err(_) => {
                     if exit.load(Ordering::Relaxed) {
                         re.recycle(msgs_);
@@ -50,29 +137,249 @@ pub fn receiver(
     let timer = Duration::new(1, 0);
     sock.set_read_timeout(Some(timer))?;
     Ok(spawn(move || {
-        let _ = recv_loop(&sock, &exit, &recycler, &channel);
+        let _ = recv_loop(&sock, &exit, &recycler, &channel, None);
         ()
     }))
 }
 
-fn recv_send(sock: &UdpSocket, recycler: &BlobRecycler, r: &BlobReceiver) -> Result<()> {
+/// Service like `receiver` that first drops the packets that break the
+/// limits of `config`, counting them in the returned metrics and recording
+/// each in `rejections`.
+pub fn limited_receiver(
+    sock: UdpSocket,
+    exit: Arc<AtomicBool>,
+    recycler: PacketRecycler,
+    channel: PacketSender,
+    config: ReceiverConfig,
+    rejections: Arc<RejectLog>,
+) -> Result<(JoinHandle<()>, Arc<ReceiverMetrics>)> {
+    let timer = Duration::new(1, 0);
+    sock.set_read_timeout(Some(timer))?;
+    let metrics = Arc::new(ReceiverMetrics::default());
+    let intake = Intake {
+        config,
+        limiter: RateLimiter::new(config.packets_per_sec, config.burst, config.max_sources),
+        metrics: metrics.clone(),
+        rejections,
+    };
+    let t = spawn(move || {
+        let _ = recv_loop(&sock, &exit, &recycler, &channel, Some(intake));
+    });
+    Ok((t, metrics))
+}
+
+/// How the responder batches its sends.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ResponderConfig {
+    /// The most responses sent with one system call.
+    pub batch_size: usize,
+    /// How long to wait for more responses before sending a partial batch.
+    pub linger: Duration,
+    /// Failed attempts to send a response before it's dropped.
+    pub max_retries: usize,
+    /// Send batches with `sendmmsg` on Linux. Otherwise, and on other
+    /// platforms, each response is sent with its own `send_to`.
+    pub use_sendmmsg: bool,
+}
+
+impl Default for ResponderConfig {
+    fn default() -> Self {
+        ResponderConfig {
+            batch_size: 64,
+            linger: Duration::from_micros(100),
+            max_retries: 3,
+            use_sendmmsg: true,
+        }
+    }
+}
+
+/// Counters the responder updates as it sends.
+#[derive(Debug, Default)]
+pub struct ResponderMetrics {
+    /// Responses sent.
+    pub sent: AtomicUsize,
+    /// Batches sent, each with one system call.
+    pub batches: AtomicUsize,
+    /// Failed attempts to send a batch.
+    pub send_errors: AtomicUsize,
+    /// Responses dropped after `max_retries` failed attempts to send them.
+    pub dropped: AtomicUsize,
+}
+
+/// Send a prefix of `blobs`, each to its own address, with one system call.
+/// Returns how many were sent. Only fails if none were.
+#[cfg(target_os = "linux")]
+fn send_mmsg(sock: &UdpSocket, blobs: &[SharedBlob]) -> io::Result<usize> {
+    use libc::{c_void, iovec, mmsghdr, sendmmsg, sockaddr_in, sockaddr_in6, sockaddr_storage,
+               socklen_t, AF_INET, AF_INET6};
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let blobs: Vec<_> = blobs.iter().map(|b| b.read().unwrap()).collect();
+    let mut addrs: Vec<(sockaddr_storage, socklen_t)> = blobs
+        .iter()
+        .map(|b| unsafe {
+            let mut storage: sockaddr_storage = mem::zeroed();
+            let len = match b.meta.addr() {
+                SocketAddr::V4(a) => {
+                    let sin = &mut *(&mut storage as *mut _ as *mut sockaddr_in);
+                    sin.sin_family = AF_INET as _;
+                    sin.sin_port = a.port().to_be();
+                    sin.sin_addr.s_addr = u32::from_ne_bytes(a.ip().octets());
+                    mem::size_of::<sockaddr_in>()
+                }
+                SocketAddr::V6(a) => {
+                    let sin6 = &mut *(&mut storage as *mut _ as *mut sockaddr_in6);
+                    sin6.sin6_family = AF_INET6 as _;
+                    sin6.sin6_port = a.port().to_be();
+                    sin6.sin6_flowinfo = a.flowinfo();
+                    sin6.sin6_addr.s6_addr = a.ip().octets();
+                    sin6.sin6_scope_id = a.scope_id();
+                    mem::size_of::<sockaddr_in6>()
+                }
+            };
+            (storage, len as socklen_t)
+        })
+        .collect();
+    let mut iovs: Vec<iovec> = blobs
+        .iter()
+        .map(|b| iovec {
+            iov_base: b.data.as_ptr() as *mut c_void,
+            iov_len: b.meta.size,
+        })
+        .collect();
+    let mut hdrs: Vec<mmsghdr> = addrs
+        .iter_mut()
+        .zip(iovs.iter_mut())
+        .map(|(addr, iov)| {
+            let mut hdr: mmsghdr = unsafe { mem::zeroed() };
+            hdr.msg_hdr.msg_name = &mut addr.0 as *mut _ as *mut c_void;
+            hdr.msg_hdr.msg_namelen = addr.1;
+            hdr.msg_hdr.msg_iov = iov;
+            hdr.msg_hdr.msg_iovlen = 1;
+            hdr
+        })
+        .collect();
+    let n = unsafe { sendmmsg(sock.as_raw_fd(), hdrs.as_mut_ptr(), hdrs.len() as _, 0) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_mmsg(sock: &UdpSocket, blobs: &[SharedBlob]) -> io::Result<usize> {
+    send_each(sock, blobs)
+}
+
+/// Send a prefix of `blobs` with a `send_to` each, as `send_mmsg` does.
+fn send_each(sock: &UdpSocket, blobs: &[SharedBlob]) -> io::Result<usize> {
+    for (i, blob) in blobs.iter().enumerate() {
+        let b = blob.read().unwrap();
+        if let Err(e) = sock.send_to(&b.data[..b.meta.size], b.meta.addr()) {
+            return if i > 0 { Ok(i) } else { Err(e) };
+        }
+    }
+    Ok(blobs.len())
+}
+
+/// Send and recycle every blob in `pending`, `batch_size` at a time. The
+/// unsent blobs of a partial send are sent in the next batch. A blob that
+/// fails to send more than `max_retries` times in a row is dropped.
+fn flush(
+    sock: &UdpSocket,
+    recycler: &BlobRecycler,
+    config: &ResponderConfig,
+    metrics: &ResponderMetrics,
+    pending: &mut VecDeque<SharedBlob>,
+) {
+    let mut failures = 0;
+    while !pending.is_empty() {
+        let n = min(pending.len(), max(1, config.batch_size));
+        let batch: Vec<_> = pending.iter().take(n).cloned().collect();
+        let result = if config.use_sendmmsg {
+            send_mmsg(sock, &batch)
+        } else {
+            send_each(sock, &batch)
+        };
+        match result {
+            Ok(sent) if sent > 0 => {
+                metrics.batches.fetch_add(1, Ordering::Relaxed);
+                metrics.sent.fetch_add(sent, Ordering::Relaxed);
+                for b in pending.drain(..sent) {
+                    recycler.recycle(b);
+                }
+                failures = 0;
+            }
+            result => {
+                metrics.send_errors.fetch_add(1, Ordering::Relaxed);
+                failures += 1;
+                if failures > config.max_retries {
+                    let b = pending.pop_front().unwrap();
+                    warn!("dropping response to {}: {:?}", b.read().unwrap().meta.addr(), result);
+                    metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    recycler.recycle(b);
+                    failures = 0;
+                }
+            }
+        }
+    }
+}
+
+fn recv_send(
+    sock: &UdpSocket,
+    recycler: &BlobRecycler,
+    r: &BlobReceiver,
+    config: &ResponderConfig,
+    metrics: &ResponderMetrics,
+) -> Result<()> {
     let timer = Duration::new(1, 0);
-    let mut msgs = r.recv_timeout(timer)?;
-    Blob::send_to(recycler, sock, &mut msgs)?;
+    let mut pending = r.recv_timeout(timer)?;
+    let deadline = Instant::now() + config.linger;
+    while pending.len() < config.batch_size {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match r.recv_timeout(deadline - now) {
+            Ok(mut msgs) => pending.append(&mut msgs),
+            Err(_) => break,
+        }
+    }
+    flush(sock, recycler, config, metrics, &mut pending);
     Ok(())
 }
 
+/// Service to send the blobs from `r`, each to the address in its meta, in
+/// batches as `ResponderConfig::default` describes.
 pub fn responder(
     sock: UdpSocket,
     exit: Arc<AtomicBool>,
     recycler: BlobRecycler,
     r: BlobReceiver,
 ) -> JoinHandle<()> {
-    spawn(move || loop {
-        if recv_send(&sock, &recycler, &r).is_err() && exit.load(Ordering::Relaxed) {
+    batch_responder(sock, exit, recycler, r, ResponderConfig::default()).0
+}
+
+/// Service to send the blobs from `r` as `responder` does, in batches as
+/// `config` describes.
+pub fn batch_responder(
+    sock: UdpSocket,
+    exit: Arc<AtomicBool>,
+    recycler: BlobRecycler,
+    r: BlobReceiver,
+    config: ResponderConfig,
+) -> (JoinHandle<()>, Arc<ResponderMetrics>) {
+    let metrics = Arc::new(ResponderMetrics::default());
+    let metrics_ = metrics.clone();
+    let t = spawn(move || loop {
+        let e = recv_send(&sock, &recycler, &r, &config, &metrics_);
+        if e.is_err() && exit.load(Ordering::Relaxed) {
             break;
         }
-    })
+    });
+    (t, metrics)
 }
 
 //TODO, we would need to stick block authentication before we create the
@@ -104,11 +411,119 @@ pub fn blob_receiver(
     Ok(t)
 }
 
+/// What a `ReceiveWindow` does with a blob too far ahead of the next blob it
+/// expects to fit in it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WindowOverflow {
+    /// Drop the blob, leaving room for the missing blobs to be repaired.
+    RejectNewest,
+    /// Give up on enough of the oldest blobs, received or not, to fit the blob.
+    DropOldest,
+}
+
+/// A circular buffer of received blobs, indexed by blob index, that releases
+/// them in order.
+pub struct ReceiveWindow {
+    blobs: Vec<Option<SharedBlob>>,
+    /// The index of the next blob to release.
+    consumed: u64,
+    /// One more than the highest index held or released.
+    received: u64,
+    overflow: WindowOverflow,
+}
+
+impl ReceiveWindow {
+    /// Create a window that holds the `size` blobs after the last one it
+    /// released, starting from index 0.
+    pub fn new(size: usize, overflow: WindowOverflow) -> Self {
+        ReceiveWindow {
+            blobs: vec![None; max(1, size)],
+            consumed: 0,
+            received: 0,
+            overflow,
+        }
+    }
+
+    /// The index of the next blob to be released.
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// The index of the last blob released, if any.
+    pub fn highest_contiguous(&self) -> Option<u64> {
+        self.consumed.checked_sub(1)
+    }
+
+    /// Hold `blob` until it can be released in order. A blob that was already
+    /// released or is already held is recycled, as is one that overflows the
+    /// window under `WindowOverflow::RejectNewest`. Returns whether `blob` is
+    /// held.
+    pub fn insert(&mut self, blob: SharedBlob, recycler: &BlobRecycler) -> bool {
+        let ix = blob.read().unwrap().get_index().unwrap_or(0);
+        let size = self.blobs.len() as u64;
+        if ix == TOO_OLD_INDEX {
+            debug!("blob holds no entries");
+            recycler.recycle(blob);
+            return false;
+        }
+        if ix < self.consumed {
+            debug!("stale blob at index {}", ix);
+            recycler.recycle(blob);
+            return false;
+        }
+        if ix >= self.consumed + size {
+            if self.overflow == WindowOverflow::RejectNewest {
+                debug!("blob at index {} overflows the window", ix);
+                recycler.recycle(blob);
+                return false;
+            }
+            let consumed = ix + 1 - size;
+            warn!("window overflow, giving up on blobs {} to {}", self.consumed, consumed - 1);
+            for i in self.consumed..min(consumed, self.consumed + size) {
+                if let Some(old) = self.blobs[(i % size) as usize].take() {
+                    recycler.recycle(old);
+                }
+            }
+            self.consumed = consumed;
+        }
+        //TODO, after the block are authenticated
+        //if we get different blocks at the same index
+        //that is a network failure/attack
+        let w = (ix % size) as usize;
+        if self.blobs[w].is_some() {
+            debug!("duplicate blob at index {:}", ix);
+            recycler.recycle(blob);
+            return false;
+        }
+        self.blobs[w] = Some(blob);
+        self.received = max(self.received, ix + 1);
+        true
+    }
+
+    /// Release the blobs held from `consumed` up to the first missing one.
+    pub fn take_contiguous(&mut self) -> VecDeque<SharedBlob> {
+        let size = self.blobs.len() as u64;
+        let mut q = VecDeque::new();
+        while let Some(b) = self.blobs[(self.consumed % size) as usize].take() {
+            q.push_back(b);
+            self.consumed += 1;
+        }
+        q
+    }
+
+    /// The indexes of the blobs missing before the highest one received.
+    pub fn missing(&self) -> Vec<u64> {
+        let size = self.blobs.len() as u64;
+        (self.consumed..self.received)
+            .filter(|i| self.blobs[(i % size) as usize].is_none())
+            .collect()
+    }
+}
+
 fn recv_window(
-    window: &mut Vec<Option<SharedBlob>>,
+    window: &mut ReceiveWindow,
     subs: &Arc<RwLock<subscribers::Subscribers>>,
     recycler: &BlobRecycler,
-    consumed: &mut usize,
     r: &BlobReceiver,
     s: &BlobSender,
     cast: &BlobSender,
@@ -149,32 +564,10 @@ fn recv_window(
         }
     }
     //send a contiguous set of blocks
-    let mut contq = VecDeque::new();
-    while let Some(b) = dq.pop_front() {
-        let b_ = b.clone();
-        let p = b.write().unwrap();
-        let pix = p.get_index()? as usize;
-        let w = pix % NUM_BLOBS;
-        //TODO, after the block are authenticated
-        //if we get different blocks at the same index
-        //that is a network failure/attack
-        {
-            if window[w].is_none() {
-                window[w] = Some(b_);
-            } else {
-                debug!("duplicate blob at index {:}", w);
-            }
-            loop {
-                let k = *consumed % NUM_BLOBS;
-                if window[k].is_none() {
-                    break;
-                }
-                contq.push_back(window[k].clone().unwrap());
-                window[k] = None;
-                *consumed += 1;
-            }
-        }
+    for b in dq {
+        window.insert(b, recycler);
     }
+    let contq = window.take_contiguous();
     if !contq.is_empty() {
         s.send(contq)?;
     }
@@ -190,13 +583,104 @@ pub fn window(
     cast: BlobSender,
 ) -> JoinHandle<()> {
     spawn(move || {
-        let mut window = vec![None; NUM_BLOBS];
-        let mut consumed = 0;
+        let mut window = ReceiveWindow::new(NUM_BLOBS, WindowOverflow::RejectNewest);
+        loop {
+            if exit.load(Ordering::Relaxed) {
+                break;
+            }
+            let _ = recv_window(&mut window, &subs, &recycler, &r, &s, &cast);
+        }
+    })
+}
+
+/// A request for the blobs a receiver is missing, which a broadcaster serves
+/// with `repair_blobs`, or a `repair::RepairServer` over the network.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct RepairRequest {
+    pub missing_indices: Vec<u64>,
+}
+
+/// The index of a blob that answers a `RepairRequest` with the indices that
+/// can no longer be repaired, as a bincode-serialized `Vec<u64>`, rather than
+/// holding entries.
+pub const TOO_OLD_INDEX: u64 = u64::MAX;
+
+/// How an `entry_window` holds blobs and asks for the missing ones.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WindowConfig {
+    /// The number of blobs the window holds.
+    pub size: usize,
+    pub overflow: WindowOverflow,
+    /// How often to request the blobs missing from the window.
+    pub repair_interval: Duration,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            size: NUM_BLOBS,
+            overflow: WindowOverflow::RejectNewest,
+            repair_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+fn recv_entry_window(
+    window: &mut ReceiveWindow,
+    recycler: &BlobRecycler,
+    r: &BlobReceiver,
+    entries: &mpsc::Sender<Entry>,
+    timer: Duration,
+) -> Result<()> {
+    let mut dq = r.recv_timeout(timer)?;
+    while let Ok(mut nq) = r.try_recv() {
+        dq.append(&mut nq)
+    }
+    for b in dq {
+        window.insert(b, recycler);
+    }
+    for b in window.take_contiguous() {
+        let mut q = VecDeque::new();
+        q.push_back(b);
+        match blobs_to_entries(&q) {
+            Ok(es) => for e in es {
+                entries.send(e)?;
+            },
+            Err(e) => warn!("failed to read entries from a blob: {:?}", e),
+        }
+        recycler.recycle(q.pop_front().unwrap());
+    }
+    Ok(())
+}
+
+/// Service to reassemble the blobs from `r`, as packed by a `broadcaster`,
+/// and send their entries to `entries` in order. While blobs are missing
+/// from the window, a `RepairRequest` for them is sent to `repairs` every
+/// `repair_interval`.
+pub fn entry_window(
+    exit: Arc<AtomicBool>,
+    recycler: BlobRecycler,
+    r: BlobReceiver,
+    entries: mpsc::Sender<Entry>,
+    repairs: mpsc::Sender<RepairRequest>,
+    config: WindowConfig,
+) -> JoinHandle<()> {
+    spawn(move || {
+        let mut window = ReceiveWindow::new(config.size, config.overflow);
+        let mut last_repair = Instant::now();
         loop {
             if exit.load(Ordering::Relaxed) {
                 break;
             }
-            let _ = recv_window(&mut window, &subs, &recycler, &mut consumed, &r, &s, &cast);
+            let timer = config.repair_interval;
+            let _ = recv_entry_window(&mut window, &recycler, &r, &entries, timer);
+            if last_repair.elapsed() >= config.repair_interval {
+                last_repair = Instant::now();
+                let missing_indices = window.missing();
+                if !missing_indices.is_empty() {
+                    let _ = repairs.send(RepairRequest { missing_indices });
+                }
+            }
         }
     })
 }
@@ -249,6 +733,144 @@ pub fn retransmitter(
     })
 }
 
+/// The blobs a broadcaster sent most recently, at their index modulo
+/// `NUM_BLOBS`, kept to answer retransmission requests.
+pub type BroadcastWindow = Arc<RwLock<Vec<Option<SharedBlob>>>>;
+
+/// The most blobs a `BroadcastHistory` remembers.
+pub const MAX_BROADCAST_HISTORY: usize = 1 << 16;
+
+/// Which entries of a broadcast its recent blobs hold, so that blobs that
+/// have left the `BroadcastWindow` can be rebuilt from the ledger.
+#[derive(Debug, Default)]
+pub struct BroadcastHistory {
+    /// The index of the oldest blob remembered.
+    first_index: u64,
+    /// The position in the broadcast of each blob's first entry, and the
+    /// number of entries it holds.
+    blobs: VecDeque<(u64, u64)>,
+    /// The number of entries broadcast.
+    num_entries: u64,
+}
+
+impl BroadcastHistory {
+    /// Remember the next blob, which holds the next `num_entries` entries.
+    pub fn record(&mut self, num_entries: u64) {
+        self.blobs.push_back((self.num_entries, num_entries));
+        self.num_entries += num_entries;
+        if self.blobs.len() > MAX_BROADCAST_HISTORY {
+            self.blobs.pop_front();
+            self.first_index += 1;
+        }
+    }
+
+    /// The index of the oldest blob remembered.
+    pub fn first_index(&self) -> u64 {
+        self.first_index
+    }
+
+    /// The position in the broadcast of the first entry of the blob with
+    /// `index`, and the number of entries it holds, if it's remembered.
+    pub fn entries(&self, index: u64) -> Option<(u64, u64)> {
+        if index < self.first_index {
+            return None;
+        }
+        self.blobs.get((index - self.first_index) as usize).cloned()
+    }
+}
+
+pub type SharedBroadcastHistory = Arc<RwLock<BroadcastHistory>>;
+
+/// Return the blob with `index` if `window` still holds it.
+pub fn find_broadcast_blob(window: &BroadcastWindow, index: u64) -> Option<SharedBlob> {
+    let window = window.read().unwrap();
+    match window[index as usize % NUM_BLOBS] {
+        Some(ref blob) if blob.read().unwrap().get_index().ok() == Some(index) => {
+            Some(blob.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Return the blobs `req` asks for that `window` still holds. They're shared
+/// with the window, so send copies of them rather than recycling them.
+pub fn repair_blobs(window: &BroadcastWindow, req: &RepairRequest) -> Vec<SharedBlob> {
+    req.missing_indices
+        .iter()
+        .filter_map(|&ix| find_broadcast_blob(window, ix))
+        .collect()
+}
+
+fn broadcast(
+    entries: &mpsc::Receiver<Entry>,
+    sock: &UdpSocket,
+    peers: &[SocketAddr],
+    recycler: &BlobRecycler,
+    window: &BroadcastWindow,
+    history: &SharedBroadcastHistory,
+    index: &mut u64,
+) -> Result<()> {
+    let mut batch = vec![entries.recv()?];
+    while let Ok(entry) = entries.try_recv() {
+        batch.push(entry);
+    }
+    let mut blobs = VecDeque::new();
+    entries_to_blobs(&batch, recycler, &mut blobs)?;
+    for blob in &blobs {
+        let mut b = blob.write().unwrap();
+        b.set_index(*index)?;
+        *index += 1;
+        for peer in peers {
+            if let Err(err) = sock.send_to(&b.data[..b.meta.size], peer) {
+                warn!("failed to broadcast to {}: {:?}", peer, err);
+            }
+        }
+    }
+    {
+        let mut history = history.write().unwrap();
+        for blob in &blobs {
+            history.record(num_blob_entries(&blob.read().unwrap())?);
+        }
+    }
+    let mut window = window.write().unwrap();
+    for blob in blobs {
+        let w = blob.read().unwrap().get_index()? as usize % NUM_BLOBS;
+        if let Some(old) = window[w].replace(blob) {
+            recycler.recycle(old);
+        }
+    }
+    Ok(())
+}
+
+/// Service to send the entries from `entries` to every peer, packed into
+/// blobs with consecutive indexes starting at 0. A peer that can't be sent
+/// to is skipped. The last `NUM_BLOBS` blobs are kept in the returned
+/// window, and which entries each blob holds in the returned history. The
+/// service exits once `entries` disconnects.
+pub fn broadcaster(
+    entries: mpsc::Receiver<Entry>,
+    sock: UdpSocket,
+    peers: Vec<SocketAddr>,
+) -> (JoinHandle<()>, BroadcastWindow, SharedBroadcastHistory) {
+    let window = Arc::new(RwLock::new(vec![None; NUM_BLOBS]));
+    let history = Arc::new(RwLock::new(BroadcastHistory::default()));
+    let window_ = window.clone();
+    let history_ = history.clone();
+    let t = spawn(move || {
+        let recycler = BlobRecycler::default();
+        let mut index = 0;
+        loop {
+            let r = broadcast(&entries, &sock, &peers, &recycler, &window_, &history_, &mut index);
+            match r {
+                Ok(()) => (),
+                Err(Error::RecvError(_)) => break,
+                Err(err) => warn!("failed to broadcast entries: {:?}", err),
+            }
+        }
+    });
+    (t, window, history)
+}
+
 #[cfg(all(feature = "unstable", test))]
 mod bench {
     extern crate test;
@@ -353,7 +975,8 @@ mod bench {
 
 #[cfg(test)]
 mod test {
-    use packet::{Blob, BlobRecycler, Packet, PacketRecycler, Packets, PACKET_DATA_SIZE};
+    use packet::{Blob, BlobRecycler, Packet, PacketRecycler, Packets, SharedBlob, NUM_BLOBS,
+                 PACKET_DATA_SIZE};
     use std::collections::VecDeque;
     use std::io;
     use std::io::Write;
@@ -362,9 +985,19 @@ mod test {
     use std::sync::mpsc::channel;
     use std::sync::{Arc, RwLock};
     use std::time::Duration;
-    use streamer::{blob_receiver, receiver, responder, retransmitter, window, BlobReceiver,
-                   PacketReceiver};
+    use streamer::{batch_responder, blob_receiver, broadcaster, entry_window,
+                   find_broadcast_blob, limited_receiver, receiver, repair_blobs, responder,
+                   retransmitter, window, BlobReceiver, PacketReceiver, ReceiveWindow,
+                   ReceiverConfig, RepairRequest, ResponderConfig, WindowConfig, WindowOverflow};
     use subscribers::{Node, Subscribers};
+    use entry::{create_entry, Entry};
+    use reject_log::{Reason, RejectLog, Stage, Subject, DEFAULT_CAPACITY};
+    use event::Event;
+    use hash::Hash;
+    use ledger::{blobs_to_entries, entries_to_blobs, Block};
+    use signature::{KeyPair, KeyPairUtil};
+    use std::net::SocketAddr;
+    use transaction::Transaction;
 
     fn get_msgs(r: PacketReceiver, num: &mut usize) {
         for _t in 0..5 {
@@ -415,6 +1048,160 @@ mod test {
         t_responder.join().expect("join");
     }
 
+    #[test]
+    pub fn limited_receiver_test() {
+        let read = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let addr = read.local_addr().unwrap();
+        let flooder = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let client = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let exit = Arc::new(AtomicBool::new(false));
+        let config = ReceiverConfig {
+            packets_per_sec: 1,
+            burst: 20,
+            max_sources: 16,
+            max_packet_size: 64,
+        };
+        let (s_reader, r_reader) = channel();
+        let rejections = Arc::new(RejectLog::default());
+        let recycler = PacketRecycler::default();
+        let (t_receiver, metrics) =
+            limited_receiver(read, exit.clone(), recycler, s_reader, config, rejections.clone())
+                .unwrap();
+
+        flooder.send_to(&[0u8; 65], addr).unwrap();
+        for i in 0..200 {
+            flooder.send_to(&[0u8; 32], addr).unwrap();
+            if i % 20 == 0 {
+                client.send_to(&[1u8; 32], addr).unwrap();
+            }
+        }
+        let (mut flooded, mut served) = (0, 0);
+        while let Ok(msgs) = r_reader.recv_timeout(Duration::from_millis(500)) {
+            for p in &msgs.read().unwrap().packets {
+                if p.meta.addr() == client.local_addr().unwrap() {
+                    served += 1;
+                } else {
+                    flooded += 1;
+                }
+            }
+        }
+        assert_eq!(served, 10);
+        assert!((20..=22).contains(&flooded));
+        assert_eq!(metrics.rate_limited.load(Ordering::Relaxed), 200 - flooded);
+        assert_eq!(metrics.oversized.load(Ordering::Relaxed), 1);
+
+        let recent = rejections.recent(DEFAULT_CAPACITY);
+        assert_eq!(recent.len(), 200 - flooded + 1);
+        let flooder = Subject::Source(flooder.local_addr().unwrap());
+        let oversized = recent.last().unwrap();
+        assert_eq!((oversized.stage, oversized.reason), (Stage::Intake, Reason::Oversized));
+        assert_eq!(oversized.subject, flooder);
+        for x in &recent[..recent.len() - 1] {
+            let expected = (Stage::Intake, Reason::RateLimited, flooder);
+            assert_eq!((x.stage, x.reason, x.subject), expected);
+        }
+        exit.store(true, Ordering::Relaxed);
+        t_receiver.join().expect("join");
+    }
+
+    #[test]
+    pub fn limited_receiver_truncation_test() {
+        let read = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let addr = read.local_addr().unwrap();
+        let send = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let exit = Arc::new(AtomicBool::new(false));
+        let (s_reader, r_reader) = channel();
+        let rejections = Arc::new(RejectLog::default());
+        let recycler = PacketRecycler::default();
+        let config = ReceiverConfig::default();
+        let (t_receiver, metrics) =
+            limited_receiver(read, exit.clone(), recycler, s_reader, config, rejections)
+                .unwrap();
+
+        // Datagrams that fill a packet may have been truncated to fit it.
+        send.send_to(&[0u8; PACKET_DATA_SIZE + 1], addr).unwrap();
+        send.send_to(&[0u8; PACKET_DATA_SIZE], addr).unwrap();
+        send.send_to(&[0u8; PACKET_DATA_SIZE - 1], addr).unwrap();
+        let mut sizes = vec![];
+        while let Ok(msgs) = r_reader.recv_timeout(Duration::from_millis(500)) {
+            sizes.extend(msgs.read().unwrap().packets.iter().map(|p| p.meta.size));
+        }
+        assert_eq!(sizes, vec![PACKET_DATA_SIZE - 1]);
+        assert_eq!(metrics.oversized.load(Ordering::Relaxed), 2);
+        exit.store(true, Ordering::Relaxed);
+        t_receiver.join().expect("join");
+    }
+
+    /// Send 41 responses, alternately to two sockets and once to a peer that
+    /// can't be sent to, through a batch responder. Returns the payloads each
+    /// socket received.
+    fn batch_responses(use_sendmmsg: bool) -> Vec<Vec<u8>> {
+        let reads: Vec<_> = (0..2)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").expect("bind"))
+            .collect();
+        let send = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let bad_peer: SocketAddr = "[::1]:9".parse().unwrap();
+        let exit = Arc::new(AtomicBool::new(false));
+        let resp_recycler = BlobRecycler::default();
+        let config = ResponderConfig {
+            batch_size: 8,
+            linger: Duration::from_millis(1),
+            max_retries: 1,
+            use_sendmmsg,
+        };
+        let (s_responder, r_responder) = channel();
+        let (t_responder, metrics) =
+            batch_responder(send, exit.clone(), resp_recycler.clone(), r_responder, config);
+        for i in 0..40 {
+            let b = resp_recycler.allocate();
+            {
+                let mut w = b.write().unwrap();
+                w.data[0] = i as u8;
+                w.meta.size = PACKET_DATA_SIZE;
+                w.meta.set_addr(&reads[i % 2].local_addr().unwrap());
+            }
+            let mut msgs = VecDeque::new();
+            msgs.push_back(b);
+            if i == 5 {
+                let b = resp_recycler.allocate();
+                b.write().unwrap().meta.size = 1;
+                b.write().unwrap().meta.set_addr(&bad_peer);
+                msgs.push_back(b);
+            }
+            s_responder.send(msgs).expect("send");
+        }
+        drop(s_responder);
+        exit.store(true, Ordering::Relaxed);
+        t_responder.join().expect("join");
+        assert_eq!(metrics.sent.load(Ordering::Relaxed), 40);
+        assert_eq!(metrics.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.send_errors.load(Ordering::Relaxed), 2);
+
+        reads
+            .iter()
+            .map(|read| {
+                read.set_read_timeout(Some(Duration::new(1, 0))).unwrap();
+                let mut buf = [0u8; PACKET_DATA_SIZE];
+                (0..20)
+                    .map(|_| {
+                        let (size, _) = read.recv_from(&mut buf).unwrap();
+                        assert_eq!(size, PACKET_DATA_SIZE);
+                        buf[0]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn batch_responder_test() {
+        let expected: Vec<Vec<u8>> = (0..2)
+            .map(|r| (0..40).filter(|i| i % 2 == r).collect())
+            .collect();
+        assert_eq!(batch_responses(true), expected);
+        assert_eq!(batch_responses(false), expected);
+    }
+
     fn get_blobs(r: BlobReceiver, num: &mut usize) {
         for _t in 0..5 {
             let timer = Duration::new(1, 0);
@@ -526,4 +1313,193 @@ mod test {
         t_retransmit.join().expect("join");
     }
 
+    /// Return `len` chained entries that each hold `num_trs` transactions.
+    fn transaction_entries(len: usize, num_trs: usize) -> Vec<Entry> {
+        let keypair = KeyPair::new();
+        let to = KeyPair::new().pubkey();
+        let mut id = Hash::default();
+        let mut entries = vec![];
+        for i in 0..len {
+            let events = (0..num_trs)
+                .map(|j| {
+                    let tokens = (i * num_trs + j) as i64;
+                    Event::Transaction(Transaction::new(&keypair, to, tokens, id))
+                })
+                .collect();
+            let entry = create_entry(&id, 0, events);
+            id = entry.id;
+            entries.push(entry);
+        }
+        entries
+    }
+
+    #[test]
+    pub fn broadcast_test() {
+        let read = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let addr = read.local_addr().unwrap();
+        let send = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let exit = Arc::new(AtomicBool::new(false));
+        let recycler = BlobRecycler::default();
+        let (s_reader, r_reader) = channel();
+        let t_receiver = blob_receiver(exit.clone(), recycler.clone(), read, s_reader).unwrap();
+
+        // An IPv4 socket can't send to the IPv6 peer, which mustn't stop it
+        // sending to the other.
+        let bad_peer: SocketAddr = "[::1]:9".parse().unwrap();
+        let (s_entries, r_entries) = channel();
+        let (t_broadcaster, window, history) = broadcaster(r_entries, send, vec![bad_peer, addr]);
+        let entries = transaction_entries(20, 20);
+        for entry in &entries {
+            s_entries.send(entry.clone()).unwrap();
+        }
+        drop(s_entries);
+        t_broadcaster.join().expect("join");
+        let num_blobs = window.read().unwrap().iter().filter(|x| x.is_some()).count();
+        assert!(num_blobs > 1);
+        assert!(find_broadcast_blob(&window, num_blobs as u64 - 1).is_some());
+        assert!(find_broadcast_blob(&window, num_blobs as u64).is_none());
+        let history = history.read().unwrap();
+        let (first, n) = history.entries(num_blobs as u64 - 1).unwrap();
+        assert_eq!(first + n, entries.len() as u64);
+        assert_eq!(history.entries(num_blobs as u64), None);
+
+        let mut blobs = VecDeque::new();
+        while blobs.len() < num_blobs {
+            let mut q = r_reader.recv_timeout(Duration::new(5, 0)).unwrap();
+            blobs.append(&mut q);
+        }
+        exit.store(true, Ordering::Relaxed);
+        t_receiver.join().expect("join");
+
+        let mut blobs: Vec<_> = blobs.into_iter().collect();
+        blobs.sort_by_key(|b| b.read().unwrap().get_index().unwrap());
+        for (i, blob) in blobs.iter().enumerate() {
+            assert_eq!(blob.read().unwrap().get_index().unwrap(), i as u64);
+        }
+        let received = blobs_to_entries(&blobs.into_iter().collect()).unwrap();
+        assert_eq!(received, entries);
+        assert!(received[..].verify(&Hash::default()));
+    }
+
+    fn indexed_blob(recycler: &BlobRecycler, ix: u64) -> SharedBlob {
+        let b = recycler.allocate();
+        b.write().unwrap().set_index(ix).unwrap();
+        b.write().unwrap().meta.size = PACKET_DATA_SIZE;
+        b
+    }
+
+    fn indexes(q: &VecDeque<SharedBlob>) -> Vec<u64> {
+        q.iter()
+            .map(|b| b.read().unwrap().get_index().unwrap())
+            .collect()
+    }
+
+    #[test]
+    pub fn receive_window_test() {
+        let recycler = BlobRecycler::default();
+        let mut window = ReceiveWindow::new(8, WindowOverflow::RejectNewest);
+        assert_eq!(window.highest_contiguous(), None);
+
+        // Shuffled, with 2 missing and 4 duplicated.
+        let inserts = [(3, true), (1, true), (4, true), (0, true), (5, true), (4, false)];
+        for &(ix, kept) in &inserts {
+            assert_eq!(window.insert(indexed_blob(&recycler, ix), &recycler), kept);
+        }
+        assert_eq!(window.missing(), vec![2]);
+        assert_eq!(indexes(&window.take_contiguous()), vec![0, 1]);
+        assert_eq!(window.highest_contiguous(), Some(1));
+        assert!(window.take_contiguous().is_empty());
+
+        // A released blob is stale.
+        assert!(!window.insert(indexed_blob(&recycler, 1), &recycler));
+        assert!(window.insert(indexed_blob(&recycler, 2), &recycler));
+        assert!(window.missing().is_empty());
+        assert_eq!(indexes(&window.take_contiguous()), vec![2, 3, 4, 5]);
+        assert_eq!(window.consumed(), 6);
+    }
+
+    #[test]
+    pub fn receive_window_overflow_test() {
+        let recycler = BlobRecycler::default();
+        let mut window = ReceiveWindow::new(4, WindowOverflow::RejectNewest);
+        assert!(window.insert(indexed_blob(&recycler, 1), &recycler));
+        assert!(!window.insert(indexed_blob(&recycler, 4), &recycler));
+        assert_eq!(window.missing(), vec![0]);
+        assert!(window.insert(indexed_blob(&recycler, 3), &recycler));
+        assert_eq!(window.consumed(), 0);
+
+        let mut window = ReceiveWindow::new(4, WindowOverflow::DropOldest);
+        assert!(window.insert(indexed_blob(&recycler, 1), &recycler));
+        assert!(window.insert(indexed_blob(&recycler, 3), &recycler));
+        assert!(window.insert(indexed_blob(&recycler, 5), &recycler));
+        assert_eq!(window.consumed(), 2);
+        assert_eq!(window.missing(), vec![2, 4]);
+        assert!(window.insert(indexed_blob(&recycler, 100), &recycler));
+        assert_eq!(window.consumed(), 97);
+        assert_eq!(window.missing(), vec![97, 98, 99]);
+        assert!(!window.insert(indexed_blob(&recycler, 5), &recycler));
+    }
+
+    #[test]
+    pub fn entry_window_test() {
+        let exit = Arc::new(AtomicBool::new(false));
+        let recycler = BlobRecycler::default();
+        let entries = transaction_entries(40, 20);
+        let mut blobs = VecDeque::new();
+        entries_to_blobs(&entries, &recycler, &mut blobs).unwrap();
+        assert!(blobs.len() > 2);
+        for (i, b) in blobs.iter().enumerate() {
+            b.write().unwrap().set_index(i as u64).unwrap();
+        }
+        let broadcast_window = Arc::new(RwLock::new(vec![None; NUM_BLOBS]));
+        for (i, b) in blobs.iter().enumerate() {
+            broadcast_window.write().unwrap()[i] = Some(b.clone());
+        }
+
+        let (s_blobs, r_blobs) = channel();
+        let (s_entries, r_entries) = channel();
+        let (s_repairs, r_repairs) = channel();
+        let config = WindowConfig {
+            repair_interval: Duration::from_millis(10),
+            ..WindowConfig::default()
+        };
+        let t_window = entry_window(
+            exit.clone(),
+            recycler.clone(),
+            r_blobs,
+            s_entries,
+            s_repairs,
+            config,
+        );
+
+        // Send every blob but the second, in reverse order, and the last twice.
+        let mut q: VecDeque<_> = blobs.iter().skip(2).rev().cloned().collect();
+        q.push_front(blobs.back().unwrap().clone());
+        q.push_back(blobs[0].clone());
+        s_blobs.send(q).unwrap();
+        let num_first = blobs_to_entries(&blobs.iter().take(1).cloned().collect()).unwrap();
+        let mut received = vec![];
+        for _ in 0..num_first.len() {
+            received.push(r_entries.recv_timeout(Duration::new(5, 0)).unwrap());
+        }
+        let req = r_repairs.recv_timeout(Duration::new(5, 0)).unwrap();
+        assert_eq!(
+            req,
+            RepairRequest {
+                missing_indices: vec![1],
+            }
+        );
+        assert!(r_entries.try_recv().is_err());
+
+        let q: VecDeque<_> = repair_blobs(&broadcast_window, &req).into_iter().collect();
+        assert_eq!(indexes(&q), vec![1]);
+        s_blobs.send(q).unwrap();
+        while received.len() < entries.len() {
+            received.push(r_entries.recv_timeout(Duration::new(5, 0)).unwrap());
+        }
+        assert_eq!(received, entries);
+        exit.store(true, Ordering::Relaxed);
+        t_window.join().expect("join");
+        assert!(r_entries.try_recv().is_err());
+    }
 }
@@ -7,7 +7,7 @@
 //!
 //! It's up to the external state machine to keep this updated.
 use packet::Blob;
-use rayon::prelude::*;
+use parallel::*;
 use result::{Error, Result};
 use std::net::{SocketAddr, UdpSocket};
 
@@ -88,7 +88,7 @@ impl Subscribers {
 #[cfg(test)]
 mod test {
     use packet::Blob;
-    use rayon::prelude::*;
+    use parallel::*;
     use std::net::UdpSocket;
     use std::time::Duration;
     use subscribers::{Node, Subscribers};
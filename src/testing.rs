@@ -0,0 +1,193 @@
+//! The `testing` module generates keypairs, events and chains of entries for
+//! property tests, as [proptest](https://docs.rs/proptest) strategies. Other
+//! crates can use it by enabling the `testing` feature.
+//!
+//! Its tests are the properties every entry chain must hold.
+
+use chrono::prelude::*;
+use entry::{create_entry, Entry};
+use event::Event;
+use hash::Hash;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use ring::signature::Ed25519KeyPair;
+use signature::{KeyPair, PublicKey, Signature};
+use transaction::Transaction;
+use untrusted;
+
+/// Return the keypair derived from a seed of 32 `n` bytes. The same `n`
+/// always gives the same keypair.
+pub fn seeded_keypair(n: u8) -> KeyPair {
+    Ed25519KeyPair::from_seed_unchecked(untrusted::Input::from(&[n; 32])).unwrap()
+}
+
+pub fn arb_hash() -> impl Strategy<Value = Hash> {
+    any::<[u8; 32]>().prop_map(|x| Hash::clone_from_slice(&x))
+}
+
+pub fn arb_pubkey() -> impl Strategy<Value = PublicKey> {
+    any::<[u8; 32]>().prop_map(|x| PublicKey::clone_from_slice(&x))
+}
+
+fn arb_signature() -> impl Strategy<Value = Signature> {
+    vec(any::<u8>(), 64).prop_map(|x| Signature::clone_from_slice(&x))
+}
+
+fn arb_date() -> impl Strategy<Value = DateTime<Utc>> {
+    (0..4_000_000_000i64).prop_map(|secs| Utc.timestamp_opt(secs, 0).unwrap())
+}
+
+/// An event of any kind, signed by one of 256 seeded keypairs.
+pub fn arb_event() -> impl Strategy<Value = Event> {
+    let transaction = (any::<u8>(), arb_pubkey(), 1..1_000_000i64, arb_hash()).prop_map(
+        |(from, to, tokens, last_id)| {
+            Event::Transaction(Transaction::new(&seeded_keypair(from), to, tokens, last_id))
+        },
+    );
+    let signature = (any::<u8>(), arb_signature())
+        .prop_map(|(from, tx_sig)| Event::new_signature(&seeded_keypair(from), tx_sig));
+    let timestamp = (any::<u8>(), arb_date())
+        .prop_map(|(from, dt)| Event::new_timestamp(&seeded_keypair(from), dt));
+    let time_source = (any::<u8>(), arb_pubkey())
+        .prop_map(|(from, source)| Event::new_time_source(&seeded_keypair(from), source));
+    prop_oneof![transaction, signature, timestamp, time_source]
+}
+
+/// Up to `max_len` events.
+pub fn arb_events(max_len: usize) -> impl Strategy<Value = Vec<Event>> {
+    vec(arb_event(), 0..=max_len)
+}
+
+/// A valid chain from `start_hash` of up to `max_len` entries, each with up
+/// to `max_events` events and up to `max_hashes` hashes before them.
+pub fn arb_chain(
+    start_hash: Hash,
+    max_len: usize,
+    max_events: usize,
+    max_hashes: u64,
+) -> impl Strategy<Value = Vec<Entry>> {
+    vec((0..=max_hashes, arb_events(max_events)), 0..=max_len).prop_map(move |entries| {
+        let mut id = start_hash;
+        entries
+            .into_iter()
+            .map(|(num_hashes, events)| {
+                let entry = create_entry(&id, num_hashes, events);
+                id = entry.id;
+                entry
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::{deserialize, serialize};
+    use entry::{create_entry, next_hash, Entry};
+    use hash::Hash;
+    use ledger::Block;
+    use proptest::prelude::*;
+    use serde_json;
+    use testing::*;
+
+    /// Return whether each of `entries` follows from the one before, checking
+    /// them one at a time.
+    fn verify_each(entries: &[Entry], start_hash: &Hash) -> bool {
+        let mut id = *start_hash;
+        entries.iter().all(|entry| {
+            let valid = entry.verify(&id);
+            id = entry.id;
+            valid
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_create_entry_verifies(
+            start in arb_hash(),
+            num_hashes in 0..64u64,
+            events in arb_events(4),
+        ) {
+            let has_events = !events.is_empty();
+            let entry = create_entry(&start, num_hashes, events);
+            prop_assert_eq!(entry.num_hashes, num_hashes + has_events as u64);
+            prop_assert!(entry.verify(&start));
+        }
+
+        #[test]
+        fn prop_num_hashes_is_exact(
+            start in arb_hash(),
+            num_hashes in 0..64u64,
+            events in arb_events(2),
+            other in 0..66u64,
+        ) {
+            let mut entry = create_entry(&start, num_hashes, events);
+            prop_assume!(other != entry.num_hashes);
+            entry.num_hashes = other;
+            prop_assert!(!entry.verify(&start));
+        }
+
+        #[test]
+        fn prop_slice_verify_is_fold(
+            start in arb_hash(),
+            chain in arb_chain(Hash::default(), 6, 3, 8),
+            corrupt in any::<prop::sample::Index>(),
+            bad_id in arb_hash(),
+        ) {
+            prop_assert!(chain.verify(&Hash::default()));
+            prop_assert_eq!(chain.verify(&start), verify_each(&chain, &start));
+
+            let mut chain = chain;
+            if !chain.is_empty() {
+                let i = corrupt.index(chain.len());
+                chain[i].id = bad_id;
+            }
+            let zero = Hash::default();
+            prop_assert_eq!(chain.verify(&zero), verify_each(&chain, &zero));
+        }
+
+        #[test]
+        fn prop_reordering_events_fails(
+            start in arb_hash(),
+            num_hashes in 0..8u64,
+            events in arb_events(5),
+            i in any::<prop::sample::Index>(),
+            j in any::<prop::sample::Index>(),
+        ) {
+            prop_assume!(events.len() >= 2);
+            let (i, j) = (i.index(events.len()), j.index(events.len()));
+            prop_assume!(events[i] != events[j]);
+            let mut entry = create_entry(&start, num_hashes, events);
+            entry.events.swap(i, j);
+            prop_assert!(!entry.verify(&start));
+        }
+
+        #[test]
+        fn prop_serialization_preserves_verification(
+            chain in arb_chain(Hash::default(), 4, 3, 8),
+        ) {
+            let zero = Hash::default();
+            let bytes: Vec<Entry> = deserialize(&serialize(&chain).unwrap()).unwrap();
+            let json = serde_json::to_string(&chain).unwrap();
+            let parsed: Vec<Entry> = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(&bytes, &chain);
+            prop_assert_eq!(&parsed, &chain);
+            prop_assert!(bytes.verify(&zero));
+            prop_assert!(parsed.verify(&zero));
+        }
+
+        #[test]
+        fn prop_next_hash_composes(start in arb_hash(), a in 0..256u64, b in 0..256u64) {
+            let split = next_hash(&next_hash(&start, a, &[]), b, &[]);
+            prop_assert_eq!(next_hash(&start, a + b, &[]), split);
+        }
+    }
+
+    #[test]
+    fn test_seeded_keypair_is_deterministic() {
+        use signature::KeyPairUtil;
+        assert_eq!(seeded_keypair(1).pubkey(), seeded_keypair(1).pubkey());
+        assert_ne!(seeded_keypair(1).pubkey(), seeded_keypair(2).pubkey());
+    }
+}
@@ -1,11 +1,17 @@
 //! The `transaction` module provides functionality for creating log transactions.
 
 use bincode::serialize;
+#[cfg(feature = "native")]
 use chrono::prelude::*;
 use hash::Hash;
-use plan::{Condition, Payment, Plan};
-use rayon::prelude::*;
-use signature::{KeyPair, KeyPairUtil, PublicKey, Signature, SignatureUtil};
+use hex;
+use parallel::*;
+use plan::Plan;
+#[cfg(feature = "native")]
+use plan::{Condition, Payment};
+#[cfg(feature = "native")]
+use signature::{KeyPair, KeyPairUtil};
+use signature::{PublicKey, Signature, SignatureUtil};
 
 pub const SIGNED_DATA_OFFSET: usize = 112;
 pub const SIG_OFFSET: usize = 8;
@@ -14,19 +20,23 @@ pub const PUB_KEY_OFFSET: usize = 80;
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct TransactionData {
     pub tokens: i64,
+    #[serde(with = "hex")]
     pub last_id: Hash,
     pub plan: Plan,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Transaction {
+    #[serde(with = "hex")]
     pub sig: Signature,
+    #[serde(with = "hex")]
     pub from: PublicKey,
     pub data: TransactionData,
 }
 
 impl Transaction {
     /// Create and sign a new Transaction. Used for unit-testing.
+    #[cfg(feature = "native")]
     pub fn new(from_keypair: &KeyPair, to: PublicKey, tokens: i64, last_id: Hash) -> Self {
         let from = from_keypair.pubkey();
         let plan = Plan::Pay(Payment { tokens, to });
@@ -44,6 +54,7 @@ impl Transaction {
     }
 
     /// Create and sign a postdated Transaction. Used for unit-testing.
+    #[cfg(feature = "native")]
     pub fn new_on_date(
         from_keypair: &KeyPair,
         to: PublicKey,
@@ -69,11 +80,28 @@ impl Transaction {
         tr
     }
 
+    /// Create and sign a Transaction that spends `tokens` with `plan`.
+    #[cfg(feature = "native")]
+    pub fn new_with_plan(from_keypair: &KeyPair, plan: Plan, tokens: i64, last_id: Hash) -> Self {
+        let mut tr = Transaction {
+            sig: Signature::default(),
+            data: TransactionData {
+                plan,
+                tokens,
+                last_id,
+            },
+            from: from_keypair.pubkey(),
+        };
+        tr.sign(from_keypair);
+        tr
+    }
+
     fn get_sign_data(&self) -> Vec<u8> {
         serialize(&(&self.data)).unwrap()
     }
 
     /// Sign this transaction.
+    #[cfg(feature = "native")]
     pub fn sign(&mut self, keypair: &KeyPair) {
         let sign_data = self.get_sign_data();
         self.sig = Signature::clone_from_slice(keypair.sign(&sign_data).as_ref());
@@ -86,9 +114,15 @@ impl Transaction {
     pub fn verify_plan(&self) -> bool {
         self.data.plan.verify(self.data.tokens)
     }
+
+    /// Return true if the transaction is signed by `from` and its plan spends
+    /// exactly `tokens`.
+    pub fn verify(&self) -> bool {
+        self.verify_sig() && self.verify_plan()
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "native"))]
 pub fn test_tx() -> Transaction {
     let keypair1 = KeyPair::new();
     let pubkey1 = keypair1.pubkey();
@@ -123,7 +157,7 @@ pub fn verify_transactions(transactions: &[Transaction]) -> bool {
     verify_signatures(transactions) && verify_plans(transactions)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "native"))]
 mod tests {
     use super::*;
     use bincode::{deserialize, serialize};
@@ -136,6 +170,23 @@ mod tests {
         assert!(tr0.verify_plan());
     }
 
+    #[test]
+    fn test_verify() {
+        let keypair = KeyPair::new();
+        let zero = Hash::default();
+        let mut tr = Transaction::new(&keypair, keypair.pubkey(), 42, zero);
+        assert!(tr.verify());
+
+        // A valid signature doesn't make up for an unbalanced plan.
+        tr.data.plan = Plan::new_payment(43, keypair.pubkey());
+        tr.sign(&keypair);
+        assert!(tr.verify_sig());
+        assert!(!tr.verify());
+
+        let tr = Transaction::new(&keypair, keypair.pubkey(), -1, zero);
+        assert!(!tr.verify());
+    }
+
     #[test]
     fn test_transfer() {
         let zero = Hash::default();